@@ -126,6 +126,83 @@ for line in lines_that_matter:
 print(
     f"[pakgen] skipped {skipped} packages due to them not being from github or not being projects."
 )
+# Packages whose default branch is a development branch that often doesn't build.
+# These get installed from a known-good ref unless the user asks for another one.
+DEFAULT_REFS = {
+    "json": "v3.11.3",
+    "catch2": "v3.5.4",
+}
+
+# Templates from src/pkg_templates.json the packages are based on, for the fields
+# they don't set themselves.
+TEMPLATES = {
+    "json": "header-only-cmake-lib",
+    "catch2": "standard-cmake-lib",
+    "{fmt}": "standard-cmake-lib",
+    "spdlog": "standard-cmake-lib",
+    "doctest": "header-only-cmake-lib",
+}
+
+# Arguments for cmake's configure step, replacing the template's.
+CMAKE_ARGS = {
+    "{fmt}": ["-DFMT_TEST=OFF", "-DFMT_DOC=OFF"],
+}
+
+# What the machine building a package needs, checked before the install starts.
+REQUIREMENTS = {
+    "seastar": {"platforms": [{"os": "linux"}], "gcc": "11", "clang": "14"},
+}
+
+# The programs tool packages provide, which have to end up in <prefix>/bin.
+BINARIES = {
+    "bear": ["bear"],
+    "include-what-you-use": ["include-what-you-use"],
+}
+
+# Other places to clone a package from when its url can't be, tried in order.
+MIRRORS = {}
+
+# The SHA-256 of packages whose url is an archive.
+SHA256 = {}
+
+# The fingerprints of the OpenPGP keys a package's tags, commits or archives are signed with.
+SIGNING_KEYS = {}
+
+# What to tell the user after a package is installed, like the flags needed to link it.
+NOTES = {}
+
+# Words `cinstall search` finds a package by, besides its name and description.
+TAGS = {}
+
+# Registry packages a package needs installed before it can be built.
+DEPENDENCIES = {
+    "spdlog": ["{fmt}"],
+}
+
+# `-D` definitions for cmake's configure step, like {"BUILD_SHARED_LIBS": "ON"}.
+DEFINES = {
+    # use the {fmt} installed as a dependency instead of the copy spdlog bundles.
+    "spdlog": {"SPDLOG_FMT_EXTERNAL": "ON"},
+}
+
+# The make targets that install a package, for makefiles without an `install` one.
+MAKE_TARGETS = {}
+
+# Environment variables every build step of a package runs with.
+ENV = {}
+
+# Shell commands that replace the build system's steps for a package. They run with
+# PREFIX, DESTDIR and JOBS set and have to install into $DESTDIR$PREFIX.
+CONFIGURE_COMMANDS = {}
+BUILD_COMMANDS = {}
+INSTALL_COMMANDS = {}
+
+# Patches applied to a package before it is built, URLs or names of patch files.
+PATCHES = {}
+
+# Named sets of build options a package offers, picked with `cinstall install --variant`.
+VARIANTS = {}
+
 json_object = {}
 
 for package in parsed_info:
@@ -134,6 +211,44 @@ for package in parsed_info:
         "description": package["description"],
         "language": package["language"],
     }
+    if package["name"] in DEFAULT_REFS:
+        json_object[package["name"]]["default_ref"] = DEFAULT_REFS[package["name"]]
+    if package["name"] in TEMPLATES:
+        json_object[package["name"]]["template"] = TEMPLATES[package["name"]]
+    if package["name"] in CMAKE_ARGS:
+        json_object[package["name"]]["cmake_args"] = CMAKE_ARGS[package["name"]]
+    if package["name"] in BINARIES:
+        json_object[package["name"]]["binaries"] = BINARIES[package["name"]]
+    if package["name"] in REQUIREMENTS:
+        json_object[package["name"]]["requires"] = REQUIREMENTS[package["name"]]
+    if package["name"] in MIRRORS:
+        json_object[package["name"]]["mirrors"] = MIRRORS[package["name"]]
+    if package["name"] in SHA256:
+        json_object[package["name"]]["sha256"] = SHA256[package["name"]]
+    if package["name"] in SIGNING_KEYS:
+        json_object[package["name"]]["signing_keys"] = SIGNING_KEYS[package["name"]]
+    if package["name"] in NOTES:
+        json_object[package["name"]]["notes"] = NOTES[package["name"]]
+    if package["name"] in TAGS:
+        json_object[package["name"]]["tags"] = TAGS[package["name"]]
+    if package["name"] in DEPENDENCIES:
+        json_object[package["name"]]["dependencies"] = DEPENDENCIES[package["name"]]
+    if package["name"] in DEFINES:
+        json_object[package["name"]]["defines"] = DEFINES[package["name"]]
+    if package["name"] in MAKE_TARGETS:
+        json_object[package["name"]]["make_targets"] = MAKE_TARGETS[package["name"]]
+    if package["name"] in ENV:
+        json_object[package["name"]]["env"] = ENV[package["name"]]
+    if package["name"] in CONFIGURE_COMMANDS:
+        json_object[package["name"]]["configure"] = CONFIGURE_COMMANDS[package["name"]]
+    if package["name"] in BUILD_COMMANDS:
+        json_object[package["name"]]["build"] = BUILD_COMMANDS[package["name"]]
+    if package["name"] in INSTALL_COMMANDS:
+        json_object[package["name"]]["install"] = INSTALL_COMMANDS[package["name"]]
+    if package["name"] in PATCHES:
+        json_object[package["name"]]["patches"] = PATCHES[package["name"]]
+    if package["name"] in VARIANTS:
+        json_object[package["name"]]["variants"] = VARIANTS[package["name"]]
 
 import json
 
@@ -0,0 +1,238 @@
+// Package artifacts: an installed package as a single archive.
+//
+// `cinstall export-artifact fmt -o fmt.tar.zst` archives exactly the files in
+// the package's manifest, relative to its prefix, along with what cinstall
+// knows about it. `cinstall import-artifact fmt.tar.zst` installs that on
+// another machine without cloning or building anything, so machines without a
+// toolchain can have the package too. The archive holds
+//
+//   cinstall-artifact.json   the package, its prefix and the platform it was built on
+//   files/...                the files, laid out as they are under the prefix
+//
+// and is compressed by tar according to its extension (`.tar.zst`, `.tar.gz`,
+// `.tar.xz`). Importing into another prefix rewrites paths the same way
+// `cinstall relocate` does. A package installed with `--versioned` is archived
+// as the files its links in the prefix point to, and imported as a plain
+// install.
+
+use crate::database::{self, InstalledPackage, MergedDatabase};
+use crate::installer::{self, InstallError, Step};
+use crate::manifest::Manifest;
+use crate::{modulefile, outputln, relocate, staging, workspace};
+use serde::{Deserialize, Serialize};
+use std::path::{Component, Path, PathBuf};
+
+const METADATA: &str = "cinstall-artifact.json";
+
+#[derive(Serialize, Deserialize)]
+struct Metadata {
+    package: InstalledPackage,
+    /// `std::env::consts::OS` and `ARCH` of the machine it was exported on.
+    os: String,
+    arch: String,
+    /// The files, relative to the prefix.
+    files: Vec<PathBuf>,
+}
+
+/// A fresh directory in the workspace to put an artifact together in.
+fn work_dir(name: &str) -> Result<PathBuf, InstallError> {
+    let dir = workspace::root()
+        .join("artifacts")
+        .join(name.replace('/', "_"));
+    if dir.exists() {
+        std::fs::remove_dir_all(&dir)
+            .map_err(|e| InstallError::BadDirectory(format!("{}: {}", dir.display(), e)))?;
+    }
+    std::fs::create_dir_all(&dir).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    Ok(dir)
+}
+
+fn run(step: Step) -> Result<(), InstallError> {
+    if !step.run()?.success() {
+        return Err(InstallError::StepFailed(step.to_string()));
+    }
+    Ok(())
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+/// Archive the installed package `name` into `output`.
+pub fn export(name: &str, output: &Path) -> Result<(), InstallError> {
+    let installed = match MergedDatabase::open().find(name) {
+        Some((_, installed)) => installed.clone(),
+        None => {
+            return Err(InstallError::UnknownFatal(format!(
+                "`{}` is not installed.",
+                name
+            )))
+        }
+    };
+    let manifest = Manifest::load(name)?;
+    let dir = work_dir(&format!("export-{}", name))?;
+
+    let mut files = vec![];
+    for file in &manifest.files {
+        // links outside the prefix, like the ones `--symlink-bin` makes, are
+        // this machine's business.
+        let Ok(relative) = file.path.strip_prefix(&installed.prefix) else {
+            outputln!(
+                purple,
+                "`{}` is outside of {}, leaving it out.",
+                (file.path.display()),
+                (installed.prefix.display())
+            );
+            continue;
+        };
+        if !file.path.exists() && !file.path.is_symlink() {
+            outputln!(
+                purple,
+                "`{}` is already gone, leaving it out.",
+                (file.path.display())
+            );
+            continue;
+        }
+        // the versioned tree isn't on the machine it is imported on.
+        let from = match (&installed.version_dir, std::fs::read_link(&file.path)) {
+            (Some(version_dir), Ok(to)) if to.starts_with(version_dir) => to,
+            _ => file.path.clone(),
+        };
+        staging::copy_tree(&from, &dir.join("files").join(relative))?;
+        files.push(relative.to_path_buf());
+    }
+
+    let metadata = Metadata {
+        package: InstalledPackage {
+            version_dir: None,
+            ..installed
+        },
+        os: std::env::consts::OS.into(),
+        arch: std::env::consts::ARCH.into(),
+        files,
+    };
+    let json = serde_json::to_string_pretty(&metadata)
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    std::fs::write(dir.join(METADATA), json).map_err(|_| InstallError::FailedToWriteToFile)?;
+
+    installer::verify_has_programs(&["tar"])?;
+    let output = absolute(output);
+    // `-a` picks the compression from the extension.
+    run(Step::new("tar", &dir)
+        .arg("-caf")
+        .arg(output.display().to_string())
+        .arg(METADATA)
+        .arg("files"))?;
+    let _ = std::fs::remove_dir_all(&dir);
+    outputln!(
+        "archived {} files of `{}` into {}.",
+        (metadata.files.len()),
+        name,
+        (output.display())
+    );
+    Ok(())
+}
+
+/// Install the package in the artifact `archive`, into its original prefix
+/// unless `prefix` is given. Returns the package as it was recorded.
+pub fn import(archive: &Path, prefix: Option<&Path>) -> Result<InstalledPackage, InstallError> {
+    installer::verify_has_programs(&["tar"])?;
+    let archive = absolute(archive);
+    let stem = archive
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy()
+        .to_string();
+    let dir = work_dir(&format!("import-{}", stem))?;
+    run(Step::new("tar", &dir)
+        .arg("-xf")
+        .arg(archive.display().to_string()))?;
+
+    let text = std::fs::read_to_string(dir.join(METADATA)).map_err(|_| {
+        InstallError::UnknownFatal(format!(
+            "{} isn't an artifact made by `cinstall export-artifact`.",
+            archive.display()
+        ))
+    })?;
+    let metadata: Metadata = serde_json::from_str(&text)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", METADATA, e)))?;
+    if metadata.os != std::env::consts::OS || metadata.arch != std::env::consts::ARCH {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` was built for {}/{}, this machine is {}/{}.",
+            metadata.package.name,
+            metadata.os,
+            metadata.arch,
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        )));
+    }
+
+    // the archive could come from anyone, none of its files may end up
+    // outside the prefix.
+    if let Some(bad) = metadata
+        .files
+        .iter()
+        .find(|f| !f.components().all(|c| matches!(c, Component::Normal(_))))
+    {
+        return Err(InstallError::UnknownFatal(format!(
+            "the artifact lists `{}`, which isn't a path inside the prefix.",
+            bad.display()
+        )));
+    }
+
+    let name = metadata.package.name.clone();
+    if let Some((_, installed)) = MergedDatabase::open().find(&name) {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` is already installed into {}, uninstall it first.",
+            name,
+            installed.prefix.display()
+        )));
+    }
+    let from = metadata.package.prefix.clone();
+    let to = prefix.map_or_else(|| from.clone(), absolute);
+    outputln!(
+        "installing `{}` from {} into {}",
+        name,
+        (archive.display()),
+        (to.display())
+    );
+
+    let staging = dir.join("staging");
+    let staged_prefix = staging.join(to.strip_prefix("/").unwrap_or(&to));
+    let mut rpaths_left = 0;
+    for relative in &metadata.files {
+        let file = dir.join("files").join(relative);
+        let staged = staged_prefix.join(relative);
+        if from == to {
+            staging::copy_tree(&file, &staged)?;
+        } else if !relocate::restage(&file, &staged, &from, &to)? {
+            rpaths_left += 1;
+        }
+    }
+    if rpaths_left > 0 {
+        outputln!(
+            purple,
+            "the rpath of {} file(s) still points at {}, they may not find their libraries until it is changed (`patchelf --set-rpath`).",
+            rpaths_left,
+            (from.display())
+        );
+    }
+
+    staging::hold_back_system_paths(&staging, &to, false)?;
+    let files = staging::commit(&name, &staging)?;
+    let _ = std::fs::remove_dir_all(&dir);
+    Manifest::new(&name, &to, &files).save()?;
+    let package = InstalledPackage {
+        prefix: to,
+        installed_at: chrono::Utc::now(),
+        ..metadata.package
+    };
+    if let Err(e) = modulefile::write(&package, &files) {
+        outputln!(red, "warning: failed to write the environment files. {}", e);
+    }
+    database::record_install(package.clone())?;
+    Ok(package)
+}
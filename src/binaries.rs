@@ -0,0 +1,160 @@
+// Tool packages.
+//
+// Some packages are programs rather than libraries, and the registry lists the
+// executables they provide in `binaries`. Once such a package is installed its
+// executables have to be in `<prefix>/bin`, copied there from the build tree if
+// the build system didn't install them, and that directory has to be on PATH to
+// be of any use. `--symlink-bin` links them into `~/.local/bin` as well, for
+// prefixes that aren't on PATH.
+
+use crate::installer::{InstallError, InstallOptions};
+use crate::outputln;
+use crate::paths;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// An executable called `name` anywhere in the checkout, skipping `.git`.
+fn find_built(dir: &Path, name: &str) -> Option<PathBuf> {
+    let entries = std::fs::read_dir(dir).ok()?;
+    let mut subdirs = vec![];
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            if entry.file_name() != ".git" {
+                subdirs.push(path);
+            }
+        } else if file_type.is_file()
+            && entry.file_name() == name
+            && entry
+                .metadata()
+                .is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+        {
+            return Some(path);
+        }
+    }
+    subdirs.iter().find_map(|d| find_built(d, name))
+}
+
+fn on_path(dir: &Path) -> bool {
+    std::env::var_os("PATH").is_some_and(|path| std::env::split_paths(&path).any(|p| p == dir))
+}
+
+fn warn_not_on_path(dir: &Path) {
+    outputln!(
+        purple,
+        "{} isn't on your PATH, add `export PATH=\"{}:$PATH\"` to your shell's profile.",
+        (dir.display()),
+        (dir.display())
+    );
+}
+
+/// Link `binary` into `~/.local/bin`, returning the link.
+fn symlink(binary: &Path, links: &Path) -> Result<Option<PathBuf>, InstallError> {
+    let name = binary.file_name().unwrap_or_default();
+    let link = links.join(name);
+    if link.exists() && !link.is_symlink() {
+        outputln!(
+            purple,
+            "{} already exists and isn't a link, leaving it alone.",
+            (link.display())
+        );
+        return Ok(None);
+    }
+    let bad_directory =
+        |e: std::io::Error| InstallError::BadDirectory(format!("{}: {}", link.display(), e));
+    std::fs::create_dir_all(links).map_err(bad_directory)?;
+    if link.is_symlink() {
+        std::fs::remove_file(&link).map_err(bad_directory)?;
+    }
+    std::os::unix::fs::symlink(binary, &link).map_err(bad_directory)?;
+    Ok(Some(link))
+}
+
+/// Make sure the package's executables are in `<prefix>/bin` and can be run,
+/// returning any files that were added.
+pub fn install(
+    source: &Path,
+    options: &InstallOptions,
+    installed: &[PathBuf],
+) -> Result<Vec<PathBuf>, InstallError> {
+    let bin = options.prefix.join("bin");
+    let mut binaries: Vec<PathBuf> = options.binaries.iter().map(|b| bin.join(b)).collect();
+    if binaries.is_empty() {
+        if !options.symlink_bin {
+            return Ok(vec![]);
+        }
+        // without a recipe, whatever the build put in bin is what is meant.
+        binaries = installed
+            .iter()
+            .filter(|f| f.parent() == Some(bin.as_path()))
+            .cloned()
+            .collect();
+    }
+
+    let mut added = vec![];
+    for binary in &binaries {
+        if binary.exists() {
+            continue;
+        }
+        let name = binary.file_name().unwrap_or_default().to_string_lossy();
+        match find_built(source, &name) {
+            Some(built) => {
+                outputln!(
+                    "`{}` wasn't installed by the build, copying it from {}.",
+                    name,
+                    (built.display())
+                );
+                let bad_directory = |e: std::io::Error| {
+                    InstallError::BadDirectory(format!(
+                        "{}: {} (you may need to `sudo`)",
+                        binary.display(),
+                        e
+                    ))
+                };
+                std::fs::create_dir_all(&bin).map_err(bad_directory)?;
+                // copy() keeps the permission bits.
+                std::fs::copy(&built, binary).map_err(bad_directory)?;
+                added.push(binary.clone());
+            }
+            None => outputln!(
+                red,
+                "the package should provide `{}`, but it wasn't installed or built.",
+                name
+            ),
+        }
+    }
+    let binaries: Vec<&PathBuf> = binaries.iter().filter(|b| b.exists()).collect();
+    if binaries.is_empty() {
+        return Ok(added);
+    }
+
+    if options.symlink_bin {
+        let links = paths::home_dir().join(".local").join("bin");
+        if links != bin {
+            for binary in &binaries {
+                if let Some(link) = symlink(binary, &links)? {
+                    added.push(link);
+                }
+            }
+            outputln!(
+                green,
+                "linked {} program(s) into {}.",
+                (binaries.len()),
+                (links.display())
+            );
+        }
+        if !on_path(&links) {
+            warn_not_on_path(&links);
+        }
+    } else if !on_path(&bin) {
+        warn_not_on_path(&bin);
+        outputln!(
+            purple,
+            "or pass --symlink-bin to link the package's programs into ~/.local/bin."
+        );
+    }
+    Ok(added)
+}
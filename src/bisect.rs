@@ -0,0 +1,158 @@
+// `cinstall bisect`: find the upstream commit that broke a consumer's build.
+//
+// git bisect is driven over the package's checkout. Every candidate is built
+// and installed into its own throwaway prefix, then the user's test command is
+// run (from the directory cinstall was started in) with that prefix in
+// CINSTALL_PREFIX, CMAKE_PREFIX_PATH and PKG_CONFIG_PATH. Like `git bisect run`,
+// exit code 0 marks the commit good, 125 skips it, and anything else is bad.
+// Candidates that fail to build are skipped.
+
+use crate::doctor;
+use crate::installer::{self, InstallError, InstallMethod, InstallOptions};
+use crate::outputln;
+use crate::registry::PackageRegistry;
+use crate::ui::{self, Tone};
+use crate::{run, source, staging, workspace};
+use std::path::Path;
+use std::process::Command;
+
+fn git(dir: &Path, args: &[&str]) -> Result<String, InstallError> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .map_err(|e| InstallError::CouldNotStartProcess(format!("git: {}", e)))?;
+    let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+    if !output.status.success() {
+        return Err(InstallError::UnknownFatal(format!(
+            "`git {}` failed. {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(stdout)
+}
+
+enum Verdict {
+    Good,
+    Bad,
+    Skip,
+}
+
+/// Build and install the current commit into a fresh prefix and run `test` against it.
+fn try_commit(
+    name: &str,
+    checkout: &Path,
+    commit: &str,
+    test: &str,
+    run_id: &str,
+) -> Result<Verdict, InstallError> {
+    let prefix = workspace::root().join("bisect").join(run_id).join(commit);
+    let options = InstallOptions {
+        prefix: prefix.clone(),
+        ..Default::default()
+    };
+
+    let method = installer::resolve_install_method(checkout);
+    if let InstallMethod::Unknown(message) = &method {
+        outputln!(
+            purple,
+            "{} can't be built ({}), skipping it.",
+            commit,
+            message
+        );
+        return Ok(Verdict::Skip);
+    }
+    let staging = staging::staging_dir(run_id);
+    if let Err(e) = installer::build_and_install(name, checkout, &method, &options, &staging) {
+        outputln!(purple, "{} failed to build ({}), skipping it.", commit, e);
+        return Ok(Verdict::Skip);
+    }
+
+    let mut pkg_config_path: Vec<String> = doctor::pkg_config_dirs(&prefix)
+        .iter()
+        .map(|d| d.display().to_string())
+        .collect();
+    pkg_config_path.extend(std::env::var("PKG_CONFIG_PATH").ok());
+
+    outputln!("running `{}` against {}", test, commit);
+    let status = Command::new("sh")
+        .arg("-c")
+        .arg(test)
+        .env("CINSTALL_PREFIX", &prefix)
+        .env("CMAKE_PREFIX_PATH", &prefix)
+        .env("PKG_CONFIG_PATH", pkg_config_path.join(":"))
+        .status()
+        .map_err(|e| InstallError::CouldNotStartProcess(format!("sh: {}", e)))?;
+
+    let _ = std::fs::remove_dir_all(&prefix);
+    Ok(match status.code() {
+        Some(0) => Verdict::Good,
+        Some(125) => Verdict::Skip,
+        _ => Verdict::Bad,
+    })
+}
+
+fn bisect_loop(name: &str, checkout: &Path, test: &str, run_id: &str) -> Result<(), InstallError> {
+    loop {
+        let commit = git(checkout, &["rev-parse", "--short", "HEAD"])?
+            .trim()
+            .to_string();
+        // the previous candidate's build must not leak into this one.
+        git(checkout, &["clean", "-ffdxq"])?;
+
+        let verdict = try_commit(name, checkout, &commit, test, run_id)?;
+        let (word, tone) = match verdict {
+            Verdict::Good => ("good", Tone::Success),
+            Verdict::Bad => ("bad", Tone::Error),
+            Verdict::Skip => ("skip", Tone::Warning),
+        };
+        outputln!("{} is {}", commit, (ui::paint(word, tone)));
+
+        let output = git(checkout, &["bisect", word])?;
+        if output.contains("is the first bad commit") {
+            let first_line = output.lines().next().unwrap_or_default();
+            let culprit = first_line.split_whitespace().next().unwrap_or_default();
+            outputln!(green, "found the commit that broke it:");
+            eprintln!("{}", git(checkout, &["show", "--stat", culprit])?);
+            return Ok(());
+        }
+        if output.contains("only 'skip'ped commits left") {
+            outputln!(
+                purple,
+                "the first bad commit could not be narrowed down, it is one of these:"
+            );
+            eprintln!("{}", output);
+            return Ok(());
+        }
+    }
+}
+
+pub fn bisect(
+    registry: &PackageRegistry,
+    target: &str,
+    good: &str,
+    bad: &str,
+    test: &str,
+) -> Result<(), InstallError> {
+    let source = source::resolve(registry, target, None, &[], false)?;
+    let run_id = run::start();
+    outputln!(
+        "bisecting `{}` between {} (good) and {} (bad)",
+        (source.name),
+        good,
+        bad
+    );
+
+    // bisecting needs the history between the good and bad commits.
+    let (checkout, _) = workspace::checkout(&source.url, None, true)?;
+    // a bisect left over from an earlier run that was interrupted.
+    let _ = git(&checkout, &["bisect", "reset"]);
+    git(&checkout, &["bisect", "start", bad, good])?;
+
+    let result = bisect_loop(&source.name, &checkout, test, &run_id);
+    let _ = git(&checkout, &["bisect", "reset"]);
+    let _ = std::fs::remove_dir_all(workspace::root().join("bisect").join(&run_id));
+    run::finish();
+    result
+}
@@ -0,0 +1,278 @@
+// Standalone bundles of tools.
+//
+// `install --bundle appimage` (or `tarball`) builds the project as usual but
+// doesn't install it. Instead everything it would have installed goes into a
+// directory along with the libraries its programs need that don't come with
+// the system, and an `AppRun` script that starts the main program with those
+// libraries. That is made into `<name>-<arch>.AppImage` with appimagetool, or
+// packed as `<name>-<os>-<arch>.tar.gz`, in the current directory. It is a way
+// of trying a tool out without touching the prefix.
+//
+// Libraries in the system's own directories (`/lib`, `/usr/lib`, ...) are
+// left out, anything else a program links against (from /usr/local, a home
+// directory, or built along with it) is bundled.
+
+use crate::installer::{InstallError, InstallOptions, Step};
+use crate::{doctor, outputln, staging, workspace};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// An AppImage, made with appimagetool. Linux only.
+    #[value(name = "appimage")]
+    AppImage,
+    /// A .tar.gz to extract anywhere and run `AppRun` from.
+    Tarball,
+}
+
+/// The directories the system keeps its own libraries in.
+const SYSTEM_LIBRARY_DIRS: &[&str] = &[
+    "/lib/",
+    "/lib32/",
+    "/lib64/",
+    "/usr/lib/",
+    "/usr/lib32/",
+    "/usr/lib64/",
+];
+
+fn is_system_library(path: &Path) -> bool {
+    let path = path.display().to_string();
+    SYSTEM_LIBRARY_DIRS.iter().any(|dir| path.starts_with(dir))
+}
+
+fn io_error(path: &Path, e: std::io::Error) -> InstallError {
+    InstallError::UnknownFatal(format!("{}: {}", path.display(), e))
+}
+
+/// The regular files in `dir` that can be run.
+fn programs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut programs: Vec<PathBuf> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            std::fs::metadata(p).is_ok_and(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        })
+        .collect();
+    programs.sort();
+    programs
+}
+
+/// The shared libraries in `dir`.
+fn libraries(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| p.is_file() && p.to_string_lossy().contains(".so"))
+        .collect()
+}
+
+/// What `ldd` says `file` links against, as `(name, path)`, with no path for
+/// the libraries it can't find.
+fn linked_libraries(file: &Path, lib_dir: &Path) -> Vec<(String, Option<PathBuf>)> {
+    let output = std::process::Command::new("ldd")
+        .arg(file)
+        .env("LD_LIBRARY_PATH", lib_dir)
+        .output();
+    let Ok(output) = output else {
+        return vec![];
+    };
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (name, rest) = line.trim().split_once(" => ")?;
+            let path = rest.split(" (").next().unwrap_or_default().trim();
+            let path = (path != "not found" && !path.is_empty()).then(|| PathBuf::from(path));
+            Some((name.to_string(), path))
+        })
+        .collect()
+}
+
+/// Copy the libraries the programs and libraries in `usr` need that aren't
+/// the system's into `usr/lib`.
+fn bundle_libraries(usr: &Path) -> Result<(), InstallError> {
+    let lib_dir = usr.join("lib");
+    if doctor::find_program("ldd").is_none() {
+        outputln!(
+            purple,
+            "ldd isn't available, only the libraries the project installs itself are bundled."
+        );
+        return Ok(());
+    }
+    let mut files = programs(&usr.join("bin"));
+    files.extend(libraries(&lib_dir));
+    let mut missing = vec![];
+    for file in files {
+        for (name, path) in linked_libraries(&file, &lib_dir) {
+            let Some(path) = path else {
+                if !missing.contains(&name) {
+                    missing.push(name);
+                }
+                continue;
+            };
+            let target = lib_dir.join(&name);
+            if is_system_library(&path) || path.starts_with(usr) || target.exists() {
+                continue;
+            }
+            std::fs::create_dir_all(&lib_dir).map_err(|e| io_error(&lib_dir, e))?;
+            // copy what the link points at, the bundle has no use for the link.
+            std::fs::copy(&path, &target).map_err(|e| io_error(&path, e))?;
+            outputln!("bundling {}", (path.display()));
+        }
+    }
+    for name in missing {
+        outputln!(
+            purple,
+            "`{}` isn't installed here, so it isn't bundled. the bundle needs it where it runs.",
+            name
+        );
+    }
+    Ok(())
+}
+
+/// The program `AppRun` starts: the first one the registry lists, or the one
+/// named like the package, or the first there is.
+fn main_program(name: &str, bin: &Path, options: &InstallOptions) -> Result<String, InstallError> {
+    let programs: Vec<String> = programs(bin)
+        .iter()
+        .filter_map(|p| p.file_name().map(|n| n.to_string_lossy().to_string()))
+        .collect();
+    options
+        .binaries
+        .iter()
+        .find(|b| programs.contains(b))
+        .or_else(|| programs.iter().find(|p| *p == name))
+        .or_else(|| programs.first())
+        .cloned()
+        .ok_or_else(|| {
+            InstallError::UnknownFatal(format!(
+                "`{}` doesn't install any programs, only tools can be bundled.",
+                name
+            ))
+        })
+}
+
+fn write(path: &Path, contents: &str, executable: bool) -> Result<(), InstallError> {
+    std::fs::write(path, contents).map_err(|_| InstallError::FailedToWriteToFile)?;
+    if executable {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+            .map_err(|e| io_error(path, e))?;
+    }
+    Ok(())
+}
+
+/// Lay out an AppDir in `dir` from what was staged in `staging`.
+fn app_dir(
+    name: &str,
+    dir: &Path,
+    staging: &Path,
+    options: &InstallOptions,
+) -> Result<String, InstallError> {
+    let prefix = options.prefix.strip_prefix("/").unwrap_or(&options.prefix);
+    let staged = staging.join(prefix);
+    if !staged.is_dir() {
+        return Err(InstallError::UnknownFatal(format!(
+            "nothing was installed into {}, so there is nothing to bundle.",
+            options.prefix.display()
+        )));
+    }
+    let usr = dir.join("usr");
+    staging::copy_tree(&staged, &usr)?;
+    let program = main_program(name, &usr.join("bin"), options)?;
+    bundle_libraries(&usr)?;
+
+    write(
+        &dir.join("AppRun"),
+        &format!(
+            r#"#!/bin/sh
+HERE="$(dirname "$(readlink -f "$0")")"
+export PATH="$HERE/usr/bin:$PATH"
+export LD_LIBRARY_PATH="$HERE/usr/lib${{LD_LIBRARY_PATH:+:$LD_LIBRARY_PATH}}"
+exec "$HERE/usr/bin/{}" "$@"
+"#,
+            program
+        ),
+        true,
+    )?;
+    write(
+        &dir.join(format!("{}.desktop", name)),
+        &format!(
+            "[Desktop Entry]\nType=Application\nName={}\nExec={}\nIcon={}\nCategories=Development;\nTerminal=true\n",
+            name, program, name
+        ),
+        false,
+    )?;
+    write(
+        &dir.join(format!("{}.svg", name)),
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="64" height="64"><rect width="64" height="64" fill="gray"/></svg>"#,
+        false,
+    )?;
+    Ok(program)
+}
+
+/// Bundle what was built for `name` and staged in `staging` into the current
+/// directory, returning the file that was written.
+pub fn create(
+    name: &str,
+    staging: &Path,
+    options: &InstallOptions,
+    format: Format,
+) -> Result<PathBuf, InstallError> {
+    if format == Format::AppImage && std::env::consts::OS != "linux" {
+        return Err(InstallError::UnknownFatal(
+            "AppImages only run on Linux, use `--bundle tarball` here.".into(),
+        ));
+    }
+    let cwd = std::env::current_dir()
+        .map_err(|e| InstallError::BadDirectory(format!("the current directory: {}", e)))?;
+    let arch = std::env::consts::ARCH;
+    let work = workspace::root().join("bundles").join(name);
+    if work.exists() {
+        std::fs::remove_dir_all(&work).map_err(|e| io_error(&work, e))?;
+    }
+    let dir_name = format!("{}-{}-{}", name, std::env::consts::OS, arch);
+    let dir = work.join(&dir_name);
+    std::fs::create_dir_all(&dir).map_err(|e| io_error(&dir, e))?;
+    let program = app_dir(name, &dir, staging, options)?;
+
+    let mut format = format;
+    if format == Format::AppImage && doctor::find_program("appimagetool").is_none() {
+        outputln!(
+            purple,
+            "appimagetool isn't installed (https://github.com/AppImage/appimagetool), making a tarball instead."
+        );
+        format = Format::Tarball;
+    }
+    let (output, step) = match format {
+        Format::AppImage => {
+            let output = cwd.join(format!("{}-{}.AppImage", name, arch));
+            let step = Step::new("appimagetool", &work)
+                .arg(dir.display().to_string())
+                .arg(output.display().to_string())
+                .env("ARCH", arch);
+            (output, step)
+        }
+        Format::Tarball => {
+            let output = cwd.join(format!("{}.tar.gz", dir_name));
+            let step = Step::new("tar", &work)
+                .arg("-czf")
+                .arg(output.display().to_string())
+                .arg(&dir_name);
+            (output, step)
+        }
+    };
+    outputln!(green, "running {}", step);
+    if !step.run()?.success() {
+        return Err(InstallError::StepFailed(step.to_string()));
+    }
+    let _ = std::fs::remove_dir_all(&work);
+    let _ = std::fs::remove_dir_all(staging);
+    outputln!("the bundle starts `{}`.", program);
+    Ok(output)
+}
@@ -0,0 +1,178 @@
+// Cancelling one install while it runs.
+//
+// An install is cancelled through the `CancellationToken` its `Installer` is
+// given, which it polls before starting each step and while one is going. A
+// program driving installs (a TUI, or one embedding cinstall) keeps a clone of
+// the token and calls `cancel` on it from another thread.
+//
+// `cinstall cancel <run>` cancels the install run with that ID (the one its
+// messages start with) and no other, e.g. one package of `upgrade --all`
+// while the rest go on. It leaves a `<run>.cancel` file next to the run's log,
+// which cancels the run's token once the run sees it. Ctrl-C while a step is
+// going cancels the run the same way; pressed again it quits at once.
+//
+// Each step runs in a process group of its own, so that everything it started
+// (`make` and the compilers it runs) is stopped with it: the group is asked to
+// stop with SIGTERM and killed if it hasn't after a few seconds. Git talking to
+// a remote with a terminal to ask for credentials on stays in cinstall's group,
+// which the terminal belongs to, and only it is stopped. A run that is
+// cancelled before its files are copied into place installs nothing and
+// removes its staging directory; once they are being copied it is seen
+// through.
+
+use crate::installer::InstallError;
+use crate::run;
+use std::path::PathBuf;
+use std::process::{Child, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long a step has to stop after being asked to before it is killed.
+const GRACE: Duration = Duration::from_secs(5);
+
+/// Cancels the install it is given to, from anywhere a clone of it is held.
+#[derive(Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Cancel the install, which stops at the next step or stops the one
+    /// that is going.
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The token of the install in progress.
+static WATCHED: Mutex<Option<CancellationToken>> = Mutex::new(None);
+
+/// Set by Ctrl-C while a step is going.
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Cancel the install in progress when `token` is.
+pub fn watch(token: &CancellationToken) {
+    INTERRUPTED.store(false, Ordering::SeqCst);
+    *WATCHED.lock().unwrap() = Some(token.clone());
+}
+
+fn marker_path(id: &str) -> PathBuf {
+    run::logs_dir().join(format!("{}.cancel", id))
+}
+
+/// Whether the install in progress has been cancelled, in any of the ways it
+/// can be.
+pub fn is_cancelled() -> bool {
+    let Some(token) = WATCHED.lock().unwrap().clone() else {
+        return false;
+    };
+    let requested = run::id().is_some_and(|id| marker_path(&id).exists());
+    if requested || INTERRUPTED.load(Ordering::SeqCst) {
+        token.cancel();
+    }
+    token.is_cancelled()
+}
+
+/// Fail with `Cancelled` if the install in progress has been cancelled.
+pub fn check() -> Result<(), InstallError> {
+    match is_cancelled() {
+        true => Err(InstallError::Cancelled),
+        false => Ok(()),
+    }
+}
+
+/// Forget that run `id` was cancelled, once it is over.
+pub fn clear(id: &str) {
+    let _ = std::fs::remove_file(marker_path(id));
+    *WATCHED.lock().unwrap() = None;
+}
+
+extern "C" fn interrupted(_: libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+    // a second Ctrl-C quits as it always would.
+    unsafe {
+        libc::signal(libc::SIGINT, libc::SIG_DFL);
+    }
+}
+
+/// Ctrl-C cancels the install instead of quitting while this is held. Build
+/// steps are in process groups of their own, so the terminal doesn't stop
+/// them; git asking for credentials is stopped by it along with cinstall.
+pub struct Interruptible(());
+
+impl Interruptible {
+    pub fn start() -> Self {
+        let handler = interrupted as extern "C" fn(libc::c_int);
+        unsafe {
+            libc::signal(libc::SIGINT, handler as libc::sighandler_t);
+        }
+        Self(())
+    }
+}
+
+impl Drop for Interruptible {
+    fn drop(&mut self) {
+        unsafe {
+            libc::signal(libc::SIGINT, libc::SIG_DFL);
+        }
+    }
+}
+
+/// Send `signal` to `child`, or to the process group it leads when `group`.
+fn signal(child: &Child, signal: libc::c_int, group: bool) {
+    let pid = child.id() as libc::pid_t;
+    unsafe {
+        libc::kill(if group { -pid } else { pid }, signal);
+    }
+}
+
+/// Stop `child`, and everything it started when it leads a process `group`,
+/// asking them to first.
+pub fn stop(mut child: Child, group: bool) -> std::io::Result<ExitStatus> {
+    signal(&child, libc::SIGTERM, group);
+    let asked = Instant::now();
+    while asked.elapsed() < GRACE {
+        if let Some(status) = child.try_wait()? {
+            // what it started may not have stopped with it.
+            if group {
+                signal(&child, libc::SIGKILL, group);
+            }
+            return Ok(status);
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    signal(&child, libc::SIGKILL, group);
+    child.wait()
+}
+
+/// Cancel the run `id`, which has to be going.
+pub fn request(id: &str) -> Result<(), InstallError> {
+    if !run::is_id(id) {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` isn't a run ID, they are 8 letters and digits like the ones messages start with.",
+            id
+        )));
+    }
+    let log = std::fs::read_to_string(run::log_path(id))
+        .map_err(|_| InstallError::UnknownFatal(format!("there is no run `{}`.", id)))?;
+    let finished = log.lines().any(|line| {
+        serde_json::from_str::<serde_json::Value>(line)
+            .is_ok_and(|event| event["event"] == "finished")
+    });
+    if finished {
+        return Err(InstallError::UnknownFatal(format!(
+            "run `{}` has already finished.",
+            id
+        )));
+    }
+    std::fs::write(marker_path(id), "").map_err(|_| InstallError::FailedToWriteToFile)
+}
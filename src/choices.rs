@@ -0,0 +1,138 @@
+// Remembering what was chosen for a repository.
+//
+// Some projects need a hand: the make target that installs them when `make
+// install` doesn't, the headers to copy when there is no build system, or
+// CMake options added when asked whether to build. What you choose is
+// remembered for the repository in `~/.local/share/cinstall/choices.json`
+// once the install succeeds, and offered the next time it is installed:
+// without a terminal or with `--yes` it is used as it is. CMake options are
+// always used again, and shown in the plan before building.
+//
+// `cinstall install --forget <url>` forgets what was chosen for it.
+
+use crate::{outputln, paths};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use url::Url;
+
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+pub struct Choices {
+    /// The make target that installed it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub make_target: Option<String>,
+    /// The headers that were copied, relative to the project.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    /// The CMake options that were added.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cmake_args: Vec<String>,
+}
+
+/// What was chosen for the repository being installed before, and what has
+/// been chosen for it this time.
+static CURRENT: Mutex<Option<(Choices, Choices)>> = Mutex::new(None);
+
+fn choices_path() -> PathBuf {
+    paths::data_dir().join("choices.json")
+}
+
+fn load() -> BTreeMap<String, Choices> {
+    std::fs::read_to_string(choices_path())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save(choices: &BTreeMap<String, Choices>) {
+    let path = choices_path();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    let saved = serde_json::to_string_pretty(choices)
+        .map_err(|e| e.to_string())
+        .and_then(|json| std::fs::write(&path, json).map_err(|e| e.to_string()));
+    if let Err(e) = saved {
+        outputln!(
+            red,
+            "warning: failed to remember the choices in {}. {}",
+            (path.display()),
+            e
+        );
+    }
+}
+
+/// Start installing `url`, with what was chosen for it before.
+pub fn start(url: &Url) {
+    let before = load().remove(url.as_str()).unwrap_or_default();
+    *CURRENT.lock().unwrap() = Some((before, Choices::default()));
+}
+
+/// What was chosen for the repository being installed before.
+pub fn remembered() -> Choices {
+    CURRENT
+        .lock()
+        .unwrap()
+        .as_ref()
+        .map(|(before, _)| before.clone())
+        .unwrap_or_default()
+}
+
+fn choose(change: impl FnOnce(&mut Choices)) {
+    if let Some((_, now)) = CURRENT.lock().unwrap().as_mut() {
+        change(now);
+    }
+}
+
+pub fn chose_make_target(target: &str) {
+    choose(|c| c.make_target = Some(target.to_string()));
+}
+
+pub fn chose_headers(headers: Vec<String>) {
+    choose(|c| c.headers = headers);
+}
+
+pub fn chose_cmake_args(cmake_args: Vec<String>) {
+    choose(|c| c.cmake_args = cmake_args);
+}
+
+/// `url` was installed, remember what was chosen for it on top of what was
+/// chosen before.
+pub fn finish(url: &Url) {
+    let Some((before, now)) = CURRENT.lock().unwrap().take() else {
+        return;
+    };
+    let mut remembered = before.clone();
+    if now.make_target.is_some() {
+        remembered.make_target = now.make_target;
+    }
+    if !now.headers.is_empty() {
+        remembered.headers = now.headers;
+    }
+    if !now.cmake_args.is_empty() {
+        remembered.cmake_args = now.cmake_args;
+    }
+    // what was used again as it was chosen is remembered already.
+    if remembered == before {
+        return;
+    }
+    let mut all = load();
+    all.insert(url.to_string(), remembered);
+    save(&all);
+    outputln!(
+        "remembered what you chose for {}, `cinstall install --forget {}` forgets it.",
+        url,
+        url
+    );
+}
+
+/// Forget what was chosen for `url`, returning whether anything was.
+pub fn forget(url: &Url) -> bool {
+    let mut all = load();
+    let forgotten = all.remove(url.as_str()).is_some();
+    if forgotten {
+        save(&all);
+    }
+    forgotten
+}
@@ -0,0 +1,521 @@
+// The command line interface for cinstall.
+//
+// Every operation is its own subcommand so that flags can be added to
+// each of them without clashing with the package name/url positional.
+
+use crate::bundle::Format;
+use crate::completion::Shell;
+use crate::formula;
+use crate::history::Operation;
+use crate::project::Template;
+use crate::registry::Language;
+use clap::{Args, CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(
+    name = "cinstall",
+    version,
+    about = "Install any C/C++ project locally using one command.",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// `cinstall <target> [flags]` is shorthand for `cinstall install <target> [flags]`,
+    /// so `cinstall fmtlib/fmt` installs https://github.com/fmtlib/fmt.
+    #[command(flatten)]
+    pub install: Option<InstallArgs>,
+
+    /// Never show output through a pager.
+    #[arg(long, global = true)]
+    pub no_pager: bool,
+
+    /// Screen reader friendly output: no colour, and every message says what
+    /// kind it is in words.
+    #[arg(long, global = true)]
+    pub a11y: bool,
+
+    /// For other build systems calling cinstall: no colour or prompts, one
+    /// line per message starting with `cinstall: <kind>: `, and only the JSON
+    /// report of each install on stdout. On by default under CI and make.
+    #[arg(long, global = true)]
+    pub porcelain: bool,
+
+    /// Show every line of build output, instead of counting the ones that only
+    /// say what is being compiled.
+    #[arg(long, short, global = true)]
+    pub verbose: bool,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Install a package from the registry or a project from a URL.
+    Install(InstallArgs),
+    /// Output all known packages.
+    List {
+        /// Only list packages whose name contains this string.
+        filter: Option<String>,
+        /// List the packages that are installed instead of the registry.
+        #[arg(long)]
+        installed: bool,
+        /// Show full descriptions and URLs instead of fitting the terminal.
+        #[arg(long, short = 'l')]
+        long: bool,
+    },
+    /// Search the package registry by name, tags and description, best
+    /// matches first. Abbreviations and small typos match too.
+    Search {
+        /// The words to look for, all of which have to match.
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Show full descriptions and URLs instead of fitting the terminal.
+        #[arg(long, short = 'l')]
+        long: bool,
+    },
+    /// Show everything known about a package.
+    Info {
+        /// The name of the package.
+        package: String,
+        /// Show the package as it is installed on this machine, with its
+        /// platform overrides applied.
+        #[arg(long)]
+        resolved: bool,
+        /// Print only the package's notes, what it says to do after installing it.
+        #[arg(long)]
+        notes: bool,
+        /// Also ask GitHub for the repository's stars, last commit, latest
+        /// release and license (cached for a day, uses `GITHUB_TOKEN`).
+        #[arg(long)]
+        github: bool,
+    },
+    /// Remove a package that was previously installed.
+    Uninstall {
+        /// The name of the package.
+        package: String,
+    },
+    /// Build an installed package again if upstream has something newer: new
+    /// commits on the branch it was installed from, or a newer tag.
+    Upgrade {
+        /// The name of the package.
+        package: Option<String>,
+        /// Upgrade every installed package, with a summary at the end.
+        #[arg(long, conflicts_with = "package", required_unless_present = "package")]
+        all: bool,
+        /// Build it again even if nothing is newer.
+        #[arg(long)]
+        force: bool,
+        /// Only say what has something new, without building anything.
+        #[arg(long)]
+        dry_run: bool,
+        /// With --all, go on with the other packages when one fails (the
+        /// default).
+        #[arg(long, conflicts_with_all = ["fail_fast", "package"])]
+        keep_going: bool,
+        /// With --all, stop at the first package that fails.
+        #[arg(long, conflicts_with = "package")]
+        fail_fast: bool,
+        /// Build without showing what was found out about the project and
+        /// asking first.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// List the installed packages upstream has something newer for, without
+    /// changing anything.
+    Outdated,
+    /// Go back to what was installed of a package before it was last upgraded,
+    /// putting its files back when `rollback_files` is set, otherwise building
+    /// that commit again.
+    Rollback {
+        /// The name of the package.
+        package: String,
+        /// Roll back without asking first.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Remove an installed package from CMake's user package registry
+    /// (`~/.cmake/packages`), where `install --cmake-register` added it.
+    Deregister {
+        /// The name of the package.
+        package: String,
+    },
+    /// Move an installed package to another prefix without rebuilding it.
+    Relocate {
+        /// The name of the package.
+        package: String,
+        /// The prefix to move it to, e.g. ~/.local.
+        #[arg(long)]
+        to: PathBuf,
+    },
+    /// Archive the files of an installed package, to install it on machines
+    /// without a toolchain with `import-artifact`.
+    ExportArtifact {
+        /// The name of the package.
+        package: String,
+        /// The archive to write, compressed according to its extension
+        /// (`.tar.zst`, `.tar.gz`, `.tar.xz`). `<package>.tar.zst` by default.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Write a Homebrew formula or an Arch PKGBUILD that builds an installed
+    /// package the way cinstall built it, as a start for packaging it.
+    GenerateFormula {
+        /// The name of the package.
+        package: String,
+        #[arg(long, value_enum, default_value = "brew")]
+        format: formula::Format,
+        /// Write it to this file instead of stdout.
+        #[arg(long, short)]
+        output: Option<PathBuf>,
+    },
+    /// Install a package from an archive made by `export-artifact`.
+    ImportArtifact {
+        /// The archive.
+        file: PathBuf,
+        /// Install into this directory instead of the prefix it was exported from.
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+    },
+    /// Show every install, upgrade, uninstall and rollback that has been performed.
+    History {
+        /// Only show transactions for this package.
+        #[arg(long)]
+        package: Option<String>,
+        /// Only show transactions of this kind.
+        #[arg(long, value_enum)]
+        operation: Option<Operation>,
+        /// Only show transactions that failed.
+        #[arg(long)]
+        failed: bool,
+        /// Only show the most recent N transactions.
+        #[arg(long, short = 'n')]
+        limit: Option<usize>,
+    },
+    /// Remove the builds in the store (the `store_dir` config key) that no
+    /// installed package uses any more.
+    Gc,
+    /// Revert the most recent install or uninstall.
+    Undo {
+        /// Undo it without asking first, which is needed without a terminal.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Stop an install that is running, and only that one, leaving nothing of
+    /// it installed.
+    Cancel {
+        /// The ID of its run, which its messages start with.
+        run: String,
+    },
+    /// Read or change the settings in `~/.config/cinstall/config.toml`.
+    Config {
+        #[command(subcommand)]
+        action: ConfigAction,
+    },
+    /// Create a new C/C++ project that is set up to use cinstall packages.
+    New {
+        /// The name of the project, also used for its directory.
+        name: String,
+        /// What kind of project to create.
+        #[arg(long, value_enum, default_value = "cmake-app")]
+        template: Template,
+    },
+    /// Build a package from a checkout of your own instead of upstream, until
+    /// `--remove`. Without a package, lists the packages built from checkouts.
+    Develop {
+        /// The name of the package.
+        package: Option<String>,
+        /// The checkout to build it from. Without it, the checkout it is
+        /// built from already is built again.
+        #[arg(long, conflicts_with = "remove")]
+        path: Option<PathBuf>,
+        /// Build it from upstream again from the next install on.
+        #[arg(long, requires = "package")]
+        remove: bool,
+        /// Build without showing what was found out about the project and
+        /// asking first.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Show which dependencies of the project in this directory are installed,
+    /// missing or outdated, and which installed packages it doesn't need.
+    Status {
+        /// Record what is installed now in `cinstall.lock`.
+        #[arg(long)]
+        write_lock: bool,
+    },
+    /// Add find_package and target_link_libraries calls for an installed package to a CMakeLists.
+    Integrate {
+        /// The name of an installed package.
+        package: String,
+        /// The CMakeLists to change.
+        #[arg(default_value = "CMakeLists.txt")]
+        cmake_lists: PathBuf,
+        /// Apply the changes without asking.
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Find the upstream commit of a package that broke your build, using git bisect.
+    Bisect {
+        /// A package name, URL or GitHub `owner/repo`.
+        package: String,
+        /// A ref where the test command passes.
+        #[arg(long)]
+        good: String,
+        /// A ref where the test command fails.
+        #[arg(long)]
+        bad: String,
+        /// A shell command that exits 0 when things work, e.g. `cmake --build build`. The
+        /// candidate's prefix is in $CINSTALL_PREFIX.
+        #[arg(long)]
+        test: String,
+    },
+    /// Work with the package registry.
+    Registry {
+        #[command(subcommand)]
+        action: RegistryAction,
+    },
+    /// Show how much space installed packages take up.
+    Size {
+        /// Break this package down by directory and kind of file.
+        package: Option<String>,
+        /// List every installed package, biggest first.
+        #[arg(long, conflicts_with = "package", required_unless_present = "package")]
+        all: bool,
+        /// Show full paths instead of fitting the terminal.
+        #[arg(long, short = 'l')]
+        long: bool,
+    },
+    /// Check that the files of installed packages are still there and unchanged.
+    Verify {
+        /// The package to check, every installed package if not given.
+        package: Option<String>,
+        /// Compare the size and SHA-256 of every file with the ones recorded
+        /// when it was installed, finding modified and truncated files.
+        #[arg(long)]
+        hashes: bool,
+    },
+    /// Check that the tools cinstall needs are installed and that installed packages can be found.
+    Doctor {
+        /// Check this prefix instead of the configured one.
+        #[arg(long)]
+        prefix: Option<PathBuf>,
+    },
+    /// Print a shell script that enables tab completion.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+    /// Used by the completion scripts; prints candidates for the last word.
+    #[command(name = "__complete", hide = true)]
+    Complete {
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        words: Vec<String>,
+    },
+}
+
+#[derive(Args, Default)]
+pub struct InstallArgs {
+    /// A package name learnt from `list`, a URL, a GitHub `owner/repo` or a
+    /// local directory (`./fmt`), which is built in place. Add
+    /// `@<ref>` to install a tag, branch or commit, or `#pull/<number>` to
+    /// install a pull request.
+    pub target: String,
+    /// Install into this directory instead of /usr/local (or the `prefix` config key).
+    #[arg(long)]
+    pub prefix: Option<PathBuf>,
+    /// Install this tag, branch or commit. `head` means the default branch, even for
+    /// registry packages that pin a known-good ref.
+    #[arg(long = "ref")]
+    pub git_ref: Option<String>,
+    /// Install from a GitHub fork instead, given as `owner/repo` or `owner/repo@branch`.
+    #[arg(long)]
+    pub fork: Option<String>,
+    /// Clone the project's whole history, for projects whose build needs tags or
+    /// older commits (e.g. to work out their version). Only the commit being
+    /// built is downloaded otherwise.
+    #[arg(long)]
+    pub full_history: bool,
+    /// Write a JSON report of the install to this file: how it went, and a
+    /// summary of the warnings and errors the build printed.
+    #[arg(long)]
+    pub report: Option<PathBuf>,
+    /// Before building, show open GitHub issues labelled `build` or `compile`
+    /// that mention this platform.
+    #[arg(long)]
+    pub check_upstream: bool,
+    /// Accept URLs from this host as well as GitHub and GitLab, e.g. a
+    /// self-hosted GitLab. Can be given more than once.
+    #[arg(long, value_name = "HOST")]
+    pub host_ok: Vec<String>,
+    /// Accept any URL git can clone, from any host, including SSH remotes on
+    /// unknown hosts (`git@git.example.com:owner/repo.git`).
+    #[arg(long)]
+    pub allow_any_host: bool,
+    /// Install even if the registry says the package doesn't build on this
+    /// system (an old compiler, glibc or kernel, or the wrong platform).
+    #[arg(long)]
+    pub ignore_requirements: bool,
+    /// Link the programs the package installs into ~/.local/bin, for prefixes
+    /// that aren't on PATH.
+    #[arg(long)]
+    pub symlink_bin: bool,
+    /// Build for both x86_64 and i386 on Linux, with the libraries of each in
+    /// lib/<triple>, or a universal arm64/x86_64 build on macOS. CMake and
+    /// Meson projects only.
+    #[arg(long)]
+    pub multiarch: bool,
+    /// If the project's latest GitHub release has a prebuilt archive for this
+    /// machine, install it without asking.
+    #[arg(long, conflicts_with = "from_source")]
+    pub prebuilt: bool,
+    /// Always build from source, without looking for prebuilt releases.
+    #[arg(long)]
+    pub from_source: bool,
+    /// The SHA-256 the archive being installed has to have, it isn't built if
+    /// it doesn't.
+    #[arg(long, value_name = "HEX")]
+    pub sha256: Option<String>,
+    /// Check upstream's OpenPGP signature of the tag, commit or archive before
+    /// building, against the keys the registry lists for the package.
+    #[arg(long)]
+    pub verify_signature: bool,
+    /// The fingerprint of a key the signature may be made with, for projects
+    /// the registry lists no keys for. Implies --verify-signature.
+    #[arg(long = "signing-key", value_name = "FINGERPRINT")]
+    pub signing_keys: Vec<String>,
+    /// Build without showing what was found out about the project and asking
+    /// first.
+    #[arg(long, short = 'y')]
+    pub yes: bool,
+    /// Clone over SSH (`git@github.com:owner/repo.git`) instead of HTTPS, with
+    /// your SSH keys and agent. For private repositories.
+    #[arg(long)]
+    pub ssh: bool,
+    /// Clone over HTTPS even if the `protocol` config key is set to ssh.
+    #[arg(long, conflicts_with = "ssh")]
+    pub https: bool,
+    /// Build a variant the registry offers for the package, like `minimal`,
+    /// adding its options to the package's. Can be given more than once;
+    /// `cinstall info <package>` lists them.
+    #[arg(long = "variant", value_name = "NAME")]
+    pub variants: Vec<String>,
+    /// Add the CMake config files the package installs to CMake's user
+    /// package registry (`~/.cmake/packages`), so `find_package` finds them
+    /// in any prefix (or set the `cmake_register` config key).
+    #[arg(long)]
+    pub cmake_register: bool,
+    /// Don't install the registry packages the package depends on first, for
+    /// when they are installed some other way.
+    #[arg(long)]
+    pub no_deps: bool,
+    /// Install the files the build puts outside the prefix (config in /etc,
+    /// systemd units, dynamic linker paths) without asking first.
+    #[arg(long)]
+    pub allow_system_paths: bool,
+    /// Install into a directory of its own, /opt/cinstall/<package>/<version>
+    /// (the `versions_dir` config key), and link its files into the prefix.
+    #[arg(long)]
+    pub versioned: bool,
+    /// Build the project's programs into a standalone AppImage or tarball in
+    /// the current directory, with the libraries they need, instead of
+    /// installing them.
+    #[arg(long, value_enum, value_name = "FORMAT")]
+    pub bundle: Option<Format>,
+    /// Print the commands that would be run and where files would go, without building or installing.
+    #[arg(long)]
+    pub dry_run: bool,
+    /// Build and install it even if the same commit is installed already.
+    #[arg(long)]
+    pub force: bool,
+    /// Forget the make target, headers and cmake options chosen for the
+    /// project when it was installed before, instead of installing it.
+    #[arg(long)]
+    pub forget: bool,
+}
+
+#[derive(Subcommand)]
+pub enum ConfigAction {
+    /// Print the value of a key.
+    Get { key: String },
+    /// Set a key to a value.
+    Set { key: String, value: String },
+    /// Remove a key, going back to the default.
+    Unset { key: String },
+    /// Print every key that is set.
+    List,
+    /// Print every key that can be set.
+    Keys,
+}
+
+#[derive(Subcommand)]
+pub enum RegistryAction {
+    /// Work out a registry entry for a project, check it, and print it or file it.
+    Propose(ProposeArgs),
+    /// Add a package of your own, which can then be installed by name.
+    Add {
+        name: String,
+        /// The package's URL or GitHub `owner/repo`.
+        url: String,
+        #[arg(long, default_value = "")]
+        description: String,
+        #[arg(long, value_enum, default_value = "c++")]
+        language: Language,
+    },
+    /// Remove a package added with `registry add`.
+    Remove { name: String },
+    /// Fetch the latest registry, so new packages can be installed without
+    /// updating cinstall.
+    Update {
+        /// Fetch it from here instead of the `registry_url` config key or
+        /// cinstall's repository.
+        #[arg(long)]
+        url: Option<String>,
+    },
+    /// Check a registry file (or the registries in use) for entries that
+    /// won't load, bad URLs, unknown fields and duplicate names.
+    Validate {
+        /// The JSON or TOML file to check.
+        file: Option<PathBuf>,
+        /// The templates the file's packages use, `pkg_templates.json` (or
+        /// `.toml`) next to it by default.
+        #[arg(long)]
+        templates: Option<PathBuf>,
+        /// Check that every package's URL answers, too.
+        #[arg(long)]
+        ping: bool,
+    },
+}
+
+#[derive(Args)]
+pub struct ProposeArgs {
+    /// The project's URL or GitHub `owner/repo`.
+    pub url: String,
+    /// The package name, the last part of the URL by default.
+    #[arg(long)]
+    pub name: Option<String>,
+    /// The description, taken from GitHub or the README by default.
+    #[arg(long)]
+    pub description: Option<String>,
+    /// Pin the package to this tag, branch or commit.
+    #[arg(long = "ref")]
+    pub git_ref: Option<String>,
+    /// Another URL the project can be cloned from, tried when the main one
+    /// can't be. Can be given more than once.
+    #[arg(long = "mirror", value_name = "URL")]
+    pub mirrors: Vec<String>,
+    /// Build and install the project into a throwaway prefix first.
+    #[arg(long)]
+    pub test_install: bool,
+    /// File the entry as an issue on cinstall's repository, with $GITHUB_TOKEN,
+    /// or print a link to a prefilled one.
+    #[arg(long)]
+    pub open: bool,
+}
+
+/// Print a usage error in the same format clap uses and exit.
+pub fn usage_error(message: impl std::fmt::Display) -> ! {
+    Cli::command()
+        .error(clap::error::ErrorKind::InvalidValue, message)
+        .exit()
+}
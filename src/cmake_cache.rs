@@ -0,0 +1,130 @@
+// What changed in the CMake cache since a package was last built.
+//
+// After a CMake project is built, the cache variables it was configured with
+// are kept in `~/.local/share/cinstall/cmake/<package>.cache`. When the package
+// is built again, the new cache is compared with that one right after the
+// configure step, and the variables that changed are shown: options that were
+// passed differently, a different compiler that was found, a dependency that
+// turned up or went away. With a terminal to ask on, and without `--yes`, the
+// build only goes on if you say so.
+//
+// Only the variables a user could set are compared, CMake's internal and
+// static entries are left out.
+
+use crate::installer::InstallError;
+use crate::table::Table;
+use crate::{output, outputln, paths};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+type Cache = BTreeMap<String, String>;
+
+/// Where the cache of the last build of `name` is kept.
+fn saved_path(name: &str) -> PathBuf {
+    paths::data_dir()
+        .join("cmake")
+        .join(format!("{}.cache", name.replace('/', "_")))
+}
+
+/// The `CMakeCache.txt` of the project in `dir`, in `build/` or in the
+/// project itself.
+pub fn find(dir: &Path) -> Option<PathBuf> {
+    [dir.join("build"), dir.to_path_buf()]
+        .into_iter()
+        .map(|d| d.join("CMakeCache.txt"))
+        .find(|p| p.is_file())
+}
+
+/// The `NAME:TYPE=VALUE` entries of a cache, leaving out internal ones.
+fn parse(contents: &str) -> Cache {
+    contents
+        .lines()
+        .filter(|line| !line.starts_with('#') && !line.starts_with("//"))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let (name, kind) = key.split_once(':')?;
+            (kind != "INTERNAL" && kind != "STATIC").then(|| (name.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+fn read(path: &Path) -> Option<Cache> {
+    std::fs::read_to_string(path).ok().map(|c| parse(&c))
+}
+
+/// The variables that differ between `old` and `new`, as `(name, old, new)`.
+fn changes(old: &Cache, new: &Cache) -> Vec<(String, Option<String>, Option<String>)> {
+    let mut names: Vec<&String> = old.keys().chain(new.keys()).collect();
+    names.sort();
+    names.dedup();
+    names
+        .into_iter()
+        .filter(|name| old.get(*name) != new.get(*name))
+        .map(|name| (name.clone(), old.get(name).cloned(), new.get(name).cloned()))
+        .collect()
+}
+
+/// Show how the cache of the project in `dir` differs from the last build of
+/// `name`, and ask whether to build with it when `ask` is set.
+pub fn compare(name: &str, dir: &Path, ask: bool) -> Result<(), InstallError> {
+    let (Some(old), Some(new)) = (
+        read(&saved_path(name)),
+        find(dir).and_then(|path| read(&path)),
+    ) else {
+        return Ok(());
+    };
+    let changes = changes(&old, &new);
+    if changes.is_empty() {
+        outputln!("configured the same way as the last build of `{}`.", name);
+        return Ok(());
+    }
+
+    outputln!(
+        purple,
+        "{} CMake cache variables changed since `{}` was last built:",
+        (changes.len()),
+        name
+    );
+    let unset = || "(unset)".to_string();
+    let mut table = Table::new(&["VARIABLE", "LAST BUILD", "NOW"]);
+    for (variable, old, new) in changes {
+        table.row(vec![
+            variable,
+            old.unwrap_or_else(unset),
+            new.unwrap_or_else(unset),
+        ]);
+    }
+    eprint!("{}", table.render(false));
+
+    if !ask {
+        return Ok(());
+    }
+    output!(on_blue, "build with these changes? [Y/n] ");
+    let input: String = text_io::read!("{}\n");
+    if input.to_lowercase().starts_with('n') {
+        return Err(InstallError::UnknownFatal(
+            "cancelled after configuring, nothing was installed.".into(),
+        ));
+    }
+    Ok(())
+}
+
+/// Keep the cache of the project in `dir` as the last build of `name`.
+pub fn save(name: &str, dir: &Path) {
+    let Some(cache) = find(dir) else {
+        return;
+    };
+    let path = saved_path(name);
+    let saved = path
+        .parent()
+        .map_or(Ok(()), std::fs::create_dir_all)
+        .and_then(|_| std::fs::copy(&cache, &path));
+    if let Err(e) = saved {
+        outputln!(
+            red,
+            "warning: failed to keep the CMake cache in {}. {}",
+            (path.display()),
+            e
+        );
+    }
+}
@@ -0,0 +1,132 @@
+// CMake's user package registry.
+//
+// `find_package(Foo)` looks in the usual prefixes, so a package installed
+// into `~/opt/foo` isn't found unless `CMAKE_PREFIX_PATH` points there. CMake
+// also looks in its user package registry, `~/.cmake/packages/<Package>/`,
+// where every file holds a directory with a `<Package>Config.cmake` in it.
+// With `install --cmake-register` (or the `cmake_register` config key) every
+// config file a package installs is added there, so CMake projects find it
+// without touching the environment.
+//
+// The entries are named after the cinstall package, so uninstalling it (or
+// `cinstall deregister`) removes exactly them, and `relocate` points them at
+// the new prefix.
+
+use crate::outputln;
+use crate::paths;
+use std::path::{Path, PathBuf};
+
+/// `~/.cmake/packages`
+pub fn dir() -> PathBuf {
+    paths::home_dir().join(".cmake").join("packages")
+}
+
+/// The name CMake knows the config file `file` by, `Foo` for both
+/// `FooConfig.cmake` and `foo-config.cmake`.
+fn config_name(file: &Path) -> Option<String> {
+    let name = file.file_name()?.to_str()?;
+    let package = name
+        .strip_suffix("Config.cmake")
+        .or_else(|| name.strip_suffix("-config.cmake"))?;
+    (!package.is_empty()).then(|| package.to_string())
+}
+
+/// The file an entry for the cinstall package `package` is kept in.
+fn entry_name(package: &str) -> String {
+    format!(
+        "cinstall-{}",
+        package
+            .chars()
+            .map(|c| if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            })
+            .collect::<String>()
+    )
+}
+
+/// Add the config files among `files` to the registry for `package`,
+/// returning the names CMake finds them by.
+pub fn register(package: &str, files: &[PathBuf]) -> Vec<String> {
+    let mut registered = vec![];
+    for file in files {
+        let (Some(name), Some(config_dir)) = (config_name(file), file.parent()) else {
+            continue;
+        };
+        let entry_dir = dir().join(&name);
+        let written = std::fs::create_dir_all(&entry_dir).and_then(|_| {
+            std::fs::write(
+                entry_dir.join(entry_name(package)),
+                format!("{}\n", config_dir.display()),
+            )
+        });
+        match written {
+            Ok(_) => registered.push(name),
+            Err(e) => outputln!(
+                red,
+                "warning: failed to add `{}` to CMake's package registry. {}",
+                name,
+                e
+            ),
+        }
+    }
+    registered.sort();
+    registered.dedup();
+    registered
+}
+
+/// Remove the entries added for `package`, returning the names they were for.
+pub fn deregister(package: &str) -> Vec<String> {
+    let mut removed = vec![];
+    let Ok(entries) = std::fs::read_dir(dir()) else {
+        return removed;
+    };
+    for entry in entries.flatten() {
+        let file = entry.path().join(entry_name(package));
+        if std::fs::remove_file(&file).is_ok() {
+            removed.push(entry.file_name().to_string_lossy().to_string());
+            // an empty directory is the same as no entry, but clutter.
+            let _ = std::fs::remove_dir(entry.path());
+        }
+    }
+    removed.sort();
+    removed
+}
+
+/// Whether `package` has entries in the registry.
+pub fn is_registered(package: &str) -> bool {
+    std::fs::read_dir(dir()).is_ok_and(|entries| {
+        entries
+            .flatten()
+            .any(|entry| entry.path().join(entry_name(package)).is_file())
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn config_files_are_named_after_their_package() {
+        let name = |file: &str| config_name(Path::new(file));
+        assert_eq!(
+            name("/p/lib/cmake/fmt/fmtConfig.cmake").as_deref(),
+            Some("fmt")
+        );
+        assert_eq!(
+            name("/p/lib/cmake/zlib/zlib-config.cmake").as_deref(),
+            Some("zlib")
+        );
+        assert_eq!(name("/p/lib/cmake/fmt/fmtConfigVersion.cmake"), None);
+        assert_eq!(name("/p/lib/cmake/fmt/fmt-targets.cmake"), None);
+        assert_eq!(name("/p/Config.cmake"), None);
+    }
+
+    #[test]
+    fn entries_are_safe_file_names() {
+        assert_eq!(entry_name("spdlog"), "cinstall-spdlog");
+        assert_eq!(entry_name("{fmt}"), "cinstall-_fmt_");
+        assert_eq!(entry_name("../x y"), "cinstall-___x_y");
+    }
+}
@@ -0,0 +1,197 @@
+// Dynamic shell completion.
+//
+// The scripts printed by `cinstall completions <shell>` call back into
+// `cinstall __complete <words...>`, where the last word is the one being
+// completed (possibly empty). Candidates are printed one per line on stdout,
+// which lets the shells complete package names, installed packages, git refs
+// and config keys without knowing anything about cinstall themselves.
+
+use crate::cli::Cli;
+use crate::config;
+use crate::database::MergedDatabase;
+use crate::github;
+use crate::history::Operation;
+use crate::registry::PackageRegistry;
+use clap::{CommandFactory, ValueEnum};
+use std::process::Command;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+pub fn script(shell: Shell) -> &'static str {
+    match shell {
+        Shell::Bash => {
+            r#"_cinstall() {
+    local IFS=$'\n'
+    COMPREPLY=($(cinstall __complete "${COMP_WORDS[@]:1:COMP_CWORD}" 2>/dev/null))
+}
+complete -o default -F _cinstall cinstall
+"#
+        }
+        Shell::Zsh => {
+            r#"#compdef cinstall
+_cinstall() {
+    local -a candidates
+    candidates=("${(@f)$(cinstall __complete "${(@)words[2,CURRENT]}" 2>/dev/null)}")
+    compadd -a candidates
+}
+compdef _cinstall cinstall
+"#
+        }
+        Shell::Fish => {
+            r#"complete -c cinstall -f -a '(cinstall __complete (commandline -opc)[2..-1] (commandline -ct) 2>/dev/null)'
+"#
+        }
+    }
+}
+
+/// Every visible subcommand name.
+fn subcommands() -> Vec<String> {
+    Cli::command()
+        .get_subcommands()
+        .filter(|c| !c.is_hide_set())
+        .map(|c| c.get_name().to_string())
+        .collect()
+}
+
+/// Every `--flag` a subcommand accepts.
+fn flags(subcommand: &str) -> Vec<String> {
+    let cli = Cli::command();
+    let command = match cli.find_subcommand(subcommand) {
+        Some(c) => c,
+        None => return vec![],
+    };
+    command
+        .get_arguments()
+        .filter(|a| !a.is_hide_set())
+        .filter_map(|a| a.get_long())
+        .map(|long| format!("--{}", long))
+        .collect()
+}
+
+fn package_names() -> Vec<String> {
+    PackageRegistry::default()
+        .packages()
+        .keys()
+        .map(|name| name.to_string())
+        .collect()
+}
+
+fn installed_names() -> Vec<String> {
+    MergedDatabase::open()
+        .iter()
+        .map(|(_, package)| package.name.clone())
+        .collect()
+}
+
+/// Branches and tags of the package (or URL) named in `words`, from `git ls-remote`.
+fn refs(words: &[String]) -> Vec<String> {
+    let registry = PackageRegistry::default();
+    let url = words
+        .iter()
+        .skip(1)
+        .find_map(|word| match registry.get(word) {
+            Some(package) => Some(package.url.to_string()),
+            None if word.contains("://") => Some(word.clone()),
+            None => None,
+        });
+    let url = match url {
+        Some(u) => u,
+        None => return vec![],
+    };
+
+    let output = match Command::new("git")
+        .args(github::git_auth_args())
+        .args(["ls-remote", "--heads", "--tags", &url])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+    {
+        Ok(o) if o.status.success() => o,
+        _ => return vec![],
+    };
+
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| line.split_whitespace().nth(1))
+        .filter(|r| !r.ends_with("^{}"))
+        .map(|r| {
+            r.trim_start_matches("refs/heads/")
+                .trim_start_matches("refs/tags/")
+                .to_string()
+        })
+        .collect()
+}
+
+fn candidates(words: &[String], current: &str) -> Vec<String> {
+    let subcommand = match words.first() {
+        Some(s) => s.as_str(),
+        None => {
+            let mut all = subcommands();
+            all.extend(package_names());
+            return all;
+        }
+    };
+    let previous = words.last().map(String::as_str).unwrap_or("");
+
+    if current.starts_with('-') {
+        return flags(subcommand);
+    }
+
+    match (subcommand, previous) {
+        (_, "--prefix") => vec![],
+        (_, "--ref" | "--good" | "--bad") => refs(words),
+        (_, "--test") => vec![],
+        ("history", "--package") => installed_names(),
+        ("history", "--operation") => Operation::value_variants()
+            .iter()
+            .filter_map(|op| op.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect(),
+        ("install" | "info" | "bisect", _) => package_names(),
+        (
+            "uninstall" | "upgrade" | "deregister" | "integrate" | "relocate" | "export-artifact"
+            | "verify" | "size",
+            _,
+        ) => installed_names(),
+        ("completions", _) => Shell::value_variants()
+            .iter()
+            .filter_map(|s| s.to_possible_value())
+            .map(|v| v.get_name().to_string())
+            .collect(),
+        ("config", "config") | ("registry", "registry") => Cli::command()
+            .find_subcommand(subcommand)
+            .map(|c| {
+                c.get_subcommands()
+                    .map(|s| s.get_name().to_string())
+                    .collect()
+            })
+            .unwrap_or_default(),
+        ("config", "get" | "set" | "unset") => {
+            config::KEYS.iter().map(|(k, _)| k.to_string()).collect()
+        }
+        _ => vec![],
+    }
+}
+
+/// Print completions for `words`, the last of which is being completed.
+pub fn complete(words: &[String]) {
+    let (current, previous) = match words.split_last() {
+        Some((current, previous)) => (current.as_str(), previous),
+        None => ("", &[][..]),
+    };
+
+    let mut found: Vec<String> = candidates(previous, current)
+        .into_iter()
+        .filter(|c| c.starts_with(current))
+        .collect();
+    found.sort();
+    found.dedup();
+
+    for candidate in found {
+        println!("{}", candidate);
+    }
+}
@@ -0,0 +1,182 @@
+// User configuration, stored in `~/.config/cinstall/config.toml`.
+//
+// Anything set here is a default; flags passed on the command line always win.
+
+use crate::installer::InstallError;
+use crate::paths;
+use crate::source::Protocol;
+use crate::ui::Theme;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+/// Every key that can be set with `cinstall config set`, and what it does.
+pub const KEYS: &[(&str, &str)] = &[
+    ("prefix", "where packages are installed to"),
+    (
+        "pager",
+        "whether long output is shown through $PAGER (true or false)",
+    ),
+    (
+        "theme",
+        "how output looks: default, plain (no colour, ASCII only) or high-contrast",
+    ),
+    (
+        "label",
+        "the label output starts with, `installer` by default",
+    ),
+    (
+        "hosts",
+        "hosts other than github.com and gitlab.com to install from, e.g. [\"git.example.com\"]",
+    ),
+    (
+        "protocol",
+        "how git repositories are cloned: https (the default) or ssh",
+    ),
+    (
+        "retries",
+        "how many times clones and downloads are tried when the network fails, 3 by default",
+    ),
+    (
+        "retry_delay",
+        "seconds to wait before trying again, doubled every time, 2 by default",
+    ),
+    (
+        "registry_url",
+        "where `cinstall registry update` fetches the registry from, cinstall's repository by default",
+    ),
+    (
+        "package_indices",
+        "look names the registry doesn't know up in vcpkg's and Conan Center's indices (true by default)",
+    ),
+    (
+        "cmake_register",
+        "add installed packages to CMake's user package registry, as with `install --cmake-register` (true or false)",
+    ),
+    (
+        "copy_jobs",
+        "how many files are copied into place at once when installing, the number of CPUs by default",
+    ),
+    (
+        "mirrors",
+        "where else to get sources from when their host is down, e.g. { \"github.com\" = [\"https://git.example.com/github\"] }",
+    ),
+    (
+        "rollback_files",
+        "keep a copy of a package's files when upgrading it, so `cinstall rollback` puts them back instead of building it again (true or false)",
+    ),
+    (
+        "versioned",
+        "install every package into a directory of its own linked into the prefix, as with `install --versioned` (true or false)",
+    ),
+    (
+        "versions_dir",
+        "where versioned installs keep each version of a package, /opt/cinstall by default",
+    ),
+    (
+        "store_dir",
+        "where builds are kept to be reused by installs of the same commit with the same options (see `cinstall gc`), builds aren't kept when it isn't set",
+    ),
+];
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    pub prefix: Option<PathBuf>,
+    pub pager: Option<bool>,
+    pub theme: Option<Theme>,
+    pub label: Option<String>,
+    pub hosts: Option<Vec<String>>,
+    pub protocol: Option<Protocol>,
+    pub retries: Option<u32>,
+    pub retry_delay: Option<u64>,
+    pub registry_url: Option<String>,
+    pub cmake_register: Option<bool>,
+    pub package_indices: Option<bool>,
+    pub copy_jobs: Option<usize>,
+    pub mirrors: Option<BTreeMap<String, Vec<String>>>,
+    pub rollback_files: Option<bool>,
+    pub versioned: Option<bool>,
+    pub versions_dir: Option<PathBuf>,
+    pub store_dir: Option<PathBuf>,
+}
+
+pub fn config_path() -> PathBuf {
+    paths::config_dir().join("config.toml")
+}
+
+fn read_table() -> Result<toml::Table, InstallError> {
+    let path = config_path();
+    if !path.exists() {
+        return Ok(toml::Table::new());
+    }
+    let contents = std::fs::read_to_string(&path)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+    contents
+        .parse::<toml::Table>()
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))
+}
+
+fn write_table(table: &toml::Table) -> Result<(), InstallError> {
+    let path = config_path();
+    std::fs::create_dir_all(paths::config_dir())
+        .map_err(|_| InstallError::FailedToCreateDirectory)?;
+    let contents =
+        toml::to_string_pretty(table).map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    std::fs::write(&path, contents).map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+impl Config {
+    /// Load the configuration. A missing file means everything is left at its default.
+    pub fn load() -> Result<Self, InstallError> {
+        let table = read_table()?;
+        Config::deserialize(toml::Value::Table(table))
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", config_path().display(), e)))
+    }
+}
+
+fn check_key(key: &str) -> Result<(), InstallError> {
+    if KEYS.iter().any(|(k, _)| *k == key) {
+        Ok(())
+    } else {
+        Err(InstallError::UnknownFatal(format!(
+            "unknown config key `{}`. known keys are: {}",
+            key,
+            KEYS.iter().map(|(k, _)| *k).collect::<Vec<_>>().join(", ")
+        )))
+    }
+}
+
+pub fn get(key: &str) -> Result<Option<toml::Value>, InstallError> {
+    check_key(key)?;
+    Ok(read_table()?.get(key).cloned())
+}
+
+/// Set `key`. Values that look like TOML (numbers, booleans, arrays) are stored
+/// as such, anything else is stored as a string.
+pub fn set(key: &str, value: &str) -> Result<(), InstallError> {
+    check_key(key)?;
+    let mut table = read_table()?;
+    let parsed = format!("value = {}", value)
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|mut t| t.remove("value"))
+        .unwrap_or_else(|| toml::Value::String(value.to_string()));
+    table.insert(key.to_string(), parsed);
+
+    // make sure the result still makes sense before writing it.
+    Config::deserialize(toml::Value::Table(table.clone()))
+        .map_err(|e| InstallError::UnknownFatal(format!("invalid value for `{}`: {}", key, e)))?;
+    write_table(&table)
+}
+
+pub fn unset(key: &str) -> Result<(), InstallError> {
+    check_key(key)?;
+    let mut table = read_table()?;
+    table.remove(key);
+    write_table(&table)
+}
+
+pub fn list() -> Result<Vec<(String, toml::Value)>, InstallError> {
+    Ok(read_table()?.into_iter().collect())
+}
@@ -0,0 +1,297 @@
+// Tracks which packages cinstall has installed.
+//
+// There are two databases: one owned by the user for installs into their
+// home directory (~/.local), and a system one (root-writable) for installs
+// into prefixes like /usr/local. Which one a package goes into depends on
+// the prefix it was installed to. Commands that only read use `MergedDatabase`
+// to see both at once.
+//
+// A database is written to a temporary file next to it and renamed over it,
+// so a cinstall that is killed while saving leaves the old one intact.
+
+use crate::installer::{InstallError, Recipe};
+use crate::outputln;
+use crate::paths;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Scope {
+    User,
+    System,
+}
+
+impl Scope {
+    /// The database an install into `prefix` is tracked in.
+    pub fn for_prefix(prefix: &Path) -> Scope {
+        if paths::is_user_path(prefix) {
+            Scope::User
+        } else {
+            Scope::System
+        }
+    }
+
+    pub fn database_path(&self) -> PathBuf {
+        let mut path = match self {
+            Scope::User => paths::data_dir(),
+            Scope::System => PathBuf::from(paths::SYSTEM_DATA_DIR),
+        };
+        path.push("state");
+        path.push("installed.json");
+        path
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Scope::User => write!(f, "user"),
+            Scope::System => write!(f, "system"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct InstalledPackage {
+    pub name: String,
+    pub url: String,
+    pub prefix: PathBuf,
+    pub installed_at: DateTime<Utc>,
+    /// When it was first installed, if it has been reinstalled since.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub first_installed_at: Option<DateTime<Utc>>,
+    /// The ref that was asked for, if it wasn't the default branch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    /// The commit that was built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+    /// How it was built, not recorded for prebuilt binaries, artifacts, or by
+    /// versions of cinstall before `generate-formula`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build: Option<Build>,
+    /// Where it was installed to with `--versioned`, the prefix links to it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_dir: Option<PathBuf>,
+}
+
+impl InstalledPackage {
+    /// The ref and commit it was built at, as `ref @ commit`.
+    pub fn built_at(&self) -> String {
+        let git_ref = self.git_ref.as_deref().unwrap_or("default branch");
+        match &self.commit {
+            Some(commit) => format!("{} @ {}", git_ref, &commit[..commit.len().min(10)]),
+            None => git_ref.to_string(),
+        }
+    }
+}
+
+/// The build system a package was built with and what it was given.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Build {
+    /// As `InstallMethod` shows it: `cmake`, `make`, `autotools`, ...
+    pub method: String,
+    /// Whether the autotools project had no `configure` script yet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub generate: bool,
+    /// The headers copied into `include/`, relative to the project.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub headers: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cmake_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configure_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub make_targets: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub env: Vec<(String, String)>,
+    #[serde(default, skip_serializing_if = "Recipe::is_empty")]
+    pub recipe: Recipe,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binaries: Vec<String>,
+    /// The SHA-256 of the archive that was built, for archives.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    /// The submodules and FetchContent dependencies the project pulled in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nested: Vec<Nested>,
+}
+
+/// Source a package was built with besides its own.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Nested {
+    /// The path of a submodule, the name of a FetchContent dependency.
+    pub name: String,
+    pub via: Via,
+    pub url: String,
+    /// The commit, unless it was downloaded as an archive.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+/// How a project pulled in a nested dependency.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Via {
+    Submodule,
+    FetchContent,
+}
+
+impl std::fmt::Display for Via {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Via::Submodule => write!(f, "submodule"),
+            Via::FetchContent => write!(f, "FetchContent"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct DatabaseFile {
+    packages: Vec<InstalledPackage>,
+}
+
+pub struct Database {
+    scope: Scope,
+    path: PathBuf,
+    packages: Vec<InstalledPackage>,
+}
+
+impl Database {
+    /// Open the database for `scope`. A database that doesn't exist yet is empty.
+    pub fn open(scope: Scope) -> Result<Self, InstallError> {
+        let path = scope.database_path();
+        let packages = packages_at(&path)?;
+        Ok(Self {
+            scope,
+            path,
+            packages,
+        })
+    }
+
+    pub fn save(&self) -> Result<(), InstallError> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| InstallError::DatabaseError(format!("{}: {}", parent.display(), e)))?;
+        }
+
+        let file = DatabaseFile {
+            packages: self.packages.clone(),
+        };
+        let json = serde_json::to_string_pretty(&file)
+            .map_err(|e| InstallError::DatabaseError(e.to_string()))?;
+        let temp = self
+            .path
+            .with_extension(format!("json.{}", std::process::id()));
+        let written = std::fs::write(&temp, json).and_then(|_| std::fs::rename(&temp, &self.path));
+        if written.is_err() {
+            let _ = std::fs::remove_file(&temp);
+        }
+        written.map_err(|e| {
+            let hint = match self.scope {
+                Scope::System => " (the system database is only writable by root)",
+                Scope::User => "",
+            };
+            InstallError::DatabaseError(format!("{}: {}{}", self.path.display(), e, hint))
+        })
+    }
+
+    pub fn scope(&self) -> Scope {
+        self.scope
+    }
+
+    pub fn get(&self, name: &str) -> Option<&InstalledPackage> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Record `package`, replacing any previous record with the same name but
+    /// keeping when it was first installed.
+    pub fn insert(&mut self, mut package: InstalledPackage) {
+        if let Some(previous) = self.get(&package.name) {
+            package.first_installed_at = Some(
+                previous
+                    .first_installed_at
+                    .unwrap_or(previous.installed_at)
+                    .min(package.installed_at),
+            );
+        }
+        self.packages.retain(|p| p.name != package.name);
+        self.packages.push(package);
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<InstalledPackage> {
+        let index = self.packages.iter().position(|p| p.name == name)?;
+        Some(self.packages.remove(index))
+    }
+
+    pub fn packages(&self) -> &[InstalledPackage] {
+        &self.packages
+    }
+}
+
+/// The packages in the database at `path`. A database that doesn't exist yet
+/// is empty.
+pub fn packages_at(path: &Path) -> Result<Vec<InstalledPackage>, InstallError> {
+    if !path.exists() {
+        return Ok(vec![]);
+    }
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| InstallError::DatabaseError(format!("{}: {}", path.display(), e)))?;
+    serde_json::from_str::<DatabaseFile>(&contents)
+        .map(|file| file.packages)
+        .map_err(|e| InstallError::DatabaseError(format!("{}: {}", path.display(), e)))
+}
+
+/// A read-only view over the user and system databases.
+pub struct MergedDatabase {
+    databases: Vec<Database>,
+}
+
+impl MergedDatabase {
+    /// Open both databases. One that can't be read is reported and skipped
+    /// so that a broken system database doesn't stop the user from working.
+    pub fn open() -> Self {
+        let mut databases = vec![];
+        for scope in [Scope::User, Scope::System] {
+            match Database::open(scope) {
+                Ok(db) => databases.push(db),
+                Err(e) => outputln!(red, "skipping the {} database. {}", scope, e),
+            }
+        }
+        Self { databases }
+    }
+
+    /// Find an installed package, preferring the user database.
+    pub fn find(&self, name: &str) -> Option<(Scope, &InstalledPackage)> {
+        self.databases
+            .iter()
+            .find_map(|db| db.get(name).map(|p| (db.scope(), p)))
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (Scope, &InstalledPackage)> {
+        self.databases
+            .iter()
+            .flat_map(|db| db.packages().iter().map(move |p| (db.scope(), p)))
+    }
+
+    /// Every installed package once, the way `find` finds it, by name.
+    pub fn packages(&self) -> Vec<&InstalledPackage> {
+        let mut packages: Vec<&InstalledPackage> = vec![];
+        for (_, package) in self.iter() {
+            if !packages.iter().any(|p| p.name == package.name) {
+                packages.push(package);
+            }
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+        packages
+    }
+}
+
+/// Add `package` to the database matching its prefix.
+pub fn record_install(package: InstalledPackage) -> Result<Scope, InstallError> {
+    let scope = Scope::for_prefix(&package.prefix);
+    let mut db = Database::open(scope)?;
+    db.insert(package);
+    db.save()?;
+    Ok(scope)
+}
@@ -0,0 +1,78 @@
+// Building a dependency from a checkout of your own.
+//
+// `cinstall develop <name> --path ../my-lib` makes `name` be built from
+// `../my-lib` as it is, wherever it would otherwise be cloned from, until
+// `cinstall develop <name> --remove`. Installing it, directly or as a
+// dependency, builds the checkout with the registry's options for the
+// package; `cinstall status` marks it and `cinstall upgrade` leaves it alone.
+//
+// The checkouts are kept in `~/.local/share/cinstall/develop.toml`, as the
+// package name and the absolute path of its checkout.
+
+use crate::installer::InstallError;
+use crate::paths;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+fn develop_path() -> PathBuf {
+    paths::data_dir().join("develop.toml")
+}
+
+/// Every package built from a checkout, and where the checkout is.
+pub fn load() -> BTreeMap<String, PathBuf> {
+    std::fs::read_to_string(develop_path())
+        .ok()
+        .and_then(|contents| toml::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save(checkouts: &BTreeMap<String, PathBuf>) -> Result<(), InstallError> {
+    let path = develop_path();
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    }
+    let contents =
+        toml::to_string_pretty(checkouts).map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    std::fs::write(
+        &path,
+        format!(
+            "# packages built from your own checkouts, see `cinstall develop`.\n{}",
+            contents
+        ),
+    )
+    .map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+/// The checkout `name` is built from, if it is.
+pub fn path(name: &str) -> Option<PathBuf> {
+    load().remove(name)
+}
+
+/// The URL installing the checkout at `path` builds it from.
+pub fn url(path: &Path) -> Url {
+    Url::from_directory_path(path).unwrap_or_else(|_| Url::parse("file:///").unwrap())
+}
+
+/// Build `name` from the checkout at `path` from now on.
+pub fn set(name: &str, path: &Path) -> Result<PathBuf, InstallError> {
+    let path = path
+        .canonicalize()
+        .ok()
+        .filter(|p| p.is_dir())
+        .ok_or_else(|| InstallError::BadDirectory(path.display().to_string()))?;
+    let mut checkouts = load();
+    checkouts.insert(name.to_string(), path.clone());
+    save(&checkouts)?;
+    Ok(path)
+}
+
+/// Stop building `name` from a checkout, returning the one it was built from.
+pub fn remove(name: &str) -> Result<Option<PathBuf>, InstallError> {
+    let mut checkouts = load();
+    let removed = checkouts.remove(name);
+    if removed.is_some() {
+        save(&checkouts)?;
+    }
+    Ok(removed)
+}
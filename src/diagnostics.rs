@@ -0,0 +1,186 @@
+// Warnings and errors from a build.
+//
+// Every line of build output passes through here (see `governor`). Lines in
+// the `file:line:col: warning: message [-Wflag]` form that gcc, clang and most
+// other tools print are collected, once each, so that the end of an install can
+// say how many warnings of each kind a package has and in which files. That is
+// also part of the `--report` JSON.
+
+use crate::outputln;
+use crate::ui::{self, Tone};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::sync::Mutex;
+
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Serialize)]
+pub struct Kind {
+    pub severity: Severity,
+    pub count: usize,
+    pub files: BTreeSet<String>,
+}
+
+#[derive(Serialize, Default)]
+pub struct Summary {
+    pub warnings: usize,
+    pub errors: usize,
+    /// Keyed by the warning's flag (`-Wunused-variable`), or just `warning` or
+    /// `error` for the ones without one.
+    pub kinds: BTreeMap<String, Kind>,
+    #[serde(skip)]
+    seen: HashSet<String>,
+}
+
+static CURRENT: Mutex<Option<Summary>> = Mutex::new(None);
+
+/// Start collecting for a new build.
+pub fn reset() {
+    *CURRENT.lock().unwrap() = Some(Summary::default());
+}
+
+/// Everything collected since `reset`.
+pub fn take() -> Summary {
+    CURRENT.lock().unwrap().take().unwrap_or_default()
+}
+
+/// `src/x.c:3:1: warning: unused variable 'y' [-Wunused-variable]` into its
+/// file, severity and kind.
+fn parse(line: &str) -> Option<(String, Severity, String)> {
+    let (location, severity, message) = [
+        (": fatal error: ", Severity::Error),
+        (": error: ", Severity::Error),
+        (": warning: ", Severity::Warning),
+    ]
+    .iter()
+    .find_map(|(marker, severity)| {
+        line.split_once(marker)
+            .map(|(location, message)| (location, *severity, message))
+    })?;
+
+    let file = location.split(':').next()?.trim();
+    // `make: *** ...` and friends aren't about a source file.
+    if file.is_empty() || file.contains(' ') {
+        return None;
+    }
+    let kind = message
+        .rsplit_once("[-W")
+        .and_then(|(_, flag)| flag.strip_suffix(']'))
+        .map(|flag| format!("-W{}", flag))
+        .unwrap_or_else(|| match severity {
+            Severity::Error => "error".into(),
+            Severity::Warning => "warning".into(),
+        });
+    Some((file.to_string(), severity, kind))
+}
+
+/// Look at a line of build output.
+pub fn observe(line: &str) {
+    let mut current = CURRENT.lock().unwrap();
+    let Some(summary) = current.as_mut() else {
+        return;
+    };
+    let Some((file, severity, kind)) = parse(line) else {
+        return;
+    };
+    // a warning in a header shows up once for every file that includes it.
+    if !summary.seen.insert(line.trim().to_string()) {
+        return;
+    }
+    match severity {
+        Severity::Error => summary.errors += 1,
+        Severity::Warning => summary.warnings += 1,
+    }
+    let entry = summary.kinds.entry(kind).or_insert(Kind {
+        severity,
+        count: 0,
+        files: BTreeSet::new(),
+    });
+    entry.count += 1;
+    entry.files.insert(file);
+}
+
+impl Summary {
+    pub fn is_empty(&self) -> bool {
+        self.kinds.is_empty()
+    }
+
+    pub fn print(&self) {
+        if self.is_empty() {
+            return;
+        }
+        outputln!(
+            purple,
+            "the build printed {} warning(s) and {} error(s):",
+            (self.warnings),
+            (self.errors)
+        );
+        let mut kinds: Vec<_> = self.kinds.iter().collect();
+        kinds.sort_by_key(|(_, kind)| (kind.severity, std::cmp::Reverse(kind.count)));
+        for (name, kind) in kinds {
+            let tone = match kind.severity {
+                Severity::Error => Tone::Error,
+                Severity::Warning => Tone::Warning,
+            };
+            let mut files: Vec<&str> = kind.files.iter().map(String::as_str).collect();
+            let more = files.len().saturating_sub(3);
+            files.truncate(3);
+            eprintln!(
+                "  {:>5}  {}  in {}{}",
+                kind.count,
+                ui::paint(name, tone),
+                files.join(", "),
+                if more > 0 {
+                    format!(" and {} more", more)
+                } else {
+                    String::new()
+                }
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(file: &str, severity: Severity, kind: &str) -> Option<(String, Severity, String)> {
+        Some((file.to_string(), severity, kind.to_string()))
+    }
+
+    #[test]
+    fn parses_warnings_with_and_without_a_flag() {
+        assert!(
+            parse("src/x.c:3:1: warning: unused variable 'y' [-Wunused-variable]")
+                == parsed("src/x.c", Severity::Warning, "-Wunused-variable")
+        );
+        assert!(
+            parse("include/fmt/core.h:12: warning: something odd")
+                == parsed("include/fmt/core.h", Severity::Warning, "warning")
+        );
+    }
+
+    #[test]
+    fn parses_errors() {
+        assert!(
+            parse("src/x.c:9:5: error: expected ';' before '}' token")
+                == parsed("src/x.c", Severity::Error, "error")
+        );
+        assert!(
+            parse("src/x.c:1:10: fatal error: y.h: No such file or directory")
+                == parsed("src/x.c", Severity::Error, "error")
+        );
+    }
+
+    #[test]
+    fn ignores_lines_that_arent_about_a_file() {
+        assert!(parse("make: *** [Makefile:12: all] Error 2").is_none());
+        assert!(parse("[ 50%] Building C object x.o").is_none());
+        assert!(parse("In file included from src/x.c:1:").is_none());
+    }
+}
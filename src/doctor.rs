@@ -0,0 +1,224 @@
+// `cinstall doctor`: check that the system is set up to build packages and
+// that whatever gets installed can actually be found afterwards.
+//
+// The pkg-config check also runs after every install that ships `.pc` files,
+// since a prefix outside of pkg-config's search path is the most common
+// reason an installed library "isn't found" by a consumer's build. It only
+// offers to fix the shell rc with a terminal to ask on and without `--yes`,
+// and not when the rc already has the line.
+
+use crate::completion::Shell;
+use crate::{output, outputln, paths, ui};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Look for `program` in each directory of `$PATH`.
+pub fn find_program(program: &str) -> Option<PathBuf> {
+    let path = std::env::var_os("PATH")?;
+    std::env::split_paths(&path)
+        .map(|dir| dir.join(program))
+        .find(|candidate| candidate.is_file())
+}
+
+/// The directories below `prefix` that pkg-config files are installed into.
+pub fn pkg_config_dirs(prefix: &Path) -> Vec<PathBuf> {
+    ["lib/pkgconfig", "lib64/pkgconfig", "share/pkgconfig"]
+        .iter()
+        .map(|dir| prefix.join(dir))
+        .collect()
+}
+
+/// The directories holding any `.pc` file in `files`.
+pub fn pkg_config_dirs_of(files: &[PathBuf]) -> Vec<PathBuf> {
+    let mut dirs: Vec<PathBuf> = vec![];
+    for file in files {
+        if file.extension().and_then(|e| e.to_str()) != Some("pc") {
+            continue;
+        }
+        if let Some(parent) = file.parent() {
+            if !dirs.iter().any(|d| d == parent) {
+                dirs.push(parent.to_path_buf());
+            }
+        }
+    }
+    dirs
+}
+
+/// Everywhere pkg-config looks: `$PKG_CONFIG_PATH` first, then its built in path.
+fn pkg_config_search_path() -> Vec<PathBuf> {
+    let mut search: Vec<PathBuf> = std::env::var_os("PKG_CONFIG_PATH")
+        .map(|p| std::env::split_paths(&p).collect())
+        .unwrap_or_default();
+
+    if let Ok(output) = Command::new("pkg-config")
+        .args(["--variable", "pc_path", "pkg-config"])
+        .output()
+    {
+        let pc_path = String::from_utf8_lossy(&output.stdout);
+        search.extend(std::env::split_paths(pc_path.trim()));
+    }
+    search
+}
+
+fn login_shell() -> Option<Shell> {
+    let shell = std::env::var("SHELL").ok()?;
+    match shell.rsplit('/').next()? {
+        "bash" => Some(Shell::Bash),
+        "zsh" => Some(Shell::Zsh),
+        "fish" => Some(Shell::Fish),
+        _ => None,
+    }
+}
+
+fn rc_file(shell: Shell) -> PathBuf {
+    let home = paths::home_dir();
+    match shell {
+        Shell::Bash => home.join(".bashrc"),
+        Shell::Zsh => home.join(".zshrc"),
+        Shell::Fish => home.join(".config/fish/config.fish"),
+    }
+}
+
+fn export_line(shell: Shell, dirs: &[PathBuf]) -> String {
+    let joined: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+    match shell {
+        Shell::Bash | Shell::Zsh => format!(
+            "export PKG_CONFIG_PATH=\"{}${{PKG_CONFIG_PATH:+:$PKG_CONFIG_PATH}}\"",
+            joined.join(":")
+        ),
+        Shell::Fish => format!(
+            "set -gx PKG_CONFIG_PATH {} $PKG_CONFIG_PATH",
+            joined.join(" ")
+        ),
+    }
+}
+
+/// Whether the rc file of `shell` already has `line`, from an earlier offer.
+fn rc_has(shell: Shell, line: &str) -> bool {
+    std::fs::read_to_string(rc_file(shell))
+        .is_ok_and(|contents| contents.lines().any(|l| l.trim() == line))
+}
+
+fn append_to_rc(shell: Shell, line: &str) -> std::io::Result<PathBuf> {
+    let rc = rc_file(shell);
+    if let Some(parent) = rc.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&rc)?;
+    writeln!(file, "\n# added by cinstall\n{}", line)?;
+    Ok(rc)
+}
+
+/// Make sure pkg-config will search `dirs`. If it won't, offer to add them to
+/// the user's shell rc when `ask`, or explain how to do it by hand. Returns
+/// whether everything was already fine.
+pub fn check_pkg_config(dirs: &[PathBuf], ask: bool) -> bool {
+    let search = pkg_config_search_path();
+    let missing: Vec<PathBuf> = dirs
+        .iter()
+        .filter(|dir| !search.iter().any(|s| s == *dir))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return true;
+    }
+
+    for dir in &missing {
+        outputln!(purple, "pkg-config doesn't search {}.", (dir.display()));
+    }
+
+    if let Some(shell) = login_shell() {
+        let line = export_line(shell, &missing);
+        if rc_has(shell, &line) {
+            outputln!(
+                "{} already adds it, open a new shell for it to take effect.",
+                (rc_file(shell).display())
+            );
+            return false;
+        }
+    }
+
+    if let Some(shell) = login_shell().filter(|_| ask && ui::interactive()) {
+        let line = export_line(shell, &missing);
+        output!(
+            on_blue,
+            "add `{}` to {}? [Y/n] ",
+            line,
+            (rc_file(shell).display())
+        );
+        let input: String = text_io::read!("{}\n");
+        if !input.to_lowercase().starts_with('n') {
+            match append_to_rc(shell, &line) {
+                Ok(rc) => outputln!(
+                    green,
+                    "added it to {}, open a new shell for it to take effect.",
+                    (rc.display())
+                ),
+                Err(e) => outputln!(red, "failed to update the shell rc. {}", e),
+            }
+            return false;
+        }
+    }
+
+    outputln!("to fix it by hand, add one of these to your shell's rc file:");
+    for shell in [Shell::Bash, Shell::Zsh, Shell::Fish] {
+        eprintln!(
+            "  {}: {}",
+            rc_file(shell).display(),
+            export_line(shell, &missing)
+        );
+    }
+    false
+}
+
+/// Run every check, returning whether they all passed.
+pub fn doctor(prefix: &Path) -> bool {
+    let mut healthy = true;
+
+    for program in ["git", "cmake", "make", "pkg-config"] {
+        match find_program(program) {
+            Some(path) => outputln!(green, "found {} at {}.", program, (path.display())),
+            None => {
+                outputln!(red, "`{}` is not installed.", program);
+                healthy = false;
+            }
+        }
+    }
+
+    outputln!("packages are installed into {}.", (prefix.display()));
+    let probe = prefix.join(".cinstall-doctor");
+    let writable = std::fs::write(&probe, "").is_ok();
+    let _ = std::fs::remove_file(&probe);
+    if !writable {
+        outputln!(
+            purple,
+            "{} is missing or read-only, installs into it will need `sudo`.",
+            (prefix.display())
+        );
+    }
+
+    let existing: Vec<PathBuf> = pkg_config_dirs(prefix)
+        .into_iter()
+        .filter(|dir| dir.exists())
+        .collect();
+    // nothing has been installed yet, so check where pkg-config files usually go.
+    let dirs = if existing.is_empty() {
+        vec![prefix.join("lib/pkgconfig")]
+    } else {
+        existing
+    };
+    if check_pkg_config(&dirs, true) {
+        outputln!(
+            green,
+            "pkg-config can find packages installed into the prefix."
+        );
+    } else {
+        healthy = false;
+    }
+
+    healthy
+}
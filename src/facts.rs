@@ -0,0 +1,266 @@
+// The facts panel shown before a project is built.
+//
+// Once the source is checked out and the build system is known, `install`
+// shows what it found out about the project (its name and version, the build
+// system, the language standard, the license and roughly what it builds) and
+// the commands it is about to run, then asks before building anything.
+// `--yes` skips the question, and so does not having a terminal to ask on.
+//
+// Everything here is a best guess from the build files, anything that can't
+// be found is left out of the panel.
+
+use crate::installer::{InstallError, InstallMethod, InstallOptions, Step};
+use crate::output;
+use crate::table::Table;
+use std::path::{Path, PathBuf};
+
+/// Read `name` in `dir`, or nothing.
+fn read(dir: &Path, name: &str) -> String {
+    std::fs::read_to_string(dir.join(name)).unwrap_or_default()
+}
+
+/// The `Name` and `VERSION` of CMake's `project(Name VERSION 1.2.3 ...)`.
+fn cmake_project(dir: &Path) -> (Option<String>, Option<String>) {
+    let contents = read(dir, "CMakeLists.txt");
+    let Some(start) = contents.to_lowercase().find("project(") else {
+        return (None, None);
+    };
+    let args: String = contents[start + "project(".len()..]
+        .chars()
+        .take_while(|c| *c != ')')
+        .collect();
+    let words: Vec<&str> = args.split_whitespace().collect();
+    let name = words.first().map(|w| w.trim_matches('"').to_string());
+    let version = words
+        .iter()
+        .position(|w| *w == "VERSION")
+        .and_then(|i| words.get(i + 1))
+        .map(|v| v.trim_matches('"').to_string());
+    (name, version)
+}
+
+/// The name and `version:` of meson's `project('name', ..., version: '1.2.3')`.
+fn meson_project(dir: &Path) -> (Option<String>, Option<String>) {
+    let contents = read(dir, "meson.build");
+    let Some(start) = contents.find("project(") else {
+        return (None, None);
+    };
+    let args = &contents[start + "project(".len()..];
+    let quoted = |text: &str| -> Option<String> {
+        let text = text.trim_start().strip_prefix('\'')?;
+        Some(text[..text.find('\'')?].to_string())
+    };
+    let name = quoted(args);
+    let version = args
+        .find("version:")
+        .and_then(|i| quoted(&args[i + "version:".len()..]));
+    (name, version)
+}
+
+/// The most recent tag, which is usually the version.
+fn git_version(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["describe", "--tags", "--abbrev=0"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    let tag = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    (output.status.success() && !tag.is_empty()).then_some(tag)
+}
+
+/// The C or C++ standard the project asks for, like `C++17`.
+fn language_standard(dir: &Path) -> Option<String> {
+    let cmake = read(dir, "CMakeLists.txt");
+    let meson = read(dir, "meson.build");
+    let number_after = |text: &str, marker: &str| -> Option<String> {
+        let rest = &text[text.find(marker)? + marker.len()..];
+        let digits: String = rest
+            .trim_start_matches(|c: char| c.is_whitespace() || c == '"' || c == '=')
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        (!digits.is_empty()).then_some(digits)
+    };
+    if let Some(n) = number_after(&cmake, "CMAKE_CXX_STANDARD")
+        .or_else(|| number_after(&cmake, "cxx_std_"))
+        .or_else(|| number_after(&meson, "cpp_std=c++"))
+        .or_else(|| number_after(&meson, "cpp_std=gnu++"))
+    {
+        return Some(format!("C++{}", n));
+    }
+    number_after(&cmake, "CMAKE_C_STANDARD")
+        .or_else(|| number_after(&cmake, "c_std_"))
+        .or_else(|| number_after(&meson, "c_std=c"))
+        .or_else(|| number_after(&meson, "c_std=gnu"))
+        .map(|n| format!("C{}", n))
+}
+
+/// The license, from an SPDX identifier or the wording of the license file.
+pub fn license(dir: &Path) -> Option<String> {
+    let text = ["LICENSE", "LICENSE.txt", "LICENSE.md", "COPYING", "LICENCE"]
+        .iter()
+        .map(|name| read(dir, name))
+        .find(|text| !text.is_empty())?;
+    if let Some(id) = text
+        .lines()
+        .find_map(|l| l.split("SPDX-License-Identifier:").nth(1))
+    {
+        return Some(id.trim().to_string());
+    }
+    let text = text.to_lowercase();
+    let known = [
+        ("apache license", "Apache-2.0"),
+        ("lesser general public license", "LGPL"),
+        ("gnu general public license", "GPL"),
+        ("mozilla public license", "MPL-2.0"),
+        ("boost software license", "BSL-1.0"),
+        ("permission is hereby granted, free of charge", "MIT"),
+        ("redistribution and use in source and binary forms", "BSD"),
+        ("this software is provided 'as-is'", "Zlib"),
+        ("this is free and unencumbered software", "Unlicense"),
+    ];
+    let (_, name) = known.iter().find(|(phrase, _)| text.contains(phrase))?;
+    let version = ["version 3", "version 2.1", "version 2"]
+        .iter()
+        .find(|v| text.contains(*v))
+        .map(|v| v.trim_start_matches("version "));
+    Some(match (*name, version) {
+        ("GPL" | "LGPL", Some(v)) => format!("{}-{}", name, v),
+        _ => name.to_string(),
+    })
+}
+
+/// Every build file named `name` under `dir`, leaving out hidden directories
+/// and the build directory.
+fn build_files(dir: &Path, name: &str, depth: usize, found: &mut Vec<PathBuf>) {
+    if dir.join(name).is_file() {
+        found.push(dir.join(name));
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    if depth == 0 {
+        return;
+    }
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let hidden = entry.file_name().to_string_lossy().starts_with('.');
+        if path.is_dir() && !hidden && entry.file_name() != "build" {
+            build_files(&path, name, depth - 1, found);
+        }
+    }
+}
+
+/// How many libraries and programs the build files define.
+fn targets(dir: &Path, method: &InstallMethod) -> Option<(usize, usize)> {
+    let (file, libraries, programs): (&str, &[&str], &[&str]) = match method {
+        InstallMethod::RunCMake => ("CMakeLists.txt", &["add_library("], &["add_executable("]),
+        InstallMethod::Meson => (
+            "meson.build",
+            &["library(", "shared_library(", "static_library("],
+            &["executable("],
+        ),
+        _ => return None,
+    };
+    let mut files = vec![];
+    build_files(dir, file, 4, &mut files);
+    let (mut library_count, mut program_count) = (0, 0);
+    for file in files {
+        let contents = std::fs::read_to_string(file)
+            .unwrap_or_default()
+            .to_lowercase();
+        for line in contents.lines().map(str::trim_start) {
+            if line.starts_with('#') {
+                continue;
+            }
+            // meson targets are usually assigned, `lib = library(...)`.
+            let calls = |calls: &[&str]| {
+                calls
+                    .iter()
+                    .any(|c| line.starts_with(c) || line.contains(&format!("= {}", c)))
+            };
+            if calls(libraries) {
+                library_count += 1;
+            } else if calls(programs) {
+                program_count += 1;
+            }
+        }
+    }
+    Some((library_count, program_count))
+}
+
+/// Show what was found out about the project in `dir` and the `steps` that
+/// will build it, and ask whether to go on when `ask` is set. A CMake project
+/// can be given more options to configure it with, which are returned.
+pub fn confirm(
+    name: &str,
+    dir: &Path,
+    method: &InstallMethod,
+    steps: &[Step],
+    options: &InstallOptions,
+    ask: bool,
+) -> Result<Vec<String>, InstallError> {
+    let (project, version) = match method {
+        InstallMethod::RunCMake => cmake_project(dir),
+        InstallMethod::Meson => meson_project(dir),
+        _ => (None, None),
+    };
+    let version = version.or_else(|| git_version(dir));
+
+    let mut table = Table::new(&[]);
+    let project = project.unwrap_or_else(|| name.to_string());
+    table.row(vec![
+        "project".into(),
+        match version {
+            Some(version) => format!("{} {}", project, version),
+            None => project,
+        },
+    ]);
+    table.row(vec!["build system".into(), method.to_string()]);
+    if let Some(standard) = language_standard(dir) {
+        table.row(vec!["standard".into(), standard]);
+    }
+    if let Some(license) = license(dir) {
+        table.row(vec!["license".into(), license]);
+    }
+    if let Some((libraries, programs)) = targets(dir, method) {
+        table.row(vec![
+            "targets".into(),
+            format!("about {} libraries, {} programs", libraries, programs),
+        ]);
+    }
+    table.row(vec![
+        "installs into".into(),
+        options.prefix.display().to_string(),
+    ]);
+    for (i, step) in steps.iter().enumerate() {
+        let label = if i == 0 { "plan" } else { "" };
+        table.row(vec![label.into(), step.to_string()]);
+    }
+    eprint!("{}", table.render(false));
+
+    if !ask {
+        return Ok(vec![]);
+    }
+    let cmake = matches!(method, InstallMethod::RunCMake);
+    match cmake {
+        true => output!(
+            on_blue,
+            "build and install it, or add cmake options? [Y/n/o] "
+        ),
+        false => output!(on_blue, "build and install it? [Y/n] "),
+    }
+    let input: String = text_io::read!("{}\n");
+    let input = input.to_lowercase();
+    if input.starts_with('n') {
+        return Err(InstallError::UnknownFatal(
+            "cancelled, nothing was built.".into(),
+        ));
+    }
+    if cmake && input.starts_with('o') {
+        output!(on_blue, "cmake options (e.g. -DBUILD_TESTING=OFF): ");
+        let input: String = text_io::read!("{}\n");
+        return Ok(input.split_whitespace().map(String::from).collect());
+    }
+    Ok(vec![])
+}
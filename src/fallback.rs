@@ -0,0 +1,164 @@
+// Trying another build system when the detected one fails.
+//
+// Plenty of projects can be built more than one way: a CMakeLists next to a
+// handwritten Makefile, or Meson next to autotools, and the one cinstall
+// picks isn't always the one that works. When the build fails and the
+// project has another build system, cinstall offers to build it with that
+// one, without asking with `--yes` or without a terminal.
+//
+// A build system that worked when the detected one didn't is remembered for
+// the repository, in `~/.local/share/cinstall/backends.json`, and used first
+// the next time it is installed.
+
+use crate::installer::{self, InstallError, InstallMethod, InstallOptions};
+use crate::{output, outputln, paths, ui};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+fn backends_path() -> PathBuf {
+    paths::data_dir().join("backends.json")
+}
+
+fn load() -> BTreeMap<String, String> {
+    std::fs::read_to_string(backends_path())
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn save(backends: &BTreeMap<String, String>) {
+    let path = backends_path();
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    // not remembering only costs trying the detected one first next time.
+    if let Ok(json) = serde_json::to_string_pretty(backends) {
+        let _ = std::fs::write(path, json);
+    }
+}
+
+/// Every build system the project in `path` has, in the order they are
+/// detected in. Only the headers being copied isn't one.
+pub fn candidates(path: &Path) -> Vec<InstallMethod> {
+    let mut methods = vec![];
+    let makefile = path.join("Makefile");
+    if makefile.exists() {
+        if let Ok(method) = installer::resolve_makefile_install_method(&makefile) {
+            methods.push(method);
+        }
+    }
+    if path.join("CMakeLists.txt").exists() {
+        methods.push(InstallMethod::RunCMake);
+    }
+    if path.join("meson.build").exists() {
+        methods.push(InstallMethod::Meson);
+    }
+    if path.join("configure").is_file() {
+        methods.push(InstallMethod::Autotools { generate: false });
+    } else if ["configure.ac", "configure.in", "autogen.sh"]
+        .iter()
+        .any(|name| path.join(name).is_file())
+    {
+        methods.push(InstallMethod::Autotools { generate: true });
+    }
+    let others = [
+        ("SConstruct", InstallMethod::Scons),
+        ("xmake.lua", InstallMethod::Xmake),
+        ("premake5.lua", InstallMethod::Premake),
+    ];
+    for (file, method) in others {
+        if path.join(file).exists() {
+            methods.push(method);
+        }
+    }
+    methods
+}
+
+/// The build system that worked for `url` last time, if it wasn't the
+/// detected one and the project in `path` still has it.
+pub fn remembered(url: &Url, path: &Path) -> Option<InstallMethod> {
+    let name = load().remove(url.as_str())?;
+    candidates(path)
+        .into_iter()
+        .find(|method| method.to_string() == name)
+}
+
+/// Whether the build may go better with another build system.
+fn is_build_failure(e: &InstallError) -> bool {
+    matches!(
+        e,
+        InstallError::StepFailed(_)
+            | InstallError::CMakeFailed
+            | InstallError::FailedToMakeInstall
+            | InstallError::CouldNotStartProcess(_)
+    )
+}
+
+fn ask(method: &InstallMethod) -> bool {
+    output!(
+        on_blue,
+        "build it with {} instead? [Y/n] ",
+        (method.to_string())
+    );
+    let input: String = text_io::read!("{}\n");
+    !input.to_lowercase().starts_with('n')
+}
+
+/// Build and install the project in `path` with `method`, and when that fails
+/// with the project's other build systems, returning the one that worked.
+/// `detected` is the build system detection picked, which `method` may have
+/// been remembered over.
+pub fn build(
+    name: &str,
+    url: &Url,
+    path: &Path,
+    method: InstallMethod,
+    detected: &str,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<(InstallMethod, Vec<PathBuf>), InstallError> {
+    let mut tried = vec![method.to_string()];
+    let mut method = method;
+    loop {
+        let error = match installer::build_and_install(name, path, &method, options, staging) {
+            Ok(files) => {
+                let mut backends = load();
+                let changed = if method.to_string() == detected {
+                    backends.remove(url.as_str()).is_some()
+                } else {
+                    backends.insert(url.to_string(), method.to_string()) != Some(method.to_string())
+                };
+                if changed {
+                    save(&backends);
+                }
+                return Ok((method, files));
+            }
+            Err(e) => e,
+        };
+        if options.multiarch || !is_build_failure(&error) {
+            return Err(error);
+        }
+        let Some(next) = candidates(path)
+            .into_iter()
+            .find(|m| !tried.contains(&m.to_string()))
+        else {
+            return Err(error);
+        };
+        outputln!(
+            red,
+            "building `{}` with {} failed. {}",
+            name,
+            (method.to_string()),
+            error
+        );
+        outputln!("the project can be built with {} too.", (next.to_string()));
+        if options.confirm && ui::interactive() && !ask(&next) {
+            return Err(error);
+        }
+        // nothing from the failed build may end up installed.
+        let _ = std::fs::remove_dir_all(staging);
+        tried.push(next.to_string());
+        method = next;
+    }
+}
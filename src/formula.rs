@@ -0,0 +1,570 @@
+// Homebrew formulae and Arch PKGBUILDs for installed packages.
+//
+// `cinstall generate-formula <package>` turns the way a package was built, as
+// recorded when it was installed, into a skeleton Homebrew formula (`--format
+// brew`) or PKGBUILD (`--format pkgbuild`). It builds the same commit or
+// archive with the same build system and arguments, so it is a start for
+// getting a package into a real package ecosystem. The license, the names of
+// the dependencies and the test still want a look before it is submitted.
+
+use crate::database::{Build, InstalledPackage, MergedDatabase};
+use crate::installer::InstallError;
+use crate::registry::PackageRegistry;
+use crate::{facts, source, workspace};
+use std::fmt::Write as _;
+use url::Url;
+
+#[derive(Clone, Copy, PartialEq, clap::ValueEnum)]
+pub enum Format {
+    /// A Homebrew formula, `<name>.rb`.
+    Brew,
+    /// An Arch Linux PKGBUILD.
+    Pkgbuild,
+}
+
+/// What both formats need to know about the package.
+struct Package<'a> {
+    name: String,
+    installed: &'a InstalledPackage,
+    build: &'a Build,
+    url: Url,
+    homepage: String,
+    version: String,
+    description: String,
+    license: Option<String>,
+    dependencies: Vec<String>,
+}
+
+/// `{fmt}` is `fmt`, `json++` is `json`: package managers only take letters,
+/// digits and dashes.
+fn package_name(name: &str) -> String {
+    let name: String = name
+        .to_lowercase()
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+        .collect();
+    name.split('-')
+        .filter(|part| !part.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// The registry description without its links and `[License]`, and the
+/// license it names.
+fn describe(description: &str) -> (String, Option<String>) {
+    let mut text = String::new();
+    let mut license = None;
+    let mut rest = description;
+    while let Some(start) = rest.find('[') {
+        text.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find(']').map(|end| start + end) else {
+            rest = &rest[start..];
+            break;
+        };
+        let inside = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+        // `[website](https://...)` is a link, anything else is the license.
+        if let Some(link) = rest.strip_prefix('(') {
+            rest = link.split_once(')').map_or("", |(_, after)| after);
+        } else {
+            license = Some(inside.to_string());
+        }
+    }
+    text.push_str(rest);
+    let text = text
+        .trim()
+        .trim_start_matches("ap: - ")
+        .trim_end_matches('.');
+    (text.to_string(), license)
+}
+
+/// The version to call the build: the ref that was asked for without a `v`,
+/// or the date it was installed for the default branch.
+fn version(installed: &InstalledPackage) -> String {
+    match &installed.git_ref {
+        Some(git_ref) => git_ref
+            .strip_prefix('v')
+            .filter(|v| v.starts_with(|c: char| c.is_ascii_digit()))
+            .unwrap_or(git_ref)
+            .replace(['-', '/'], "_"),
+        None => installed.installed_at.format("%Y%m%d").to_string(),
+    }
+}
+
+fn load<'a>(
+    registry: &PackageRegistry,
+    name: &str,
+    installed: &'a InstalledPackage,
+) -> Result<Package<'a>, InstallError> {
+    let build = installed.build.as_ref().ok_or_else(|| {
+        InstallError::UnknownFatal(format!(
+            "cinstall doesn't know how `{}` was built, it was installed from a prebuilt binary, an artifact, or by an older cinstall. reinstall it from source first.",
+            name
+        ))
+    })?;
+    let url = Url::parse(&installed.url)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", installed.url, e)))?;
+    if source::is_local(&url) {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` was built from a directory on this machine, a formula needs a URL to download it from.",
+            name
+        )));
+    }
+    let entry = registry.get(name);
+    let (description, registry_license) =
+        entry.map_or((String::new(), None), |p| describe(&p.description));
+    // the checkout says more about the license than the registry, if it's still there.
+    let checkout = workspace::source_dir(&url);
+    let license = checkout
+        .is_dir()
+        .then(|| facts::license(&checkout))
+        .flatten()
+        .or(registry_license);
+    let homepage = url.as_str().trim_end_matches(".git").to_string();
+    Ok(Package {
+        name: package_name(name),
+        installed,
+        build,
+        url,
+        homepage,
+        version: version(installed),
+        description,
+        license,
+        dependencies: entry
+            .map(|p| p.dependencies.iter().map(|d| package_name(d)).collect())
+            .unwrap_or_default(),
+    })
+}
+
+/// The tools a build with `build` needs, by their usual package names.
+fn build_tools(build: &Build) -> Vec<&'static str> {
+    match build.method.as_str() {
+        "cmake" => vec!["cmake"],
+        "meson" => vec!["meson", "ninja"],
+        "autotools" if build.generate => vec!["autoconf", "automake", "libtool"],
+        "scons" => vec!["scons"],
+        "xmake" => vec!["xmake"],
+        "premake" => vec!["premake"],
+        _ => vec![],
+    }
+}
+
+fn install_targets(build: &Build) -> Vec<String> {
+    if build.make_targets.is_empty() {
+        vec!["install".into()]
+    } else {
+        build.make_targets.clone()
+    }
+}
+
+/// `text` as a Ruby string.
+fn ruby(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace("#{", "\\#{")
+    )
+}
+
+/// `system` with the arguments given, which are Ruby already.
+fn system(out: &mut String, args: &[String]) {
+    let _ = writeln!(out, "    system {}", args.join(", "));
+}
+
+fn ruby_args(args: &[String]) -> Vec<String> {
+    args.iter().map(|a| ruby(a)).collect()
+}
+
+fn brew_install(out: &mut String, build: &Build) {
+    for (key, value) in &build.env {
+        let _ = writeln!(out, "    ENV[{}] = {}", ruby(key), ruby(value));
+    }
+    let call = |words: &[&str]| -> Vec<String> { words.iter().map(|w| ruby(w)).collect() };
+    let prefix_arg = || "\"PREFIX=#{prefix}\"".to_string();
+    match build.method.as_str() {
+        "cmake" => {
+            let mut configure = call(&["cmake", "-S", ".", "-B", "build"]);
+            configure.push("*std_cmake_args".into());
+            configure.extend(ruby_args(&build.cmake_args));
+            system(out, &configure);
+            system(out, &call(&["cmake", "--build", "build"]));
+            if build.make_targets.is_empty() {
+                system(out, &call(&["cmake", "--install", "build"]));
+            } else {
+                let mut install = call(&["cmake", "--build", "build", "--target"]);
+                install.extend(ruby_args(&build.make_targets));
+                system(out, &install);
+            }
+        }
+        "make" => {
+            let mut install = call(&["make"]);
+            install.extend(ruby_args(&install_targets(build)));
+            install.push(prefix_arg());
+            system(out, &install);
+        }
+        "autotools" => {
+            if build.generate {
+                system(out, &call(&["autoreconf", "-fiv"]));
+            }
+            let mut configure = call(&["./configure"]);
+            configure.push("*std_configure_args".into());
+            configure.extend(ruby_args(&build.configure_args));
+            system(out, &configure);
+            system(out, &call(&["make"]));
+            let mut install = call(&["make"]);
+            install.extend(ruby_args(&install_targets(build)));
+            system(out, &install);
+        }
+        "meson" => {
+            let mut setup = call(&["meson", "setup", "build"]);
+            setup.push("*std_meson_args".into());
+            system(out, &setup);
+            system(out, &call(&["meson", "compile", "-C", "build"]));
+            system(out, &call(&["meson", "install", "-C", "build"]));
+        }
+        "scons" => {
+            system(out, &[ruby("scons"), prefix_arg()]);
+            system(out, &[ruby("scons"), ruby("install"), prefix_arg()]);
+        }
+        "xmake" => {
+            system(out, &call(&["xmake", "-y"]));
+            let mut install = call(&["xmake", "install", "-y", "-o"]);
+            install.push("prefix".into());
+            system(out, &install);
+        }
+        "premake" => {
+            system(out, &call(&["premake5", "gmake2"]));
+            system(out, &call(&["make", "config=release"]));
+            system(out, &[ruby("make"), ruby("install"), prefix_arg()]);
+        }
+        "headers" => {
+            let _ = writeln!(
+                out,
+                "    include.install {}",
+                ruby_args(&build.headers).join(", ")
+            );
+        }
+        "recipe" => {
+            let _ = writeln!(out, "    ENV[\"PREFIX\"] = prefix");
+            let _ = writeln!(out, "    ENV[\"DESTDIR\"] = \"\"");
+            let _ = writeln!(out, "    ENV[\"JOBS\"] = ENV.make_jobs.to_s");
+            let recipe = &build.recipe;
+            for command in recipe
+                .configure
+                .iter()
+                .chain(&recipe.build)
+                .chain(&recipe.install)
+            {
+                system(out, &[ruby("sh"), ruby("-c"), ruby(command)]);
+            }
+        }
+        method => {
+            let _ = writeln!(
+                out,
+                "    # cinstall doesn't know how to build with {}.",
+                method
+            );
+        }
+    }
+}
+
+/// The name of the formula's class, `FooBar` for `foo-bar`.
+fn class_name(name: &str) -> String {
+    name.split('-')
+        .map(|part| {
+            let mut chars = part.chars();
+            chars
+                .next()
+                .map(|c| c.to_uppercase().chain(chars).collect::<String>())
+                .unwrap_or_default()
+        })
+        .collect()
+}
+
+fn brew(package: &Package) -> String {
+    let mut out = String::new();
+    let installed = package.installed;
+    let _ = writeln!(out, "class {} < Formula", class_name(&package.name));
+    if !package.description.is_empty() {
+        let _ = writeln!(out, "  desc {}", ruby(&package.description));
+    }
+    let _ = writeln!(out, "  homepage {}", ruby(&package.homepage));
+    if source::is_archive(&package.url) {
+        let _ = writeln!(out, "  url {}", ruby(package.url.as_str()));
+        match &package.build.sha256 {
+            Some(sha256) => {
+                let _ = writeln!(out, "  sha256 {}", ruby(sha256));
+            }
+            None => {
+                let _ = writeln!(out, "  # sha256 \"<`shasum -a 256` of the archive>\"");
+            }
+        }
+    } else {
+        let mut url = format!("  url {}", ruby(package.url.as_str()));
+        if let Some(git_ref) = &installed.git_ref {
+            let _ = write!(url, ", tag: {}", ruby(git_ref));
+        }
+        if let Some(commit) = &installed.commit {
+            let _ = write!(url, ", revision: {}", ruby(commit));
+        }
+        let _ = writeln!(out, "{}", url);
+        if installed.git_ref.is_none() {
+            let _ = writeln!(out, "  version {}", ruby(&package.version));
+        }
+    }
+    if let Some(license) = &package.license {
+        let _ = writeln!(out, "  license {}", ruby(license));
+    }
+    let tools = build_tools(package.build);
+    if !tools.is_empty() || !package.dependencies.is_empty() {
+        out.push('\n');
+    }
+    for tool in tools {
+        let _ = writeln!(out, "  depends_on {} => :build", ruby(tool));
+    }
+    if !package.dependencies.is_empty() {
+        let _ = writeln!(
+            out,
+            "  # cinstall registry names, check them against Homebrew's."
+        );
+    }
+    for dependency in &package.dependencies {
+        let _ = writeln!(out, "  depends_on {}", ruby(dependency));
+    }
+
+    out.push_str("\n  def install\n");
+    brew_install(&mut out, package.build);
+    out.push_str("  end\n\n  test do\n");
+    match package.build.binaries.first() {
+        Some(program) => {
+            let _ = writeln!(out, "    system bin/{}, \"--version\"", ruby(program));
+        }
+        None => out.push_str("    # TODO: build a small program that uses the library.\n"),
+    }
+    out.push_str("  end\nend\n");
+    out
+}
+
+/// `text` as a shell word, quoted when it has to be.
+fn sh(text: &str) -> String {
+    let plain = !text.is_empty()
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=:,+@%".contains(c));
+    if plain {
+        text.to_string()
+    } else {
+        format!("'{}'", text.replace('\'', "'\\''"))
+    }
+}
+
+/// `text` in double quotes, as PKGBUILDs write their fields.
+fn sh_quoted(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        if "\"$`\\".contains(c) {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn sh_args(args: &[String]) -> String {
+    args.iter()
+        .map(|a| format!(" {}", sh(a)))
+        .collect::<String>()
+}
+
+/// The commands of `build()` and `package()`.
+fn pkgbuild_steps(build: &Build) -> (Vec<String>, Vec<String>) {
+    let targets = sh_args(&install_targets(build));
+    let strings = |lines: &[&str]| lines.iter().map(|l| l.to_string()).collect::<Vec<_>>();
+    match build.method.as_str() {
+        "cmake" => (
+            vec![
+                format!(
+                    "cmake -S . -B build -DCMAKE_BUILD_TYPE=Release -DCMAKE_INSTALL_PREFIX=/usr{}",
+                    sh_args(&build.cmake_args)
+                ),
+                "cmake --build build".into(),
+            ],
+            vec![if build.make_targets.is_empty() {
+                "DESTDIR=\"$pkgdir\" cmake --install build".into()
+            } else {
+                format!(
+                    "DESTDIR=\"$pkgdir\" cmake --build build --target{}",
+                    sh_args(&build.make_targets)
+                )
+            }],
+        ),
+        "make" => (
+            strings(&["make PREFIX=/usr"]),
+            vec![format!("make{} PREFIX=/usr DESTDIR=\"$pkgdir\"", targets)],
+        ),
+        "autotools" => {
+            let mut steps = vec![];
+            if build.generate {
+                steps.push("autoreconf -fi".into());
+            }
+            steps.push(format!(
+                "./configure --prefix=/usr{}",
+                sh_args(&build.configure_args)
+            ));
+            steps.push("make".into());
+            (steps, vec![format!("make{} DESTDIR=\"$pkgdir\"", targets)])
+        }
+        "meson" => (
+            strings(&[
+                "meson setup build --prefix=/usr --buildtype=release",
+                "meson compile -C build",
+            ]),
+            strings(&["meson install -C build --destdir \"$pkgdir\""]),
+        ),
+        "scons" => (
+            strings(&["scons PREFIX=/usr"]),
+            strings(&["scons install PREFIX=/usr --install-sandbox=\"$pkgdir\""]),
+        ),
+        "xmake" => (
+            strings(&["xmake -y"]),
+            strings(&["xmake install -y -o \"$pkgdir/usr\""]),
+        ),
+        "premake" => (
+            strings(&["premake5 gmake2", "make config=release"]),
+            strings(&["make install PREFIX=/usr DESTDIR=\"$pkgdir\""]),
+        ),
+        "headers" => (
+            vec![],
+            build
+                .headers
+                .iter()
+                .map(|header| {
+                    let file_name = header.rsplit('/').next().unwrap_or(header);
+                    format!(
+                        "install -Dm644 {} \"$pkgdir/usr/include/{}\"",
+                        sh(header),
+                        file_name
+                    )
+                })
+                .collect(),
+        ),
+        "recipe" => {
+            let recipe = &build.recipe;
+            let mut build_steps = vec!["export PREFIX=/usr JOBS=\"$(nproc)\"".to_string()];
+            build_steps.extend(recipe.configure.iter().chain(&recipe.build).cloned());
+            let mut package_steps =
+                vec!["export PREFIX=/usr DESTDIR=\"$pkgdir\" JOBS=\"$(nproc)\"".to_string()];
+            package_steps.extend(recipe.install.iter().cloned());
+            (build_steps, package_steps)
+        }
+        method => (
+            vec![format!(
+                "# cinstall doesn't know how to build with {}.",
+                method
+            )],
+            vec![],
+        ),
+    }
+}
+
+fn pkgbuild(package: &Package) -> String {
+    let mut out = String::new();
+    let installed = package.installed;
+    let quoted = |items: &[String]| {
+        items
+            .iter()
+            .map(|i| format!("'{}'", i))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+    let archive = source::is_archive(&package.url);
+    let headers_only = package.build.method == "headers";
+
+    let _ = writeln!(out, "pkgname={}", package.name);
+    let _ = writeln!(out, "pkgver={}", package.version);
+    out.push_str("pkgrel=1\n");
+    let _ = writeln!(out, "pkgdesc={}", sh_quoted(&package.description));
+    let arch = if headers_only {
+        "any"
+    } else {
+        std::env::consts::ARCH
+    };
+    let _ = writeln!(out, "arch=('{}')", arch);
+    let _ = writeln!(out, "url={}", sh_quoted(&package.homepage));
+    let _ = writeln!(
+        out,
+        "license=('{}')",
+        package.license.as_deref().unwrap_or("unknown")
+    );
+    if !package.dependencies.is_empty() {
+        out.push_str("# cinstall registry names, check them against Arch's.\n");
+    }
+    let _ = writeln!(out, "depends=({})", quoted(&package.dependencies));
+    let mut make_depends: Vec<String> = vec![];
+    if !archive {
+        make_depends.push("git".into());
+    }
+    make_depends.extend(build_tools(package.build).iter().map(|t| t.to_string()));
+    let _ = writeln!(out, "makedepends=({})", quoted(&make_depends));
+    let dir = if archive {
+        let _ = writeln!(out, "source=({})", sh_quoted(package.url.as_str()));
+        let _ = writeln!(
+            out,
+            "sha256sums=('{}')",
+            package.build.sha256.as_deref().unwrap_or("SKIP")
+        );
+        "$pkgname-$pkgver"
+    } else {
+        let fragment = match (&installed.commit, &installed.git_ref) {
+            (Some(commit), _) => format!("#commit={}", commit),
+            (None, Some(git_ref)) => format!("#tag={}", git_ref),
+            (None, None) => String::new(),
+        };
+        let _ = writeln!(
+            out,
+            "source=(\"$pkgname::git+{}{}\")",
+            package.url, fragment
+        );
+        out.push_str("sha256sums=('SKIP')\n");
+        "$pkgname"
+    };
+
+    let (build_steps, package_steps) = pkgbuild_steps(package.build);
+    let exports: Vec<String> = package
+        .build
+        .env
+        .iter()
+        .map(|(key, value)| format!("export {}={}", key, sh(value)))
+        .collect();
+    for (function, steps) in [("build", build_steps), ("package", package_steps)] {
+        if steps.is_empty() {
+            continue;
+        }
+        let _ = writeln!(out, "\n{}() {{", function);
+        let _ = writeln!(out, "  cd \"{}\"", dir);
+        for line in exports.iter().chain(&steps) {
+            let _ = writeln!(out, "  {}", line);
+        }
+        out.push_str("}\n");
+    }
+    out
+}
+
+/// The formula or PKGBUILD for the installed package `name`.
+pub fn generate(
+    registry: &PackageRegistry,
+    name: &str,
+    format: Format,
+) -> Result<String, InstallError> {
+    let database = MergedDatabase::open();
+    let (_, installed) = database
+        .find(name)
+        .ok_or_else(|| InstallError::UnknownFatal(format!("`{}` is not installed.", name)))?;
+    let package = load(registry, name, installed)?;
+    Ok(match format {
+        Format::Brew => brew(&package),
+        Format::Pkgbuild => pkgbuild(&package),
+    })
+}
@@ -0,0 +1,173 @@
+// Talking to the GitHub API.
+//
+// Requests go through `curl` rather than an HTTP library, the same way git and
+// the build tools are run. `$GITHUB_TOKEN` is sent when it is set, anonymous
+// requests are rate limited hard and can't create anything. Git is handed the
+// token as well, so private repositories can be cloned over HTTPS.
+//
+// What `info --github` shows about a repository is kept in
+// `~/.cache/cinstall/github` for a day, so looking a package up again doesn't
+// spend the rate limit.
+
+use crate::paths;
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use url::Url;
+
+/// cinstall's own repository, where registry proposals go.
+pub const REPOSITORY: &str = "deetonn/cinstall";
+
+pub fn token() -> Option<String> {
+    std::env::var("GITHUB_TOKEN").ok().filter(|t| !t.is_empty())
+}
+
+/// Arguments for git that give it `$GITHUB_TOKEN` for github.com, after any
+/// credential helper the user configured. The token is read by the helper when
+/// git runs it, so it isn't in the arguments or the commands that are printed.
+pub fn git_auth_args() -> Vec<String> {
+    if token().is_none() {
+        return vec![];
+    }
+    vec![
+        "-c".into(),
+        "credential.https://github.com.helper=!f() { test \"$1\" = get && echo username=x-access-token && echo \"password=$GITHUB_TOKEN\"; }; f".into(),
+    ]
+}
+
+/// `https://api.github.com/<path>` with `params` as its query.
+pub fn api_url(path: &str, params: &[(&str, &str)]) -> Url {
+    Url::parse_with_params(&format!("https://api.github.com/{}", path), params)
+        .expect("api paths are valid")
+}
+
+/// Send a request to the API, with `body` as JSON if there is one.
+pub fn request(method: &str, url: &Url, body: Option<&Value>) -> Result<Value, String> {
+    let mut curl = Command::new("curl");
+    curl.args(["-sSfL", "-X", method])
+        .args(["-H", "Accept: application/vnd.github+json"]);
+    if let Some(token) = token() {
+        curl.arg("-H")
+            .arg(format!("Authorization: Bearer {}", token));
+    }
+    if body.is_some() {
+        curl.args([
+            "-H",
+            "Content-Type: application/json",
+            "--data-binary",
+            "@-",
+        ]);
+    }
+    let mut child = curl
+        .arg(url.as_str())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("curl: {}", e))?;
+    if let (Some(body), Some(mut stdin)) = (body, child.stdin.take()) {
+        stdin
+            .write_all(body.to_string().as_bytes())
+            .map_err(|e| format!("curl: {}", e))?;
+    }
+    let output = child
+        .wait_with_output()
+        .map_err(|e| format!("curl: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    serde_json::from_slice(&output.stdout).map_err(|e| e.to_string())
+}
+
+/// `owner/repo` for a GitHub URL.
+pub fn repo_of(url: &Url) -> Option<String> {
+    if url.host_str() != Some("github.com") {
+        return None;
+    }
+    let mut segments = url.path_segments()?.filter(|s| !s.is_empty());
+    let (owner, name) = (segments.next()?, segments.next()?);
+    Some(format!("{}/{}", owner, name.trim_end_matches(".git")))
+}
+
+/// How long what the API said about a repository is trusted.
+const CACHE_FOR_HOURS: i64 = 24;
+
+/// Whether a repository looks maintained, for `info --github`.
+#[derive(Serialize, Deserialize)]
+pub struct RepoInfo {
+    pub fetched_at: DateTime<Utc>,
+    pub stars: u64,
+    pub archived: bool,
+    /// The SPDX identifier GitHub detected.
+    pub license: Option<String>,
+    /// When the default branch was last committed to.
+    pub last_commit: Option<DateTime<Utc>>,
+    /// The tag of the latest release and when it was published.
+    pub latest_release: Option<(String, DateTime<Utc>)>,
+}
+
+fn cache_path(repo: &str) -> PathBuf {
+    paths::cache_dir()
+        .join("github")
+        .join(format!("{}.json", repo.replace('/', "_")))
+}
+
+fn date(value: &Value) -> Option<DateTime<Utc>> {
+    value.as_str()?.parse().ok()
+}
+
+fn fetch_repo_info(repo: &str) -> Result<RepoInfo, String> {
+    let info = request("GET", &api_url(&format!("repos/{}", repo), &[]), None)?;
+    let commits = request(
+        "GET",
+        &api_url(&format!("repos/{}/commits", repo), &[("per_page", "1")]),
+        None,
+    )?;
+    // a repository without releases answers 404.
+    let release = request(
+        "GET",
+        &api_url(&format!("repos/{}/releases/latest", repo), &[]),
+        None,
+    )
+    .ok();
+    Ok(RepoInfo {
+        fetched_at: Utc::now(),
+        stars: info["stargazers_count"].as_u64().unwrap_or(0),
+        archived: info["archived"].as_bool().unwrap_or(false),
+        license: info["license"]["spdx_id"]
+            .as_str()
+            .filter(|id| *id != "NOASSERTION")
+            .map(String::from),
+        last_commit: date(&commits[0]["commit"]["committer"]["date"]),
+        latest_release: release.and_then(|r| {
+            Some((
+                r["tag_name"].as_str()?.to_string(),
+                date(&r["published_at"])?,
+            ))
+        }),
+    })
+}
+
+/// What the API says about `repo`, from the cache when it was asked recently.
+pub fn repo_info(repo: &str) -> Result<RepoInfo, String> {
+    let path = cache_path(repo);
+    let cached = std::fs::read_to_string(&path)
+        .ok()
+        .and_then(|contents| serde_json::from_str::<RepoInfo>(&contents).ok())
+        .filter(|info| Utc::now() - info.fetched_at < Duration::hours(CACHE_FOR_HOURS));
+    if let Some(info) = cached {
+        return Ok(info);
+    }
+    let info = fetch_repo_info(repo)?;
+    // failing to cache only costs another request next time.
+    if let Some(dir) = path.parent() {
+        let _ = std::fs::create_dir_all(dir);
+    }
+    if let Ok(json) = serde_json::to_string_pretty(&info) {
+        let _ = std::fs::write(&path, json);
+    }
+    Ok(info)
+}
@@ -0,0 +1,205 @@
+// Keeping build output readable.
+//
+// Big projects print a line for every file they compile, which buries the
+// warnings and errors that matter. A step's output goes through a governor:
+// unless `--verbose` is passed, lines that are just progress or compiler
+// invocations are counted instead of printed (on a terminal the count is kept
+// on one line that is rewritten in place), while everything else, which is
+// where warnings and errors are, is printed as is. Either way every line is
+// kept next to the run's log and handed to `diagnostics`.
+
+use crate::{diagnostics, run, ui};
+use crate::{output, outputln};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, IsTerminal, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+/// Show every line of build output.
+pub fn set_verbose() {
+    VERBOSE.store(true, Ordering::Relaxed);
+}
+
+/// Where the full output of run `id` is kept.
+pub fn output_path(id: &str) -> PathBuf {
+    run::logs_dir().join(format!("{}.out", id))
+}
+
+const COMPILERS: &[&str] = &[
+    "cc", "gcc", "g++", "c++", "clang", "clang++", "ar", "ranlib", "libtool", "ld",
+];
+
+const PROGRESS_WORDS: &[&str] = &[
+    "Building ",
+    "Linking ",
+    "Compiling ",
+    "Scanning dependencies",
+    "CC ",
+    "CXX ",
+    "CCLD ",
+    "CXXLD ",
+    "LD ",
+    "AR ",
+];
+
+/// `[ 42%]` from cmake's makefiles, or `[12/400]` from ninja, meson and xmake.
+fn is_progress_counter(line: &str) -> bool {
+    let inside = match line.strip_prefix('[').and_then(|l| l.split_once(']')) {
+        Some((inside, _)) => inside.trim(),
+        None => return false,
+    };
+    let counter = inside.strip_suffix('%').unwrap_or(inside);
+    !counter.is_empty()
+        && counter
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '/' || c == ' ')
+}
+
+/// Whether `line` is one of the many that only say what is being built.
+fn is_noise(line: &str) -> bool {
+    let line = line.trim_start();
+    if is_progress_counter(line) || PROGRESS_WORDS.iter().any(|w| line.starts_with(w)) {
+        return true;
+    }
+    if line.starts_with("make")
+        && (line.contains("Entering directory") || line.contains("Leaving directory"))
+    {
+        return true;
+    }
+    let program = line.split_whitespace().next().unwrap_or_default();
+    let program = program.rsplit('/').next().unwrap_or_default();
+    COMPILERS.contains(&program)
+        || program.ends_with("-gcc")
+        || program.ends_with("-g++")
+        || program.ends_with("-clang")
+}
+
+struct State {
+    hidden: usize,
+    /// whether the in-place counter is on screen and has to be cleared first.
+    counter_shown: bool,
+    last_drawn: Option<Instant>,
+    in_place: bool,
+    out: Option<File>,
+    /// The last lines, for telling why a step failed.
+    tail: VecDeque<String>,
+}
+
+const TAIL_LINES: usize = 20;
+
+impl State {
+    fn line(&mut self, line: &str) {
+        if let Some(out) = &mut self.out {
+            let _ = writeln!(out, "{}", line);
+        }
+        diagnostics::observe(line);
+        if self.tail.len() == TAIL_LINES {
+            self.tail.pop_front();
+        }
+        self.tail.push_back(line.to_string());
+        if VERBOSE.load(Ordering::Relaxed) || !is_noise(line) {
+            self.clear_counter();
+            eprintln!("{}", line);
+            return;
+        }
+
+        self.hidden += 1;
+        if self.in_place {
+            // redrawing on every line would be slower than the build itself.
+            if self
+                .last_drawn
+                .is_some_and(|t| t.elapsed() < Duration::from_millis(100))
+            {
+                return;
+            }
+            let width = terminal_size::terminal_size().map_or(80, |(w, _)| w.0 as usize);
+            let status = format!("{} build lines hidden, at: {}", self.hidden, line.trim());
+            let status: String = status.chars().take(width.saturating_sub(30)).collect();
+            output!("\x1b[K{}\r", status);
+            self.counter_shown = true;
+            self.last_drawn = Some(Instant::now());
+        } else if self.hidden.is_multiple_of(500) {
+            outputln!(
+                "{} build lines hidden so far, at: {}",
+                (self.hidden),
+                (line.trim())
+            );
+        }
+    }
+
+    fn clear_counter(&mut self) {
+        if self.counter_shown {
+            eprint!("\x1b[K");
+            self.counter_shown = false;
+        }
+    }
+}
+
+/// The output of a running step, being read by a thread per stream.
+pub struct Governor {
+    state: Arc<Mutex<State>>,
+    readers: Vec<JoinHandle<()>>,
+}
+
+impl Governor {
+    pub fn start() -> Self {
+        let out = run::id().and_then(|id| {
+            let _ = std::fs::create_dir_all(run::logs_dir());
+            std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(output_path(&id))
+                .ok()
+        });
+        Self {
+            state: Arc::new(Mutex::new(State {
+                hidden: 0,
+                counter_shown: false,
+                last_drawn: None,
+                in_place: std::io::stderr().is_terminal() && !ui::a11y() && !ui::porcelain(),
+                out,
+                tail: VecDeque::new(),
+            })),
+            readers: vec![],
+        }
+    }
+
+    /// Read `stream` line by line until it closes.
+    pub fn watch(&mut self, stream: impl Read + Send + 'static) {
+        let state = Arc::clone(&self.state);
+        self.readers.push(std::thread::spawn(move || {
+            for line in BufReader::new(stream).split(b'\n') {
+                let Ok(line) = line else { break };
+                let line = String::from_utf8_lossy(&line);
+                state.lock().unwrap().line(line.trim_end_matches('\r'));
+            }
+        }));
+    }
+
+    /// Wait for the streams to close and say how much was hidden, returning
+    /// the last lines of output.
+    pub fn finish(self) -> Vec<String> {
+        for reader in self.readers {
+            let _ = reader.join();
+        }
+        let mut state = self.state.lock().unwrap();
+        state.clear_counter();
+        if state.hidden > 0 {
+            let full = run::id().map(|id| output_path(&id).display().to_string());
+            outputln!(
+                "{} build lines were hidden (pass --verbose to see them){}.",
+                (state.hidden),
+                (full
+                    .map(|f| format!(", the full output is in {}", f))
+                    .unwrap_or_default())
+            );
+        }
+        state.tail.drain(..).collect()
+    }
+}
@@ -0,0 +1,257 @@
+// An append-only log of every operation that changed what is installed.
+//
+// Each line of `~/.local/share/cinstall/history.jsonl` is one transaction.
+// Nothing is ever rewritten; undoing a transaction appends a new one that
+// points back at the transaction it reverted.
+
+use crate::database::InstalledPackage;
+use crate::installer::InstallError;
+use crate::outputln;
+use crate::pager;
+use crate::paths;
+use crate::run;
+use crate::ui::{self, Tone};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write as _;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum Operation {
+    Install,
+    Upgrade,
+    Uninstall,
+    Rollback,
+    Relocate,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Install => write!(f, "install"),
+            Operation::Upgrade => write!(f, "upgrade"),
+            Operation::Uninstall => write!(f, "uninstall"),
+            Operation::Rollback => write!(f, "rollback"),
+            Operation::Relocate => write!(f, "relocate"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Outcome {
+    Success,
+    Failure(String),
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Transaction {
+    pub id: u64,
+    pub timestamp: DateTime<Utc>,
+    pub user: String,
+    pub command_line: String,
+    pub operation: Operation,
+    pub package: String,
+    pub url: Option<String>,
+    // the prefix the package was installed into or removed from.
+    #[serde(default)]
+    pub prefix: Option<PathBuf>,
+    // the ref that was asked for and the commit that was built, so undoing an
+    // uninstall puts back the same code.
+    #[serde(default)]
+    pub git_ref: Option<String>,
+    #[serde(default)]
+    pub commit: Option<String>,
+    pub outcome: Outcome,
+    // the transaction this one undid, if it was created by `cinstall undo`.
+    #[serde(default)]
+    pub reverts: Option<u64>,
+    // the install run this happened in, see `run.rs`.
+    #[serde(default)]
+    pub run: Option<String>,
+}
+
+impl Transaction {
+    pub fn succeeded(&self) -> bool {
+        matches!(self.outcome, Outcome::Success)
+    }
+
+    pub fn origin(&self) -> Origin {
+        Origin {
+            url: self.url.clone(),
+            prefix: self.prefix.clone(),
+            git_ref: self.git_ref.clone(),
+            commit: self.commit.clone(),
+        }
+    }
+}
+
+/// Where the package of a transaction came from and where it went, as much as
+/// is known.
+#[derive(Default)]
+pub struct Origin {
+    pub url: Option<String>,
+    pub prefix: Option<PathBuf>,
+    pub git_ref: Option<String>,
+    pub commit: Option<String>,
+}
+
+impl Origin {
+    pub fn of(installed: &InstalledPackage) -> Self {
+        Origin {
+            url: Some(installed.url.clone()),
+            prefix: Some(installed.prefix.clone()),
+            git_ref: installed.git_ref.clone(),
+            commit: installed.commit.clone(),
+        }
+    }
+}
+
+pub fn history_path() -> PathBuf {
+    let mut path = paths::data_dir();
+    path.push("history.jsonl");
+    path
+}
+
+/// Read every transaction, oldest first. Lines that fail to parse are skipped.
+pub fn load() -> Vec<Transaction> {
+    let contents = match std::fs::read_to_string(history_path()) {
+        Ok(c) => c,
+        Err(_) => return vec![],
+    };
+
+    contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .collect()
+}
+
+fn current_user() -> String {
+    let user = std::env::var("USER")
+        .or_else(|_| std::env::var("LOGNAME"))
+        .unwrap_or_else(|_| "unknown".into());
+    match std::env::var("SUDO_USER") {
+        Ok(sudo_user) if sudo_user != user => format!("{} (sudo from {})", user, sudo_user),
+        _ => user,
+    }
+}
+
+fn append(transaction: &Transaction) -> Result<(), InstallError> {
+    let path = history_path();
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", parent.display(), e)))?;
+    }
+
+    let line = serde_json::to_string(transaction)
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+    writeln!(file, "{}", line).map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+/// Append a transaction describing `result`. Failing to write the history
+/// never fails the operation itself, it is only reported.
+pub fn record<T>(
+    operation: Operation,
+    package: &str,
+    origin: Origin,
+    reverts: Option<u64>,
+    result: &Result<T, InstallError>,
+) {
+    let id = load().last().map(|t| t.id + 1).unwrap_or(1);
+    let transaction = Transaction {
+        id,
+        timestamp: Utc::now(),
+        user: current_user(),
+        command_line: std::env::args().collect::<Vec<_>>().join(" "),
+        operation,
+        package: package.to_string(),
+        url: origin.url,
+        prefix: origin.prefix,
+        git_ref: origin.git_ref,
+        commit: origin.commit,
+        outcome: match result {
+            Ok(_) => Outcome::Success,
+            Err(e) => Outcome::Failure(e.to_string()),
+        },
+        reverts,
+        run: run::id(),
+    };
+
+    if let Err(e) = append(&transaction) {
+        outputln!(red, "warning: failed to record history. {}", e);
+    }
+}
+
+/// The most recent transaction that `cinstall undo` is able to revert.
+/// Failed transactions, undos themselves and anything already reverted are skipped.
+pub fn last_reversible(history: &[Transaction]) -> Option<&Transaction> {
+    history.iter().rev().find(|t| {
+        t.succeeded()
+            && t.reverts.is_none()
+            && matches!(t.operation, Operation::Install | Operation::Uninstall)
+            && !history
+                .iter()
+                .any(|other| other.succeeded() && other.reverts == Some(t.id))
+    })
+}
+
+pub struct HistoryFilter {
+    pub package: Option<String>,
+    pub operation: Option<Operation>,
+    pub failed: bool,
+    pub limit: Option<usize>,
+}
+
+pub fn print_history(filter: &HistoryFilter) {
+    let history = load();
+    let matching: Vec<&Transaction> = history
+        .iter()
+        .filter(|t| filter.package.as_ref().is_none_or(|p| &t.package == p))
+        .filter(|t| filter.operation.is_none_or(|op| t.operation == op))
+        .filter(|t| !filter.failed || !t.succeeded())
+        .collect();
+
+    if matching.is_empty() {
+        outputln!(purple, "no matching transactions.");
+        return;
+    }
+
+    let skip = filter
+        .limit
+        .map_or(0, |limit| matching.len().saturating_sub(limit));
+
+    let mut out = String::new();
+    for t in matching.iter().skip(skip) {
+        let outcome = match &t.outcome {
+            Outcome::Success => ui::paint("ok", Tone::Success),
+            Outcome::Failure(_) => ui::paint("failed", Tone::Error),
+        };
+        let _ = writeln!(
+            out,
+            "#{} {} {} {} {} [{}] `{}`",
+            t.id,
+            t.timestamp.format("%Y-%m-%d %H:%M:%S"),
+            ui::paint(&t.operation.to_string(), Tone::Emphasis),
+            t.package,
+            outcome,
+            t.user,
+            t.command_line
+        );
+        if let Outcome::Failure(reason) = &t.outcome {
+            let _ = writeln!(out, "    reason: {}", reason);
+        }
+        if let Some(run) = &t.run {
+            let _ = writeln!(out, "    run {}", run);
+        }
+        if let Some(reverted) = t.reverts {
+            let _ = writeln!(out, "    (undo of #{})", reverted);
+        }
+    }
+    pager::page(&out);
+}
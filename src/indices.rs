@@ -0,0 +1,265 @@
+// Read-through lookups in vcpkg's and Conan Center's indices.
+//
+// A name that isn't in the registry may still be one of the thousands of
+// packages vcpkg and Conan Center curate. Their indices say where the source
+// comes from, so for such a name cinstall reads vcpkg's port (the repository
+// and ref of `vcpkg_from_github` and friends, and the `-D` options it
+// configures with), or failing that the newest source Conan Center's recipe
+// lists (an archive and its checksum), and offers to install from there.
+// Neither tool is needed, only their index files are read.
+//
+// The indices' own patches and build scripts aren't used, cinstall builds the
+// source the way it builds anything else. `package_indices = false` in the
+// config turns the lookup off.
+
+use crate::installer::InstallOptions;
+use crate::registry::PackageRegistry;
+use crate::source::Source;
+use crate::{output, outputln, ui};
+use std::process::{Command, Stdio};
+use url::Url;
+
+const VCPKG_PORTS: &str = "https://raw.githubusercontent.com/microsoft/vcpkg/master/ports";
+const CONAN_RECIPES: &str =
+    "https://raw.githubusercontent.com/conan-io/conan-center-index/master/recipes";
+
+/// Where an index says a package's source is.
+pub struct Found {
+    /// `vcpkg` or `Conan Center`.
+    pub index: &'static str,
+    pub url: Url,
+    pub git_ref: Option<String>,
+    pub sha256: Option<String>,
+    pub cmake_args: Vec<String>,
+    /// Whether the index patches the source before building it.
+    pub patched: bool,
+}
+
+/// The contents of `url`, if there is anything there.
+fn fetch(url: &str) -> Option<String> {
+    let output = Command::new("curl")
+        .args(["-fsSL", "--max-time", "10", url])
+        .stderr(Stdio::null())
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+/// The arguments of the first `function(...)` call in a CMake script, split
+/// into words, with quotes and comments taken out.
+fn cmake_call(script: &str, function: &str) -> Option<Vec<String>> {
+    let start = script.find(&format!("{}(", function))? + function.len() + 1;
+    let mut depth = 1;
+    let mut end = start;
+    for (i, c) in script[start..].char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            end = start + i;
+            break;
+        }
+    }
+    Some(
+        script[start..end]
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or_default())
+            .flat_map(str::split_whitespace)
+            .map(|word| word.trim_matches('"').to_string())
+            .collect(),
+    )
+}
+
+/// The word after `keyword` in `words`.
+fn after<'a>(words: &'a [String], keyword: &str) -> Option<&'a str> {
+    let i = words.iter().position(|w| w == keyword)?;
+    words.get(i + 1).map(String::as_str)
+}
+
+/// The `-D` options given to `vcpkg_cmake_configure`, leaving out the ones
+/// only for debug builds and the ones that depend on vcpkg's variables.
+fn vcpkg_options(portfile: &str) -> Vec<String> {
+    let Some(words) = cmake_call(portfile, "vcpkg_cmake_configure") else {
+        return vec![];
+    };
+    let mut options = vec![];
+    let mut debug = false;
+    for word in words {
+        if word.chars().all(|c| c.is_ascii_uppercase() || c == '_') {
+            debug = word == "OPTIONS_DEBUG";
+            continue;
+        }
+        if !debug && word.starts_with("-D") && !word.contains("${") {
+            options.push(word);
+        }
+    }
+    options
+}
+
+/// The version in a port's `vcpkg.json`.
+fn vcpkg_version(manifest: &str) -> Option<String> {
+    let manifest: serde_json::Value = serde_json::from_str(manifest).ok()?;
+    [
+        "version",
+        "version-semver",
+        "version-string",
+        "version-date",
+    ]
+    .iter()
+    .find_map(|key| manifest[key].as_str())
+    .map(String::from)
+}
+
+fn vcpkg(name: &str) -> Option<Found> {
+    let portfile = fetch(&format!("{}/{}/portfile.cmake", VCPKG_PORTS, name))?;
+    let version = fetch(&format!("{}/{}/vcpkg.json", VCPKG_PORTS, name))
+        .and_then(|manifest| vcpkg_version(&manifest))
+        .unwrap_or_default();
+    let (url, words) = if let Some(words) = cmake_call(&portfile, "vcpkg_from_github") {
+        let url = format!("https://github.com/{}", after(&words, "REPO")?);
+        (url, words)
+    } else if let Some(words) = cmake_call(&portfile, "vcpkg_from_gitlab") {
+        let host = after(&words, "GITLAB_URL").unwrap_or("https://gitlab.com");
+        let url = format!("{}/{}", host.trim_end_matches('/'), after(&words, "REPO")?);
+        (url, words)
+    } else {
+        let words = cmake_call(&portfile, "vcpkg_from_git")?;
+        (after(&words, "URL")?.to_string(), words)
+    };
+    let git_ref = after(&words, "REF").map(|r| r.replace("${VERSION}", &version));
+    Some(Found {
+        index: "vcpkg",
+        url: Url::parse(&url).ok()?,
+        // a ref still naming a variable can't be worked out, the default branch will do.
+        git_ref: git_ref.filter(|r| !r.contains("${")),
+        sha256: None,
+        cmake_args: vcpkg_options(&portfile),
+        patched: words.iter().any(|w| w == "PATCHES"),
+    })
+}
+
+/// The value of a `key: value` line of YAML, without quotes.
+fn yaml_value<'a>(line: &'a str, key: &str) -> Option<&'a str> {
+    let value = line.trim().strip_prefix(key)?.strip_prefix(':')?.trim();
+    Some(value.trim_matches('"').trim_matches('\''))
+}
+
+/// The newest version a recipe's `config.yml` lists, and its folder.
+fn conan_version(config: &str) -> Option<(String, String)> {
+    let mut lines = config
+        .lines()
+        .skip_while(|l| !l.starts_with("versions:"))
+        .skip(1);
+    let version = lines.next()?.trim().trim_end_matches(':').trim_matches('"');
+    let folder = lines.find_map(|l| yaml_value(l, "folder"))?;
+    Some((version.to_string(), folder.to_string()))
+}
+
+/// The source `conandata.yml` lists for `version`: the first URL and its checksum.
+fn conan_source(data: &str, version: &str) -> Option<(String, Option<String>)> {
+    let lines = data
+        .lines()
+        .skip_while(|l| !l.starts_with("sources:"))
+        .skip(1)
+        .skip_while(|l| l.trim().trim_end_matches(':').trim_matches('"') != version)
+        .skip(1)
+        .take_while(|l| l.starts_with("    ") || l.trim().is_empty());
+    let mut url = None;
+    let mut sha256 = None;
+    for line in lines {
+        if let Some(value) = yaml_value(line, "url") {
+            if !value.is_empty() && url.is_none() {
+                url = Some(value.to_string());
+            }
+        } else if let Some(value) = line.trim().strip_prefix("- ") {
+            if url.is_none() {
+                url = Some(value.trim_matches('"').to_string());
+            }
+        } else if let Some(value) = yaml_value(line, "sha256") {
+            sha256 = Some(value.to_string());
+        }
+    }
+    Some((url?, sha256))
+}
+
+fn conan(name: &str) -> Option<Found> {
+    let config = fetch(&format!("{}/{}/config.yml", CONAN_RECIPES, name))?;
+    let (version, folder) = conan_version(&config)?;
+    let data = fetch(&format!(
+        "{}/{}/{}/conandata.yml",
+        CONAN_RECIPES, name, folder
+    ))?;
+    let (url, sha256) = conan_source(&data, &version)?;
+    Some(Found {
+        index: "Conan Center",
+        url: Url::parse(&url).ok()?,
+        git_ref: None,
+        sha256,
+        cmake_args: vec![],
+        patched: data.contains("patches:"),
+    })
+}
+
+/// Look `name` up in vcpkg's index, then in Conan Center's.
+pub fn lookup(name: &str) -> Option<Found> {
+    outputln!(
+        "`{}` isn't in the registry, looking in vcpkg's and Conan Center's indices.",
+        name
+    );
+    vcpkg(name).or_else(|| conan(name))
+}
+
+/// Where to install `target` from, when it is a name the registry doesn't
+/// know but one of the indices does and you agree to build it from there.
+pub fn resolve(
+    registry: &PackageRegistry,
+    target: &str,
+    options: &InstallOptions,
+) -> Option<Source> {
+    let bare = !target.contains(['/', ':', '@', '#']) && target != "." && target != "..";
+    if !options.package_indices || !bare || registry.get(target).is_some() {
+        return None;
+    }
+    let found = lookup(target)?;
+    let shown_ref = found
+        .git_ref
+        .as_ref()
+        .map_or(String::new(), |r| format!(" at `{}`", r));
+    outputln!(
+        "{} builds `{}` from {}{}.",
+        (found.index),
+        target,
+        (found.url),
+        shown_ref
+    );
+    if !found.cmake_args.is_empty() {
+        outputln!("it configures it with {}", (found.cmake_args.join(" ")));
+    }
+    if found.patched {
+        outputln!(
+            purple,
+            "{} patches the source first, cinstall builds it without those patches.",
+            (found.index)
+        );
+    }
+    if options.confirm {
+        if !ui::interactive() {
+            outputln!(purple, "pass --yes to install it from there.");
+            return None;
+        }
+        output!(on_blue, "install it from there? [Y/n] ");
+        let input: String = text_io::read!("{}\n");
+        if input.to_lowercase().starts_with('n') {
+            return None;
+        }
+    }
+    let mut source = Source::plain(target.to_string(), found.url, found.git_ref);
+    source.cmake_args = found.cmake_args;
+    source.sha256 = found.sha256;
+    Some(source)
+}
@@ -1,12 +1,39 @@
+use crate::binaries;
+use crate::bundle;
+use crate::cancel::{self, CancellationToken};
+use crate::choices;
+use crate::cmake_cache;
+use crate::database::Build;
+use crate::doctor;
+use crate::facts;
+use crate::fallback;
+use crate::governor::Governor;
+use crate::mirrors;
+use crate::multiarch;
+use crate::nested;
+use crate::package_lock;
+use crate::patches;
+use crate::prebuilt;
+use crate::run;
+use crate::signature;
+use crate::source;
+use crate::staging;
+use crate::store;
+use crate::tools;
+use crate::ui;
+use crate::versions;
+use crate::workspace;
 use crate::{output, outputln};
-use colored::Colorize;
-use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
 use std::io::Write;
+use std::os::unix::process::CommandExt;
 use std::path::PathBuf;
 use std::{
     io::Error,
     path::Path,
-    process::{Command, ExitStatus},
+    process::{Child, Command, ExitStatus, Stdio},
+    time::{Duration, Instant},
 };
 use url::Url;
 
@@ -19,16 +46,358 @@ pub enum InstallError {
     CMakeFailed,
     FailedToCreateDirectory,
     FailedToMakeInstall,
+    StepFailed(String),
     FailedToChangeDirectory,
     BadDirectory(String),
     FailedToWriteToFile,
+    DatabaseError(String),
+    BadSignature(String),
+    /// The remote needs credentials that couldn't be asked for.
+    AuthRequired(String),
+    /// Another process is using the system package manager.
+    PackageManagerBusy(String),
+    /// There is no repository at the URL.
+    RepositoryNotFound(String),
+    /// The host of the URL couldn't be reached.
+    NetworkError(String),
+    /// The run was cancelled, through its token, `cinstall cancel` or Ctrl-C.
+    Cancelled,
     UnknownFatal(String),
 }
 
-impl ToString for InstallError {
-    fn to_string(&self) -> String {
-        type E = InstallError;
+impl InstallError {
+    /// Whether the error is about getting the source, so a mirror might do
+    /// better.
+    pub fn is_clone_failure(&self) -> bool {
+        matches!(
+            self,
+            InstallError::FailedToClone
+                | InstallError::AuthRequired(_)
+                | InstallError::RepositoryNotFound(_)
+                | InstallError::NetworkError(_)
+        )
+    }
+
+    /// The exit code for the error, so scripts can tell why a clone failed.
+    pub fn exit_code(&self) -> i32 {
         match self {
+            InstallError::RepositoryNotFound(_) => 3,
+            InstallError::AuthRequired(_) => 4,
+            InstallError::NetworkError(_) => 5,
+            InstallError::Cancelled => 6,
+            _ => -1,
+        }
+    }
+}
+
+/// Where packages are installed to when no prefix is given.
+pub const DEFAULT_PREFIX: &str = "/usr/local";
+
+/// Everything about an install the user can choose.
+#[derive(Clone)]
+pub struct InstallOptions {
+    pub prefix: PathBuf,
+    /// Only print what would be done.
+    pub dry_run: bool,
+    /// The tag, branch or commit to install, the default branch if not set.
+    pub git_ref: Option<String>,
+    /// Clone the whole history instead of only the commit being built.
+    pub full_history: bool,
+    /// Where to write a JSON report of the install.
+    pub report: Option<PathBuf>,
+    /// Look for open upstream build issues first.
+    pub check_upstream: bool,
+    /// Hosts other than GitHub and GitLab to accept URLs from.
+    pub host_ok: Vec<String>,
+    /// Accept URLs from any host at all.
+    pub allow_any_host: bool,
+    /// Extra arguments for cmake's configure step.
+    pub cmake_args: Vec<String>,
+    /// Carry on when the package's requirements aren't met.
+    pub ignore_requirements: bool,
+    /// The programs the package provides, which have to end up in `<prefix>/bin`.
+    pub binaries: Vec<String>,
+    /// Link the package's programs into `~/.local/bin`.
+    pub symlink_bin: bool,
+    /// Build for every architecture the machine can run (see `multiarch`).
+    pub multiarch: bool,
+    /// Install a prebuilt release without asking, if there is one.
+    pub prebuilt: bool,
+    /// Never look for prebuilt releases.
+    pub from_source: bool,
+    /// Clone over SSH instead of HTTPS (see `source::Protocol`).
+    pub ssh: bool,
+    /// Where else the project can be cloned from, tried in order when cloning it fails.
+    pub mirrors: Vec<Url>,
+    /// The checksum the downloaded archive has to have.
+    pub sha256: Option<String>,
+    /// Check upstream's signature before building (see `signature`).
+    pub verify_signature: bool,
+    /// The fingerprints of the keys the signature can be made with.
+    pub signing_keys: Vec<String>,
+    /// Show what was found out about the project and ask before building it.
+    pub confirm: bool,
+    /// What to tell the user once the package is installed.
+    pub notes: Option<String>,
+    /// The make targets to install with instead of `install`.
+    pub make_targets: Vec<String>,
+    /// Environment variables every build step runs with.
+    pub env: Vec<(String, String)>,
+    /// Commands to build with instead of the build system's.
+    pub recipe: Recipe,
+    /// Patches to apply to the source before building it (see `patches`).
+    pub patches: Vec<String>,
+    /// Bundle the built programs in the current directory instead of installing them.
+    pub bundle: Option<bundle::Format>,
+    /// Compare the CMake cache with the package's last build (see `cmake_cache`).
+    pub cmake_cache_diff: bool,
+    /// Don't install the registry packages the package depends on.
+    pub no_deps: bool,
+    /// The registry variants to build (see `registry::Variant`).
+    pub variants: Vec<String>,
+    /// Extra arguments for autotools' `./configure`.
+    pub configure_args: Vec<String>,
+    /// Add the package to CMake's user package registry (see `cmake_registry`).
+    pub cmake_register: bool,
+    /// Look names the registry doesn't know up in vcpkg's and Conan Center's
+    /// indices (see `indices`).
+    pub package_indices: bool,
+    /// Install files staged outside the prefix without asking (see
+    /// `staging::hold_back_system_paths`).
+    pub allow_system_paths: bool,
+    /// Installing a newer version of an installed package, which is recorded
+    /// in the history as an upgrade.
+    pub upgrade: bool,
+    /// Going back to what was installed before an upgrade, which is recorded
+    /// in the history as a rollback (see `rollback`).
+    pub rollback: bool,
+    /// Build even if the same commit is installed with the same options already.
+    pub force: bool,
+    /// Install into `<dir>/<package>/<version>` and link its files into the
+    /// prefix (see `versions`).
+    pub versions_dir: Option<PathBuf>,
+}
+
+/// Shell commands a registry entry builds its package with, for projects whose
+/// build system doesn't install them properly. When any are given they replace
+/// the detected build system's steps altogether. They run in the checkout with
+/// `sh -c`, with `PREFIX`, `DESTDIR` and `JOBS` set, and have to install into
+/// `$DESTDIR$PREFIX` so the files are tracked.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Recipe {
+    pub configure: Vec<String>,
+    pub build: Vec<String>,
+    pub install: Vec<String>,
+}
+
+impl Recipe {
+    pub fn is_empty(&self) -> bool {
+        self.configure.is_empty() && self.build.is_empty() && self.install.is_empty()
+    }
+}
+
+impl Default for InstallOptions {
+    fn default() -> Self {
+        Self {
+            prefix: PathBuf::from(DEFAULT_PREFIX),
+            dry_run: false,
+            git_ref: None,
+            full_history: false,
+            report: None,
+            check_upstream: false,
+            host_ok: vec![],
+            allow_any_host: false,
+            cmake_args: vec![],
+            ignore_requirements: false,
+            binaries: vec![],
+            symlink_bin: false,
+            multiarch: false,
+            prebuilt: false,
+            from_source: false,
+            ssh: false,
+            mirrors: vec![],
+            sha256: None,
+            verify_signature: false,
+            signing_keys: vec![],
+            confirm: false,
+            notes: None,
+            make_targets: vec![],
+            env: vec![],
+            recipe: Recipe::default(),
+            patches: vec![],
+            bundle: None,
+            cmake_cache_diff: false,
+            no_deps: false,
+            variants: vec![],
+            configure_args: vec![],
+            cmake_register: false,
+            package_indices: false,
+            allow_system_paths: false,
+            upgrade: false,
+            rollback: false,
+            versions_dir: None,
+            force: false,
+        }
+    }
+}
+
+impl InstallOptions {
+    /// `PREFIX=...`, understood by most handwritten makefiles.
+    fn make_prefix_arg(&self) -> String {
+        format!("PREFIX={}", self.prefix.display())
+    }
+
+    /// `DESTDIR=...`, so that `make install` writes into the staging directory.
+    fn make_destdir_arg(staging: &Path) -> String {
+        format!("DESTDIR={}", staging.display())
+    }
+}
+
+/// A command the installer runs in a directory. Every build step goes through
+/// this so that `--dry-run` can print exactly what would be executed.
+pub struct Step {
+    pub program: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub dir: PathBuf,
+    /// Whether the step runs in a process group of its own, see `cancel`.
+    pub detached: bool,
+}
+
+impl Step {
+    pub fn new(program: &str, dir: &Path) -> Self {
+        Self {
+            program: program.to_string(),
+            args: vec![],
+            env: vec![],
+            dir: dir.to_path_buf(),
+            detached: true,
+        }
+    }
+
+    /// Keep the step in cinstall's process group, so it can ask on the
+    /// terminal (git and ssh asking for credentials). Outside of the
+    /// foreground group it would be stopped the moment it read from it.
+    pub fn attached(mut self) -> Self {
+        self.detached = false;
+        self
+    }
+
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args(mut self, args: &[String]) -> Self {
+        self.args.extend(args.iter().cloned());
+        self
+    }
+
+    pub fn env(mut self, key: &str, value: &str) -> Self {
+        self.env.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    /// Set the variables in `env` the step doesn't set itself.
+    pub fn envs(mut self, env: &[(String, String)]) -> Self {
+        for (key, value) in env {
+            if !self.env.iter().any(|(k, _)| k == key) {
+                self.env.push((key.clone(), value.clone()));
+            }
+        }
+        self
+    }
+
+    pub fn run(&self) -> Result<ExitStatus, InstallError> {
+        self.run_with_output().map(|(status, _)| status)
+    }
+
+    /// Run the step, returning the last lines it printed as well.
+    pub fn run_with_output(&self) -> Result<(ExitStatus, Vec<String>), InstallError> {
+        cancel::check()?;
+        let mut command = Command::new(&self.program);
+        command
+            .args(&self.args)
+            .envs(self.env.iter().map(|(k, v)| (k, v)))
+            .current_dir(&self.dir);
+        if self.detached {
+            command.process_group(0);
+        }
+        let status = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .and_then(|mut child| {
+                let mut governor = Governor::start();
+                governor.watch(child.stdout.take().expect("stdout is piped"));
+                governor.watch(child.stderr.take().expect("stderr is piped"));
+                let status = self.wait(child);
+                let tail = governor.finish();
+                status.map(|s| (s, tail))
+            });
+        run::event(
+            "step",
+            json!({
+                "command": self.to_string(),
+                "exit_code": status.as_ref().ok().and_then(|(s, _)| s.code()),
+                "error": status.as_ref().err().map(|e| e.to_string()),
+            }),
+        );
+        cancel::check()?;
+        status.map_err(|e| InstallError::CouldNotStartProcess(format!("{}: {}", self.program, e)))
+    }
+
+    /// Wait for the step to finish, stopping it if the run is cancelled. In
+    /// accessibility mode there is a status line every half a minute, so a step
+    /// that prints nothing isn't taken for a hang.
+    fn wait(&self, mut child: Child) -> std::io::Result<ExitStatus> {
+        const STATUS_EVERY: Duration = Duration::from_secs(30);
+        let _interruptible = cancel::Interruptible::start();
+        let started = Instant::now();
+        let mut next = STATUS_EVERY;
+        loop {
+            if let Some(status) = child.try_wait()? {
+                return Ok(status);
+            }
+            if cancel::is_cancelled() {
+                outputln!(purple, "cancelled, stopping `{}`.", (self.program));
+                return cancel::stop(child, self.detached);
+            }
+            if ui::a11y() && started.elapsed() >= next {
+                outputln!(
+                    "still running `{}`, {} seconds so far.",
+                    (self.program),
+                    (next.as_secs())
+                );
+                next += STATUS_EVERY;
+            }
+            std::thread::sleep(Duration::from_millis(200));
+        }
+    }
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "(cd {} && ", self.dir.display())?;
+        for (key, value) in &self.env {
+            write!(f, "{}={} ", key, value)?;
+        }
+        write!(f, "{}", self.program)?;
+        for arg in &self.args {
+            if arg.is_empty() || arg.contains(char::is_whitespace) {
+                write!(f, " '{}'", arg)?;
+            } else {
+                write!(f, " {}", arg)?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::fmt::Display for InstallError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        type E = InstallError;
+        let message: String = match self {
             E::DeniedInstall => "user denied the install of required dependencies.".into(),
             E::UnknownPackageManager => "this system uses an unknown package manager, please install git, cmake and make manually.".into(),
             E::InstallError => "failed to execute a critical operation. (this usually means we failed to start a subcommand like git or cmake)".into(),
@@ -38,10 +407,19 @@ impl ToString for InstallError {
             E::FailedToCreateDirectory => "failed to create temporary directory to build the project from.".into(),
             E::BadDirectory(path) => format!("we were supplied a bad directory: `{}`", path),
             E::FailedToMakeInstall => "`make install` failed.".into(),
+            E::StepFailed(step) => format!("`{}` failed.", step),
             E::FailedToChangeDirectory => "failed to set the environment directory. (this is a bizzare error)".into(),
             E::FailedToWriteToFile => "failed to write to a file when installing the package.".into(),
+            E::DatabaseError(message) => format!("failed to access the install database: {}", message),
+            E::BadSignature(message) => format!("the signature check failed, {}", message),
+            E::AuthRequired(remote) => format!("`{}` needs credentials, set up a git credential helper (or $GITHUB_TOKEN for GitHub), pass --ssh to use your SSH keys, or run cinstall in a terminal to be asked for them.", remote),
+            E::PackageManagerBusy(message) => message.clone(),
+            E::RepositoryNotFound(url) => format!("there is no repository at `{}`, check the spelling (`cinstall search` finds registry packages) or, if it is private, that you have access to it.", url),
+            E::NetworkError(host) => format!("couldn't reach {}, check your connection and proxy settings and try again.", host),
+            E::Cancelled => "it was cancelled, nothing of it was installed.".into(),
             E::UnknownFatal(message) => message.clone()
-        }
+        };
+        write!(f, "{}", message)
     }
 }
 
@@ -50,7 +428,19 @@ pub fn ask_to_install(program: &str) -> Result<(), InstallError> {
         "the program `{}` is required to install this package.",
         program
     );
-    output!("install it now? [Y/n] ");
+    if ui::porcelain() {
+        return Err(InstallError::UnknownFatal(format!(
+            "install `{}` first, there is nobody to ask in porcelain mode.",
+            program
+        )));
+    }
+    let known_manager = ["/usr/bin/pacman", "/usr/bin/apt"]
+        .iter()
+        .any(|p| Path::new(p).exists());
+    if !known_manager || !tools::can_use_package_manager() {
+        return ask_to_bootstrap(program, known_manager);
+    }
+    output!(on_blue, "install it now? [Y/n] ");
     let input: String = text_io::read!("{}");
 
     if input.is_empty() {
@@ -65,18 +455,22 @@ pub fn ask_to_install(program: &str) -> Result<(), InstallError> {
 
     let status: Result<ExitStatus, Error>;
 
+    // root runs the package manager itself, anyone else through sudo.
+    let as_root = |manager: &str| {
+        if tools::is_root() {
+            Command::new(manager)
+        } else {
+            let mut sudo = Command::new("sudo");
+            sudo.arg(manager);
+            sudo
+        }
+    };
     if Path::new("/usr/bin/pacman").exists() {
-        status = Command::new("sudo")
-            .arg("pacman")
-            .arg("-S")
-            .arg(program)
-            .status();
+        package_lock::wait_for("pacman")?;
+        status = as_root("pacman").arg("-S").arg(program).status();
     } else if Path::new("/usr/bin/apt").exists() {
-        status = Command::new("sudo")
-            .arg("apt")
-            .arg("install")
-            .arg(program)
-            .status();
+        package_lock::wait_for("apt")?;
+        status = as_root("apt").arg("install").arg(program).status();
     } else {
         return Err(InstallError::UnknownPackageManager);
     }
@@ -96,31 +490,97 @@ pub fn ask_to_install(program: &str) -> Result<(), InstallError> {
     }
 }
 
-// make sure they have CMake and git.
-pub fn verify_has_programs() -> Result<(), InstallError> {
-    if !Path::new("/usr/bin/git").exists() {
-        ask_to_install("git")?;
+/// Offer to install `program` into `~/.cinstall/tools`, for when the system
+/// package manager can't be used.
+fn ask_to_bootstrap(program: &str, known_manager: bool) -> Result<(), InstallError> {
+    if !tools::can_bootstrap(program) {
+        return Err(match known_manager {
+            true => InstallError::UnknownFatal(format!(
+                "`{}` can't be installed without sudo, ask an administrator to install it.",
+                program
+            )),
+            false => InstallError::UnknownPackageManager,
+        });
     }
-
-    if !Path::new("/usr/bin/cmake").exists() {
-        ask_to_install("cmake")?;
+    output!(
+        on_blue,
+        "there is no way to install it system-wide, install it into {} for your user instead? [Y/n] ",
+        (tools::dir().display())
+    );
+    let input: String = text_io::read!("{}\n");
+    if input.to_lowercase().starts_with('n') {
+        outputln!("okay, skipping installation.");
+        return Err(InstallError::DeniedInstall);
     }
+    tools::bootstrap(program)
+}
 
-    if !Path::new("/usr/bin/make").exists() {
-        ask_to_install("make")?;
+// make sure they have everything needed to build the project.
+pub fn verify_has_programs(programs: &[&str]) -> Result<(), InstallError> {
+    for program in programs {
+        if doctor::find_program(program).is_none() {
+            ask_to_install(program)?;
+        }
     }
 
-    eprintln!("user has all required dependencies.");
+    outputln!("user has all required dependencies.");
     Ok(())
 }
 
 pub enum InstallMethod {
     RunCMake,
     MakeInstall,
+    Meson,
+    Scons,
+    Xmake,
+    Premake,
+    /// `generate` is set when there is no `configure` script yet.
+    Autotools {
+        generate: bool,
+    },
     MoveHeaders(Vec<String>),
+    /// The registry's own commands, see `Recipe`.
+    Recipe,
     Unknown(String),
 }
 
+impl std::fmt::Display for InstallMethod {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InstallMethod::RunCMake => write!(f, "cmake"),
+            InstallMethod::MakeInstall => write!(f, "make"),
+            InstallMethod::Meson => write!(f, "meson"),
+            InstallMethod::Scons => write!(f, "scons"),
+            InstallMethod::Xmake => write!(f, "xmake"),
+            InstallMethod::Premake => write!(f, "premake"),
+            InstallMethod::Autotools { .. } => write!(f, "autotools"),
+            InstallMethod::MoveHeaders(_) => write!(f, "headers"),
+            InstallMethod::Recipe => write!(f, "recipe"),
+            InstallMethod::Unknown(_) => write!(f, "unknown"),
+        }
+    }
+}
+
+impl InstallMethod {
+    /// The programs needed to build and install with this method.
+    pub fn programs(&self) -> &'static [&'static str] {
+        match self {
+            InstallMethod::RunCMake if use_ninja() => &["cmake", "ninja"],
+            InstallMethod::RunCMake => &["cmake", "make"],
+            InstallMethod::MakeInstall => &["make"],
+            InstallMethod::Meson => &["meson", "ninja"],
+            InstallMethod::Scons => &["scons"],
+            InstallMethod::Xmake => &["xmake"],
+            InstallMethod::Premake => &["premake5", "make"],
+            InstallMethod::Autotools { generate: true } => &["autoconf", "automake", "make"],
+            InstallMethod::Autotools { generate: false } => &["make"],
+            InstallMethod::MoveHeaders(_) | InstallMethod::Recipe | InstallMethod::Unknown(_) => {
+                &[]
+            }
+        }
+    }
+}
+
 macro_rules! with_temp_path {
     ($path:ident, $body:block) => {{
         let old_path = match std::env::current_dir() {
@@ -167,7 +627,9 @@ pub fn resolve_makefile_install_method(path: &Path) -> Result<InstallMethod, Ins
     let file_contents: Vec<String> = file_contents.split('\n').map(String::from).collect();
 
     // We need to check for the rule: `install:`
-    let has_install = file_contents.iter().any(|item| &**item == "install:");
+    let has_install = file_contents
+        .iter()
+        .any(|item| item.starts_with("install:"));
 
     // There is no install procedure available.
     if has_install {
@@ -179,33 +641,240 @@ pub fn resolve_makefile_install_method(path: &Path) -> Result<InstallMethod, Ins
     }
 }
 
-pub fn execute_cmake(path: &Path) -> Result<(), InstallError> {
-    with_temp_path!(path, {
-        let result = Command::new("cmake").arg(".").status();
+fn cmake_configure_step(path: &Path, options: &InstallOptions) -> Step {
+    Step::new("cmake", path)
+        .arg(".")
+        .arg(format!(
+            "-DCMAKE_INSTALL_PREFIX={}",
+            options.prefix.display()
+        ))
+        .args(&options.cmake_args)
+        .envs(&options.env)
+}
 
-        match result {
-            Ok(status) => {
-                if !status.success() {
-                    return Err(InstallError::CMakeFailed);
-                }
-                outputln!(green, "cmake was successful");
-            }
-            Err(e) => {
-                return Err(InstallError::CouldNotStartProcess(format!(
-                    "failed to start cmake: {}",
-                    e
-                )))
-            }
+/// CMake builds use Ninja when it is installed, it is a lot faster than make.
+fn use_ninja() -> bool {
+    doctor::find_program("ninja").is_some()
+}
+
+/// Configure into `build/` with Ninja, build, and install into `staging`.
+fn cmake_ninja_steps(path: &Path, options: &InstallOptions, staging: &Path) -> Vec<Step> {
+    vec![
+        Step::new("cmake", path)
+            .arg("-S")
+            .arg(".")
+            .arg("-B")
+            .arg("build")
+            .arg("-G")
+            .arg("Ninja")
+            .arg("-DCMAKE_BUILD_TYPE=Release")
+            .arg(format!(
+                "-DCMAKE_INSTALL_PREFIX={}",
+                options.prefix.display()
+            ))
+            .args(&options.cmake_args),
+        Step::new("cmake", path).arg("--build").arg("build"),
+        if options.make_targets.is_empty() {
+            Step::new("cmake", path).arg("--install").arg("build")
+        } else {
+            Step::new("cmake", path)
+                .arg("--build")
+                .arg("build")
+                .arg("--target")
+                .args(&options.make_targets)
         }
-    });
+        .env("DESTDIR", &staging.display().to_string()),
+    ]
+}
+
+/// The targets that install the package, `install` unless the registry says otherwise.
+fn install_targets(options: &InstallOptions) -> Vec<String> {
+    if options.make_targets.is_empty() {
+        vec!["install".into()]
+    } else {
+        options.make_targets.clone()
+    }
+}
+
+fn make_install_step(path: &Path, options: &InstallOptions, staging: &Path) -> Step {
+    Step::new("make", path)
+        .args(&install_targets(options))
+        .arg(options.make_prefix_arg())
+        .arg(InstallOptions::make_destdir_arg(staging))
+        .envs(&options.env)
+}
+
+fn meson_steps(path: &Path, options: &InstallOptions, staging: &Path) -> Vec<Step> {
+    vec![
+        Step::new("meson", path)
+            .arg("setup")
+            .arg("builddir")
+            .arg(format!("--prefix={}", options.prefix.display())),
+        Step::new("meson", path)
+            .arg("compile")
+            .arg("-C")
+            .arg("builddir"),
+        Step::new("meson", path)
+            .arg("install")
+            .arg("-C")
+            .arg("builddir")
+            .arg("--destdir")
+            .arg(staging.display().to_string()),
+    ]
+}
+
+/// The number of jobs to give `make -j`.
+fn jobs() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+fn autotools_steps(
+    path: &Path,
+    generate: bool,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Vec<Step> {
+    let mut steps = vec![];
+    if generate {
+        // autogen.sh likes to run configure itself, NOCONFIGURE asks it not to.
+        let script = ["autogen.sh", "bootstrap"]
+            .iter()
+            .map(|name| path.join(name))
+            .find(|script| script.is_file());
+        steps.push(match script {
+            Some(script) => Step::new(&script.display().to_string(), path).env("NOCONFIGURE", "1"),
+            None => Step::new("autoreconf", path).arg("-fi"),
+        });
+    }
+    steps.push(
+        Step::new(&path.join("configure").display().to_string(), path)
+            .arg(format!("--prefix={}", options.prefix.display()))
+            .args(&options.configure_args),
+    );
+    steps.push(Step::new("make", path).arg(format!("-j{}", jobs())));
+    steps.push(
+        Step::new("make", path)
+            .args(&install_targets(options))
+            .arg(InstallOptions::make_destdir_arg(staging)),
+    );
+    steps
+}
+
+/// The recipe's commands, in the checkout.
+fn recipe_steps(path: &Path, options: &InstallOptions, staging: &Path) -> Vec<Step> {
+    let recipe = &options.recipe;
+    recipe
+        .configure
+        .iter()
+        .chain(&recipe.build)
+        .chain(&recipe.install)
+        .map(|command| {
+            Step::new("sh", path)
+                .arg("-c")
+                .arg(command)
+                .env("PREFIX", &options.prefix.display().to_string())
+                .env("DESTDIR", &staging.display().to_string())
+                .env("JOBS", &jobs().to_string())
+        })
+        .collect()
+}
+
+/// SCons has no DESTDIR, but `--install-sandbox` does the same for `Install()` targets.
+fn scons_steps(path: &Path, options: &InstallOptions, staging: &Path) -> Vec<Step> {
+    let prefix = options.make_prefix_arg();
+    vec![
+        Step::new("scons", path)
+            .arg(format!("-j{}", jobs()))
+            .arg(&prefix),
+        Step::new("scons", path)
+            .arg("install")
+            .arg(&prefix)
+            .arg(format!("--install-sandbox={}", staging.display())),
+    ]
+}
+
+/// `xmake install -o` takes the final directory, so point it at the prefix inside `staging`.
+fn xmake_steps(path: &Path, options: &InstallOptions, staging: &Path) -> Vec<Step> {
+    let prefix = options.prefix.strip_prefix("/").unwrap_or(&options.prefix);
+    vec![
+        Step::new("xmake", path).arg("-y"),
+        Step::new("xmake", path)
+            .arg("install")
+            .arg("-y")
+            .arg("-o")
+            .arg(staging.join(prefix).display().to_string()),
+    ]
+}
 
+/// Generate makefiles with premake and build the release configuration.
+fn premake_steps(path: &Path, options: &InstallOptions) -> Vec<Step> {
+    vec![
+        Step::new("premake5", path).arg("gmake2").envs(&options.env),
+        Step::new("make", path)
+            .arg(format!("-j{}", jobs()))
+            .arg("config=release")
+            .envs(&options.env),
+    ]
+}
+
+/// Run each step in order, stopping at the first one that fails.
+fn execute_steps(steps: &[Step]) -> Result<(), InstallError> {
+    for step in steps {
+        outputln!(green, "running {}", step);
+        if !step.run()?.success() {
+            return Err(InstallError::StepFailed(step.to_string()));
+        }
+    }
     Ok(())
 }
 
-pub fn execute_make_custom(path: &Path) -> Result<(), InstallError> {
-    // `make install` failed, we run `make help` to try and output information about the Makefile
-    // and then prompt the user to input arguments.
+pub fn execute_cmake(path: &Path, options: &InstallOptions) -> Result<(), InstallError> {
+    if !cmake_configure_step(path, options).run()?.success() {
+        return Err(InstallError::CMakeFailed);
+    }
+    outputln!(green, "cmake was successful");
+    Ok(())
+}
+
+pub fn execute_make_custom(
+    path: &Path,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<(), InstallError> {
+    // `make install` failed, the target chosen the last time is offered
+    // first, and otherwise we run `make help` to try and output information
+    // about the Makefile and then prompt the user to input arguments.
     //
+    if let Some(target) = choices::remembered().make_target {
+        let reuse = !options.confirm || !ui::interactive() || {
+            output!(
+                on_blue,
+                "`make install` failed, run `make {}` as you chose the last time? [Y/n] ",
+                target
+            );
+            let input: String = text_io::read!("{}\n");
+            !input.to_lowercase().starts_with('n')
+        };
+        if reuse {
+            outputln!("running `make {}`, which you chose the last time.", target);
+            let status = Command::new("make")
+                .current_dir(path)
+                .arg(&target)
+                .arg(options.make_prefix_arg())
+                .arg(InstallOptions::make_destdir_arg(staging))
+                .status();
+            if status.is_ok_and(|s| s.success()) {
+                choices::chose_make_target(&target);
+                return Ok(());
+            }
+            outputln!(red, "`make {}` didn't work this time.", target);
+        }
+    }
+    if !ui::interactive() {
+        return Err(InstallError::FailedToMakeInstall);
+    }
     with_temp_path!(path, {
         let make_help_status = Command::new("make").arg("help").status();
 
@@ -233,7 +902,11 @@ pub fn execute_make_custom(path: &Path) -> Result<(), InstallError> {
                 continue;
             }
 
-            let current_command_exec = Command::new("make").arg(&option).status();
+            let current_command_exec = Command::new("make")
+                .arg(&option)
+                .arg(options.make_prefix_arg())
+                .arg(InstallOptions::make_destdir_arg(staging))
+                .status();
             match current_command_exec {
                 Ok(result) => {
                     if !result.success() {
@@ -241,6 +914,7 @@ pub fn execute_make_custom(path: &Path) -> Result<(), InstallError> {
                         continue;
                     }
                     done = true;
+                    choices::chose_make_target(&option);
                     outputln!("success! hopefully it is all installed now.");
                     continue;
                 }
@@ -256,27 +930,46 @@ pub fn execute_make_custom(path: &Path) -> Result<(), InstallError> {
     Ok(())
 }
 
-pub fn execute_make_install(path: &Path) -> Result<(), InstallError> {
-    with_temp_path!(path, {
-        let status = Command::new("make").arg("install").status();
-
-        match status {
-            Ok(result) => {
-                if !result.success() {
-                    return execute_make_custom(path);
-                }
-                outputln!("`make install` was successful!");
-            }
-            Err(e) => {
-                return Err(InstallError::CouldNotStartProcess(e.to_string()));
-            }
-        }
-    });
-
+pub fn execute_make_install(
+    path: &Path,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<(), InstallError> {
+    if !make_install_step(path, options, staging).run()?.success() {
+        return execute_make_custom(path, options, staging);
+    }
+    outputln!("`make install` was successful!");
     Ok(())
 }
 
 pub fn try_get_install_headers(path: &Path) -> Result<InstallMethod, InstallError> {
+    let remembered = choices::remembered().headers;
+    if !remembered.is_empty() {
+        let reuse = !ui::interactive() || {
+            output!(
+                on_blue,
+                "install the headers you chose the last time, {}? [Y/n] ",
+                (remembered.join(", "))
+            );
+            let input: String = text_io::read!("{}\n");
+            !input.to_lowercase().starts_with('n')
+        };
+        if reuse {
+            choices::chose_headers(remembered.clone());
+            return Ok(InstallMethod::MoveHeaders(
+                remembered
+                    .iter()
+                    .map(|header| path.join(header).display().to_string())
+                    .collect(),
+            ));
+        }
+    }
+    if !ui::interactive() {
+        return Err(InstallError::UnknownFatal(
+            "the project has no build system cinstall knows, and there is nobody to ask which headers to install."
+                .into(),
+        ));
+    }
     let mut files = vec![];
     with_temp_path!(path, {
         let _ = Command::new("ls").status();
@@ -296,6 +989,7 @@ pub fn try_get_install_headers(path: &Path) -> Result<InstallMethod, InstallErro
             files.push(input);
         }
     });
+    choices::chose_headers(files.clone());
 
     let full_paths_to_files: Vec<String> = files
         .iter()
@@ -324,7 +1018,7 @@ pub fn resolve_install_method(path: &Path) -> InstallMethod {
     path_to_makefile.push("Makefile");
 
     if path_to_makefile.as_path().exists() {
-        match resolve_makefile_install_method(path) {
+        match resolve_makefile_install_method(&path_to_makefile) {
             Ok(method) => return method,
             Err(e) => {
                 outputln!("cannot install using make, there is no install routine.");
@@ -337,11 +1031,38 @@ pub fn resolve_install_method(path: &Path) -> InstallMethod {
     path_to_makefile.push("CMakeLists.txt");
 
     if path_to_makefile.exists() {
-        // NOTE: This is a pre-step. After running cmake,
-        //       the Make path with of course be hit.
         return InstallMethod::RunCMake;
     }
 
+    // projects moving to meson often keep their old `configure` around for a
+    // while, meson is what they are built with.
+    if path.join("meson.build").exists() {
+        return InstallMethod::Meson;
+    }
+
+    if path.join("configure").is_file() {
+        return InstallMethod::Autotools { generate: false };
+    }
+
+    if ["configure.ac", "configure.in", "autogen.sh"]
+        .iter()
+        .any(|name| path.join(name).is_file())
+    {
+        return InstallMethod::Autotools { generate: true };
+    }
+
+    if path.join("SConstruct").exists() {
+        return InstallMethod::Scons;
+    }
+
+    if path.join("xmake.lua").exists() {
+        return InstallMethod::Xmake;
+    }
+
+    if path.join("premake5.lua").exists() {
+        return InstallMethod::Premake;
+    }
+
     match try_get_install_headers(path) {
         Ok(m) => m,
         Err(e) => InstallMethod::Unknown(e.to_string()),
@@ -372,113 +1093,592 @@ pub fn move_file(src: &Path, dest: &Path) -> Result<(), InstallError> {
     Ok(())
 }
 
-pub fn execute_install_headers(headers: &[String]) -> Result<(), InstallError> {
-    // headers must be moved into /usr/local/include/
-    const ROOT_PATH: &str = "/usr/local/include/";
+pub fn execute_install_headers(
+    headers: &[String],
+    prefix: &Path,
+) -> Result<Vec<PathBuf>, InstallError> {
+    // headers must be moved into <prefix>/include/
+    let include_dir = prefix.join("include");
+    if let Err(e) = std::fs::create_dir_all(&include_dir) {
+        return Err(InstallError::BadDirectory(format!(
+            "{}: {} (you may need to `sudo`)",
+            include_dir.display(),
+            e
+        )));
+    }
+    let mut installed = vec![];
     for item in headers.iter() {
-        let file_name = match item.split('/').last() {
+        let file_name = match item.split('/').next_back() {
             Some(last) => last,
             None => {
                 outputln!("failed to get file name for path {}.", item);
                 continue;
             }
         };
-        let buf: PathBuf = [ROOT_PATH, file_name].iter().collect();
+        let buf = include_dir.join(file_name);
         let from = Path::new(item);
         let to = buf.as_path();
 
         move_file(from, to)?;
+        installed.push(buf);
     }
-    Ok(())
+    Ok(installed)
 }
 
-pub fn execute_install_method(path: &Path, method: &InstallMethod) -> Result<(), InstallError> {
+/// Run `method`, returning every file that was installed directly. Build systems
+/// install into `staging` instead, which is committed separately.
+pub fn execute_install_method(
+    name: &str,
+    path: &Path,
+    method: &InstallMethod,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<Vec<PathBuf>, InstallError> {
+    if options.multiarch {
+        return execute_steps(&multiarch::steps(path, method, options, staging)).map(|_| vec![]);
+    }
     match method {
         InstallMethod::Unknown(message) => Err(InstallError::UnknownFatal(message.clone())),
-        InstallMethod::RunCMake => execute_cmake(path),
-        InstallMethod::MoveHeaders(headers) => execute_install_headers(headers),
-        InstallMethod::MakeInstall => execute_make_install(path),
+        InstallMethod::RunCMake if options.cmake_cache_diff => {
+            let steps = planned_steps(path, method, options, staging);
+            let (configure, rest) = steps.split_first().expect("cmake always configures first");
+            execute_steps(std::slice::from_ref(configure))?;
+            let ask = options.confirm && ui::interactive();
+            cmake_cache::compare(name, path, ask)?;
+            if use_ninja() {
+                execute_steps(rest)?;
+            } else {
+                execute_make_install(path, options, staging)?;
+            }
+            cmake_cache::save(name, path);
+            Ok(vec![])
+        }
+        // execute make after we have ran cmake.
+        InstallMethod::RunCMake if !use_ninja() => execute_cmake(path, options)
+            .and_then(|_| execute_make_install(path, options, staging))
+            .map(|_| vec![]),
+        InstallMethod::MoveHeaders(headers) => execute_install_headers(headers, &options.prefix),
+        InstallMethod::MakeInstall => execute_make_install(path, options, staging).map(|_| vec![]),
+        // premake makefiles rarely have an install rule, so this usually ends
+        // up at the prompt `execute_make_install` falls back to.
+        InstallMethod::Premake => execute_steps(&premake_steps(path, options))
+            .and_then(|_| execute_make_install(path, options, staging))
+            .map(|_| vec![]),
+        _ => execute_steps(&planned_steps(path, method, options, staging)).map(|_| vec![]),
+    }
+}
+
+/// The commands `method` will run, in order.
+pub fn planned_steps(
+    path: &Path,
+    method: &InstallMethod,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Vec<Step> {
+    if options.multiarch {
+        return multiarch::steps(path, method, options, staging);
+    }
+    let steps = match method {
+        InstallMethod::RunCMake if use_ninja() => cmake_ninja_steps(path, options, staging),
+        InstallMethod::RunCMake => vec![
+            cmake_configure_step(path, options),
+            make_install_step(path, options, staging),
+        ],
+        InstallMethod::MakeInstall => vec![make_install_step(path, options, staging)],
+        InstallMethod::Meson => meson_steps(path, options, staging),
+        InstallMethod::Scons => scons_steps(path, options, staging),
+        InstallMethod::Xmake => xmake_steps(path, options, staging),
+        InstallMethod::Premake => {
+            let mut steps = premake_steps(path, options);
+            steps.push(make_install_step(path, options, staging));
+            steps
+        }
+        InstallMethod::Autotools { generate } => autotools_steps(path, *generate, options, staging),
+        InstallMethod::Recipe => recipe_steps(path, options, staging),
+        InstallMethod::MoveHeaders(_) | InstallMethod::Unknown(_) => vec![],
+    };
+    steps.into_iter().map(|s| s.envs(&options.env)).collect()
+}
+
+/// What `generate-formula` needs to know about building the project in `path`
+/// with `method`.
+fn build_record(path: &Path, method: &InstallMethod, options: &InstallOptions) -> Build {
+    let (generate, headers) = match method {
+        InstallMethod::Autotools { generate } => (*generate, vec![]),
+        InstallMethod::MoveHeaders(headers) => (
+            false,
+            headers
+                .iter()
+                .map(|h| {
+                    Path::new(h)
+                        .strip_prefix(path)
+                        .map_or(h.clone(), |h| h.display().to_string())
+                })
+                .collect(),
+        ),
+        _ => (false, vec![]),
+    };
+    Build {
+        method: method.to_string(),
+        generate,
+        headers,
+        cmake_args: options.cmake_args.clone(),
+        configure_args: options.configure_args.clone(),
+        make_targets: options.make_targets.clone(),
+        env: options.env.clone(),
+        recipe: options.recipe.clone(),
+        binaries: options.binaries.clone(),
+        sha256: options.sha256.clone(),
+        nested: vec![],
     }
 }
 
+/// Check out `url`, or the first of its mirrors that can be cloned when it
+/// can't be, over SSH if that is the protocol asked for.
+fn checkout(url: &Url, options: &InstallOptions) -> Result<(PathBuf, Vec<Step>), InstallError> {
+    let git_ref = options.git_ref.as_deref();
+    mirrors::fail_over(url, &options.mirrors, |from| {
+        let remote = if options.ssh {
+            source::ssh_url(from)
+        } else {
+            from.clone()
+        };
+        let checkout = workspace::checkout(&remote, git_ref, options.full_history);
+        cancel::check()?;
+        checkout
+    })
+}
+
+/// Describe what installing with `method` would do, without doing it.
+fn print_plan(
+    git_steps: &[Step],
+    path: &Path,
+    method: &InstallMethod,
+    options: &InstallOptions,
+    staging: &Path,
+) {
+    outputln!(green, "dry run, nothing will be built or installed.");
+    for step in git_steps {
+        eprintln!("  {} (already done)", step);
+    }
+    for step in planned_steps(path, method, options, staging) {
+        eprintln!("  {}", step);
+    }
+
+    match method {
+        InstallMethod::MoveHeaders(headers) => {
+            let include_dir = options.prefix.join("include");
+            for header in headers {
+                let file_name = header.split('/').next_back().unwrap_or(header);
+                eprintln!(
+                    "  copy {} -> {}",
+                    header,
+                    include_dir.join(file_name).display()
+                );
+            }
+        }
+        _ => {
+            eprintln!(
+                "  files installed into {} would be copied into {}",
+                staging.display(),
+                options.prefix.display()
+            );
+        }
+    }
+}
+
+/// Build the checkout at `path` with `method` and install it into the prefix,
+/// returning every file that was installed.
+pub fn build_and_install(
+    name: &str,
+    path: &Path,
+    method: &InstallMethod,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<Vec<PathBuf>, InstallError> {
+    verify_has_programs(method.programs())?;
+
+    // once files are being copied into place the install is seen through.
+    let built = execute_install_method(name, path, method, options, staging)
+        .and_then(|files| cancel::check().map(|_| files));
+    let mut installed_files = match built {
+        Err(InstallError::Cancelled) => {
+            let _ = std::fs::remove_dir_all(staging);
+            return Err(InstallError::Cancelled);
+        }
+        built => built?,
+    };
+    outputln!("all execution steps completed successfully.");
+
+    staging::hold_back_system_paths(staging, &options.prefix, options.allow_system_paths)?;
+    let staged = staging::commit(name, staging)?;
+    if staged.is_empty() && !matches!(method, InstallMethod::MoveHeaders(_)) {
+        outputln!(
+            purple,
+            "nothing was installed into the staging directory, the makefile probably ignores DESTDIR."
+        );
+        outputln!(
+            purple,
+            "cinstall can't tell which files were installed, so `uninstall` won't be able to remove them."
+        );
+    }
+    run::event("installed", json!({ "files": staged }));
+    installed_files.extend(staged);
+    Ok(installed_files)
+}
+
 pub struct Installer {
     path: String,
+    commit: Option<String>,
+    installed_files: Vec<PathBuf>,
+    bundle: Option<PathBuf>,
+    build: Option<Build>,
+    version_dir: Option<PathBuf>,
+    store_key: Option<String>,
 }
 
 impl Installer {
-    pub fn new(url: &Url) -> Result<Self, InstallError> {
-        verify_has_programs()?;
-        let random_tag: String = thread_rng()
-            .sample_iter(&Alphanumeric)
-            .take(10)
-            .map(char::from)
-            .collect();
-
-        let temp_path = format!("/tmp/cinstall-{}", random_tag);
-
-        if !Path::new(&temp_path).exists() {
-            match std::fs::create_dir_all(&temp_path) {
-                Ok(_) => (),
-                Err(e) => {
-                    outputln!(
-                        red,
-                        "failed to create temporary directory for git repository."
-                    );
-                    outputln!(red, "reason: {}", e);
-                    return Err(InstallError::FailedToCreateDirectory);
-                }
+    pub fn new(
+        name: &str,
+        url: &Url,
+        options: &InstallOptions,
+        cancel: &CancellationToken,
+    ) -> Result<Self, InstallError> {
+        cancel::watch(cancel);
+        let archive = source::is_archive(url);
+        let local = source::is_local(url);
+        // a dry run shouldn't offer to install anything either.
+        if !options.dry_run && !local {
+            verify_has_programs(match archive {
+                true if url.path().ends_with(".zip") => &["curl", "unzip"],
+                true => &["curl", "tar"],
+                false => &["git"],
+            })?;
+        }
+        if archive && options.git_ref.is_some() {
+            return Err(InstallError::UnknownFatal(
+                "an archive is a single version of the project, it can't be installed at a ref."
+                    .into(),
+            ));
+        }
+        if local && options.verify_signature {
+            return Err(InstallError::BadSignature(
+                "a local directory has no signature to check.".into(),
+            ));
+        }
+        if local && options.git_ref.is_some() {
+            return Err(InstallError::UnknownFatal(
+                "a local directory is built as it is, check the ref out there instead of using `--ref`."
+                    .into(),
+            ));
+        }
+        let run_id = run::id().unwrap_or_else(run::start);
+
+        if let Some(asset) = prebuilt::offer(url, options) {
+            let staging = staging::staging_dir(&run_id);
+            if options.versions_dir.is_some() {
+                outputln!(
+                    purple,
+                    "a prebuilt release is installed into the prefix itself, not into a directory of its own."
+                );
             }
+            let (dir, mut installed_files) = prebuilt::install(name, &asset, options, &staging)?;
+            run::event(
+                "prebuilt",
+                json!({ "asset": asset.name, "files": installed_files }),
+            );
+            installed_files.extend(binaries::install(&dir, options, &installed_files)?);
+            return Ok(Self {
+                path: dir.display().to_string(),
+                commit: None,
+                installed_files,
+                bundle: None,
+                build: None,
+                version_dir: None,
+                store_key: None,
+            });
         }
 
-        // clone the project to our temporary path.
-        match Command::new("git")
-            .arg("clone")
-            .arg(url.to_string())
-            .arg(&temp_path)
-            .status()
-        {
-            Ok(status) => {
-                if !status.success() {
-                    let code = status.code().unwrap_or(-1);
-                    outputln!(
-                        red,
-                        "failed to git clone to repository (exited with code {})",
-                        code
-                    );
-                    return Err(InstallError::FailedToClone);
-                }
-                outputln!(green, "cloned project to {}", temp_path);
+        choices::start(url);
+
+        // get the project into the workspace. this happens even for a dry
+        // run, since the source is needed to know how the project is built.
+        let (source, mut git_steps) = if local {
+            let dir = url
+                .to_file_path()
+                .map_err(|_| InstallError::BadDirectory(url.to_string()))?;
+            if !dir.is_dir() {
+                return Err(InstallError::BadDirectory(dir.display().to_string()));
             }
-            Err(e) => {
-                outputln!(red, "failed to clone: {}", e);
-                return Err(InstallError::CouldNotStartProcess("git".into()));
+            outputln!("building the local directory {} as it is.", (dir.display()));
+            (dir, vec![])
+        } else if archive {
+            let keys = options
+                .verify_signature
+                .then_some(options.signing_keys.as_slice());
+            mirrors::fail_over(url, &options.mirrors, |from| {
+                let download = workspace::download(from, options.sha256.as_deref(), keys);
+                cancel::check()?;
+                download
+            })?
+        } else {
+            checkout(url, options)?
+        };
+        if options.verify_signature && !local && !archive {
+            signature::verify_checkout(&source, &options.signing_keys)?;
+        }
+        if !local && workspace::uses_lfs(&source) {
+            if options.dry_run {
+                outputln!("the project keeps files in Git LFS, `git lfs pull` would fetch them.");
+            } else {
+                git_steps.push(workspace::fetch_lfs(&source)?);
+            }
+        }
+        if !local && !archive {
+            if options.dry_run && source.join(".gitmodules").is_file() {
+                outputln!("the project has git submodules, `git submodule update --init` would check them out.");
+            } else if !options.dry_run {
+                git_steps.extend(workspace::update_submodules(&source, options.full_history)?);
             }
+        }
+        if !options.patches.is_empty() {
+            if local {
+                outputln!(
+                    purple,
+                    "a local directory is built as it is, the registry's patches aren't applied to it."
+                );
+            } else if options.dry_run {
+                outputln!(
+                    "the registry patches the project, {} would be applied.",
+                    (options.patches.join(", "))
+                );
+            } else {
+                git_steps.extend(patches::apply(name, &source, &options.patches)?);
+            }
+        }
+        let temp_path = source.display().to_string();
+        let commit = workspace::head_commit(&source);
+        if !local {
+            outputln!(green, "the project is checked out in {}", temp_path);
+        }
+        // a versioned install is built for a prefix of its own, which the
+        // prefix it was asked for links to.
+        let prefix = &options.prefix;
+        let version_dir = options.versions_dir.as_deref().map(|root| {
+            versions::version_dir(root, name, options.git_ref.as_deref(), commit.as_deref())
+        });
+        let new_version = version_dir.as_ref().is_some_and(|dir| !dir.exists());
+        let versioned_options;
+        let options = match &version_dir {
+            Some(dir) => {
+                outputln!("installing it into {}.", (dir.display()));
+                versioned_options = InstallOptions {
+                    prefix: dir.clone(),
+                    ..options.clone()
+                };
+                &versioned_options
+            }
+            None => options,
         };
 
         // use cmake to build a Makefile
         let path = Path::new(&temp_path);
-        let method = resolve_install_method(path);
+        let detected = if options.recipe.is_empty() {
+            resolve_install_method(path)
+        } else {
+            InstallMethod::Recipe
+        };
+        let detected_name = detected.to_string();
+        let method = match fallback::remembered(url, path) {
+            Some(remembered)
+                if options.recipe.is_empty() && remembered.to_string() != detected_name =>
+            {
+                outputln!(
+                    "building with {}, which worked the last time {} didn't.",
+                    (remembered.to_string()),
+                    detected_name
+                );
+                remembered
+            }
+            _ => detected,
+        };
+        run::event("method", json!({ "method": method.to_string() }));
 
         if let InstallMethod::Unknown(message) = &method {
             return Err(InstallError::UnknownFatal(message.clone()));
         }
+        if options.multiarch {
+            multiarch::check(&method)?;
+        }
 
-        match execute_install_method(path, &method) {
-            Ok(_) => outputln!("all execution steps completed successfully."),
-            Err(e) => {
-                return Err(e);
+        // the cmake options chosen for the project the last time are added
+        // again, and more can be chosen before building it.
+        let mut chosen_options = options.clone();
+        let mut chosen_cmake_args = vec![];
+        if matches!(method, InstallMethod::RunCMake) {
+            chosen_cmake_args = choices::remembered().cmake_args;
+            chosen_cmake_args.retain(|arg| !options.cmake_args.contains(arg));
+            if !chosen_cmake_args.is_empty() {
+                outputln!(
+                    "adding the cmake options you chose the last time, {}.",
+                    (chosen_cmake_args.join(" "))
+                );
+            }
+            chosen_options.cmake_args.extend(chosen_cmake_args.clone());
+        }
+        let staging = staging::staging_dir(&run_id);
+        if options.confirm && !options.dry_run {
+            let steps = planned_steps(path, &method, &chosen_options, &staging);
+            let ask = ui::interactive();
+            let added = facts::confirm(name, path, &method, &steps, &chosen_options, ask)?;
+            if !added.is_empty() {
+                chosen_cmake_args.extend(added.clone());
+                chosen_options.cmake_args.extend(added);
+                choices::chose_cmake_args(chosen_cmake_args);
             }
+        }
+        let options = &chosen_options;
+        if options.dry_run {
+            print_plan(&git_steps, path, &method, options, &staging);
+            return Ok(Self {
+                path: temp_path,
+                commit,
+                installed_files: vec![],
+                bundle: None,
+                build: None,
+                version_dir: None,
+                store_key: None,
+            });
+        }
+        // the options the project is built with, the ones it was asked to be
+        // built with are what is recorded.
+        let pinned = nested::pins(name, commit.as_deref());
+        let pinned_options;
+        let build_options = if pinned.is_empty() {
+            options
+        } else {
+            let mut with_pins = options.clone();
+            with_pins.cmake_args.extend(nested::checkout_pins(&pinned)?);
+            pinned_options = with_pins;
+            &pinned_options
         };
-
-        // execute make after we have ran cmake.
-        if let InstallMethod::RunCMake = method {
-            execute_make_install(path)?;
+        if let Some(format) = options.bundle {
+            verify_has_programs(method.programs())?;
+            execute_install_method(name, path, &method, build_options, &staging)?;
+            outputln!("all execution steps completed successfully.");
+            let bundle = bundle::create(name, &staging, options, format)?;
+            return Ok(Self {
+                path: temp_path,
+                commit,
+                installed_files: vec![],
+                bundle: Some(bundle),
+                build: None,
+                version_dir: None,
+                store_key: None,
+            });
         }
-
-        Ok(Self { path: temp_path })
+        // the same build kept in the store is installed from there.
+        let stored = match (&commit, local || options.force) {
+            (Some(commit), false) => {
+                store::key(url, commit, &method, build_options).and_then(|key| store::find(&key))
+            }
+            _ => None,
+        };
+        let (method, mut installed_files) = match (method, &stored) {
+            (method, Some(dir)) => {
+                outputln!(
+                    green,
+                    "the same build is in the store at {}, installing it from there.",
+                    (dir.display())
+                );
+                let files = store::install(dir, name, build_options, &staging)?;
+                (method, files)
+            }
+            (method @ InstallMethod::Recipe, None) => {
+                let files = build_and_install(name, path, &method, build_options, &staging)?;
+                (method, files)
+            }
+            (method, None) => fallback::build(
+                name,
+                url,
+                path,
+                method,
+                &detected_name,
+                build_options,
+                &staging,
+            )?,
+        };
+        let added = binaries::install(path, options, &installed_files)?;
+        installed_files.extend(added);
+        let store_key = match (&commit, local) {
+            (Some(commit), false) => store::key(url, commit, &method, build_options),
+            _ => None,
+        };
+        if let (Some(key), Some(commit), None) = (&store_key, &commit, &stored) {
+            store::save(key, url, commit, &options.prefix, &installed_files);
+        }
+        if let Some(dir) = &version_dir {
+            installed_files =
+                match versions::link(name, dir, prefix, &installed_files, options.force) {
+                    Ok(links) => links,
+                    Err(e) => {
+                        // a version that can't be linked isn't left behind unused.
+                        if new_version {
+                            let _ = std::fs::remove_dir_all(dir);
+                            if let Some(package_dir) = dir.parent() {
+                                let _ = std::fs::remove_dir(package_dir);
+                            }
+                        }
+                        return Err(e);
+                    }
+                };
+        }
+        let mut build = build_record(path, &method, options);
+        build.nested = nested::find(path, &pinned);
+        choices::finish(url);
+
+        Ok(Self {
+            path: temp_path,
+            commit,
+            installed_files,
+            bundle: None,
+            build: Some(build),
+            version_dir,
+            store_key,
+        })
     }
 
     pub fn temp_path(&self) -> &String {
         &self.path
     }
+
+    /// The commit that was checked out.
+    pub fn commit(&self) -> Option<&str> {
+        self.commit.as_deref()
+    }
+
+    /// The bundle that was made instead of installing, with `--bundle`.
+    pub fn bundle(&self) -> Option<&Path> {
+        self.bundle.as_deref()
+    }
+
+    /// How the project was built, when it was.
+    pub fn build(&self) -> Option<&Build> {
+        self.build.as_ref()
+    }
+
+    /// The directory a versioned install went into, which the prefix links to.
+    pub fn version_dir(&self) -> Option<&Path> {
+        self.version_dir.as_deref()
+    }
+
+    /// The key of the build in the store, when there is a store.
+    pub fn store_key(&self) -> Option<&str> {
+        self.store_key.as_deref()
+    }
+
+    /// Every file that was written while installing the project.
+    pub fn installed_files(&self) -> &[PathBuf] {
+        &self.installed_files
+    }
 }
@@ -0,0 +1,266 @@
+// `cinstall integrate`: wire an installed package into a consumer's CMakeLists.
+//
+// The package's exported CMake config (found through its install manifest)
+// tells us the name to give `find_package` and the imported targets to link.
+
+use crate::installer::InstallError;
+use crate::manifest::Manifest;
+use crate::ui::{self, Tone};
+use crate::{output, outputln};
+use std::path::{Path, PathBuf};
+
+/// What a package exports to CMake.
+pub struct CMakeExports {
+    pub package: String,
+    pub targets: Vec<String>,
+}
+
+/// Find `<Name>Config.cmake` or `<name>-config.cmake` among the installed files.
+fn find_config(manifest: &Manifest) -> Option<(String, PathBuf)> {
+    manifest.files.iter().find_map(|file| {
+        let name = file.path.file_name()?.to_str()?;
+        let package = name
+            .strip_suffix("Config.cmake")
+            .or_else(|| name.strip_suffix("-config.cmake"))?;
+        if package.is_empty() {
+            return None;
+        }
+        Some((package.to_string(), file.path.clone()))
+    })
+}
+
+/// Pull `Foo::foo` out of `add_library(Foo::foo STATIC IMPORTED)`.
+fn imported_targets(contents: &str) -> Vec<String> {
+    contents
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("add_library("))
+        .filter(|rest| rest.contains("IMPORTED"))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(|target| target.trim_end_matches(')').to_string())
+        .collect()
+}
+
+pub fn cmake_exports(package: &str) -> Result<CMakeExports, InstallError> {
+    let manifest = Manifest::load(package)?;
+    let (name, config) = find_config(&manifest).ok_or_else(|| {
+        InstallError::UnknownFatal(format!(
+            "`{}` didn't install a CMake package config, so it can't be found with find_package.",
+            package
+        ))
+    })?;
+
+    // the targets are usually exported into a sibling `*Targets.cmake` file.
+    let config_dir = config.parent().unwrap_or(Path::new("/"));
+    let mut targets = vec![];
+    for file in &manifest.files {
+        if file.path.parent() != Some(config_dir)
+            || file.path.extension().and_then(|e| e.to_str()) != Some("cmake")
+        {
+            continue;
+        }
+        if let Ok(contents) = std::fs::read_to_string(&file.path) {
+            for target in imported_targets(&contents) {
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+        }
+    }
+
+    if targets.is_empty() {
+        return Err(InstallError::UnknownFatal(format!(
+            "could not find any imported targets in the CMake config of `{}`.",
+            package
+        )));
+    }
+
+    Ok(CMakeExports {
+        package: name,
+        targets,
+    })
+}
+
+/// The first executable or library defined in a CMakeLists, and the index of
+/// the line its definition starts and ends on.
+fn consumer_target(lines: &[String]) -> Option<(String, usize, usize)> {
+    for (start, line) in lines.iter().enumerate() {
+        let trimmed = line.trim_start();
+        let rest = match trimmed
+            .strip_prefix("add_executable(")
+            .or_else(|| trimmed.strip_prefix("add_library("))
+        {
+            Some(r) => r,
+            None => continue,
+        };
+        if rest.contains("ALIAS") || rest.contains("IMPORTED") {
+            continue;
+        }
+        let name = rest.split_whitespace().next()?.trim_end_matches(')');
+
+        // the definition ends at the first line that closes the parenthesis.
+        let mut depth = 0i32;
+        for (end, line) in lines.iter().enumerate().skip(start) {
+            depth += line.matches('(').count() as i32;
+            depth -= line.matches(')').count() as i32;
+            if depth <= 0 {
+                return Some((name.to_string(), start, end));
+            }
+        }
+        return Some((name.to_string(), start, start));
+    }
+    None
+}
+
+/// Print the lines that will be added, with a little context around them.
+fn print_diff(path: &Path, lines: &[String], added: &[bool]) {
+    eprintln!(
+        "{}",
+        ui::paint(&format!("--- {}", path.display()), Tone::Heading)
+    );
+    eprintln!(
+        "{}",
+        ui::paint(&format!("+++ {}", path.display()), Tone::Heading)
+    );
+    for (i, line) in lines.iter().enumerate() {
+        let near_change = (i.saturating_sub(2)..=i + 2).any(|j| added.get(j) == Some(&true));
+        if added[i] {
+            eprintln!("{}", ui::paint(&format!("+{}", line), Tone::Success));
+        } else if near_change {
+            eprintln!(" {}", line);
+        }
+    }
+}
+
+pub fn integrate(package: &str, cmake_lists: &Path, assume_yes: bool) -> Result<(), InstallError> {
+    let exports = cmake_exports(package)?;
+    let contents = std::fs::read_to_string(cmake_lists)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", cmake_lists.display(), e)))?;
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    let mut added: Vec<bool> = vec![false; lines.len()];
+
+    let (consumer, start, end) = consumer_target(&lines).ok_or_else(|| {
+        InstallError::UnknownFatal(format!(
+            "{} doesn't define an executable or library to link `{}` into.",
+            cmake_lists.display(),
+            package
+        ))
+    })?;
+
+    let link = format!(
+        "target_link_libraries({} PRIVATE {})",
+        consumer,
+        exports.targets.join(" ")
+    );
+    // commented out lines (like the ones `cinstall new` writes) don't count.
+    let is_code = |l: &&String| !l.trim_start().starts_with('#');
+    let already_linked = exports.targets.iter().all(|target| {
+        lines
+            .iter()
+            .filter(is_code)
+            .any(|l| l.contains(target.as_str()))
+    });
+    if !already_linked {
+        lines.insert(end + 1, link);
+        added.insert(end + 1, true);
+    }
+
+    let find = format!("find_package({} REQUIRED)", exports.package);
+    let already_found = lines.iter().filter(is_code).any(|l| {
+        l.trim_start()
+            .starts_with(&format!("find_package({}", exports.package))
+    });
+    if !already_found {
+        lines.insert(start, find);
+        added.insert(start, true);
+    }
+
+    if !added.contains(&true) {
+        outputln!(
+            green,
+            "{} already uses `{}`.",
+            (cmake_lists.display()),
+            package
+        );
+        return Ok(());
+    }
+
+    print_diff(cmake_lists, &lines, &added);
+
+    if !assume_yes {
+        output!(on_blue, "apply these changes? [Y/n] ");
+        let input: String = text_io::read!("{}\n");
+        if input.to_lowercase().starts_with('n') {
+            outputln!("okay, leaving {} alone.", (cmake_lists.display()));
+            return Ok(());
+        }
+    }
+
+    let mut new_contents = lines.join("\n");
+    new_contents.push('\n');
+    std::fs::write(cmake_lists, new_contents).map_err(|_| InstallError::FailedToWriteToFile)?;
+    outputln!(
+        green,
+        "`{}` now links against `{}`.",
+        consumer,
+        (exports.targets.join(" "))
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lines(text: &str) -> Vec<String> {
+        text.lines().map(String::from).collect()
+    }
+
+    #[test]
+    fn imported_targets_are_found() {
+        let contents = "\
+# Create imported target fmt::fmt
+add_library(fmt::fmt STATIC IMPORTED)
+add_library(fmt::fmt-header-only INTERFACE IMPORTED)
+
+set_target_properties(fmt::fmt PROPERTIES
+  INTERFACE_INCLUDE_DIRECTORIES \"${_IMPORT_PREFIX}/include\"
+)
+add_library(helper STATIC helper.c)
+";
+        assert_eq!(
+            imported_targets(contents),
+            ["fmt::fmt", "fmt::fmt-header-only"]
+        );
+        assert!(imported_targets("").is_empty());
+    }
+
+    #[test]
+    fn consumer_target_spans_its_definition() {
+        let cmake = lines(
+            "\
+cmake_minimum_required(VERSION 3.14)
+project(app)
+add_library(fmt::fmt ALIAS fmt)
+add_executable(app
+  main.cpp
+  util.cpp)
+add_library(lib lib.cpp)
+",
+        );
+        assert_eq!(consumer_target(&cmake), Some(("app".into(), 3, 5)));
+
+        let cmake = lines("  add_library(lib)\n");
+        assert_eq!(consumer_target(&cmake), Some(("lib".into(), 0, 0)));
+    }
+
+    #[test]
+    fn imported_and_alias_targets_arent_consumers() {
+        let cmake = lines(
+            "\
+add_library(Foo::foo STATIC IMPORTED)
+add_library(foo::foo ALIAS foo)
+",
+        );
+        assert_eq!(consumer_target(&cmake), None);
+    }
+}
@@ -1,153 +1,1979 @@
+pub mod artifact;
+pub mod binaries;
+pub mod bisect;
+pub mod bundle;
+pub mod cancel;
+pub mod choices;
+pub mod cli;
+pub mod cmake_cache;
+pub mod cmake_registry;
+pub mod completion;
+pub mod config;
+pub mod database;
+pub mod develop;
+pub mod diagnostics;
+pub mod doctor;
+pub mod facts;
+pub mod fallback;
+pub mod formula;
+pub mod github;
+pub mod governor;
+pub mod history;
+pub mod indices;
 pub mod installer;
+pub mod integrate;
+pub mod manifest;
+pub mod mirrors;
+pub mod modulefile;
+pub mod multiarch;
+pub mod nested;
+pub mod package_lock;
+pub mod pager;
+pub mod patches;
+pub mod paths;
+pub mod prebuilt;
+pub mod preflight;
+pub mod project;
+pub mod propose;
 pub mod registry;
+pub mod relocate;
+pub mod retry;
+pub mod rollback;
+pub mod run;
+pub mod search;
+pub mod signature;
+pub mod size;
+pub mod source;
+pub mod staging;
+pub mod status;
+pub mod store;
+pub mod table;
+pub mod tools;
+pub mod ui;
+pub mod upgrade;
+pub mod upstream;
+pub mod verify;
+pub mod versions;
+pub mod workspace;
 
-use colored::Colorize;
-use installer::Installer;
+use cancel::CancellationToken;
+use clap::Parser;
+use cli::{usage_error, Cli, Command, ConfigAction, InstallArgs, RegistryAction};
+use config::Config;
+use database::{Database, InstalledPackage, MergedDatabase};
+use history::{HistoryFilter, Operation, Origin};
+use installer::{InstallError, InstallOptions, Installer};
+use manifest::Manifest;
+use project::Template;
 use registry::*;
+use source::{Protocol, Source};
+use std::path::{Path, PathBuf};
+use table::Table;
+use upgrade::{Plan, Verdict};
 use url::Url;
 
 macro_rules! outputln {
     ($format:literal $(, $arg:tt)*) => {
-        eprintln!(concat!("[{}] ", $format), "installer".bold().cyan() $(, $arg)*)
+        eprintln!(concat!("{}", $format), $crate::ui::label($crate::ui::Tone::Info) $(, $arg)*)
     };
     ($col:ident, $format:literal $(, $arg:tt)*) => {
-        eprintln!(concat!("[{}] ", $format), "installer".bold().$col() $(, $arg)*)
+        eprintln!(concat!("{}", $format), $crate::ui::label($crate::ui::Tone::from_color(stringify!($col))) $(, $arg)*)
     };
 }
 
 macro_rules! output {
     ($format:literal $(, $arg:tt)*) => {
-        eprint!(concat!("[{}] ", $format), "installer".bold().cyan() $(, $arg)*)
+        eprint!(concat!("{}", $format), $crate::ui::label($crate::ui::Tone::Info) $(, $arg)*)
     };
     ($col:ident, $format:literal $(, $arg:tt)*) => {
-        eprint!(concat!("[{}] ", $format), "installer".bold().$col() $(, $arg)*)
+        eprint!(concat!("{}", $format), $crate::ui::label($crate::ui::Tone::from_color(stringify!($col))) $(, $arg)*)
     };
 }
 
 pub(crate) use output;
 pub(crate) use outputln;
 
-fn usage(program_name: &str, message: Option<String>) -> ! {
-    outputln!("usage: {} [...options]", program_name);
-    outputln!("  [url]: A github URL to a project that is using CMake or Make.");
-    outputln!("  [package]: The name of a package name learnt from `--list-packages`");
-    outputln!("  [--list-packages [...opts]]: Skip installation and output all known packages.");
-    outputln!("    [filter]: The filter to apply when listing packages. This just checks if the package name contains that string.");
-    if let Some(msg) = message {
-        outputln!("reason: {}", msg);
+fn package_table(long: bool) -> Table {
+    if long {
+        Table::new(&["NAME", "VERSION", "LANGUAGE", "TAGS", "URL", "DESCRIPTION"])
+    } else {
+        Table::new(&["NAME", "VERSION", "LANGUAGE", "TAGS", "DESCRIPTION"])
     }
-    std::process::exit(-1);
 }
 
-fn main() {
-    let registry = PackageRegistry::default();
-    let mut argv = std::env::args();
-    let program_name = argv.next().unwrap_or("cinstall".into());
+/// A package as a row of `package_table`. The version is the ref the registry
+/// pins it to, the default branch is installed otherwise.
+fn package_row(name: &str, package: &Package, long: bool) -> Vec<String> {
+    let mut row = vec![
+        name.to_string(),
+        package.default_ref.clone().unwrap_or_else(|| "-".into()),
+        package.language.to_string(),
+        package.tags.join(","),
+    ];
+    if long {
+        row.push(package.url.to_string());
+    }
+    row.push(package.description.to_string());
+    row
+}
+
+fn list_packages(registry: &PackageRegistry, filter: Option<&str>, long: bool) {
+    let mut table = package_table(long);
+    let mut packages: Vec<_> = registry.packages().iter().collect();
+    packages.sort_by(|a, b| a.0.cmp(b.0));
+    for (name, package) in packages {
+        if let Some(filter) = filter {
+            if !name.contains(filter) {
+                continue;
+            }
+        }
+        table.row(package_row(name, package, long));
+    }
+    table.print(long);
+}
 
-    //  NOTE: We check for 2 because the first argument is always
-    //  going to be the program name.
-    if argv.len() < 1 {
-        usage(&program_name, Some("Too little arguments".into()));
+fn list_installed(filter: Option<&str>, long: bool) {
+    let mut table = Table::new(&[
+        "NAME",
+        "SCOPE",
+        "INSTALLED",
+        "REF",
+        "COMMIT",
+        "PREFIX",
+        "URL",
+    ]);
+    for (scope, package) in MergedDatabase::open().iter() {
+        if let Some(filter) = filter {
+            if !package.name.contains(filter) {
+                continue;
+            }
+        }
+        table.row(vec![
+            package.name.clone(),
+            scope.to_string(),
+            package.installed_at.format("%Y-%m-%d %H:%M").to_string(),
+            package.git_ref.clone().unwrap_or_else(|| "-".into()),
+            package
+                .commit
+                .as_ref()
+                .map_or("-".into(), |c| c.chars().take(10).collect()),
+            package.prefix.display().to_string(),
+            package.url.clone(),
+        ]);
     }
+    if table.is_empty() {
+        outputln!(purple, "no packages are installed.");
+        return;
+    }
+    table.print(long);
+}
 
-    let first_arg = match argv.next() {
-        Some(data) => data,
-        None => usage(
-            &program_name,
-            Some("could not find package name/url argument.".into()),
+fn search_packages(registry: &PackageRegistry, query: &str, long: bool) {
+    let mut table = package_table(long);
+    for hit in search::search(registry.packages(), query) {
+        let mut highlights = vec![hit.name_spans, vec![], vec![], vec![]];
+        if long {
+            highlights.push(vec![]);
+        }
+        highlights.push(hit.description_spans);
+        table.highlighted_row(package_row(hit.name, hit.package, long), highlights);
+    }
+    if table.is_empty() {
+        outputln!(purple, "no packages matched `{}`.", query);
+        return;
+    }
+    table.print(long);
+}
+
+/// Show a package's notes so they stand out from the rest of the output.
+fn print_notes(name: &str, notes: &str) {
+    outputln!(on_blue, "notes for `{}`:", name);
+    for line in notes.lines() {
+        eprintln!("  {}", line);
+    }
+}
+
+/// Add what is known about `name` as it is installed to `table`.
+fn installed_info(table: &mut Table, name: &str) {
+    let database = MergedDatabase::open();
+    let Some((scope, installed)) = database.find(name) else {
+        table.row(vec!["installed".into(), "no".into()]);
+        return;
+    };
+    let first = installed.first_installed_at.map_or(String::new(), |at| {
+        format!(", first on {}", at.format("%Y-%m-%d %H:%M"))
+    });
+    table.row(vec![
+        "installed".into(),
+        format!(
+            "yes, on {}{} ({} database)",
+            installed.installed_at.format("%Y-%m-%d %H:%M"),
+            first,
+            scope
         ),
+    ]);
+    table.row(vec![
+        "prefix".into(),
+        installed.prefix.display().to_string(),
+    ]);
+    table.row(vec![
+        "installed ref".into(),
+        installed
+            .git_ref
+            .clone()
+            .unwrap_or_else(|| "the default branch".into()),
+    ]);
+    if let Some(commit) = &installed.commit {
+        table.row(vec!["commit".into(), commit.clone()]);
+    }
+    if let Some(build) = &installed.build {
+        table.row(vec!["built with".into(), build.method.clone()]);
+        for (i, nested) in build.nested.iter().enumerate() {
+            let label = if i == 0 { "nested sources" } else { "" };
+            let at = nested
+                .commit
+                .as_ref()
+                .map_or(String::new(), |c| format!(" at {}", &c[..c.len().min(12)]));
+            table.row(vec![
+                label.into(),
+                format!("{} ({}) {}{}", nested.name, nested.via, nested.url, at),
+            ]);
+        }
+    }
+    match size::of(name) {
+        Some((bytes, files)) => {
+            table.row(vec!["files".into(), files.to_string()]);
+            table.row(vec!["size".into(), size::human_size(bytes)]);
+        }
+        None => table.row(vec!["files".into(), "unknown, there is no manifest".into()]),
+    }
+}
+
+fn package_info(registry: &PackageRegistry, name: &str, resolved: bool, notes: bool, github: bool) {
+    let package = match registry.get(name) {
+        Some(p) => p,
+        // a package installed from a URL is only known from the database.
+        None => match MergedDatabase::open().find(name) {
+            Some((_, installed)) if !notes => {
+                let mut table = Table::new(&[]);
+                table.row(vec!["name".into(), name.to_string()]);
+                table.row(vec!["url".into(), installed.url.clone()]);
+                table.row(vec!["registry".into(), "not in the registry".into()]);
+                installed_info(&mut table, name);
+                if github {
+                    github_info(&mut table, &installed.url);
+                }
+                table.print(true);
+                return;
+            }
+            _ => source::unknown_package(registry, name),
+        },
+    };
+    let platform = registry::current_platform();
+    let package = if resolved {
+        match package.resolve(&platform) {
+            Ok(p) => p,
+            Err(e) => {
+                outputln!(red, "the registry entry for `{}` is invalid. {}", name, e);
+                std::process::exit(-1);
+            }
+        }
+    } else {
+        package.clone()
     };
 
-    if first_arg == "--list-packages" {
-        let mut filter: Option<String> = None;
-        if let Some(next) = argv.next() {
-            // expect this to be a filter.
-            filter = Some(next);
+    if notes {
+        match &package.notes {
+            Some(notes) => println!("{}", notes),
+            None => outputln!("`{}` has no notes.", name),
         }
-        for (name, package) in registry.packages().iter() {
-            let (desc, url, lang) = (
-                package.description,
-                package.url,
-                package.language.to_string(),
+        return;
+    }
+
+    let mut table = Table::new(&[]);
+    table.row(vec!["name".into(), name.to_string()]);
+    table.row(vec!["description".into(), package.description.to_string()]);
+    table.row(vec!["url".into(), package.url.to_string()]);
+    if let Some(homepage) = &package.homepage {
+        table.row(vec!["homepage".into(), homepage.clone()]);
+    }
+    if let Some(license) = &package.license {
+        table.row(vec!["license".into(), license.clone()]);
+    }
+    if !package.tags.is_empty() {
+        table.row(vec!["tags".into(), package.tags.join(", ")]);
+    }
+    if !package.dependencies.is_empty() {
+        table.row(vec!["dependencies".into(), package.dependencies.join(", ")]);
+    }
+    table.row(vec![
+        "language".into(),
+        format!("{} (not always accurate)", package.language),
+    ]);
+    if let Some(default_ref) = &package.default_ref {
+        table.row(vec!["default ref".into(), default_ref.clone()]);
+    }
+    if !package.cmake_args.is_empty() {
+        table.row(vec!["cmake args".into(), package.cmake_args.join(" ")]);
+    }
+    if !package.defines.is_empty() {
+        let defines: Vec<String> = package
+            .defines
+            .iter()
+            .map(|(k, v)| format!("-D{}={}", k, v))
+            .collect();
+        table.row(vec!["defines".into(), defines.join(" ")]);
+    }
+    if !package.make_targets.is_empty() {
+        table.row(vec!["make targets".into(), package.make_targets.join(" ")]);
+    }
+    if !package.env.is_empty() {
+        let env: Vec<String> = package
+            .env
+            .iter()
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect();
+        table.row(vec!["environment".into(), env.join(" ")]);
+    }
+    for (i, (variant_name, variant)) in package.variants.iter().enumerate() {
+        let label = if i == 0 { "variants" } else { "" };
+        table.row(vec![label.into(), format!("{}: {}", variant_name, variant)]);
+    }
+    if !package.patches.is_empty() {
+        table.row(vec!["patches".into(), package.patches.join(", ")]);
+    }
+    for (phase, commands) in [
+        ("configure", &package.configure),
+        ("build", &package.build),
+        ("install", &package.install),
+    ] {
+        for (i, command) in commands.iter().enumerate() {
+            let label = if i == 0 { phase } else { "" };
+            table.row(vec![label.into(), command.clone()]);
+        }
+    }
+    if !package.binaries.is_empty() {
+        table.row(vec!["programs".into(), package.binaries.join(", ")]);
+    }
+    if !package.requires.is_empty() {
+        table.row(vec!["requires".into(), package.requires.to_string()]);
+    }
+    if !package.mirrors.is_empty() {
+        table.row(vec!["mirrors".into(), package.mirrors.join(", ")]);
+    }
+    if let Some(sha256) = &package.sha256 {
+        table.row(vec!["sha256".into(), sha256.clone()]);
+    }
+    if !package.signing_keys.is_empty() {
+        table.row(vec!["signing keys".into(), package.signing_keys.join(", ")]);
+    }
+    if let Some(notes) = &package.notes {
+        table.row(vec!["notes".into(), notes.replace('\n', " ")]);
+    }
+    if let Some(template) = &package.template {
+        table.row(vec!["template".into(), template.clone()]);
+    }
+    let overlaid = registry.overlaid(name);
+    if !overlaid.is_empty() {
+        table.row(vec![
+            "overlay".into(),
+            format!(
+                "{} from {}",
+                overlaid.join(", "),
+                registry::overlay_path().display()
+            ),
+        ]);
+    }
+    if resolved {
+        table.row(vec!["resolved for".into(), platform.to_string()]);
+    }
+    for o in &package.overrides {
+        let fields: Vec<String> = o
+            .fields
+            .iter()
+            .map(|(key, value)| format!("{} = {}", key, value))
+            .collect();
+        table.row(vec![format!("on {}", o.condition), fields.join(", ")]);
+    }
+
+    installed_info(&mut table, name);
+    if github {
+        github_info(&mut table, &package.url);
+    }
+    table.print(true);
+}
+
+/// The rows `info --github` adds, what GitHub says about the repository at `url`.
+fn github_info(table: &mut Table, url: &str) {
+    let Some(repo) = Url::parse(url).ok().and_then(|u| github::repo_of(&u)) else {
+        table.row(vec!["github".into(), "not a GitHub repository".into()]);
+        return;
+    };
+    let info = match github::repo_info(&repo) {
+        Ok(info) => info,
+        Err(e) => {
+            outputln!(purple, "couldn't ask GitHub about `{}`. {}", repo, e);
+            return;
+        }
+    };
+    let date = |d: &chrono::DateTime<chrono::Utc>| d.format("%Y-%m-%d").to_string();
+    table.row(vec!["stars".into(), info.stars.to_string()]);
+    if let Some(last_commit) = &info.last_commit {
+        table.row(vec!["last commit".into(), date(last_commit)]);
+    }
+    table.row(vec![
+        "latest release".into(),
+        info.latest_release
+            .as_ref()
+            .map_or("none".into(), |(tag, at)| {
+                format!("{} on {}", tag, date(at))
+            }),
+    ]);
+    if let Some(license) = &info.license {
+        table.row(vec!["github license".into(), license.clone()]);
+    }
+    if info.archived {
+        table.row(vec![
+            "archived".into(),
+            "yes, the repository is read-only".into(),
+        ]);
+    }
+}
+
+/// Remove an installed package, returning the record it had in the database.
+fn uninstall(name: &str) -> Result<InstalledPackage, InstallError> {
+    let (scope, installed) = match MergedDatabase::open().find(name) {
+        Some((scope, installed)) => (scope, installed.clone()),
+        None => {
+            return Err(InstallError::UnknownFatal(format!(
+                "`{}` is not installed.",
+                name
+            )))
+        }
+    };
+
+    let manifest = Manifest::load(name)?;
+    let removed = manifest.remove_files()?;
+    outputln!(
+        "removed {} files from {}.",
+        removed,
+        (installed.prefix.display())
+    );
+
+    let mut db = Database::open(scope)?;
+    db.remove(name);
+    db.save()?;
+    manifest.delete()?;
+    modulefile::remove(name);
+    deregister(name);
+    rollback::forget(name);
+    if let Some(dir) = &installed.version_dir {
+        versions::remove(dir)?;
+    }
+
+    Ok(installed)
+}
+
+/// Take `name` out of CMake's package registry, returning whether it was in it.
+fn deregister(name: &str) -> bool {
+    let removed = cmake_registry::deregister(name);
+    if !removed.is_empty() {
+        outputln!(
+            "removed {} from CMake's package registry.",
+            (removed.join(", "))
+        );
+    }
+    !removed.is_empty()
+}
+
+fn uninstall_package(name: &str) {
+    let result = uninstall(name);
+    let origin = result.as_ref().map(Origin::of).unwrap_or_default();
+    history::record(Operation::Uninstall, name, origin, None, &result);
+
+    match result {
+        Ok(_) => outputln!(green, "successfully uninstalled `{}`", name),
+        Err(e) => {
+            outputln!(red, "failed to uninstall `{}`. {}", name, e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn upgrade_package(registry: &PackageRegistry, name: &str, force: bool, yes: bool, dry_run: bool) {
+    let Some((_, installed)) = MergedDatabase::open()
+        .find(name)
+        .map(|(s, p)| (s, p.clone()))
+    else {
+        usage_error(format!("`{}` is not installed.", name));
+    };
+    let git_ref = match upgrade::plan(&installed, force) {
+        Plan::Build { git_ref, why } => {
+            outputln!("upgrading `{}`, {}.", name, why);
+            git_ref
+        }
+        Plan::UpToDate(why) => {
+            outputln!(green, "`{}` is up to date, {}.", name, why);
+            return;
+        }
+        Plan::Skip(why) => {
+            outputln!(
+                purple,
+                "leaving `{}` as it is, {}. pass --force to build it again.",
+                name,
+                why
             );
-            if let Some(filter) = &filter {
-                if !name.contains(filter) {
-                    continue;
-                }
-            }
-            eprintln!(
-                "[{}] {} - {} ({}) [{} (not always accurate)]",
-                "package".bold().bright_cyan(),
-                name.italic().white(),
-                desc.blue().bold(),
-                url.purple(),
-                lang.italic()
+            return;
+        }
+        Plan::Unknown(why) => {
+            outputln!(
+                red,
+                "can't tell whether `{}` has something new, {}",
+                name,
+                why
             );
+            std::process::exit(-1);
         }
+    };
+    if dry_run {
+        outputln!("dry run, `{}` isn't built.", name);
+        return;
+    }
+
+    // the same package at the same prefix, following the same branch.
+    let target = match registry.get(name) {
+        Some(_) => name.to_string(),
+        None => installed.url.clone(),
+    };
+    let args = InstallArgs {
+        target: target.clone(),
+        prefix: Some(installed.prefix.clone()),
+        git_ref: Some(git_ref.unwrap_or_else(|| "head".into())),
+        yes,
+        ..Default::default()
+    };
+    // upgrade already decided it has to be built.
+    let options = InstallOptions {
+        upgrade: true,
+        force: true,
+        versions_dir: installed
+            .version_dir
+            .as_deref()
+            .and_then(versions::root_of)
+            .map(Path::to_path_buf),
+        ..install_options(&args)
+    };
+    install(registry, &target, None, &options);
+}
 
+/// Record that the installed `name` follows `git_ref`, after it was built at
+/// a commit to put back what was there before.
+fn follow_ref(name: &str, git_ref: Option<String>) {
+    if let Some((_, installed)) = MergedDatabase::open().find(name) {
+        let installed = InstalledPackage {
+            git_ref,
+            ..installed.clone()
+        };
+        if let Err(e) = database::record_install(installed) {
+            outputln!(red, "warning: {}", e);
+        }
+    }
+}
+
+/// `cinstall rollback`: go back to what was installed of `name` before its
+/// last upgrade.
+fn rollback_package(registry: &PackageRegistry, name: &str, yes: bool) {
+    let Some(previous) = rollback::previous(name) else {
+        usage_error(format!(
+            "there is nothing to roll `{}` back to, what is installed is only kept when it is upgraded.",
+            name
+        ));
+    };
+    let older = &previous.package;
+    match MergedDatabase::open().find(name) {
+        Some((_, newer)) => outputln!(
+            "rolling `{}` back from {} to {}.",
+            name,
+            (newer.built_at()),
+            (older.built_at())
+        ),
+        None => outputln!(
+            "`{}` isn't installed, putting {} back.",
+            name,
+            (older.built_at())
+        ),
+    }
+    if !previous.has_files() {
+        outputln!(
+            "its files weren't kept (see the `rollback_files` config key), it is built again."
+        );
+    }
+    if !yes && ui::interactive() {
+        output!(on_blue, "roll it back now? [Y/n] ");
+        let input: String = text_io::read!("{}\n");
+        if input.to_lowercase().starts_with('n') {
+            outputln!("okay, leaving everything as it is.");
+            return;
+        }
+    }
+
+    if previous.has_files() {
+        let result = rollback::restore(&previous);
+        history::record(Operation::Rollback, name, Origin::of(older), None, &result);
+        if let Err(e) = result {
+            outputln!(red, "failed to roll `{}` back. {}", name, e);
+            std::process::exit(-1);
+        }
+    } else {
+        let newer = Manifest::load(name).ok();
+        let target = match registry.get(name) {
+            Some(_) => name.to_string(),
+            None => older.url.clone(),
+        };
+        // the commit, a branch has moved on since.
+        let git_ref = older.commit.clone().or(older.git_ref.clone());
+        let args = InstallArgs {
+            target: target.clone(),
+            prefix: Some(older.prefix.clone()),
+            git_ref: Some(git_ref.unwrap_or_else(|| "head".into())),
+            yes: true,
+            ..Default::default()
+        };
+        let options = InstallOptions {
+            rollback: true,
+            force: true,
+            versions_dir: older
+                .version_dir
+                .as_deref()
+                .and_then(versions::root_of)
+                .map(Path::to_path_buf),
+            ..install_options(&args)
+        };
+        install(registry, &target, None, &options);
+        if let (Some(newer), Some(older)) = (newer, &previous.manifest) {
+            if let Err(e) = rollback::remove_newer_files(&newer, older) {
+                outputln!(red, "warning: {}", e);
+            }
+        }
+        // it follows the ref it did before, rather than the commit it was built at.
+        follow_ref(name, older.git_ref.clone());
+    }
+    rollback::forget(name);
+    outputln!(
+        green,
+        "rolled `{}` back to {}, `cinstall upgrade {}` upgrades it again.",
+        name,
+        (older.built_at()),
+        name
+    );
+}
+
+/// `cinstall outdated`: the installed packages upstream has something newer for.
+fn outdated() {
+    let database = MergedDatabase::open();
+    let installed = database.packages();
+    if installed.is_empty() {
+        outputln!(purple, "no packages are installed.");
         return;
     }
+    let mut table = Table::new(&["NAME", "INSTALLED", "AVAILABLE"]);
+    let mut unchecked = vec![];
+    for package in &installed {
+        if let Some(path) = develop::path(&package.name) {
+            unchecked.push(format!(
+                "`{}` (built from your checkout at {})",
+                package.name,
+                path.display()
+            ));
+            continue;
+        }
+        if !upgrade::has_upstream(package) {
+            unchecked.push(format!(
+                "`{}` (installed from {})",
+                package.name, package.url
+            ));
+            continue;
+        }
+        match upgrade::check(package) {
+            Verdict::Newer { available, .. } => {
+                table.row(vec![package.name.clone(), package.built_at(), available]);
+            }
+            Verdict::UpToDate(_) => {}
+            Verdict::Unknown(why) => unchecked.push(format!("`{}` ({})", package.name, why)),
+        }
+    }
 
-    if let Some(package) = registry.get(&first_arg) {
-        // in this case we can just assume the URL is correct.
-        let url = Url::parse(package.url).unwrap_or_else(|err| {
-            panic!(
-                "the internal package registry contained an invalid URL. This is a bug. Url={} Msg={}",
-                package.url, err
-            );
-        });
+    if table.is_empty() && unchecked.is_empty() {
+        outputln!(green, "everything installed is up to date.");
+    } else if table.is_empty() {
+        outputln!(green, "everything that could be checked is up to date.");
+    } else {
+        table.print(false);
+        outputln!("`cinstall upgrade <name>` or `cinstall upgrade --all` builds them again.");
+    }
+    if !unchecked.is_empty() {
+        outputln!(purple, "couldn't check {}.", (unchecked.join(", ")));
+    }
+}
 
-        let _ = match Installer::new(&url) {
-            Ok(i) => i,
-            Err(e) => {
-                let message = e.to_string();
-                outputln!(red, "failed to install package. {}", message);
-                return;
+/// `upgrade --all`: check every installed package, then build the ones with
+/// something new, each in a cinstall of its own so a failure doesn't stop the
+/// rest, unless `fail_fast`.
+fn upgrade_all(force: bool, yes: bool, dry_run: bool, fail_fast: bool) {
+    let database = MergedDatabase::open();
+    let installed = database.packages();
+    if installed.is_empty() {
+        outputln!(purple, "no packages are installed.");
+        return;
+    }
+    // the global flags this cinstall was given, for the ones doing the builds.
+    let globals: Vec<String> = std::env::args()
+        .filter(|a| {
+            ["--no-pager", "--a11y", "--porcelain", "--verbose", "-v"].contains(&a.as_str())
+        })
+        .collect();
+
+    let mut table = Table::new(&["NAME", "RESULT", "WHY"]);
+    let (mut upgraded, mut skipped, mut failed, mut not_tried) = (0, 0, 0, 0);
+    for package in installed {
+        let name = &package.name;
+        if fail_fast && failed > 0 {
+            not_tried += 1;
+            table.row(vec![
+                name.clone(),
+                "not tried".into(),
+                "an upgrade before it failed, and --fail-fast was given".into(),
+            ]);
+            continue;
+        }
+        outputln!("checking `{}`.", name);
+        let (result, why) = match upgrade::plan(package, force) {
+            Plan::UpToDate(why) => {
+                skipped += 1;
+                ("up to date", why)
+            }
+            Plan::Skip(why) => {
+                skipped += 1;
+                ("skipped", why)
+            }
+            Plan::Unknown(why) => {
+                failed += 1;
+                ("failed", why)
+            }
+            Plan::Build { why, .. } if dry_run => {
+                upgraded += 1;
+                ("would upgrade", why)
+            }
+            Plan::Build { why, .. } => {
+                outputln!("upgrading `{}`, {}.", name, why);
+                let mut upgrade = std::process::Command::new(
+                    std::env::current_exe().unwrap_or_else(|_| PathBuf::from("cinstall")),
+                );
+                upgrade.args(&globals).arg("upgrade").arg(name);
+                if force {
+                    upgrade.arg("--force");
+                }
+                if yes {
+                    upgrade.arg("--yes");
+                }
+                match upgrade.status() {
+                    Ok(status) if status.success() => {
+                        upgraded += 1;
+                        ("upgraded", why)
+                    }
+                    // cancelled on purpose, which isn't the upgrade failing.
+                    Ok(status) if status.code() == Some(InstallError::Cancelled.exit_code()) => {
+                        skipped += 1;
+                        ("cancelled", why)
+                    }
+                    Ok(status) => {
+                        failed += 1;
+                        let code = status.code().unwrap_or(-1);
+                        (
+                            "failed",
+                            format!("{}, the build failed (exited with {})", why, code),
+                        )
+                    }
+                    Err(e) => {
+                        failed += 1;
+                        ("failed", format!("couldn't run cinstall: {}", e))
+                    }
+                }
             }
         };
+        table.row(vec![name.clone(), result.to_string(), why]);
+    }
 
-        outputln!(green, "successfully installed package `{}`", first_arg);
-        return;
+    // in porcelain mode stdout is the reports of the builds.
+    if ui::porcelain() {
+        eprint!("{}", table.render(false));
+    } else {
+        table.print(false);
     }
+    let done = if dry_run {
+        "would be upgraded"
+    } else {
+        "upgraded"
+    };
+    let stopped = match not_tried {
+        0 => String::new(),
+        n => format!(", {} not tried", n),
+    };
+    outputln!(
+        "{} {}, {} skipped, {} failed{}.",
+        upgraded,
+        done,
+        skipped,
+        failed,
+        stopped
+    );
+    if failed > 0 {
+        std::process::exit(-1);
+    }
+}
 
-    let link = &first_arg;
+fn relocate_package(name: &str, to: &Path) {
+    let result = relocate::relocate(name, to);
+    let origin = Origin {
+        prefix: Some(to.to_path_buf()),
+        ..Default::default()
+    };
+    history::record(Operation::Relocate, name, origin, None, &result);
 
-    let url = match Url::parse(link) {
-        Ok(url) => url,
+    match result {
+        Ok(_) => outputln!(green, "successfully moved `{}` to {}", name, (to.display())),
         Err(e) => {
-            usage(
-                &program_name,
-                Some(format!(
-                    "invalid argument (expect package-name/url): {} ({})",
-                    e, link
-                )),
-            );
+            outputln!(red, "failed to move `{}`. {}", name, e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn generate_formula(
+    registry: &PackageRegistry,
+    name: &str,
+    format: formula::Format,
+    output: Option<&Path>,
+) {
+    let generated = match formula::generate(registry, name, format) {
+        Ok(generated) => generated,
+        Err(e) => {
+            outputln!(red, "failed to generate a formula for `{}`. {}", name, e);
+            std::process::exit(-1);
         }
     };
+    let Some(output) = output else {
+        print!("{}", generated);
+        return;
+    };
+    if let Err(e) = std::fs::write(output, generated) {
+        outputln!(red, "failed to write {}. {}", (output.display()), e);
+        std::process::exit(-1);
+    }
+    outputln!(
+        green,
+        "wrote {}, check its license, dependencies and test before submitting it.",
+        (output.display())
+    );
+}
+
+fn import_artifact(file: &Path, prefix: Option<&Path>) {
+    let result = artifact::import(file, prefix);
+    let name = match &result {
+        Ok(package) => package.name.clone(),
+        Err(_) => file.display().to_string(),
+    };
+    let origin = result.as_ref().map(Origin::of).unwrap_or_default();
+    history::record(Operation::Install, &name, origin, None, &result);
 
-    if url.host_str() != Some("github.com") {
-        usage(&program_name, Some("host must be github.com".into()));
+    match result {
+        Ok(package) => outputln!(
+            green,
+            "successfully installed `{}` into {}",
+            name,
+            (package.prefix.display())
+        ),
+        Err(e) => {
+            outputln!(red, "failed to import {}. {}", (file.display()), e);
+            std::process::exit(-1);
+        }
     }
+}
 
-    let installer = match Installer::new(&url) {
-        Ok(installer) => installer,
+fn load_config() -> Config {
+    match Config::load() {
+        Ok(config) => config,
         Err(e) => {
-            outputln!("failed to install project.");
-            let e = e.to_string();
-            outputln!("{}", e);
-            return;
+            outputln!(red, "failed to load the config file. {}", e);
+            std::process::exit(-1);
         }
+    }
+}
+
+/// Combine flags from the command line with the user's config.
+fn install_options(args: &InstallArgs) -> InstallOptions {
+    let config = load_config();
+    let mut options = InstallOptions {
+        dry_run: args.dry_run,
+        git_ref: args.git_ref.clone(),
+        full_history: args.full_history,
+        report: args.report.clone(),
+        check_upstream: args.check_upstream,
+        host_ok: args.host_ok.clone(),
+        allow_any_host: args.allow_any_host,
+        ignore_requirements: args.ignore_requirements,
+        symlink_bin: args.symlink_bin,
+        multiarch: args.multiarch,
+        prebuilt: args.prebuilt,
+        from_source: args.from_source,
+        ssh: args.ssh || (!args.https && config.protocol == Some(Protocol::Ssh)),
+        sha256: args.sha256.clone(),
+        verify_signature: args.verify_signature || !args.signing_keys.is_empty(),
+        signing_keys: args.signing_keys.clone(),
+        confirm: !args.yes,
+        bundle: args.bundle,
+        cmake_cache_diff: true,
+        no_deps: args.no_deps,
+        variants: args.variants.clone(),
+        cmake_register: args.cmake_register || config.cmake_register == Some(true),
+        package_indices: config.package_indices != Some(false),
+        allow_system_paths: args.allow_system_paths,
+        force: args.force,
+        versions_dir: (args.versioned || config.versioned == Some(true)).then(|| {
+            config
+                .versions_dir
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(versions::DEFAULT_ROOT))
+        }),
+        ..Default::default()
+    };
+
+    if let Some(prefix) = args.prefix.clone().or(config.prefix) {
+        options.prefix = match std::env::current_dir() {
+            Ok(cwd) => cwd.join(prefix),
+            Err(_) => prefix,
+        };
+    }
+
+    options
+}
+
+fn record_install(name: &str, url: &Url, options: &InstallOptions, installer: &Installer) {
+    let prefix = options.prefix.clone();
+    let files = installer.installed_files();
+    if let Err(e) = Manifest::new(name, &prefix, files).save() {
+        outputln!(red, "warning: failed to save the install manifest. {}", e);
+    }
+
+    let package = InstalledPackage {
+        name: name.to_string(),
+        url: url.to_string(),
+        prefix,
+        installed_at: chrono::Utc::now(),
+        first_installed_at: None,
+        git_ref: options.git_ref.clone(),
+        commit: installer.commit().map(String::from),
+        build: installer.build().cloned(),
+        version_dir: installer.version_dir().map(Path::to_path_buf),
     };
+    match modulefile::write(&package, files) {
+        Ok(script) => outputln!(
+            "`source {}` (or `module load {}` with `module use {}`) sets up the environment for it.",
+            (script.display()),
+            name,
+            (modulefile::modulefiles_dir().display())
+        ),
+        Err(e) => outputln!(red, "warning: failed to write the environment files. {}", e),
+    }
+    if options.cmake_register {
+        let registered = cmake_registry::register(name, files);
+        if registered.is_empty() {
+            outputln!(
+                purple,
+                "`{}` installs no CMake config files, there is nothing to add to CMake's package registry.",
+                name
+            );
+        } else {
+            outputln!(
+                "added {} to CMake's package registry in {}, `find_package` finds them from now on.",
+                (registered.join(", ")),
+                (cmake_registry::dir().display())
+            );
+        }
+    }
+    match database::record_install(package) {
+        Ok(scope) => {
+            outputln!("recorded `{}` in the {} database.", name, scope);
+            if let Some(key) = installer.store_key() {
+                store::add_reference(key, &scope.database_path(), name);
+            }
+        }
+        Err(e) => outputln!(red, "warning: {}", e),
+    }
+}
 
-    outputln!(green, "successfully installed project at {}", link);
-    let tmp_path = installer.temp_path();
+fn install_from(name: &str, url: &Url, options: &InstallOptions) -> Result<(), InstallError> {
+    let run_id = run::start();
     outputln!(
-        green,
-        "the temporary folder used to install it is at {}",
-        tmp_path
+        "starting run {}, its log is at {}",
+        run_id,
+        (run::log_path(&run_id).display())
+    );
+    run::event(
+        "started",
+        serde_json::json!({
+            "package": name,
+            "url": url.as_str(),
+            "prefix": options.prefix,
+            "ref": options.git_ref,
+            "dry_run": options.dry_run,
+        }),
+    );
+
+    if options.check_upstream {
+        upstream::check(url);
+    }
+    diagnostics::reset();
+    let result = install_run(name, url, options);
+    cancel::clear(&run_id);
+    let diagnostics = diagnostics::take();
+    diagnostics.print();
+    run::event(
+        "finished",
+        match &result {
+            Ok(_) => serde_json::json!({ "success": true }),
+            Err(e) => serde_json::json!({ "success": false, "error": e.to_string() }),
+        },
     );
+
+    let report = serde_json::json!({
+        "run": run_id,
+        "package": name,
+        "url": url.as_str(),
+        "ref": options.git_ref,
+        "prefix": options.prefix,
+        "success": result.is_ok(),
+        "error": result.as_ref().err().map(|e| e.to_string()),
+        "diagnostics": diagnostics,
+    });
+    // in porcelain mode stdout is the report, one line per install.
+    if ui::porcelain() {
+        println!("{}", report);
+    }
+    if let Some(path) = &options.report {
+        let written = serde_json::to_string_pretty(&report)
+            .map_err(|e| e.to_string())
+            .and_then(|json| std::fs::write(path, json).map_err(|e| e.to_string()));
+        match written {
+            Ok(_) => outputln!("wrote the report to {}", (path.display())),
+            Err(e) => outputln!(
+                red,
+                "failed to write the report to {}. {}",
+                (path.display()),
+                e
+            ),
+        }
+    }
+    result
+}
+
+fn install_run(name: &str, url: &Url, options: &InstallOptions) -> Result<(), InstallError> {
+    let installer = Installer::new(name, url, options, &CancellationToken::new())?;
+    if options.dry_run {
+        return Ok(());
+    }
+    if let Some(bundle) = installer.bundle() {
+        outputln!(
+            green,
+            "bundled `{}` into {}, nothing was installed.",
+            name,
+            (bundle.display())
+        );
+        return Ok(());
+    }
+
+    record_install(name, url, options, &installer);
+    let pc_dirs = doctor::pkg_config_dirs_of(installer.installed_files());
+    if !pc_dirs.is_empty() {
+        doctor::check_pkg_config(&pc_dirs, options.confirm);
+    }
     outputln!(
         green,
-        "note: use `sudo rm -rf /tmp/cinstall-*` to remove any temporary directories."
+        "successfully installed `{}` from {} into {}",
+        name,
+        url,
+        (options.prefix.display())
+    );
+    if !source::is_local(url) {
+        let tmp_path = installer.temp_path();
+        outputln!(
+            green,
+            "the temporary folder used to install it is at {}",
+            tmp_path
+        );
+        outputln!(
+            green,
+            "note: use `rm -rf {}` to remove the checkouts kept for reinstalls.",
+            (workspace::root().display())
+        );
+    }
+    // last, so they aren't missed.
+    if let Some(notes) = &options.notes {
+        print_notes(name, notes);
+    }
+    Ok(())
+}
+
+/// Add the dependencies of `name` to `order`, each after its own, failing on
+/// a cycle.
+fn dependency_order(
+    registry: &PackageRegistry,
+    name: &str,
+    dependencies: &[String],
+    visiting: &mut Vec<String>,
+    order: &mut Vec<String>,
+) -> Result<(), String> {
+    visiting.push(name.to_string());
+    for dependency in dependencies {
+        if order.contains(dependency) {
+            continue;
+        }
+        if visiting.contains(dependency) {
+            return Err(format!(
+                "the dependencies of `{}` go round in a circle ({} -> {})",
+                visiting[0],
+                visiting.join(" -> "),
+                dependency
+            ));
+        }
+        let Some(package) = registry.get(dependency) else {
+            return Err(format!(
+                "`{}` depends on `{}`, which isn't in the registry",
+                name, dependency
+            ));
+        };
+        let package = package
+            .resolve(&registry::current_platform())
+            .unwrap_or_else(|_| package.clone());
+        dependency_order(registry, dependency, &package.dependencies, visiting, order)?;
+        order.push(dependency.clone());
+    }
+    visiting.pop();
+    Ok(())
+}
+
+/// Install the registry packages `name` depends on that aren't installed yet.
+fn install_dependencies(
+    registry: &PackageRegistry,
+    name: &str,
+    dependencies: &[String],
+    options: &InstallOptions,
+) {
+    let db = MergedDatabase::open();
+    // the dependencies aren't looked at any further, they may not even be in
+    // the registry.
+    if options.no_deps {
+        for dependency in dependencies.iter().filter(|d| db.find(d).is_none()) {
+            outputln!(
+                purple,
+                "`{}` needs `{}`, which isn't installed and is skipped because of --no-deps.",
+                name,
+                dependency
+            );
+        }
+        return;
+    }
+    let mut order = vec![];
+    if let Err(e) = dependency_order(registry, name, dependencies, &mut vec![], &mut order) {
+        outputln!(red, "failed to install `{}`. {}.", name, e);
+        std::process::exit(-1);
+    }
+    for dependency in order.iter().filter(|d| db.find(d).is_none()) {
+        if options.dry_run {
+            outputln!(
+                "`{}` needs `{}`, which would be installed first.",
+                name,
+                dependency
+            );
+            continue;
+        }
+        outputln!("`{}` needs `{}`, installing it first.", name, dependency);
+        // the ref, checksum, keys and variants asked for are the dependent's,
+        // and a bundle needs its dependencies installed.
+        let options = InstallOptions {
+            git_ref: None,
+            bundle: None,
+            variants: vec![],
+            report: None,
+            upgrade: false,
+            sha256: None,
+            verify_signature: false,
+            signing_keys: vec![],
+            ..options.clone()
+        };
+        install(registry, dependency, None, &options);
+    }
+}
+
+/// The install of `name` that installing `url` with `options` would only
+/// repeat: from the same URL into the same prefix, at the same ref and
+/// commit, built with the same options. A directory on this machine is
+/// always built again, it may have changed.
+fn already_installed(name: &str, url: &Url, options: &InstallOptions) -> Option<InstalledPackage> {
+    if source::is_local(url) {
+        return None;
+    }
+    let (_, installed) = MergedDatabase::open()
+        .find(name)
+        .map(|(s, p)| (s, p.clone()))?;
+    let same_build = installed.build.as_ref().is_some_and(|build| {
+        build.cmake_args == options.cmake_args
+            && build.configure_args == options.configure_args
+            && build.sha256 == options.sha256
+    });
+    if installed.url != url.as_str()
+        || installed.prefix != options.prefix
+        || installed.git_ref != options.git_ref
+        || !same_build
+    {
+        return None;
+    }
+    if source::is_archive(url) {
+        return Some(installed);
+    }
+    let commit = installed.commit.as_deref()?;
+    // a commit asked for by its hash doesn't need asking upstream.
+    let asked = options.git_ref.as_deref().filter(|r| {
+        r.len() >= 7 && r.chars().all(|c| c.is_ascii_hexdigit()) && commit.starts_with(*r)
+    });
+    let remote = match options.ssh {
+        true => source::ssh_url(url),
+        false => url.clone(),
+    };
+    let upstream = match asked {
+        Some(_) => Some(commit.to_string()),
+        None => upgrade::remote_commit(remote.as_str(), options.git_ref.as_deref()),
+    };
+    (upstream.as_deref() == Some(commit)).then_some(installed)
+}
+
+/// What `target` refers to, exiting if the registry entry for it is invalid.
+fn resolve_source(
+    registry: &PackageRegistry,
+    target: &str,
+    fork: Option<&str>,
+    options: &InstallOptions,
+) -> Source {
+    let resolved = source::resolve(
+        registry,
+        target,
+        fork,
+        &options.host_ok,
+        options.allow_any_host,
+    );
+    resolved.unwrap_or_else(|e| {
+        outputln!(red, "failed to install `{}`. {}", target, e);
+        std::process::exit(e.exit_code());
+    })
+}
+
+/// `cinstall install --forget <target>`: forget what was chosen for it.
+fn forget_choices(registry: &PackageRegistry, args: &InstallArgs) {
+    let options = install_options(args);
+    let url = match indices::resolve(registry, &args.target, &options) {
+        Some(source) => source.url,
+        None => resolve_source(registry, &args.target, args.fork.as_deref(), &options).url,
+    };
+    match choices::forget(&url) {
+        true => outputln!(green, "forgot what you chose for {}.", url),
+        false => outputln!("nothing was chosen for {}.", url),
+    }
+}
+
+fn install(registry: &PackageRegistry, target: &str, fork: Option<&str>, options: &InstallOptions) {
+    let mut source = match develop::path(target) {
+        // a checkout of something the registry doesn't know keeps the name it was given.
+        Some(path) if registry.get(target).is_none() => {
+            Source::plain(target.to_string(), develop::url(&path), None)
+        }
+        _ => match indices::resolve(registry, target, options) {
+            Some(source) => source,
+            None => resolve_source(registry, target, fork, options),
+        },
+    };
+    if let Some(path) = develop::path(&source.name) {
+        outputln!(
+            "`{}` is built from your checkout at {} (see `cinstall develop`).",
+            (source.name),
+            (path.display())
+        );
+        source.url = develop::url(&path);
+        source.git_ref = None;
+        source.pinned = None;
+        source.mirrors.clear();
+        source.sha256 = None;
+        source.signing_keys.clear();
+    }
+    let (name, url) = (source.name, source.url);
+    if source.git_ref.is_some() && options.git_ref.is_some() {
+        usage_error(
+            "`--ref` can't be combined with a ref in the target, a pull request or a fork branch",
+        );
+    }
+
+    // `--ref head` asks for the default branch, even if the registry pins a ref.
+    let pinned = source.pinned;
+    let git_ref = match options.git_ref.as_deref() {
+        Some(r) if r.eq_ignore_ascii_case("head") => None,
+        Some(r) => Some(r.to_string()),
+        None => source.git_ref.or(pinned.clone()),
+    };
+    if let (Some(pinned), None) = (pinned, &options.git_ref) {
+        outputln!(
+            "`{}` is pinned to `{}` in the registry, use `--ref head` for the default branch.",
+            name,
+            pinned
+        );
+    }
+
+    let sha256 = options.sha256.clone().or(source.sha256);
+    if let Some(sha256) = &sha256 {
+        if !source::is_archive(&url) {
+            usage_error("a checksum only applies to archives, pin a git repository to a commit with `--ref` instead");
+        }
+        let hex = sha256.trim_start_matches("sha256:");
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            usage_error(format!(
+                "`{}` isn't a SHA-256, expected 64 hex digits",
+                sha256
+            ));
+        }
+    }
+
+    let problems = preflight::problems(&source.requires);
+    if !problems.is_empty() {
+        outputln!(
+            red,
+            "`{}` doesn't build on this system ({}):",
+            name,
+            (source.requires)
+        );
+        for problem in &problems {
+            eprintln!("  {}", problem);
+        }
+        if !options.ignore_requirements {
+            outputln!(red, "pass --ignore-requirements to try anyway.");
+            std::process::exit(-1);
+        }
+        outputln!(purple, "trying anyway because of --ignore-requirements.");
+    }
+    let mut cmake_args = source.cmake_args;
+    let mut configure_args = vec![];
+    for wanted in &options.variants {
+        let Some(variant) = source.variants.get(wanted) else {
+            let offered: Vec<&str> = source.variants.keys().map(String::as_str).collect();
+            usage_error(match offered.is_empty() {
+                true => format!("`{}` has no variants", name),
+                false => format!(
+                    "`{}` has no variant `{}`, it has {}",
+                    name,
+                    wanted,
+                    offered.join(", ")
+                ),
+            });
+        };
+        outputln!("building the `{}` variant of `{}`.", wanted, name);
+        cmake_args.extend(variant.all_cmake_args());
+        configure_args.extend(variant.configure_args.iter().cloned());
+    }
+    install_dependencies(registry, &name, &source.dependencies, options);
+
+    let options = &InstallOptions {
+        git_ref,
+        cmake_args,
+        configure_args,
+        binaries: source.binaries,
+        mirrors: source.mirrors,
+        sha256,
+        signing_keys: [options.signing_keys.clone(), source.signing_keys].concat(),
+        notes: source.notes,
+        make_targets: source.make_targets,
+        env: source.env,
+        recipe: source.recipe,
+        patches: source.patches,
+        ..options.clone()
+    };
+
+    if !options.force && !options.dry_run && options.bundle.is_none() {
+        if let Some(installed) = already_installed(&name, &url, options) {
+            outputln!(
+                green,
+                "`{}` is already installed at {} in {}, pass --force to build it again.",
+                name,
+                (installed.built_at()),
+                (installed.prefix.display())
+            );
+            return;
+        }
+    }
+
+    let put_aside = match options.upgrade && !options.dry_run && options.bundle.is_none() {
+        true => rollback::put_aside(&name, load_config().rollback_files == Some(true)),
+        false => None,
+    };
+    let result = install_from(&name, &url, options);
+    if let Some(put_aside) = put_aside {
+        match result.is_ok() {
+            true => put_aside.keep(),
+            false => put_aside.discard(),
+        }
+    }
+    // a bundle isn't installed, so there is nothing to undo.
+    if !options.dry_run && options.bundle.is_none() {
+        let operation = match (options.upgrade, options.rollback) {
+            (true, _) => Operation::Upgrade,
+            (_, true) => Operation::Rollback,
+            _ => Operation::Install,
+        };
+        let origin = match MergedDatabase::open().find(&name) {
+            Some((_, installed)) if result.is_ok() => Origin::of(installed),
+            _ => Origin {
+                url: Some(url.to_string()),
+                prefix: Some(options.prefix.clone()),
+                ..Default::default()
+            },
+        };
+        history::record(operation, &name, origin, None, &result);
+    }
+
+    if let Err(e) = result {
+        if options.dry_run {
+            outputln!(red, "failed to plan the install of `{}`. {}", name, e);
+        } else {
+            outputln!(red, "failed to install `{}`. {}", name, e);
+        }
+        if let Some(id) = run::id() {
+            outputln!(
+                red,
+                "the log for this run is at {}",
+                (run::log_path(&id).display())
+            );
+        }
+        std::process::exit(e.exit_code());
+    }
+    run::finish();
+}
+
+fn undo(yes: bool) {
+    let history = history::load();
+    let transaction = match history::last_reversible(&history) {
+        Some(t) => t,
+        None => {
+            outputln!(purple, "there is nothing to undo.");
+            return;
+        }
+    };
+
+    outputln!(
+        "the most recent reversible transaction is #{}: {} `{}` ({}).",
+        (transaction.id),
+        (transaction.operation),
+        (transaction.package),
+        (transaction.timestamp.format("%Y-%m-%d %H:%M"))
+    );
+    if !yes {
+        if !ui::interactive() {
+            outputln!(
+                red,
+                "there is no terminal to ask on, pass --yes to undo it."
+            );
+            std::process::exit(-1);
+        }
+        output!(on_blue, "undo it now? [Y/n] ");
+        let input: String = text_io::read!("{}\n");
+        if input.to_lowercase().starts_with('n') {
+            outputln!("okay, leaving everything as it is.");
+            return;
+        }
+    }
+
+    let name = &transaction.package;
+    let (operation, result) = match transaction.operation {
+        Operation::Install => {
+            let result = uninstall(name);
+            (Operation::Uninstall, result.map(|_| ()))
+        }
+        Operation::Uninstall => {
+            let url = transaction.url.as_deref().map(Url::parse);
+            let result = match url {
+                Some(Ok(url)) => {
+                    // undo already asked, and it goes back where it was, at the
+                    // commit that was removed rather than wherever its branch is now.
+                    let git_ref = transaction.commit.clone().or(transaction.git_ref.clone());
+                    let args = InstallArgs {
+                        yes: true,
+                        prefix: transaction.prefix.clone(),
+                        git_ref,
+                        ..Default::default()
+                    };
+                    let result = install_from(name, &url, &install_options(&args));
+                    // it follows the ref it did before, as after a rollback.
+                    if result.is_ok() {
+                        follow_ref(name, transaction.git_ref.clone());
+                    }
+                    result
+                }
+                _ => Err(InstallError::UnknownFatal(format!(
+                    "the history has no valid URL to reinstall `{}` from.",
+                    name
+                ))),
+            };
+            (Operation::Install, result)
+        }
+        _ => unreachable!("last_reversible only returns installs and uninstalls"),
+    };
+
+    history::record(
+        operation,
+        name,
+        transaction.origin(),
+        Some(transaction.id),
+        &result,
+    );
+    run::finish();
+
+    match result {
+        Ok(_) => outputln!(green, "undid transaction #{}.", (transaction.id)),
+        Err(e) => {
+            outputln!(
+                red,
+                "failed to undo transaction #{}. {}",
+                (transaction.id),
+                e
+            );
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn configure(action: ConfigAction) {
+    let result = match action {
+        ConfigAction::Get { key } => config::get(&key).map(|value| match value {
+            Some(value) => println!("{}", value),
+            None => outputln!(purple, "`{}` is not set.", key),
+        }),
+        ConfigAction::Set { key, value } => config::set(&key, &value),
+        ConfigAction::Unset { key } => config::unset(&key),
+        ConfigAction::List => config::list().map(|values| {
+            for (key, value) in values {
+                println!("{} = {}", key, value);
+            }
+        }),
+        ConfigAction::Keys => {
+            for (key, description) in config::KEYS {
+                println!("{} - {}", key, description);
+            }
+            Ok(())
+        }
+    };
+
+    if let Err(e) = result {
+        outputln!(red, "{}", e);
+        std::process::exit(-1);
+    }
+}
+
+fn develop_package(
+    registry: &PackageRegistry,
+    name: Option<&str>,
+    path: Option<&Path>,
+    remove: bool,
+    yes: bool,
+) {
+    let Some(name) = name else {
+        let checkouts = develop::load();
+        if checkouts.is_empty() {
+            outputln!("no packages are built from your own checkouts.");
+        }
+        for (name, path) in checkouts {
+            println!("{}  {}", name, path.display());
+        }
+        return;
+    };
+    if remove {
+        match develop::remove(name) {
+            Ok(Some(path)) => outputln!(
+                green,
+                "`{}` isn't built from {} any more, install it again to build it from upstream.",
+                name,
+                (path.display())
+            ),
+            Ok(None) => outputln!(purple, "`{}` isn't built from a checkout.", name),
+            Err(e) => {
+                outputln!(red, "{}", e);
+                std::process::exit(-1);
+            }
+        }
+        return;
+    }
+
+    if let Some(path) = path {
+        if let Err(e) = develop::set(name, path) {
+            outputln!(red, "{}", e);
+            std::process::exit(-1);
+        }
+        let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+        if let Some(root) = project::find_root(&dir) {
+            let declared = project::ProjectManifest::load(&root)
+                .is_ok_and(|m| m.dependencies.contains_key(name));
+            if !declared {
+                outputln!(
+                    purple,
+                    "`{}` isn't a dependency in {}.",
+                    name,
+                    (root.join(project::MANIFEST_NAME).display())
+                );
+            }
+        }
+    } else if develop::path(name).is_none() {
+        usage_error(format!(
+            "`{}` isn't built from a checkout, pass --path to say which.",
+            name
+        ));
+    }
+
+    // where it is installed already, if it is.
+    let prefix = MergedDatabase::open()
+        .find(name)
+        .map(|(_, p)| p.prefix.clone());
+    let args = InstallArgs {
+        target: name.to_string(),
+        prefix,
+        yes,
+        ..Default::default()
+    };
+    install(registry, name, None, &install_options(&args));
+}
+
+fn project_status(registry: &PackageRegistry, write_lock: bool) {
+    let dir = std::env::current_dir().unwrap_or_else(|_| PathBuf::from("."));
+    match status::status(registry, &dir, write_lock) {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            outputln!(red, "{}", e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn new_project(name: &str, template: Template) {
+    let options = install_options(&InstallArgs::default());
+    match project::scaffold(name, template, &options.prefix) {
+        Ok(root) => {
+            outputln!(green, "created the project `{}`.", name);
+            outputln!(
+                "add dependencies to {}/{} and build with `cmake -B build && cmake --build build`.",
+                (root.display()),
+                (project::MANIFEST_NAME)
+            );
+        }
+        Err(e) => {
+            outputln!(red, "failed to create the project. {}", e);
+            std::process::exit(-1);
+        }
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    tools::add_to_path();
+    // the config is loaded properly later, an invalid one shouldn't stop listings.
+    let config = Config::load().unwrap_or_default();
+    if cli.no_pager || config.pager == Some(false) {
+        pager::disable();
+    }
+    ui::configure(
+        config.theme,
+        config.label,
+        cli.a11y,
+        ui::detect_porcelain(cli.porcelain),
     );
+    if cli.verbose {
+        governor::set_verbose();
+    }
+    let registry = PackageRegistry::default();
+
+    let command = match (cli.command, cli.install) {
+        (Some(command), _) => command,
+        (None, Some(args)) => Command::Install(args),
+        (None, None) => usage_error("expected a subcommand or a package name/url."),
+    };
+
+    match command {
+        Command::Install(args) if args.forget => forget_choices(&registry, &args),
+        Command::Install(args) => install(
+            &registry,
+            &args.target,
+            args.fork.as_deref(),
+            &install_options(&args),
+        ),
+        Command::List {
+            filter,
+            installed,
+            long,
+        } => {
+            if installed {
+                list_installed(filter.as_deref(), long)
+            } else {
+                list_packages(&registry, filter.as_deref(), long)
+            }
+        }
+        Command::Search { query, long } => search_packages(&registry, &query.join(" "), long),
+        Command::Info {
+            package,
+            resolved,
+            notes,
+            github,
+        } => package_info(&registry, &package, resolved, notes, github),
+        Command::Uninstall { package } => uninstall_package(&package),
+        Command::Deregister { package } => {
+            if !deregister(&package) {
+                outputln!(
+                    "`{}` isn't in CMake's package registry in {}.",
+                    package,
+                    (cmake_registry::dir().display())
+                );
+            }
+        }
+        Command::Upgrade {
+            package,
+            all,
+            force,
+            yes,
+            dry_run,
+            keep_going: _,
+            fail_fast,
+        } => match package {
+            Some(package) => upgrade_package(&registry, &package, force, yes, dry_run),
+            None if all => upgrade_all(force, yes, dry_run, fail_fast),
+            None => usage_error("name a package to upgrade, or pass --all"),
+        },
+        Command::Outdated => outdated(),
+        Command::Relocate { package, to } => relocate_package(&package, &to),
+        Command::ExportArtifact { package, output } => {
+            let output = output
+                .unwrap_or_else(|| PathBuf::from(format!("{}.tar.zst", package.replace('/', "_"))));
+            if let Err(e) = artifact::export(&package, &output) {
+                outputln!(red, "failed to export `{}`. {}", package, e);
+                std::process::exit(-1);
+            }
+        }
+        Command::GenerateFormula {
+            package,
+            format,
+            output,
+        } => generate_formula(&registry, &package, format, output.as_deref()),
+        Command::ImportArtifact { file, prefix } => import_artifact(&file, prefix.as_deref()),
+        Command::History {
+            package,
+            operation,
+            failed,
+            limit,
+        } => history::print_history(&HistoryFilter {
+            package,
+            operation,
+            failed,
+            limit,
+        }),
+        Command::Rollback { package, yes } => rollback_package(&registry, &package, yes),
+        Command::Gc => {
+            if let Err(e) = store::gc() {
+                outputln!(red, "failed to clean the store up. {}", e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Undo { yes } => undo(yes),
+        Command::Cancel { run } => match cancel::request(&run) {
+            Ok(_) => outputln!(green, "cancelling run {}, it stops in a moment.", run),
+            Err(e) => {
+                outputln!(red, "failed to cancel run {}. {}", run, e);
+                std::process::exit(-1);
+            }
+        },
+        Command::Config { action } => configure(action),
+        Command::New { name, template } => new_project(&name, template),
+        Command::Develop {
+            package,
+            path,
+            remove,
+            yes,
+        } => develop_package(&registry, package.as_deref(), path.as_deref(), remove, yes),
+        Command::Status { write_lock } => project_status(&registry, write_lock),
+        Command::Integrate {
+            package,
+            cmake_lists,
+            yes,
+        } => {
+            if let Err(e) = integrate::integrate(&package, &cmake_lists, yes) {
+                outputln!(red, "failed to integrate `{}`. {}", package, e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Bisect {
+            package,
+            good,
+            bad,
+            test,
+        } => {
+            if let Err(e) = bisect::bisect(&registry, &package, &good, &bad, &test) {
+                outputln!(red, "failed to bisect `{}`. {}", package, e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Registry {
+            action: RegistryAction::Propose(args),
+        } => {
+            if let Err(e) = propose::propose(&registry, &args) {
+                outputln!(red, "failed to propose `{}`. {}", (args.url), e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Registry {
+            action:
+                RegistryAction::Add {
+                    name,
+                    url,
+                    description,
+                    language,
+                },
+        } => {
+            if let Err(e) = registry::add(&name, &url, &description, language) {
+                outputln!(red, "failed to add `{}`. {}", name, e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Registry {
+            action: RegistryAction::Remove { name },
+        } => {
+            if let Err(e) = registry::remove(&name) {
+                outputln!(red, "failed to remove `{}`. {}", name, e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Registry {
+            action: RegistryAction::Update { url },
+        } => {
+            if let Err(e) = registry::update(url.as_deref()) {
+                outputln!(red, "failed to update the registry. {}", e);
+                std::process::exit(-1);
+            }
+        }
+        Command::Registry {
+            action:
+                RegistryAction::Validate {
+                    file,
+                    templates,
+                    ping,
+                },
+        } => match registry::validate(file.as_deref(), templates.as_deref(), ping) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(-1),
+            Err(e) => {
+                outputln!(red, "failed to validate the registry. {}", e);
+                std::process::exit(-1);
+            }
+        },
+        Command::Size { package, long, .. } => match package {
+            Some(package) => {
+                if !size::package(&package, long) {
+                    std::process::exit(-1);
+                }
+            }
+            None => size::all(long),
+        },
+        Command::Verify { package, hashes } => {
+            if !verify::verify(package.as_deref(), hashes) {
+                std::process::exit(-1);
+            }
+        }
+        Command::Doctor { prefix } => {
+            let options = install_options(&InstallArgs {
+                prefix,
+                ..Default::default()
+            });
+            if !doctor::doctor(&options.prefix) {
+                std::process::exit(-1);
+            }
+        }
+        Command::Completions { shell } => print!("{}", completion::script(shell)),
+        Command::Complete { words } => completion::complete(&words),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use registry::{Language, Package};
+
+    fn registry_of(packages: &[(&str, &[&str])]) -> PackageRegistry {
+        packages
+            .iter()
+            .map(|(name, dependencies)| {
+                let mut package = Package::new("https://github.com/me/x", "", Language::C);
+                package.dependencies = dependencies.iter().map(|d| d.to_string()).collect();
+                (name.to_string(), package)
+            })
+            .collect()
+    }
+
+    fn order(registry: &PackageRegistry, name: &str) -> Result<Vec<String>, String> {
+        let dependencies = registry.get(name).unwrap().dependencies.clone();
+        let mut order = vec![];
+        dependency_order(registry, name, &dependencies, &mut vec![], &mut order)?;
+        Ok(order)
+    }
+
+    #[test]
+    fn dependencies_come_after_their_own() {
+        let registry = registry_of(&[
+            ("app", &["spdlog", "zlib"]),
+            ("spdlog", &["{fmt}"]),
+            ("{fmt}", &[]),
+            ("zlib", &[]),
+        ]);
+        assert_eq!(
+            order(&registry, "app").unwrap(),
+            ["{fmt}", "spdlog", "zlib"]
+        );
+        assert!(order(&registry, "zlib").unwrap().is_empty());
+    }
+
+    #[test]
+    fn shared_dependencies_are_installed_once() {
+        let registry = registry_of(&[
+            ("app", &["a", "b"]),
+            ("a", &["zlib"]),
+            ("b", &["zlib"]),
+            ("zlib", &[]),
+        ]);
+        assert_eq!(order(&registry, "app").unwrap(), ["zlib", "a", "b"]);
+    }
+
+    #[test]
+    fn dependency_cycles_are_found() {
+        let registry = registry_of(&[("app", &["a"]), ("a", &["b"]), ("b", &["a"])]);
+        let e = order(&registry, "app").unwrap_err();
+        assert!(e.contains("(app -> a -> b -> a)"), "{}", e);
+
+        let registry = registry_of(&[("app", &["app"])]);
+        assert!(order(&registry, "app").is_err());
+    }
+
+    #[test]
+    fn unknown_dependencies_are_reported() {
+        let registry = registry_of(&[("app", &["nope"])]);
+        let e = order(&registry, "app").unwrap_err();
+        assert_eq!(e, "`app` depends on `nope`, which isn't in the registry");
+    }
 }
@@ -0,0 +1,195 @@
+// Install manifests list every file a package put on the system, so that
+// it can be removed again by `cinstall uninstall`.
+//
+// One manifest per package lives in `~/.local/share/cinstall/manifests/`.
+// The size and SHA-256 of every regular file are recorded too, so that
+// `cinstall verify --hashes` can tell when one was changed after the install.
+
+use crate::installer::InstallError;
+use crate::multiarch;
+use crate::outputln;
+use crate::paths;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ManifestFile {
+    pub path: PathBuf,
+    /// The architecture of a library in `lib/<triple>` (see `multiarch`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+    /// Not recorded for symlinks, or by versions of cinstall before checksums.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Manifest {
+    pub package: String,
+    pub prefix: PathBuf,
+    pub files: Vec<ManifestFile>,
+}
+
+pub fn manifests_dir() -> PathBuf {
+    let mut path = paths::data_dir();
+    path.push("manifests");
+    path
+}
+
+/// `package` as a file name, package names can contain characters like `/`
+/// that can't be in one.
+pub fn file_name(package: &str) -> String {
+    package
+        .chars()
+        .map(|c| if c == '/' || c == '\0' { '_' } else { c })
+        .collect()
+}
+
+fn manifest_path(package: &str) -> PathBuf {
+    manifests_dir().join(format!("{}.json", file_name(package)))
+}
+
+impl Manifest {
+    pub fn new(package: &str, prefix: &Path, files: &[PathBuf]) -> Self {
+        let mut unique: Vec<PathBuf> = vec![];
+        for file in files {
+            if !unique.contains(file) {
+                unique.push(file.clone());
+            }
+        }
+
+        Self {
+            package: package.to_string(),
+            prefix: prefix.to_path_buf(),
+            files: unique
+                .into_iter()
+                .map(|path| {
+                    let regular = std::fs::symlink_metadata(&path)
+                        .ok()
+                        .filter(|m| m.is_file());
+                    ManifestFile {
+                        arch: multiarch::arch_of(prefix, &path).map(String::from),
+                        size: regular.as_ref().map(|m| m.len()),
+                        sha256: regular.and_then(|_| sha256_of(&path)),
+                        path,
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    pub fn load(package: &str) -> Result<Self, InstallError> {
+        let path = manifest_path(package);
+        let contents = std::fs::read_to_string(&path).map_err(|_| {
+            InstallError::UnknownFatal(format!(
+                "there is no install manifest for `{}`, so cinstall doesn't know which files it installed.",
+                package
+            ))
+        })?;
+        serde_json::from_str(&contents)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self) -> Result<(), InstallError> {
+        let path = manifest_path(&self.package);
+        std::fs::create_dir_all(manifests_dir())
+            .map_err(|_| InstallError::FailedToCreateDirectory)?;
+        let json = serde_json::to_string_pretty(self)
+            .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+        std::fs::write(&path, json).map_err(|_| InstallError::FailedToWriteToFile)
+    }
+
+    pub fn delete(&self) -> Result<(), InstallError> {
+        let path = manifest_path(&self.package);
+        if path.exists() {
+            std::fs::remove_file(&path)
+                .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+        }
+        Ok(())
+    }
+
+    /// Delete every file in the manifest, then any directories that were left
+    /// empty, without ever walking above the prefix. Returns how many files were removed.
+    pub fn remove_files(&self) -> Result<usize, InstallError> {
+        let mut removed = 0;
+        let mut parents: Vec<PathBuf> = vec![];
+
+        for file in &self.files {
+            let path = &file.path;
+            if !path.exists() && !path.is_symlink() {
+                outputln!(purple, "`{}` is already gone, skipping.", (path.display()));
+                continue;
+            }
+
+            std::fs::remove_file(path).map_err(|e| {
+                InstallError::BadDirectory(format!(
+                    "{}: {} (you may need to `sudo`)",
+                    path.display(),
+                    e
+                ))
+            })?;
+            removed += 1;
+
+            if let Some(parent) = path.parent() {
+                if !parents.iter().any(|p| p == parent) {
+                    parents.push(parent.to_path_buf());
+                }
+            }
+        }
+
+        // deepest directories first so that nested empty directories collapse.
+        parents.sort_by_key(|p| std::cmp::Reverse(p.components().count()));
+        for parent in parents {
+            remove_empty_dirs(&parent, &self.prefix);
+        }
+
+        Ok(removed)
+    }
+}
+
+/// The SHA-256 of the file at `path`, in hex.
+pub fn sha256_of(path: &Path) -> Option<String> {
+    let mut file = std::fs::File::open(path).ok()?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher).ok()?;
+    Some(format!("{:x}", hasher.finalize()))
+}
+
+/// Map every file recorded in any manifest to the package that installed it.
+pub fn owners() -> HashMap<PathBuf, String> {
+    let mut owners = HashMap::new();
+    let entries = match std::fs::read_dir(manifests_dir()) {
+        Ok(e) => e,
+        Err(_) => return owners,
+    };
+
+    for entry in entries.flatten() {
+        let manifest = std::fs::read_to_string(entry.path())
+            .ok()
+            .and_then(|c| serde_json::from_str::<Manifest>(&c).ok());
+        if let Some(manifest) = manifest {
+            for file in manifest.files {
+                owners.insert(file.path, manifest.package.clone());
+            }
+        }
+    }
+    owners
+}
+
+fn remove_empty_dirs(dir: &Path, stop_at: &Path) {
+    let mut current = Some(dir);
+    while let Some(dir) = current {
+        if dir == stop_at || !dir.starts_with(stop_at) {
+            break;
+        }
+        // remove_dir only succeeds on empty directories.
+        if std::fs::remove_dir(dir).is_err() {
+            break;
+        }
+        current = dir.parent();
+    }
+}
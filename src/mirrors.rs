@@ -0,0 +1,132 @@
+// Getting the source from somewhere else when its host is down.
+//
+// A package can be cloned from the mirrors its registry entry lists, and the
+// `mirrors` config key adds places to get anything on a host from, e.g.
+// `mirrors = { "github.com" = ["https://git.example.com/github"] }` clones
+// https://github.com/fmtlib/fmt from https://git.example.com/github/fmtlib/fmt.
+//
+// Before cloning or downloading from a host, cinstall checks that it answers,
+// once per run. A host that doesn't is skipped for the next source without
+// waiting through the retries, and one a clone or download failed on because
+// of the network is taken as down for the rest of the run. When every source
+// is down they are tried anyway, in case the check was wrong.
+
+use crate::config::Config;
+use crate::installer::InstallError;
+use crate::outputln;
+use std::collections::BTreeMap;
+use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use url::Url;
+
+/// Whether each host checked this run answered.
+static HEALTH: Mutex<BTreeMap<String, bool>> = Mutex::new(BTreeMap::new());
+
+fn host(url: &Url) -> String {
+    match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    }
+}
+
+/// Whether the host of `url` answers at all. Only HTTP hosts are asked, git
+/// over SSH has no cheap way to.
+fn answers(url: &Url) -> bool {
+    if !matches!(url.scheme(), "http" | "https") {
+        return true;
+    }
+    let mut root = url.clone();
+    root.set_path("/");
+    root.set_query(None);
+    Command::new("curl")
+        .args(["-sS", "-o", "/dev/null", "-I", "--max-time", "10"])
+        .arg(root.as_str())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Whether the host of `url` is up, asking it the first time.
+pub fn is_up(url: &Url) -> bool {
+    let host = host(url);
+    if let Some(&up) = HEALTH.lock().unwrap().get(&host) {
+        return up;
+    }
+    let up = answers(url);
+    HEALTH.lock().unwrap().insert(host, up);
+    up
+}
+
+/// Ask the host of `url` again, after something from it failed.
+pub fn recheck(url: &Url) -> bool {
+    let up = answers(url);
+    HEALTH.lock().unwrap().insert(host(url), up);
+    up
+}
+
+/// Where `url` is on the mirrors the `mirrors` config key gives for its host.
+fn configured(url: &Url) -> Vec<Url> {
+    let mirrors = Config::load()
+        .ok()
+        .and_then(|c| c.mirrors)
+        .unwrap_or_default();
+    mirrors
+        .get(&host(url))
+        .or_else(|| mirrors.get(url.host_str().unwrap_or_default()))
+        .into_iter()
+        .flatten()
+        .filter_map(|base| {
+            Url::parse(&format!("{}{}", base.trim_end_matches('/'), url.path())).ok()
+        })
+        .collect()
+}
+
+/// `url`, then the package's own `mirrors`, then the configured ones.
+pub fn sources(url: &Url, mirrors: &[Url]) -> Vec<Url> {
+    let mut sources = vec![url.clone()];
+    for source in mirrors.iter().cloned().chain(configured(url)) {
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+    }
+    sources
+}
+
+/// Get `url` with `get`, going on to its mirrors while it fails to get the
+/// source. Sources on hosts that are down are left for last.
+pub fn fail_over<T>(
+    url: &Url,
+    mirrors: &[Url],
+    mut get: impl FnMut(&Url) -> Result<T, InstallError>,
+) -> Result<T, InstallError> {
+    let sources = sources(url, mirrors);
+    if sources.len() == 1 {
+        return get(url);
+    }
+    let (up, down): (Vec<Url>, Vec<Url>) = sources.into_iter().partition(is_up);
+    for source in &down {
+        outputln!(
+            purple,
+            "{} isn't answering, trying it last.",
+            (host(source))
+        );
+    }
+    let mut result = Err(InstallError::FailedToClone);
+    for (i, source) in up.iter().chain(&down).enumerate() {
+        if source != url {
+            outputln!(purple, "trying the mirror {}", source);
+        } else if i > 0 {
+            outputln!(purple, "trying {} itself", source);
+        }
+        result = get(source);
+        match &result {
+            Err(InstallError::NetworkError(_)) => {
+                HEALTH.lock().unwrap().insert(host(source), false);
+            }
+            Err(e) if e.is_clone_failure() => {}
+            _ => break,
+        }
+    }
+    result
+}
@@ -0,0 +1,144 @@
+// Environment files for installed packages.
+//
+// Packages installed into their own prefix (as on HPC systems, where every
+// library version gets a directory) aren't found by compilers, pkg-config or
+// cmake until the environment points at them. Every install writes a script
+// to `source` and an environment module for `module load`, with the variables
+// the package's files need:
+//
+//   ~/.local/share/cinstall/env/<package>.sh
+//   ~/.local/share/cinstall/modulefiles/<package>
+//
+// `module use ~/.local/share/cinstall/modulefiles` makes all of them loadable.
+
+use crate::database::InstalledPackage;
+use crate::{doctor, paths};
+use std::path::{Path, PathBuf};
+
+pub fn env_dir() -> PathBuf {
+    paths::data_dir().join("env")
+}
+
+pub fn modulefiles_dir() -> PathBuf {
+    paths::data_dir().join("modulefiles")
+}
+
+fn file_name(package: &str) -> String {
+    package
+        .chars()
+        .map(|c| if c == '/' || c == '\0' { '_' } else { c })
+        .collect()
+}
+
+pub fn script_path(package: &str) -> PathBuf {
+    env_dir().join(format!("{}.sh", file_name(package)))
+}
+
+pub fn modulefile_path(package: &str) -> PathBuf {
+    modulefiles_dir().join(file_name(package))
+}
+
+/// The variables `files` need, with the directories to put in front of each.
+fn variables(prefix: &Path, files: &[PathBuf]) -> Vec<(&'static str, Vec<PathBuf>)> {
+    let has_files_in = |dir: &Path| files.iter().any(|f| f.starts_with(dir));
+    let existing = |dirs: &[&str]| -> Vec<PathBuf> {
+        dirs.iter()
+            .map(|d| prefix.join(d))
+            .filter(|d| has_files_in(d))
+            .collect()
+    };
+    let mut libraries: Vec<PathBuf> = vec![];
+    for file in files {
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        if name.ends_with(".so") || name.contains(".so.") || name.ends_with(".dylib") {
+            if let Some(dir) = file.parent().filter(|d| !libraries.iter().any(|l| l == d)) {
+                libraries.push(dir.to_path_buf());
+            }
+        }
+    }
+    let library_path = if std::env::consts::OS == "macos" {
+        "DYLD_LIBRARY_PATH"
+    } else {
+        "LD_LIBRARY_PATH"
+    };
+
+    vec![
+        ("PATH", existing(&["bin"])),
+        (library_path, libraries),
+        ("PKG_CONFIG_PATH", doctor::pkg_config_dirs_of(files)),
+        ("CPATH", existing(&["include"])),
+        ("MANPATH", existing(&["share/man", "man"])),
+        ("CMAKE_PREFIX_PATH", vec![prefix.to_path_buf()]),
+    ]
+    .into_iter()
+    .filter(|(_, dirs)| !dirs.is_empty())
+    .collect()
+}
+
+fn joined(dirs: &[PathBuf]) -> String {
+    let dirs: Vec<String> = dirs.iter().map(|d| d.display().to_string()).collect();
+    dirs.join(":")
+}
+
+fn describe(package: &InstalledPackage) -> String {
+    let mut what = format!("`{}` from {}", package.name, package.url);
+    match (&package.git_ref, &package.commit) {
+        (Some(git_ref), Some(commit)) => what += &format!(" ({}, {})", git_ref, commit),
+        (Some(version), None) | (None, Some(version)) => what += &format!(" ({})", version),
+        (None, None) => {}
+    }
+    format!("{} installed into {}", what, package.prefix.display())
+}
+
+fn script(package: &InstalledPackage, variables: &[(&str, Vec<PathBuf>)]) -> String {
+    let mut text = format!(
+        "# written by cinstall: {}\n# use it with `source {}`\n",
+        describe(package),
+        script_path(&package.name).display()
+    );
+    for (name, dirs) in variables {
+        text += &format!("export {name}=\"{}${{{name}:+:${name}}}\"\n", joined(dirs));
+    }
+    text
+}
+
+fn modulefile(package: &InstalledPackage, variables: &[(&str, Vec<PathBuf>)]) -> String {
+    let mut text = format!(
+        "#%Module1.0\n## written by cinstall: {}\nmodule-whatis \"{}\"\n",
+        describe(package),
+        package.name
+    );
+    for (name, dirs) in variables {
+        for dir in dirs.iter().rev() {
+            text += &format!("prepend-path {} {}\n", name, dir.display());
+        }
+    }
+    text
+}
+
+/// Write the script and module for `package`, returning the script's path.
+pub fn write(package: &InstalledPackage, files: &[PathBuf]) -> Result<PathBuf, String> {
+    let variables = variables(&package.prefix, files);
+    for (dir, path, text) in [
+        (
+            env_dir(),
+            script_path(&package.name),
+            script(package, &variables),
+        ),
+        (
+            modulefiles_dir(),
+            modulefile_path(&package.name),
+            modulefile(package, &variables),
+        ),
+    ] {
+        std::fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        std::fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))?;
+    }
+    Ok(script_path(&package.name))
+}
+
+/// Forget the environment of an uninstalled package.
+pub fn remove(package: &str) {
+    let _ = std::fs::remove_file(script_path(package));
+    let _ = std::fs::remove_file(modulefile_path(package));
+}
@@ -0,0 +1,189 @@
+// `--multiarch`: installing a package for more than one architecture.
+//
+// On x86_64 Linux the package is built twice, for x86_64 and for i386 (with
+// `-m32`, which needs the 32-bit toolchain, e.g. gcc-multilib), and the
+// libraries of each go into `lib/<triple>` the way Debian lays them out.
+// Headers and programs are shared, the native build is installed last so its
+// programs win. On macOS a single universal build for arm64 and x86_64 is made
+// instead, the compilers can target both at once.
+//
+// Only CMake and Meson projects can be built like this, they are the build
+// systems that can be told where libraries go and build out of the source tree.
+
+use crate::doctor;
+use crate::installer::{InstallError, InstallMethod, InstallOptions, Step};
+use std::path::Path;
+
+struct Arch {
+    name: &'static str,
+    /// The multiarch triple, also the directory under `lib`.
+    triple: &'static str,
+    flags: &'static str,
+}
+
+/// The native architecture comes last.
+const LINUX_X86_64: &[Arch] = &[
+    Arch {
+        name: "i386",
+        triple: "i386-linux-gnu",
+        flags: "-m32",
+    },
+    Arch {
+        name: "x86_64",
+        triple: "x86_64-linux-gnu",
+        flags: "-m64",
+    },
+];
+
+const MACOS_ARCHITECTURES: &[&str] = &["arm64", "x86_64"];
+
+/// One of the builds a multiarch install is made of.
+struct Target {
+    /// Used to name the build directory.
+    name: String,
+    /// Where libraries go, relative to the prefix, when not `lib`.
+    libdir: Option<String>,
+    cmake_args: Vec<String>,
+    /// For the compiler and linker.
+    flags: String,
+}
+
+fn targets() -> Result<Vec<Target>, InstallError> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("macos", _) => Ok(vec![Target {
+            name: "universal".into(),
+            libdir: None,
+            cmake_args: vec![format!(
+                "-DCMAKE_OSX_ARCHITECTURES={}",
+                MACOS_ARCHITECTURES.join(";")
+            )],
+            flags: MACOS_ARCHITECTURES
+                .iter()
+                .map(|a| format!("-arch {}", a))
+                .collect::<Vec<_>>()
+                .join(" "),
+        }]),
+        ("linux", "x86_64") => Ok(LINUX_X86_64
+            .iter()
+            .map(|arch| Target {
+                name: arch.triple.into(),
+                libdir: Some(format!("lib/{}", arch.triple)),
+                cmake_args: vec![
+                    format!("-DCMAKE_C_FLAGS={}", arch.flags),
+                    format!("-DCMAKE_CXX_FLAGS={}", arch.flags),
+                ],
+                flags: arch.flags.into(),
+            })
+            .collect()),
+        (os, arch) => Err(InstallError::UnknownFatal(format!(
+            "--multiarch isn't supported on {}/{}, only x86_64 Linux and macOS.",
+            os, arch
+        ))),
+    }
+}
+
+/// Fail early if `method` can't be used for a multiarch install here.
+pub fn check(method: &InstallMethod) -> Result<(), InstallError> {
+    if !matches!(method, InstallMethod::RunCMake | InstallMethod::Meson) {
+        return Err(InstallError::UnknownFatal(format!(
+            "--multiarch only works for CMake and Meson projects, this one uses {}.",
+            method
+        )));
+    }
+    targets().map(|_| ())
+}
+
+fn cmake_steps(
+    path: &Path,
+    options: &InstallOptions,
+    staging: &Path,
+    target: &Target,
+) -> Vec<Step> {
+    let build = format!("build-{}", target.name);
+    let mut configure = Step::new("cmake", path)
+        .arg("-S")
+        .arg(".")
+        .arg("-B")
+        .arg(&build)
+        .arg("-DCMAKE_BUILD_TYPE=Release")
+        .arg(format!(
+            "-DCMAKE_INSTALL_PREFIX={}",
+            options.prefix.display()
+        ))
+        .args(&target.cmake_args);
+    if let Some(libdir) = &target.libdir {
+        configure = configure.arg(format!("-DCMAKE_INSTALL_LIBDIR={}", libdir));
+    }
+    if doctor::find_program("ninja").is_some() {
+        configure = configure.arg("-G").arg("Ninja");
+    }
+    vec![
+        configure.args(&options.cmake_args),
+        Step::new("cmake", path).arg("--build").arg(&build),
+        Step::new("cmake", path)
+            .arg("--install")
+            .arg(&build)
+            .env("DESTDIR", &staging.display().to_string()),
+    ]
+}
+
+fn meson_steps(
+    path: &Path,
+    options: &InstallOptions,
+    staging: &Path,
+    target: &Target,
+) -> Vec<Step> {
+    let build = format!("builddir-{}", target.name);
+    let mut setup = Step::new("meson", path)
+        .arg("setup")
+        .arg(&build)
+        .arg(format!("--prefix={}", options.prefix.display()));
+    if let Some(libdir) = &target.libdir {
+        setup = setup.arg(format!("--libdir={}", libdir));
+    }
+    vec![
+        setup
+            .env("CFLAGS", &target.flags)
+            .env("CXXFLAGS", &target.flags)
+            .env("LDFLAGS", &target.flags),
+        Step::new("meson", path)
+            .arg("compile")
+            .arg("-C")
+            .arg(&build),
+        Step::new("meson", path)
+            .arg("install")
+            .arg("-C")
+            .arg(&build)
+            .arg("--destdir")
+            .arg(staging.display().to_string()),
+    ]
+}
+
+/// The commands that build and stage the project for every architecture.
+pub fn steps(
+    path: &Path,
+    method: &InstallMethod,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Vec<Step> {
+    targets()
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|target| match method {
+            InstallMethod::RunCMake => cmake_steps(path, options, staging, target),
+            _ => meson_steps(path, options, staging, target),
+        })
+        .map(|step| step.envs(&options.env))
+        .collect()
+}
+
+/// The architecture an installed file is for, if it is in a `lib/<triple>`
+/// directory of the prefix.
+pub fn arch_of(prefix: &Path, file: &Path) -> Option<&'static str> {
+    let relative = file.strip_prefix(prefix.join("lib")).ok()?;
+    let dir = relative.components().next()?.as_os_str().to_str()?;
+    LINUX_X86_64
+        .iter()
+        .find(|arch| arch.triple == dir)
+        .map(|arch| arch.name)
+}
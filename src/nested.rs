@@ -0,0 +1,186 @@
+// The dependencies a project pulls in itself.
+//
+// Some projects don't leave their dependencies to the system: they are git
+// submodules, or CMake's FetchContent downloads them while configuring.
+// Either way the package is built from more source than its own repository,
+// so after a build the URL and commit of each of them is recorded with the
+// install, and `cinstall info` lists them.
+//
+// Submodules are pinned by the commit of the project already. FetchContent
+// often follows a branch, so when the same commit of a package is built
+// again, each dependency it cloned is checked out at the commit the last
+// build used and handed to CMake with `FETCHCONTENT_SOURCE_DIR_<NAME>`, and
+// the rebuild gets the same source as the build before it.
+
+use crate::cmake_cache;
+use crate::database::{MergedDatabase, Nested, Via};
+use crate::installer::InstallError;
+use crate::outputln;
+use crate::workspace;
+use std::path::Path;
+use std::process::Command;
+use url::Url;
+
+/// What `git <args>` prints in `dir`, if it succeeds.
+fn git_output(dir: &Path, args: &[&str]) -> Option<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// The submodules checked out in `dir`, nested ones included.
+fn submodules(dir: &Path) -> Vec<Nested> {
+    if !dir.join(".gitmodules").is_file() {
+        return vec![];
+    }
+    let listed = git_output(
+        dir,
+        &[
+            "submodule",
+            "foreach",
+            "--quiet",
+            "--recursive",
+            r#"printf '%s\t%s\t%s\n' "$displaypath" "$sha1" "$(git config remote.origin.url)""#,
+        ],
+    )
+    .unwrap_or_default();
+    listed
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let (path, commit, url) = (fields.next()?, fields.next()?, fields.next()?);
+            Some(Nested {
+                name: path.to_string(),
+                via: Via::Submodule,
+                url: url.to_string(),
+                commit: Some(commit.to_string()),
+            })
+        })
+        .collect()
+}
+
+/// The URL FetchContent downloaded the archive of `name` from, which it
+/// writes into the sub-build it populates the dependency with.
+fn archive_url(deps: &Path, name: &str) -> Option<String> {
+    let script = std::fs::read_to_string(
+        deps.join(format!("{}-subbuild", name))
+            .join("CMakeLists.txt"),
+    )
+    .ok()?;
+    let mut words = script.split_whitespace().map(|w| w.trim_matches('"'));
+    words.find(|w| *w == "URL")?;
+    words.next().map(String::from)
+}
+
+/// The dependencies FetchContent put in `_deps/` of the build of `dir`.
+fn fetched(dir: &Path) -> Vec<Nested> {
+    let Some(deps) = cmake_cache::find(dir).and_then(|cache| Some(cache.parent()?.join("_deps")))
+    else {
+        return vec![];
+    };
+    let Ok(entries) = std::fs::read_dir(&deps) else {
+        return vec![];
+    };
+    let mut fetched = vec![];
+    for entry in entries.flatten() {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        let Some(name) = file_name.strip_suffix("-src") else {
+            continue;
+        };
+        let src = entry.path();
+        let (url, commit) = if src.join(".git").exists() {
+            (
+                git_output(&src, &["config", "remote.origin.url"]),
+                git_output(&src, &["rev-parse", "HEAD"]),
+            )
+        } else {
+            (archive_url(&deps, name), None)
+        };
+        // a directory handed in with FETCHCONTENT_SOURCE_DIR isn't in `_deps/`,
+        // anything without a URL wasn't downloaded.
+        if let Some(url) = url {
+            fetched.push(Nested {
+                name: name.to_string(),
+                via: Via::FetchContent,
+                url,
+                commit,
+            });
+        }
+    }
+    fetched
+}
+
+/// The nested dependencies the project in `dir` was built with, `pinned`
+/// being the ones that were handed to CMake rather than fetched.
+pub fn find(dir: &Path, pinned: &[Nested]) -> Vec<Nested> {
+    let mut nested = submodules(dir);
+    nested.extend(fetched(dir));
+    for pin in pinned {
+        if !nested.iter().any(|n| n.name == pin.name) {
+            nested.push(pin.clone());
+        }
+    }
+    nested.sort_by(|a, b| a.name.cmp(&b.name));
+    nested
+}
+
+/// The FetchContent dependencies the last build of `name` cloned, when it
+/// was of `commit` too.
+pub fn pins(name: &str, commit: Option<&str>) -> Vec<Nested> {
+    let database = MergedDatabase::open();
+    let Some((_, installed)) = database.find(name) else {
+        return vec![];
+    };
+    let cloned: Vec<Nested> = installed
+        .build
+        .iter()
+        .flat_map(|build| &build.nested)
+        .filter(|n| n.via == Via::FetchContent && n.commit.is_some())
+        .cloned()
+        .collect();
+    if cloned.is_empty() {
+        return vec![];
+    }
+    if commit.is_none() || installed.commit.as_deref() != commit {
+        outputln!(
+            "`{}` is built at another commit than last time, its FetchContent dependencies aren't pinned.",
+            name
+        );
+        return vec![];
+    }
+    outputln!(
+        "building the FetchContent dependencies of `{}` at the commits its last build used.",
+        name
+    );
+    cloned
+}
+
+/// Check out the `pinned` dependencies, returning the CMake arguments that
+/// make FetchContent use the checkouts.
+pub fn checkout_pins(pinned: &[Nested]) -> Result<Vec<String>, InstallError> {
+    let mut args = vec![];
+    for nested in pinned {
+        let Ok(url) = Url::parse(&nested.url) else {
+            outputln!(
+                purple,
+                "`{}` was fetched from {}, which can't be cloned again, it isn't pinned.",
+                (nested.name),
+                (nested.url)
+            );
+            continue;
+        };
+        let (dir, _) = workspace::checkout(&url, nested.commit.as_deref(), false)?;
+        args.push(format!(
+            "-DFETCHCONTENT_SOURCE_DIR_{}={}",
+            nested.name.to_uppercase(),
+            dir.display()
+        ));
+    }
+    Ok(args)
+}
@@ -0,0 +1,121 @@
+// Waiting for the system package manager to be free.
+//
+// apt and pacman only let one process change the system at a time. When
+// another one is running (an update in another terminal, unattended-upgrades
+// on a fresh machine), installing a missing tool fails with the package
+// manager's own lock error. Instead, cinstall waits for it to finish for a
+// while, counting down, and fails saying what holds the lock if it doesn't.
+// In accessibility and porcelain mode the countdown isn't redrawn, a line
+// saying how long is left is printed every few seconds instead.
+
+use crate::installer::InstallError;
+use crate::{output, outputln, ui};
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// How long to wait for another package manager to finish.
+const WAIT: Duration = Duration::from_secs(120);
+
+/// How often to say how long is left when the countdown isn't redrawn.
+const STATUS_EVERY: Duration = Duration::from_secs(10);
+
+/// The processes that hold apt's locks while they run.
+const APT_PROCESSES: &[&str] = &[
+    "apt",
+    "apt-get",
+    "aptitude",
+    "dpkg",
+    "unattended-upgr",
+    "packagekitd",
+];
+
+const PACMAN_LOCK: &str = "/var/lib/pacman/db.lck";
+
+/// The first running process named one of `names`, as `name (pid)`.
+fn running(names: &[&str]) -> Option<String> {
+    let me = std::process::id().to_string();
+    std::fs::read_dir("/proc")
+        .ok()?
+        .flatten()
+        .find_map(|entry| {
+            let pid = entry.file_name().to_string_lossy().to_string();
+            if pid == me || !pid.chars().all(|c| c.is_ascii_digit()) {
+                return None;
+            }
+            let comm = std::fs::read_to_string(entry.path().join("comm")).ok()?;
+            let comm = comm.trim();
+            names
+                .contains(&comm)
+                .then(|| format!("`{}` (pid {})", comm, pid))
+        })
+}
+
+/// The process holding the lock of `manager`, if any.
+fn holder(manager: &str) -> Option<String> {
+    match manager {
+        "pacman" => running(&["pacman", "yay", "paru", "packagekitd"]),
+        "apt" => running(APT_PROCESSES),
+        _ => None,
+    }
+}
+
+/// Wait until `manager` is free, failing if it doesn't get free in time.
+pub fn wait_for(manager: &str) -> Result<(), InstallError> {
+    let Some(mut held_by) = holder(manager) else {
+        // a lock file nothing is running for won't go away by waiting.
+        if manager == "pacman" && Path::new(PACMAN_LOCK).exists() {
+            return Err(InstallError::PackageManagerBusy(format!(
+                "pacman is locked but isn't running, the lock was probably left behind by one that was killed. if nothing else is changing packages, remove it with `sudo rm {}`.",
+                PACMAN_LOCK
+            )));
+        }
+        return Ok(());
+    };
+
+    let redraw = !ui::a11y() && !ui::porcelain();
+    let start = Instant::now();
+    let mut next_status = Duration::ZERO;
+    while start.elapsed() < WAIT {
+        let left = (WAIT - start.elapsed()).as_secs();
+        if redraw {
+            eprint!("\r");
+            output!(
+                purple,
+                "{} is busy, {} is using it. waiting for it to finish ({}s left)... ",
+                manager,
+                held_by,
+                left
+            );
+            let _ = std::io::stderr().flush();
+        } else if start.elapsed() >= next_status {
+            outputln!(
+                purple,
+                "{} is busy, {} is using it. waiting for it to finish, {} seconds left.",
+                manager,
+                held_by,
+                left
+            );
+            next_status += STATUS_EVERY;
+        }
+        std::thread::sleep(Duration::from_secs(1));
+        match holder(manager) {
+            Some(now) => held_by = now,
+            None => {
+                if redraw {
+                    eprintln!();
+                }
+                return Ok(());
+            }
+        }
+    }
+    if redraw {
+        eprintln!();
+    }
+    Err(InstallError::PackageManagerBusy(format!(
+        "{} is still being used by {} after {} seconds, try again once it is done.",
+        manager,
+        held_by,
+        WAIT.as_secs()
+    )))
+}
@@ -0,0 +1,56 @@
+// Long output is shown through a pager, the way git does it.
+//
+// Only output that doesn't fit on the screen is paged, and only when stdout is
+// a terminal. `--no-pager` or `pager = false` in the config turn it off.
+
+use std::io::{IsTerminal, Write};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use terminal_size::{terminal_size, Height};
+
+static DISABLED: AtomicBool = AtomicBool::new(false);
+
+pub fn disable() {
+    DISABLED.store(true, Ordering::Relaxed);
+}
+
+fn should_page(text: &str) -> bool {
+    if DISABLED.load(Ordering::Relaxed) || !std::io::stdout().is_terminal() {
+        return false;
+    }
+    match terminal_size() {
+        Some((_, Height(height))) => text.lines().count() >= height as usize,
+        None => false,
+    }
+}
+
+/// Print `text` to stdout, through `$PAGER` (or `less -R`) if it is too long for the screen.
+pub fn page(text: &str) {
+    if !should_page(text) {
+        print!("{}", text);
+        return;
+    }
+
+    let pager = std::env::var("PAGER")
+        .ok()
+        .filter(|p| !p.trim().is_empty())
+        .unwrap_or_else(|| "less -R".to_string());
+    let child = Command::new("sh")
+        .arg("-c")
+        .arg(&pager)
+        .stdin(Stdio::piped())
+        .spawn();
+
+    let mut child = match child {
+        Ok(c) => c,
+        Err(_) => {
+            print!("{}", text);
+            return;
+        }
+    };
+    if let Some(mut stdin) = child.stdin.take() {
+        // the user quitting the pager early closes the pipe, which is fine.
+        let _ = stdin.write_all(text.as_bytes());
+    }
+    let _ = child.wait();
+}
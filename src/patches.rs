@@ -0,0 +1,91 @@
+// Patches applied to a package before it is built.
+//
+// Some projects need a small fix to build or install cleanly, like an include
+// newer compilers insist on or an install rule with a hardcoded path. Registry
+// entries can list `patches`, each a URL or the name of a patch file, which are
+// applied with `git apply` once the source is checked out and before it is
+// configured. Patch files are looked for among the ones bundled with cinstall
+// first, then in `~/.config/cinstall/patches`.
+//
+// The checkout is reset before every install, so a patch is never applied
+// twice.
+
+use crate::installer::{InstallError, Step};
+use crate::{outputln, paths, retry, workspace};
+use std::path::{Path, PathBuf};
+
+/// The patches built into cinstall, as `(name, include_str!("patches/<name>"))`.
+const BUNDLED: &[(&str, &str)] = &[];
+
+/// `~/.config/cinstall/patches`, where your own patch files go.
+pub fn dir() -> PathBuf {
+    paths::config_dir().join("patches")
+}
+
+/// Whether `patch` names a patch to download rather than a file.
+fn is_url(patch: &str) -> bool {
+    patch.starts_with("https://") || patch.starts_with("http://")
+}
+
+/// Get `patch` into `into`, downloading it if it is a URL, and return where it is.
+fn fetch(patch: &str, into: &Path) -> Result<PathBuf, InstallError> {
+    let file_name = patch.rsplit('/').next().unwrap_or(patch);
+    let file = into.join(file_name);
+    if is_url(patch) {
+        let curl = Step::new("curl", into)
+            .arg("-fsSL")
+            .arg("-o")
+            .arg(file.display().to_string())
+            .arg(patch);
+        if !retry::run(&curl)?.success() {
+            return Err(InstallError::StepFailed(curl.to_string()));
+        }
+        return Ok(file);
+    }
+    if let Some((_, contents)) = BUNDLED.iter().find(|(name, _)| *name == patch) {
+        std::fs::write(&file, contents).map_err(|_| InstallError::FailedToWriteToFile)?;
+        return Ok(file);
+    }
+    let path = dir().join(patch);
+    if path.is_file() {
+        return Ok(path);
+    }
+    Err(InstallError::UnknownFatal(format!(
+        "the patch `{}` isn't bundled with cinstall or in {}.",
+        patch,
+        dir().display()
+    )))
+}
+
+/// Apply `patches` to the source of `name` in `source`, in order, returning
+/// the commands that applied them.
+pub fn apply(name: &str, source: &Path, patches: &[String]) -> Result<Vec<Step>, InstallError> {
+    if patches.is_empty() {
+        return Ok(vec![]);
+    }
+    let into = workspace::root().join("patches").join(name);
+    std::fs::create_dir_all(&into).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    outputln!("applying {} patches from the registry.", (patches.len()));
+
+    let mut steps = vec![];
+    for patch in patches {
+        let file = fetch(patch, &into)?;
+        let step = Step::new("git", source)
+            .arg("apply")
+            .arg("--whitespace=nowarn")
+            .arg(file.display().to_string());
+        let (status, output) = step.run_with_output()?;
+        if !status.success() {
+            for line in &output {
+                eprintln!("  {}", line);
+            }
+            return Err(InstallError::UnknownFatal(format!(
+                "the patch `{}` doesn't apply to `{}`, upstream may have changed since it was made.",
+                patch, name
+            )));
+        }
+        outputln!(green, "applied {}", patch);
+        steps.push(step);
+    }
+    Ok(steps)
+}
@@ -0,0 +1,50 @@
+// Well known locations cinstall reads from and writes to.
+//
+// These follow the XDG base directory spec, falling back to the
+// usual `~/.local/share` style directories when the variables are unset.
+
+use std::path::{Path, PathBuf};
+
+/// Where the system-wide state lives. Only root can write here.
+pub const SYSTEM_DATA_DIR: &str = "/var/lib/cinstall";
+
+pub fn home_dir() -> PathBuf {
+    match std::env::var_os("HOME") {
+        Some(home) if !home.is_empty() => PathBuf::from(home),
+        _ => PathBuf::from("/"),
+    }
+}
+
+fn xdg_dir(var: &str, fallback: &[&str]) -> PathBuf {
+    let mut dir = match std::env::var_os(var) {
+        Some(dir) if Path::new(&dir).is_absolute() => PathBuf::from(dir),
+        _ => {
+            let mut dir = home_dir();
+            dir.extend(fallback);
+            dir
+        }
+    };
+    dir.push("cinstall");
+    dir
+}
+
+/// `~/.local/share/cinstall`
+pub fn data_dir() -> PathBuf {
+    xdg_dir("XDG_DATA_HOME", &[".local", "share"])
+}
+
+/// `~/.config/cinstall`
+pub fn config_dir() -> PathBuf {
+    xdg_dir("XDG_CONFIG_HOME", &[".config"])
+}
+
+/// `~/.cache/cinstall`
+pub fn cache_dir() -> PathBuf {
+    xdg_dir("XDG_CACHE_HOME", &[".cache"])
+}
+
+/// Returns true if `path` lives inside the current user's home directory.
+pub fn is_user_path(path: &Path) -> bool {
+    let home = home_dir();
+    home != Path::new("/") && path.starts_with(home)
+}
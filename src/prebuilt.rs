@@ -0,0 +1,184 @@
+// Prebuilt release assets.
+//
+// Projects like cmake or ninja attach binaries for each platform to their
+// GitHub releases, and installing one takes seconds where building takes an
+// hour. Before cloning a GitHub project the latest release is looked at, and
+// if it has an archive for this OS and architecture, installing that is
+// offered instead. `--prebuilt` takes it without asking and `--from-source`
+// never looks. Anything going wrong here just means building from source.
+
+use crate::installer::{InstallError, InstallOptions};
+use crate::source;
+use crate::{github, output, outputln, staging, ui, workspace};
+use serde::Deserialize;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+#[derive(Deserialize)]
+pub struct Asset {
+    pub name: String,
+    pub browser_download_url: String,
+    /// `sha256:<hex>`, for assets uploaded since GitHub started recording it.
+    #[serde(default)]
+    pub digest: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+/// How asset names spell each OS and architecture.
+const OS_WORDS: &[(&str, &[&str])] = &[
+    ("linux", &["linux"]),
+    ("macos", &["macos", "darwin", "mac", "osx", "apple"]),
+    ("freebsd", &["freebsd"]),
+];
+
+const ARCH_WORDS: &[(&str, &[&str])] = &[
+    ("x86_64", &["x86_64", "amd64", "x64"]),
+    ("aarch64", &["aarch64", "arm64"]),
+    ("x86", &["i386", "i686", "x86"]),
+    ("arm", &["arm", "armv7", "armhf"]),
+];
+
+fn words_for(table: &[(&str, &'static [&'static str])], key: &str) -> &'static [&'static str] {
+    table
+        .iter()
+        .find(|(k, _)| *k == key)
+        .map_or(&[], |(_, words)| *words)
+}
+
+/// How well an asset called `name` fits this machine, nothing if it doesn't.
+/// Assets that don't say their architecture are assumed to be for x86_64.
+fn score(name: &str) -> Option<u8> {
+    let lower = name.to_lowercase();
+    let tokens: Vec<&str> = lower
+        .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+        .collect();
+    let has = |words: &[&str]| tokens.iter().any(|t| words.contains(t));
+
+    if !has(words_for(OS_WORDS, std::env::consts::OS))
+        || has(&["src", "source", "debug", "symbols"])
+    {
+        return None;
+    }
+    let arch = std::env::consts::ARCH;
+    if has(words_for(ARCH_WORDS, arch)) || has(&["universal", "universal2"]) {
+        return Some(2);
+    }
+    let other_arch = ARCH_WORDS
+        .iter()
+        .filter(|(a, _)| *a != arch)
+        .any(|(_, words)| has(words));
+    (!other_arch && arch == "x86_64").then_some(1)
+}
+
+fn latest_release(repo: &str) -> Result<Release, String> {
+    let api = github::api_url(&format!("repos/{}/releases/latest", repo), &[]);
+    let release = github::request("GET", &api, None)?;
+    serde_json::from_value(release).map_err(|e| e.to_string())
+}
+
+/// The release asset to install instead of building `url`, if there is one
+/// and the user wants it.
+pub fn offer(url: &Url, options: &InstallOptions) -> Option<Asset> {
+    if options.from_source
+        || options.bundle.is_some()
+        || options.git_ref.is_some()
+        || options.multiarch
+        || options.verify_signature
+        || source::is_archive(url)
+        || source::is_local(url)
+    {
+        return None;
+    }
+    let repo = github::repo_of(url)?;
+    let release = latest_release(&repo).ok()?;
+    let asset = release
+        .assets
+        .into_iter()
+        .filter(|a| Url::parse(&a.browser_download_url).is_ok_and(|u| source::is_archive(&u)))
+        .filter_map(|a| score(&a.name).map(|s| (s, a)))
+        .max_by_key(|(s, _)| *s)
+        .map(|(_, a)| a)?;
+
+    outputln!(
+        "{} {} has a prebuilt {} for this machine.",
+        repo,
+        (release.tag_name),
+        (asset.name)
+    );
+    if options.dry_run {
+        outputln!("it would be offered instead of building from source.");
+        return None;
+    }
+    if options.prebuilt {
+        return Some(asset);
+    }
+    if !ui::interactive() {
+        outputln!("pass --prebuilt to install it, building from source.");
+        return None;
+    }
+    output!(
+        on_blue,
+        "install it instead of building from source? [Y/n] "
+    );
+    let input: String = text_io::read!("{}\n");
+    if input.to_lowercase().starts_with('n') {
+        return None;
+    }
+    Some(asset)
+}
+
+fn is_executable(path: &Path) -> bool {
+    path.is_file() && std::fs::metadata(path).is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+/// The directories of a prefix a release archive can have.
+const PREFIX_DIRS: &[&str] = &["bin", "lib", "lib64", "include", "share", "libexec", "man"];
+
+/// Download and extract `asset` and install it into the prefix, returning the
+/// extracted directory and the files that were installed. Archives laid out
+/// like a prefix (`bin/`, `lib/`, `share/`, ...) are installed as they are,
+/// otherwise the programs at their top are put in `bin`.
+pub fn install(
+    name: &str,
+    asset: &Asset,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<(PathBuf, Vec<PathBuf>), InstallError> {
+    let url = Url::parse(&asset.browser_download_url)
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    let (dir, _) = workspace::download(&url, asset.digest.as_deref(), None)?;
+
+    let prefix = staging.join(options.prefix.strip_prefix("/").unwrap_or(&options.prefix));
+    let dirs: Vec<&&str> = PREFIX_DIRS
+        .iter()
+        .filter(|d| dir.join(d).is_dir())
+        .collect();
+    if dirs.is_empty() {
+        let entries = std::fs::read_dir(&dir)
+            .map_err(|e| InstallError::BadDirectory(format!("{}: {}", dir.display(), e)))?;
+        for entry in entries.flatten() {
+            if is_executable(&entry.path()) {
+                staging::copy_tree(&entry.path(), &prefix.join("bin").join(entry.file_name()))?;
+            }
+        }
+    } else {
+        for d in dirs {
+            staging::copy_tree(&dir.join(d), &prefix.join(d))?;
+        }
+    }
+
+    let files = staging::commit(name, staging)?;
+    if files.is_empty() {
+        return Err(InstallError::UnknownFatal(format!(
+            "{} has nothing cinstall knows how to install.",
+            asset.name
+        )));
+    }
+    Ok((dir, files))
+}
@@ -0,0 +1,201 @@
+// Checking a package's requirements before building it.
+//
+// A package that needs a newer compiler than the machine has usually fails
+// halfway through the build with an error about some C++ feature, which says
+// nothing about the real problem. The registry can say what a package needs
+// (see `Requirements`), and this compares that against the machine so the
+// install can stop before it starts and say exactly what is missing.
+
+use crate::registry::{self, Requirements};
+use std::process::Command;
+
+/// The leading numbers of a version, `12.2.0-14)` is `[12, 2, 0]`.
+fn parse_version(text: &str) -> Option<Vec<u64>> {
+    let mut numbers = vec![];
+    for part in text.split('.') {
+        let digits: String = part.chars().take_while(char::is_ascii_digit).collect();
+        let Ok(number) = digits.parse() else { break };
+        numbers.push(number);
+        if digits.len() != part.len() {
+            break;
+        }
+    }
+    (!numbers.is_empty()).then_some(numbers)
+}
+
+/// Whether `have` is at least `want`, `5.4` is the same as `5.4.0`.
+fn at_least(have: &[u64], want: &[u64]) -> bool {
+    let len = have.len().max(want.len());
+    let padded = |v: &[u64]| -> Vec<u64> { (0..len).map(|i| *v.get(i).unwrap_or(&0)).collect() };
+    padded(have) >= padded(want)
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+fn kernel_version() -> Option<String> {
+    command_output("uname", &["-r"])
+}
+
+/// `glibc 2.36` from getconf, nothing on musl, macOS, ...
+fn glibc_version() -> Option<String> {
+    command_output("getconf", &["GNU_LIBC_VERSION"])?
+        .strip_prefix("glibc ")
+        .map(String::from)
+}
+
+/// The compiler cmake and make will pick up, as `("gcc" | "clang", version)`.
+fn compiler() -> Option<(&'static str, String)> {
+    let program = std::env::var("CXX").unwrap_or_else(|_| "c++".into());
+    let version = command_output(&program, &["--version"])?;
+    let first = version.lines().next()?;
+    let kind = if first.contains("clang") {
+        "clang"
+    } else if version.contains("Free Software Foundation") {
+        "gcc"
+    } else {
+        return None;
+    };
+    // `clang version 15.0.7`, `g++ (Debian 12.2.0-14) 12.2.0`
+    let words: Vec<&str> = first.split_whitespace().collect();
+    let after = words
+        .iter()
+        .position(|w| *w == "version")
+        .map_or(0, |i| i + 1);
+    let version = words[after..]
+        .iter()
+        .find(|w| w.contains('.') && parse_version(w).is_some())?;
+    Some((kind, version.to_string()))
+}
+
+/// Add a problem if `have` is older than `want`, or wasn't found.
+fn check_version(
+    problems: &mut Vec<String>,
+    what: &str,
+    want: &str,
+    have: Option<String>,
+    missing: &str,
+) {
+    let Some(wanted) = parse_version(want) else {
+        problems.push(format!(
+            "the registry asks for {} `{}`, which isn't a version.",
+            what, want
+        ));
+        return;
+    };
+    match have {
+        Some(have) if parse_version(&have).is_some_and(|v| at_least(&v, &wanted)) => {}
+        Some(have) => problems.push(format!(
+            "it needs {} {} or newer, this system has {}.",
+            what, want, have
+        )),
+        None => problems.push(format!("it needs {} {} or newer, {}.", what, want, missing)),
+    }
+}
+
+/// Everything about this machine that doesn't meet `requires`.
+pub fn problems(requires: &Requirements) -> Vec<String> {
+    let mut problems = vec![];
+    let platform = registry::current_platform();
+    if !requires.platforms.is_empty() && !requires.platforms.iter().any(|p| p.matches(&platform)) {
+        let platforms: Vec<String> = requires.platforms.iter().map(|p| p.to_string()).collect();
+        problems.push(format!(
+            "it only builds on {}, this is {}.",
+            platforms.join(" or "),
+            platform
+        ));
+    }
+    if let Some(want) = &requires.kernel {
+        if std::env::consts::OS == "linux" {
+            check_version(
+                &mut problems,
+                "Linux",
+                want,
+                kernel_version(),
+                "and the kernel version couldn't be found",
+            );
+        }
+    }
+    if let Some(want) = &requires.glibc {
+        check_version(
+            &mut problems,
+            "glibc",
+            want,
+            glibc_version(),
+            "and this system's C library isn't glibc",
+        );
+    }
+    if requires.gcc.is_some() || requires.clang.is_some() {
+        match compiler() {
+            Some((kind, have)) => {
+                let want = if kind == "gcc" {
+                    &requires.gcc
+                } else {
+                    &requires.clang
+                };
+                if let Some(want) = want {
+                    check_version(&mut problems, kind, want, Some(have), "");
+                }
+            }
+            None => problems.push(format!(
+                "it needs {}, and the compiler (`$CXX` or `c++`) couldn't be run or is neither gcc nor clang.",
+                [("gcc", &requires.gcc), ("clang", &requires.clang)]
+                    .iter()
+                    .filter_map(|(kind, v)| v.as_ref().map(|v| format!("{} {} or newer", kind, v)))
+                    .collect::<Vec<_>>()
+                    .join(" or ")
+            )),
+        }
+    }
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn versions_are_their_leading_numbers() {
+        assert_eq!(parse_version("12.2.0"), Some(vec![12, 2, 0]));
+        assert_eq!(parse_version("12.2.0-14)"), Some(vec![12, 2, 0]));
+        assert_eq!(parse_version("6.1.0-18-amd64"), Some(vec![6, 1, 0]));
+        assert_eq!(parse_version("2.36"), Some(vec![2, 36]));
+        assert_eq!(parse_version("15rc1.2"), Some(vec![15]));
+        assert_eq!(parse_version("version"), None);
+        assert_eq!(parse_version(""), None);
+    }
+
+    #[test]
+    fn missing_parts_count_as_zero() {
+        assert!(at_least(&[5, 4], &[5, 4, 0]));
+        assert!(at_least(&[5, 4, 0], &[5, 4]));
+        assert!(at_least(&[6], &[5, 10]));
+        assert!(at_least(&[12, 2, 0], &[11]));
+        assert!(!at_least(&[5, 3, 9], &[5, 4]));
+        assert!(!at_least(&[2, 31], &[2, 36]));
+    }
+
+    #[test]
+    fn check_version_says_what_is_missing() {
+        let mut problems = vec![];
+        check_version(&mut problems, "glibc", "2.28", Some("2.36".into()), "-");
+        assert!(problems.is_empty());
+
+        check_version(&mut problems, "glibc", "2.38", Some("2.36".into()), "-");
+        check_version(&mut problems, "glibc", "2.28", None, "and it isn't glibc");
+        check_version(&mut problems, "gcc", "new", Some("12".into()), "-");
+        assert_eq!(
+            problems,
+            [
+                "it needs glibc 2.38 or newer, this system has 2.36.",
+                "it needs glibc 2.28 or newer, and it isn't glibc.",
+                "the registry asks for gcc `new`, which isn't a version.",
+            ]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+// Projects that depend on cinstall packages.
+//
+// A project declares its dependencies in a `cinstall.toml` at its root.
+// `cinstall new` scaffolds a small project that already has one, along with
+// a CMakeLists that can find packages installed by cinstall. Next to it, a
+// `cinstall.lock` can record the commit of each dependency the project was
+// known to work with.
+
+use crate::installer::InstallError;
+use crate::outputln;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+pub const MANIFEST_NAME: &str = "cinstall.toml";
+pub const LOCK_NAME: &str = "cinstall.lock";
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectInfo {
+    pub name: String,
+}
+
+/// A dependency is either a ref (`"*"` for whatever is newest), or a table
+/// that can also point at a URL for packages that aren't in the registry.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Dependency {
+    Ref(String),
+    Detailed {
+        url: Option<String>,
+        #[serde(rename = "ref")]
+        git_ref: Option<String>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ProjectManifest {
+    pub project: ProjectInfo,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+}
+
+impl ProjectManifest {
+    pub fn load(dir: &Path) -> Result<Self, InstallError> {
+        let path = dir.join(MANIFEST_NAME);
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&contents)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))
+    }
+}
+
+impl Dependency {
+    /// The ref asked for, if it isn't whatever is newest.
+    pub fn git_ref(&self) -> Option<&str> {
+        let git_ref = match self {
+            Dependency::Ref(git_ref) => Some(git_ref.as_str()),
+            Dependency::Detailed { git_ref, .. } => git_ref.as_deref(),
+        };
+        git_ref.filter(|r| *r != "*")
+    }
+}
+
+/// The directory of the `cinstall.toml` that `dir` is in, if any.
+pub fn find_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join(MANIFEST_NAME).is_file())
+        .map(Path::to_path_buf)
+}
+
+/// What a dependency was locked at.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Locked {
+    pub url: String,
+    #[serde(rename = "ref", default, skip_serializing_if = "Option::is_none")]
+    pub git_ref: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub commit: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Lockfile {
+    #[serde(default)]
+    pub packages: BTreeMap<String, Locked>,
+}
+
+impl Lockfile {
+    /// The lockfile in `dir`, if there is one.
+    pub fn load(dir: &Path) -> Result<Option<Self>, InstallError> {
+        let path = dir.join(LOCK_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let contents = std::fs::read_to_string(&path)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&contents)
+            .map(Some)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))
+    }
+
+    pub fn save(&self, dir: &Path) -> Result<(), InstallError> {
+        let contents =
+            toml::to_string_pretty(self).map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+        std::fs::write(
+            dir.join(LOCK_NAME),
+            format!("# written by `cinstall status --write-lock`.\n{}", contents),
+        )
+        .map_err(|_| InstallError::FailedToWriteToFile)
+    }
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum Template {
+    /// A C++ executable.
+    CmakeApp,
+    /// A C++ library that installs a CMake package config.
+    CmakeLib,
+    /// A C executable.
+    CApp,
+}
+
+impl Template {
+    /// The files making up the template, as (path, contents) pairs.
+    fn files(&self) -> Vec<(&'static str, &'static str)> {
+        let mut files = vec![
+            (MANIFEST_NAME, include_str!("templates/cinstall.toml")),
+            (".gitignore", include_str!("templates/gitignore")),
+        ];
+        files.extend(match self {
+            Template::CmakeApp => vec![
+                (
+                    "CMakeLists.txt",
+                    include_str!("templates/cmake-app/CMakeLists.txt"),
+                ),
+                ("src/main.cpp", include_str!("templates/cmake-app/main.cpp")),
+            ],
+            Template::CmakeLib => vec![
+                (
+                    "CMakeLists.txt",
+                    include_str!("templates/cmake-lib/CMakeLists.txt"),
+                ),
+                (
+                    "include/{{name}}/{{name}}.hpp",
+                    include_str!("templates/cmake-lib/lib.hpp"),
+                ),
+                (
+                    "src/{{name}}.cpp",
+                    include_str!("templates/cmake-lib/lib.cpp"),
+                ),
+            ],
+            Template::CApp => vec![
+                (
+                    "CMakeLists.txt",
+                    include_str!("templates/c-app/CMakeLists.txt"),
+                ),
+                ("src/main.c", include_str!("templates/c-app/main.c")),
+            ],
+        });
+        files
+    }
+}
+
+fn render(text: &str, name: &str, prefix: &Path) -> String {
+    // C and C++ identifiers can't contain `-`.
+    text.replace("{{ident}}", &name.replace('-', "_"))
+        .replace("{{name}}", name)
+        .replace("{{prefix}}", &prefix.display().to_string())
+}
+
+/// Create a new project called `name` in a directory of the same name.
+pub fn scaffold(name: &str, template: Template, prefix: &Path) -> Result<PathBuf, InstallError> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if !valid {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` is not a valid project name, use letters, digits, `_` and `-`.",
+            name
+        )));
+    }
+
+    let root = PathBuf::from(name);
+    if root.exists() {
+        return Err(InstallError::BadDirectory(format!(
+            "{} already exists",
+            root.display()
+        )));
+    }
+
+    for (path, contents) in template.files() {
+        let path = root.join(render(path, name, prefix));
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|_| InstallError::FailedToCreateDirectory)?;
+        }
+        std::fs::write(&path, render(contents, name, prefix))
+            .map_err(|_| InstallError::FailedToWriteToFile)?;
+        outputln!(green, "created {}", (path.display()));
+    }
+
+    Ok(root)
+}
@@ -0,0 +1,244 @@
+// `cinstall registry propose`: suggest a project for the package registry.
+//
+// The project is checked out and looked at the way an install would, and a
+// registry entry is filled in from what is found: the language from its
+// sources, the description from GitHub or its README. The entry is checked
+// against the registry before it is printed. `--test-install` also builds and
+// installs it into a throwaway prefix, and `--open` files it as an issue on
+// cinstall's repository (with $GITHUB_TOKEN) or prints a link to a prefilled one.
+
+use crate::cli::ProposeArgs;
+use crate::installer::{self, InstallError, InstallMethod, InstallOptions};
+use crate::outputln;
+use crate::registry::{Language, PackageRegistry};
+use crate::{github, run, source, staging, workspace};
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Command;
+use url::Url;
+
+fn tracked_files(dir: &Path) -> Vec<String> {
+    Command::new("git")
+        .arg("ls-files")
+        .current_dir(dir)
+        .output()
+        .map(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .lines()
+                .map(String::from)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// C++ if there are at least as many C++ sources as C ones. Headers say nothing
+/// either way.
+fn infer_language(dir: &Path) -> Language {
+    let (mut c, mut cxx) = (0, 0);
+    for file in tracked_files(dir) {
+        match file.rsplit_once('.').map(|(_, ext)| ext) {
+            Some("c") => c += 1,
+            Some("cpp" | "cc" | "cxx" | "hpp" | "hh" | "hxx" | "ipp") => cxx += 1,
+            _ => {}
+        }
+    }
+    if c > cxx {
+        Language::C
+    } else {
+        Language::CXX
+    }
+}
+
+/// The first line of prose in the README, skipping headings, badges and HTML.
+fn readme_description(dir: &Path) -> Option<String> {
+    let readme = ["README.md", "README", "README.txt", "README.rst"]
+        .iter()
+        .find_map(|name| std::fs::read_to_string(dir.join(name)).ok())?;
+    readme
+        .lines()
+        .map(str::trim)
+        .find(|line| {
+            !line.is_empty() && !line.starts_with(['#', '[', '!', '<', '=', '-', '*', '`', '|'])
+        })
+        .map(String::from)
+}
+
+fn github_description(url: &Url) -> Option<String> {
+    let repo = github::repo_of(url)?;
+    let info = github::request(
+        "GET",
+        &github::api_url(&format!("repos/{}", repo), &[]),
+        None,
+    )
+    .ok()?;
+    info.get("description")?.as_str().map(String::from)
+}
+
+fn normalized(url: &str) -> String {
+    url.trim_end_matches('/')
+        .trim_end_matches(".git")
+        .to_lowercase()
+}
+
+/// Everything wrong with adding `name` for `url`.
+fn problems(registry: &PackageRegistry, name: &str, url: &Url, description: &str) -> Vec<String> {
+    let mut problems = vec![];
+    let valid_name = |c: char| c.is_ascii_lowercase() || c.is_ascii_digit() || "._+-".contains(c);
+    if name.is_empty() || !name.chars().all(valid_name) {
+        problems.push(format!(
+            "`{}` isn't a valid name, names are lowercase letters, digits and `. _ + -` (use --name)",
+            name
+        ));
+    }
+    if registry.get(name).is_some() {
+        problems.push(format!(
+            "there is already a package called `{}` (use --name)",
+            name
+        ));
+    }
+    if let Some((existing, _)) = registry
+        .packages()
+        .iter()
+        .find(|(_, p)| normalized(&p.url) == normalized(url.as_str()))
+    {
+        problems.push(format!(
+            "{} is already in the registry as `{}`",
+            url, existing
+        ));
+    }
+    if description.is_empty() {
+        problems.push("the description is empty (use --description)".into());
+    } else if description.len() > 200 {
+        problems.push("the description is longer than 200 characters (use --description)".into());
+    }
+    problems
+}
+
+/// Build and install the checkout into a throwaway prefix.
+fn test_install(name: &str, dir: &Path, method: &InstallMethod) -> Result<(), InstallError> {
+    let run_id = run::id().unwrap_or_else(run::start);
+    let prefix = workspace::root().join("propose").join(&run_id);
+    let options = InstallOptions {
+        prefix: prefix.clone(),
+        ..Default::default()
+    };
+    outputln!("test installing `{}` into {}", name, (prefix.display()));
+    let result =
+        installer::build_and_install(name, dir, method, &options, &staging::staging_dir(&run_id));
+    let _ = std::fs::remove_dir_all(&prefix);
+    result.map(|_| ())
+}
+
+fn open_issue(name: &str, body: &str) {
+    let title = format!("Add `{}` to the registry", name);
+    if github::token().is_none() {
+        let link = Url::parse_with_params(
+            &format!("https://github.com/{}/issues/new", github::REPOSITORY),
+            &[("title", title.as_str()), ("body", body)],
+        )
+        .expect("the issue URL is valid");
+        outputln!("set $GITHUB_TOKEN to file it from here, or open this link to file it:");
+        eprintln!("{}", link);
+        return;
+    }
+    let api = github::api_url(&format!("repos/{}/issues", github::REPOSITORY), &[]);
+    match github::request("POST", &api, Some(&json!({ "title": title, "body": body }))) {
+        Ok(issue) => outputln!(
+            green,
+            "filed {}",
+            (issue
+                .get("html_url")
+                .and_then(Value::as_str)
+                .unwrap_or("the issue"))
+        ),
+        Err(e) => outputln!(red, "failed to file the issue. {}", e),
+    }
+}
+
+fn propose_run(
+    registry: &PackageRegistry,
+    args: &ProposeArgs,
+    url: &Url,
+    name: &str,
+    git_ref: Option<&str>,
+) -> Result<(), InstallError> {
+    let (dir, _) = workspace::checkout(url, git_ref, false)?;
+    let method = installer::resolve_install_method(&dir);
+    if let InstallMethod::Unknown(message) = &method {
+        return Err(InstallError::UnknownFatal(format!(
+            "cinstall can't install this project ({}), so it can't go in the registry.",
+            message
+        )));
+    }
+    outputln!(green, "`{}` is installed with {}", name, method);
+
+    let description = args
+        .description
+        .clone()
+        .or_else(|| github_description(url))
+        .or_else(|| readme_description(&dir))
+        .unwrap_or_default();
+    let problems = problems(registry, name, url, description.trim());
+    if !problems.is_empty() {
+        for problem in &problems {
+            outputln!(red, "{}", problem);
+        }
+        return Err(InstallError::UnknownFatal(
+            "the entry isn't ready for the registry.".into(),
+        ));
+    }
+
+    let mut entry = json!({
+        "url": url.as_str(),
+        "description": description.trim(),
+        "language": infer_language(&dir),
+    });
+    if let Some(git_ref) = git_ref {
+        entry["default_ref"] = json!(git_ref);
+    }
+    if !args.mirrors.is_empty() {
+        entry["mirrors"] = json!(args.mirrors);
+    }
+
+    let tested = if args.test_install {
+        test_install(name, &dir, &method)?;
+        outputln!(green, "the test install worked.");
+        "passed"
+    } else {
+        "not run"
+    };
+
+    let entry = serde_json::to_string_pretty(&json!({ name: entry }))
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    outputln!(green, "the registry entry for `{}`:", name);
+    println!("{}", entry);
+
+    if args.open {
+        let body = format!(
+            "Proposed with `cinstall registry propose`.\n\n```json\n{}\n```\n\nInstall method: {}\nTest install: {}\n",
+            entry, method, tested
+        );
+        open_issue(name, &body);
+    }
+    Ok(())
+}
+
+pub fn propose(registry: &PackageRegistry, args: &ProposeArgs) -> Result<(), InstallError> {
+    if registry.get(&args.url).is_some() {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` is already in the registry.",
+            args.url
+        )));
+    }
+    let source = source::resolve(registry, &args.url, None, &[], false)?;
+    let name = args
+        .name
+        .clone()
+        .unwrap_or_else(|| source.name.to_lowercase());
+    let git_ref = args.git_ref.as_deref().or(source.git_ref.as_deref());
+
+    run::start();
+    let result = propose_run(registry, args, &source.url, &name, git_ref);
+    run::finish();
+    result
+}
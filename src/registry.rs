@@ -2,40 +2,313 @@
 //       so that the user can simply use `cinstall fmt` for example.
 //
 // This only really needs a map of a simple name to the URL.
+//
+// Packages can name a template from `pkg_templates.json` (e.g.
+// "standard-cmake-lib"), which supplies any field the package leaves out.
+// Templates can be based on other templates, so options shared by many
+// packages live in one place.
+//
+// Any field can also be given per platform in `overrides`, e.g.
+// `{"if": {"os": "macos"}, "cmake_args": [...]}`. Every override whose
+// condition matches the machine is applied in order when the package is
+// installed; `info --resolved` shows the result.
+//
+// `requires` says what a package needs from the machine building it, the
+// platforms it builds on and the oldest kernel, glibc and compilers it works
+// with. These are checked before anything is cloned (see `preflight`).
+//
+// The registry is built into the binary, and `cinstall registry update`
+// fetches the latest one (with its templates) into
+// `~/.cache/cinstall/registry.json`, which is used instead from then on, so
+// new packages don't need a new cinstall.
+//
+// Your own packages can be added with `cinstall registry add`, which keeps
+// them in `~/.config/cinstall/registry.toml`. They are in the same format as
+// the registry and win over packages of the same name in it.
+//
+// Registries, templates and overlays can be JSON or TOML, going by the file
+// name, with a table per package:
+//
+//     [mylib]
+//     url = "https://github.com/me/mylib"
+//     description = "my library"
+//     language = "C"
+//     default_ref = "v1.2.0"
+//     license = "MIT"
+//     tags = ["logging"]
+//     dependencies = ["{fmt}"]
+//     cmake_args = ["-DMYLIB_TESTS=OFF"]
+//
+// Packages that don't build right with the defaults can carry a recipe:
+// `defines` for cmake (`BUILD_SHARED_LIBS = "ON"`), `make_targets` to run
+// instead of `make install`, `env` for every build step, and `configure`,
+// `build` and `install` shell commands that replace the build system's
+// altogether.
+//
+// `variants` are named sets of build options a package offers, e.g.
+//
+//     [curl.variants.minimal]
+//     description = "only HTTP(S)"
+//     cmake_args = ["-DHTTP_ONLY=ON"]
+//
+// which `install curl --variant minimal` adds to the package's own options.
+//
+// Fields of any package can be changed without forking the registry in
+// `~/.config/cinstall/overlay.toml` (or `.json`), e.g. `{"fmt": {"url": "https://git.internal/fmt"}}`
+// to clone `fmt` from an internal mirror. The overlay is applied last, over
+// the built in registry, the one `registry update` fetched and your own
+// packages.
 
+use crate::config::Config;
+use crate::installer::{InstallError, Step};
+use crate::{outputln, paths, retry, search, signature, source};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+use url::Url;
 
-#[derive(Serialize, Deserialize)]
+/// Where `registry update` fetches from when `registry_url` isn't set.
+pub const DEFAULT_URL: &str =
+    "https://raw.githubusercontent.com/deetonn/cinstall/main/src/pkg_reg.json";
+
+#[derive(Serialize, Deserialize, Clone, Copy, clap::ValueEnum)]
 pub enum Language {
+    #[value(name = "c++", alias = "cxx")]
     CXX,
     C,
 }
 
-impl ToString for Language {
-    fn to_string(&self) -> String {
+impl std::fmt::Display for Language {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            Language::CXX => "C++".into(),
-            Language::C => "C".into(),
+            Language::CXX => write!(f, "C++"),
+            Language::C => write!(f, "C"),
         }
     }
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Package {
-    pub url: &'static str,
+    pub url: String,
     // simple description for that package.
-    pub description: &'static str,
+    pub description: String,
     // which language is used
     pub language: Language,
+    // the ref to install when none is asked for, for upstreams whose default branch isn't stable.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default_ref: Option<String>,
+    // extra arguments for cmake's configure step.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cmake_args: Vec<String>,
+    // the template the fields that aren't set here come from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub template: Option<String>,
+    // fields that are different on some platforms.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub overrides: Vec<Override>,
+    // the programs a tool package provides, empty for libraries.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub binaries: Vec<String>,
+    // what the machine building the package needs.
+    #[serde(default, skip_serializing_if = "Requirements::is_empty")]
+    pub requires: Requirements,
+    // other places to clone the package from, tried in order when `url` can't be.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mirrors: Vec<String>,
+    // the SHA-256 of the archive `url` points at, checked before it is built.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // fingerprints of the OpenPGP keys upstream signs its tags, commits or archives with.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub signing_keys: Vec<String>,
+    // what to tell the user after installing, like flags needed to link it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notes: Option<String>,
+    // the project's website, when it isn't the repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub homepage: Option<String>,
+    // the SPDX identifier of the project's license.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    // words `search` finds the package by, like `json` or `logging`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    // registry packages that have to be installed first.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    // `-D` definitions for cmake's configure step, like `BUILD_SHARED_LIBS = "ON"`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defines: BTreeMap<String, String>,
+    // the make targets that install the package, for makefiles without an `install` one.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub make_targets: Vec<String>,
+    // environment variables every build step runs with.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+    // shell commands run instead of the build system's, see `installer::Recipe`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configure: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub build: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub install: Vec<String>,
+    // patches applied before building, URLs or names of patch files (see `patches`).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub patches: Vec<String>,
+    // named sets of build options, picked with `install --variant`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variants: BTreeMap<String, Variant>,
+}
+
+/// Build options that go together, like "minimal" or "fips". They are added to
+/// the package's own when the variant is asked for.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Variant {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub description: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cmake_args: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub defines: BTreeMap<String, String>,
+    /// Arguments for `./configure`, for autotools projects.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub configure_args: Vec<String>,
+}
+
+impl Variant {
+    /// The variant's cmake arguments, with its defines as `-D` flags.
+    pub fn all_cmake_args(&self) -> Vec<String> {
+        self.cmake_args
+            .iter()
+            .cloned()
+            .chain(self.defines.iter().map(|(k, v)| format!("-D{}={}", k, v)))
+            .collect()
+    }
+}
+
+impl std::fmt::Display for Variant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let args = [self.all_cmake_args(), self.configure_args.clone()].concat();
+        match (self.description.is_empty(), args.is_empty()) {
+            (false, false) => write!(f, "{} ({})", self.description, args.join(" ")),
+            (false, true) => write!(f, "{}", self.description),
+            _ => write!(f, "{}", args.join(" ")),
+        }
+    }
+}
+
+/// The oldest versions a package builds with, as `major[.minor[.patch]]`.
+/// Anything left out isn't checked.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Requirements {
+    /// The platforms the package builds on, any platform if empty.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub platforms: Vec<Condition>,
+    /// The Linux kernel, only checked on Linux.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub kernel: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub glibc: Option<String>,
+    /// gcc, when the compiler is gcc.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub gcc: Option<String>,
+    /// clang, when the compiler is clang.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub clang: Option<String>,
+}
+
+impl Requirements {
+    pub fn is_empty(&self) -> bool {
+        self.platforms.is_empty()
+            && self.kernel.is_none()
+            && self.glibc.is_none()
+            && self.gcc.is_none()
+            && self.clang.is_none()
+    }
+}
+
+impl std::fmt::Display for Requirements {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = vec![];
+        if !self.platforms.is_empty() {
+            let platforms: Vec<String> = self.platforms.iter().map(|p| p.to_string()).collect();
+            parts.push(platforms.join(" or "));
+        }
+        let at_least = |versions: &[(&str, &Option<String>)]| -> Vec<String> {
+            versions
+                .iter()
+                .filter_map(|(what, v)| v.as_ref().map(|v| format!("{} >= {}", what, v)))
+                .collect()
+        };
+        parts.extend(at_least(&[
+            ("kernel", &self.kernel),
+            ("glibc", &self.glibc),
+        ]));
+        // only the compiler in use is checked.
+        let compilers = at_least(&[("gcc", &self.gcc), ("clang", &self.clang)]);
+        if !compilers.is_empty() {
+            parts.push(compilers.join(" or "));
+        }
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+/// Which machines an override is for. A condition that is left out matches
+/// anything.
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct Condition {
+    /// As in Rust's `std::env::consts::OS`: `linux`, `macos`, `windows`, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os: Option<String>,
+    /// As in Rust's `std::env::consts::ARCH`: `x86_64`, `aarch64`, ...
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub arch: Option<String>,
+}
+
+impl Condition {
+    pub fn matches(&self, platform: &Condition) -> bool {
+        let matches = |want: &Option<String>, have: &Option<String>| want.is_none() || want == have;
+        matches(&self.os, &platform.os) && matches(&self.arch, &platform.arch)
+    }
+}
+
+impl std::fmt::Display for Condition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parts: Vec<&str> = [&self.os, &self.arch]
+            .into_iter()
+            .filter_map(|p| p.as_deref())
+            .collect();
+        if parts.is_empty() {
+            write!(f, "any platform")
+        } else {
+            write!(f, "{}", parts.join("/"))
+        }
+    }
+}
+
+/// This machine, as a condition.
+pub fn current_platform() -> Condition {
+    Condition {
+        os: Some(std::env::consts::OS.to_string()),
+        arch: Some(std::env::consts::ARCH.to_string()),
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Override {
+    #[serde(rename = "if")]
+    pub condition: Condition,
+    #[serde(flatten)]
+    pub fields: Map<String, Value>,
 }
 
 impl Package {
-    pub fn get_url(&self) -> &'static str {
-        self.url
+    pub fn get_url(&self) -> &str {
+        &self.url
     }
-    pub fn get_description(&self) -> &'static str {
-        self.description
+    pub fn get_description(&self) -> &str {
+        &self.description
     }
     pub fn get_language(&self) -> &Language {
         &self.language
@@ -43,31 +316,487 @@ impl Package {
 }
 
 impl Package {
-    pub fn new(url: &'static str, desc: &'static str, lang: Language) -> Self {
+    pub fn new(url: &str, desc: &str, lang: Language) -> Self {
         Self {
-            url,
-            description: desc,
+            url: url.to_string(),
+            description: desc.to_string(),
             language: lang,
+            default_ref: None,
+            cmake_args: vec![],
+            template: None,
+            overrides: vec![],
+            binaries: vec![],
+            requires: Requirements::default(),
+            mirrors: vec![],
+            sha256: None,
+            signing_keys: vec![],
+            notes: None,
+            homepage: None,
+            license: None,
+            tags: vec![],
+            dependencies: vec![],
+            defines: BTreeMap::new(),
+            make_targets: vec![],
+            env: BTreeMap::new(),
+            configure: vec![],
+            build: vec![],
+            install: vec![],
+            patches: vec![],
+            variants: BTreeMap::new(),
+        }
+    }
+
+    /// The package as it is installed on `platform`, with every override that
+    /// matches it applied.
+    pub fn resolve(&self, platform: &Condition) -> Result<Package, String> {
+        let mut fields = match serde_json::to_value(self) {
+            Ok(Value::Object(fields)) => fields,
+            _ => return Err("a package is always an object".into()),
+        };
+        fields.remove("overrides");
+        for o in self
+            .overrides
+            .iter()
+            .filter(|o| o.condition.matches(platform))
+        {
+            fields.extend(o.fields.clone());
         }
+        serde_json::from_value(Value::Object(fields)).map_err(|e| e.to_string())
+    }
+}
+
+/// Fill in the fields `entry` leaves out from its template, and from that
+/// template's template, and so on. Fields set closer to the package win.
+fn expand(
+    name: &str,
+    entry: &Value,
+    templates: &Map<String, Value>,
+    seen: &mut Vec<String>,
+) -> Result<Value, String> {
+    let Some(template) = entry.get("template").and_then(Value::as_str) else {
+        return Ok(entry.clone());
+    };
+    if seen.iter().any(|t| t == template) {
+        return Err(format!(
+            "`{}` has a template cycle through `{}`",
+            name, template
+        ));
+    }
+    seen.push(template.to_string());
+    let base = templates
+        .get(template)
+        .ok_or_else(|| format!("`{}` uses the unknown template `{}`", name, template))?;
+
+    let mut merged = expand(name, base, templates, seen)?;
+    if let (Value::Object(merged), Value::Object(fields)) = (&mut merged, entry) {
+        merged.extend(fields.clone());
     }
+    Ok(merged)
+}
+
+/// Parse a registry, template or overlay file, as TOML if `name` ends in
+/// `.toml` and as JSON otherwise.
+fn parse(contents: &str, name: &str) -> Result<Map<String, Value>, String> {
+    if name.ends_with(".toml") {
+        toml::from_str(contents).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(contents).map_err(|e| e.to_string())
+    }
+}
+
+/// Read and parse `path`, if it exists.
+fn read(path: &Path) -> Option<Result<Map<String, Value>, String>> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    Some(parse(&contents, &path.display().to_string()))
+}
+
+/// `<dir>/<stem>.toml`, or `<stem>.json` if only that exists.
+fn either_format(dir: &Path, stem: &str) -> PathBuf {
+    let toml = dir.join(format!("{}.toml", stem));
+    let json = dir.join(format!("{}.json", stem));
+    if !toml.exists() && json.exists() {
+        json
+    } else {
+        toml
+    }
+}
+
+/// Check that `package` has a URL, and still makes a package with a URL with
+/// each of its overrides applied, whichever platform it is installed on.
+fn check_loaded(package: &Package) -> Result<(), String> {
+    let check_url = |url: &str| {
+        Url::parse(url)
+            .map(|_| ())
+            .map_err(|e| format!("`{}` isn't a URL ({})", url, e))
+    };
+    check_url(&package.url)?;
+    for o in &package.overrides {
+        let resolved = package
+            .resolve(&o.condition)
+            .map_err(|e| format!("the override for {} is invalid ({})", o.condition, e))?;
+        check_url(&resolved.url)?;
+    }
+    Ok(())
+}
+
+fn load(
+    packages: &Map<String, Value>,
+    templates: &Map<String, Value>,
+) -> Result<HashMap<String, Package>, String> {
+    packages
+        .iter()
+        .map(|(name, entry)| {
+            let entry = expand(name, entry, templates, &mut vec![])?;
+            let package =
+                serde_json::from_value(entry).map_err(|e| format!("`{}`: {}", name, e))?;
+            check_loaded(&package).map_err(|e| format!("`{}`: {}", name, e))?;
+            Ok((name.clone(), package))
+        })
+        .collect()
 }
 
 pub struct PackageRegistry {
-    reg: HashMap<&'static str, Package>,
+    reg: HashMap<String, Package>,
+    // the fields the overlay changed, by package.
+    overlaid: HashMap<String, Vec<String>>,
+}
+
+fn embedded_templates() -> Map<String, Value> {
+    parse(include_str!("pkg_templates.json"), "pkg_templates.json")
+        .unwrap_or_else(|e| panic!("failed to deserialize registry templates: {}", e))
+}
+
+fn embedded() -> HashMap<String, Package> {
+    match parse(include_str!("pkg_reg.json"), "pkg_reg.json")
+        .and_then(|packages| load(&packages, &embedded_templates()))
+    {
+        Ok(m) => m,
+        Err(e) => panic!("failed to deserialize registry json: {}", e),
+    }
+}
+
+/// `~/.cache/cinstall/registry.toml`, or `.json`, whichever was fetched.
+pub fn cache_path() -> PathBuf {
+    either_format(&paths::cache_dir(), "registry")
+}
+
+fn templates_cache_path() -> PathBuf {
+    either_format(&paths::cache_dir(), "templates")
+}
+
+/// The templates `registry update` fetched last, or the built in ones.
+fn templates() -> Map<String, Value> {
+    match read(&templates_cache_path()) {
+        Some(Ok(templates)) => templates,
+        _ => embedded_templates(),
+    }
+}
+
+/// The registry `registry update` fetched last, if there is one.
+fn cached() -> Option<Result<HashMap<String, Package>, String>> {
+    Some(read(&cache_path())?.and_then(|packages| load(&packages, &templates())))
+}
+
+/// `~/.config/cinstall/registry.toml`, or `registry.json` if that is what
+/// there is.
+pub fn user_path() -> PathBuf {
+    either_format(&paths::config_dir(), "registry")
+}
+
+/// The packages added with `registry add`, if there are any.
+fn user() -> Option<Result<HashMap<String, Package>, String>> {
+    Some(read(&user_path())?.and_then(|packages| load(&packages, &templates())))
+}
+
+/// `~/.config/cinstall/overlay.toml`, or `overlay.json`.
+pub fn overlay_path() -> PathBuf {
+    either_format(&paths::config_dir(), "overlay")
+}
+
+/// `package` with `fields` replaced.
+fn overlay_package(package: &Package, fields: &Map<String, Value>) -> Result<Package, String> {
+    let mut entry = match serde_json::to_value(package) {
+        Ok(Value::Object(entry)) => entry,
+        _ => return Err("a package is always an object".into()),
+    };
+    entry.extend(fields.clone());
+    let package: Package =
+        serde_json::from_value(Value::Object(entry)).map_err(|e| e.to_string())?;
+    check_loaded(&package)?;
+    Ok(package)
+}
+
+/// Apply the overlay to `packages`, returning the fields it changed for each
+/// package. Entries that can't be applied are reported and left out.
+fn apply_overlay(packages: &mut HashMap<String, Package>) -> HashMap<String, Vec<String>> {
+    let mut overlaid = HashMap::new();
+    let overlay = match read(&overlay_path()) {
+        None => return overlaid,
+        Some(Ok(overlay)) => overlay,
+        Some(Err(e)) => {
+            outputln!(
+                purple,
+                "the overlay {} is invalid ({}), it is left out.",
+                (overlay_path().display()),
+                e
+            );
+            return overlaid;
+        }
+    };
+    for (name, fields) in overlay {
+        let result = match (packages.get(&name), &fields) {
+            (None, _) => Err("there is no such package".to_string()),
+            (Some(package), Value::Object(fields)) => overlay_package(package, fields),
+            (Some(_), _) => Err("expected an object of fields".to_string()),
+        };
+        match result {
+            Ok(package) => {
+                packages.insert(name.clone(), package);
+                let changed = fields
+                    .as_object()
+                    .into_iter()
+                    .flat_map(|f| f.keys().cloned());
+                overlaid.insert(name, changed.collect());
+            }
+            Err(e) => outputln!(
+                purple,
+                "the overlay for `{}` in {} is left out: {}.",
+                name,
+                (overlay_path().display()),
+                e
+            ),
+        }
+    }
+    overlaid
+}
+
+fn read_user_entries() -> Result<Map<String, Value>, InstallError> {
+    let path = user_path();
+    read(&path)
+        .unwrap_or(Ok(Map::new()))
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))
+}
+
+/// Write your packages back, in the format the file already has.
+fn write_user_entries(entries: &Map<String, Value>) -> Result<(), InstallError> {
+    std::fs::create_dir_all(paths::config_dir())
+        .map_err(|_| InstallError::FailedToCreateDirectory)?;
+    let path = user_path();
+    let contents = if path.extension().is_some_and(|e| e == "json") {
+        serde_json::to_string_pretty(entries)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string())
+    } else {
+        toml::to_string_pretty(entries).map_err(|e| e.to_string())
+    }
+    .map_err(InstallError::UnknownFatal)?;
+    std::fs::write(path, contents).map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+/// `cinstall registry add`: add a package of your own, or replace one.
+pub fn add(
+    name: &str,
+    url: &str,
+    description: &str,
+    language: Language,
+) -> Result<(), InstallError> {
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '@' || c == '#') {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` can't be a package name, names can't contain spaces, `@` or `#`.",
+            name
+        )));
+    }
+    let url = Url::parse(url)
+        .ok()
+        .or_else(|| source::github_url(url))
+        .ok_or_else(|| {
+            InstallError::UnknownFatal(format!("`{}` isn't a URL or a GitHub `owner/repo`.", url))
+        })?;
+    let package = Package::new(url.as_str(), description, language);
+    let entry =
+        serde_json::to_value(&package).map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+
+    let mut entries = read_user_entries()?;
+    let replaced = entries.insert(name.to_string(), entry).is_some();
+    write_user_entries(&entries)?;
+    if replaced {
+        outputln!(green, "your package `{}` now points at {}.", name, url);
+    } else if shared().contains_key(name) {
+        outputln!(
+            green,
+            "added `{}` ({}), it is used instead of the registry's `{}`.",
+            name,
+            url,
+            name
+        );
+    } else {
+        outputln!(green, "added `{}` ({}).", name, url);
+    }
+    Ok(())
+}
+
+/// `cinstall registry remove`: remove a package added with `registry add`.
+pub fn remove(name: &str) -> Result<(), InstallError> {
+    let mut entries = read_user_entries()?;
+    if entries.remove(name).is_none() {
+        let message = if shared().contains_key(name) {
+            format!("`{}` is part of the registry, only packages added with `registry add` can be removed.", name)
+        } else {
+            format!(
+                "there is no package `{}` in {}.",
+                name,
+                (user_path().display())
+            )
+        };
+        return Err(InstallError::UnknownFatal(message));
+    }
+    write_user_entries(&entries)?;
+    outputln!(green, "removed `{}`.", name);
+    Ok(())
+}
+
+/// The registry without your own packages: the one `registry update` fetched,
+/// or the built in one.
+fn shared() -> HashMap<String, Package> {
+    match cached() {
+        Some(Ok(m)) => m,
+        Some(Err(e)) => {
+            outputln!(
+                    purple,
+                    "the cached registry at {} is invalid ({}), using the built in one. run `cinstall registry update` to fetch it again.",
+                    (cache_path().display()),
+                    e
+                );
+            embedded()
+        }
+        None => embedded(),
+    }
 }
 
 impl Default for PackageRegistry {
     fn default() -> Self {
-        let json = include_str!("pkg_reg.json");
-        let map = match serde_json::from_str::<HashMap<&'static str, Package>>(json) {
-            Ok(m) => m,
-            Err(e) => panic!("failed to deserialize registry json: {}", e),
-        };
+        let mut map = shared();
+        match user() {
+            Some(Ok(user)) => map.extend(user),
+            Some(Err(e)) => outputln!(
+                purple,
+                "your packages in {} are invalid ({}), they are left out.",
+                (user_path().display()),
+                e
+            ),
+            None => {}
+        }
+        let overlaid = apply_overlay(&mut map);
 
-        Self { reg: map }
+        Self { reg: map, overlaid }
     }
 }
 
+/// A registry of just these packages.
+impl FromIterator<(String, Package)> for PackageRegistry {
+    fn from_iter<I: IntoIterator<Item = (String, Package)>>(packages: I) -> Self {
+        Self {
+            reg: packages.into_iter().collect(),
+            overlaid: HashMap::new(),
+        }
+    }
+}
+
+/// Download `url` to `to`, returning whether it worked. `optional` files
+/// that aren't there aren't reported.
+fn fetch(url: &str, to: &std::path::Path, optional: bool) -> Result<bool, InstallError> {
+    let curl = Step::new("curl", &paths::cache_dir())
+        .arg(if optional { "-fsL" } else { "-fsSL" })
+        .arg("-o")
+        .arg(to.display().to_string())
+        .arg(url);
+    Ok(retry::run(&curl)?.success())
+}
+
+/// `cinstall registry update`: fetch the registry from `url`, or the
+/// configured one, and cache it.
+pub fn update(url: Option<&str>) -> Result<(), InstallError> {
+    let config = Config::load().unwrap_or_default();
+    let url = url
+        .map(str::to_string)
+        .or(config.registry_url)
+        .unwrap_or_else(|| DEFAULT_URL.to_string());
+    std::fs::create_dir_all(paths::cache_dir())
+        .map_err(|_| InstallError::FailedToCreateDirectory)?;
+
+    outputln!("fetching the registry from {}", url);
+    let extension = if url.ends_with(".toml") {
+        "toml"
+    } else {
+        "json"
+    };
+    let cache_dir = paths::cache_dir();
+    let packages_path = cache_dir.join(format!("registry.{}.new", extension));
+    if !fetch(&url, &packages_path, false)? {
+        return Err(InstallError::UnknownFatal(format!(
+            "failed to download {}.",
+            url
+        )));
+    }
+    // the templates live next to the registry, older registries may not have them.
+    let templates_name = format!("pkg_templates.{}", extension);
+    let templates_url = match url.rsplit_once('/') {
+        Some((base, _)) => format!("{}/{}", base, templates_name),
+        None => templates_name,
+    };
+    let templates_path = cache_dir.join(format!("templates.{}.new", extension));
+    let has_templates = fetch(&templates_url, &templates_path, true)?;
+
+    let parse_file = |path: &PathBuf| {
+        let contents = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        parse(&contents, &format!("registry.{}", extension))
+    };
+    let templates = match has_templates {
+        true => parse_file(&templates_path),
+        false => Ok(embedded_templates()),
+    };
+    // don't replace a working registry with one that can't be loaded.
+    let fetched = parse_file(&packages_path)
+        .and_then(|packages| load(&packages, &templates?))
+        .map_err(|e| {
+            let _ = std::fs::remove_file(&packages_path);
+            let _ = std::fs::remove_file(&templates_path);
+            InstallError::UnknownFatal(format!("the registry at {} is invalid: {}", url, e))
+        })?;
+
+    let previous = PackageRegistry::default().reg;
+    // only one format is kept, so an old one can't be picked up instead.
+    for stem in ["registry", "templates"] {
+        for old in ["toml", "json"] {
+            let _ = std::fs::remove_file(cache_dir.join(format!("{}.{}", stem, old)));
+        }
+    }
+    let move_into_place = |from: &PathBuf, stem: &str| {
+        std::fs::rename(from, cache_dir.join(format!("{}.{}", stem, extension)))
+            .map_err(|_| InstallError::FailedToWriteToFile)
+    };
+    move_into_place(&packages_path, "registry")?;
+    if has_templates {
+        move_into_place(&templates_path, "templates")?;
+    }
+
+    let mut added: Vec<&String> = fetched
+        .keys()
+        .filter(|name| !previous.contains_key(*name))
+        .collect();
+    added.sort();
+    outputln!(
+        green,
+        "the registry has {} packages, {} of them new.",
+        (fetched.len()),
+        (added.len())
+    );
+    for name in added {
+        eprintln!("  {}", name);
+    }
+    Ok(())
+}
+
 impl PackageRegistry {
     pub fn new() -> Self {
         Self::default()
@@ -77,7 +806,456 @@ impl PackageRegistry {
         self.reg.get(id)
     }
 
-    pub fn packages(&self) -> &HashMap<&'static str, Package> {
+    pub fn packages(&self) -> &HashMap<String, Package> {
         &self.reg
     }
+
+    /// The package whose name is closest to `id`, for when `id` isn't one.
+    pub fn closest(&self, id: &str) -> Option<&str> {
+        let id: Vec<char> = id.to_lowercase().chars().collect();
+        // a few typos, or a name with a suffix or prefix more or less.
+        let allowed = (id.len() / 2).max(2);
+        self.reg
+            .keys()
+            .map(|name| {
+                let candidate: Vec<char> = name.to_lowercase().chars().collect();
+                (search::distance(&id, &candidate), name)
+            })
+            .filter(|(distance, _)| *distance <= allowed)
+            .min()
+            .map(|(_, name)| name.as_str())
+    }
+
+    /// The fields of `id` that were changed by the overlay.
+    pub fn overlaid(&self, id: &str) -> &[String] {
+        self.overlaid.get(id).map(Vec::as_slice).unwrap_or_default()
+    }
+}
+
+/// A registry file's entries in the order they are written, duplicates
+/// included, which parsing into a map would quietly drop.
+struct Entries(Vec<(String, Value)>);
+
+impl<'de> Deserialize<'de> for Entries {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> serde::de::Visitor<'de> for Visitor {
+            type Value = Entries;
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "a map of package names to packages")
+            }
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Entries, A::Error> {
+                let mut entries = vec![];
+                while let Some(entry) = map.next_entry()? {
+                    entries.push(entry);
+                }
+                Ok(Entries(entries))
+            }
+        }
+        deserializer.deserialize_map(Visitor)
+    }
+}
+
+/// What `registry validate` found wrong with a file.
+#[derive(Default)]
+struct Problems {
+    errors: Vec<String>,
+    warnings: Vec<String>,
+}
+
+/// Whether `value` is `null`, `""`, `[]` or `{}`.
+fn is_empty_value(value: &Value) -> bool {
+    match value {
+        Value::Null => true,
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(o) => o.is_empty(),
+        _ => false,
+    }
+}
+
+/// The fields set in `fields` that `package` doesn't have, which are ignored
+/// when it is loaded, so usually typos.
+fn unknown_fields(fields: &Map<String, Value>, package: &Package) -> Vec<String> {
+    let Ok(Value::Object(known)) = serde_json::to_value(package) else {
+        return vec![];
+    };
+    fields
+        .iter()
+        .filter(|(key, value)| {
+            !known.contains_key(*key) && *key != "overrides" && !is_empty_value(value)
+        })
+        .map(|(key, _)| key.clone())
+        .collect()
+}
+
+/// The name with case and punctuation left out, so `{fmt}` and `fmt` are the
+/// same. `+` is kept, `json++` isn't `json`.
+fn normalized(name: &str) -> String {
+    name.chars()
+        .filter(|c| c.is_alphanumeric() || *c == '+')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn check_url(what: &str, url: &str) -> Option<String> {
+    match Url::parse(url) {
+        Err(e) => Some(format!("{} `{}` isn't a URL ({})", what, url, e)),
+        Ok(url) if !["https", "http", "ssh", "git", "file"].contains(&url.scheme()) => {
+            Some(format!(
+                "{} `{}` uses `{}`, which git can't clone",
+                what,
+                url,
+                url.scheme()
+            ))
+        }
+        Ok(_) => None,
+    }
+}
+
+/// Whether `url` answers, following redirects.
+fn answers(url: &str) -> bool {
+    std::process::Command::new("curl")
+        .args(["-sSfIL", "-o", "/dev/null", "--max-time", "10", url])
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Check one package, `entry` being what the file says and `known` every
+/// package name it can depend on.
+fn check_package(
+    name: &str,
+    entry: &Value,
+    templates: &Map<String, Value>,
+    known: &dyn Fn(&str) -> bool,
+    problems: &mut Problems,
+) -> Option<Package> {
+    let mut error = |message: String| problems.errors.push(format!("{}: {}", name, message));
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == '@' || c == '#') {
+        error("names can't be empty or contain spaces, `@` or `#`".into());
+    }
+    let expanded = match expand(name, entry, templates, &mut vec![]) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            error(e);
+            return None;
+        }
+    };
+    let package: Package = match serde_json::from_value(expanded.clone()) {
+        Ok(package) => package,
+        Err(e) => {
+            error(e.to_string());
+            return None;
+        }
+    };
+    if let Value::Object(fields) = &expanded {
+        for field in unknown_fields(fields, &package) {
+            error(format!("unknown field `{}`", field));
+        }
+    }
+    for o in &package.overrides {
+        match package.resolve(&o.condition) {
+            Ok(resolved) => {
+                for field in unknown_fields(&o.fields, &resolved) {
+                    error(format!(
+                        "unknown field `{}` in the override for {}",
+                        field, o.condition
+                    ));
+                }
+            }
+            Err(e) => error(format!(
+                "the override for {} is invalid: {}",
+                o.condition, e
+            )),
+        }
+    }
+    let urls = std::iter::once(("the url", &package.url))
+        .chain(package.mirrors.iter().map(|m| ("the mirror", m)))
+        .chain(package.homepage.iter().map(|h| ("the homepage", h)));
+    for (what, url) in urls {
+        if let Some(message) = check_url(what, url) {
+            error(message);
+        }
+    }
+    if let Some(sha256) = &package.sha256 {
+        let hex = sha256.trim_start_matches("sha256:");
+        if hex.len() != 64 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            error(format!("`{}` isn't a SHA-256", sha256));
+        }
+    }
+    for key in &package.signing_keys {
+        if let Some(message) = signature::key_problem(key) {
+            error(message);
+        }
+    }
+    for dependency in &package.dependencies {
+        if dependency == name {
+            error("it depends on itself".into());
+        } else if !known(dependency) {
+            error(format!(
+                "it depends on `{}`, which isn't a package",
+                dependency
+            ));
+        }
+    }
+    let mut warning = |message: String| problems.warnings.push(format!("{}: {}", name, message));
+    if package.description.trim().is_empty() {
+        warning("it has no description".into());
+    }
+    for (variant_name, variant) in &package.variants {
+        if variant.all_cmake_args().is_empty() && variant.configure_args.is_empty() {
+            warning(format!("the variant `{}` changes nothing", variant_name));
+        }
+    }
+    Some(package)
+}
+
+/// Check every package in the registry file `name`, with `templates`, and
+/// return what is wrong.
+fn check_file(contents: &str, name: &str, templates: &Map<String, Value>, ping: bool) -> Problems {
+    let mut problems = Problems::default();
+    let entries = if name.ends_with(".toml") {
+        toml::from_str::<Entries>(contents).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str::<Entries>(contents).map_err(|e| e.to_string())
+    };
+    let entries = match entries {
+        Ok(Entries(entries)) => entries,
+        Err(e) => {
+            problems.errors.push(e);
+            return problems;
+        }
+    };
+
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    for (name, _) in &entries {
+        match seen.get(&normalized(name)) {
+            Some(other) if *other == name => problems
+                .errors
+                .push(format!("{}: it is in the file twice", name)),
+            Some(other) => problems.warnings.push(format!(
+                "{}: it looks the same as `{}`, which one is meant is easily mixed up",
+                name, other
+            )),
+            None => {
+                seen.insert(normalized(name), name);
+            }
+        }
+    }
+    let shared = shared();
+    let names: Vec<&str> = entries.iter().map(|(name, _)| name.as_str()).collect();
+    let known = |name: &str| names.contains(&name) || shared.contains_key(name);
+    let mut urls: HashMap<String, &str> = HashMap::new();
+    for (name, entry) in &entries {
+        let Some(package) = check_package(name, entry, templates, &known, &mut problems) else {
+            continue;
+        };
+        let url = package
+            .url
+            .trim_end_matches('/')
+            .trim_end_matches(".git")
+            .to_lowercase();
+        if let Some(other) = urls.get(&url) {
+            problems
+                .warnings
+                .push(format!("{}: it has the same url as `{}`", name, other));
+        } else {
+            urls.insert(url, name);
+        }
+        if ping && !answers(&package.url) {
+            problems
+                .warnings
+                .push(format!("{}: {} doesn't answer", name, package.url));
+        }
+    }
+    problems
+}
+
+/// `cinstall registry validate`: check the registry file `path`, or the
+/// registries in use, returning whether there were no errors.
+pub fn validate(
+    path: Option<&Path>,
+    templates_path: Option<&Path>,
+    ping: bool,
+) -> Result<bool, InstallError> {
+    let files = match path {
+        Some(path) => vec![path.to_path_buf()],
+        None => [cache_path(), user_path()]
+            .into_iter()
+            .filter(|p| p.exists())
+            .collect(),
+    };
+    let mut contents = vec![];
+    if path.is_none() && !cache_path().exists() {
+        contents.push((
+            "the built in registry".to_string(),
+            include_str!("pkg_reg.json").to_string(),
+        ));
+    }
+    for file in &files {
+        let text = std::fs::read_to_string(file)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", file.display(), e)))?;
+        contents.push((file.display().to_string(), text));
+    }
+    // a file of your own is checked with the templates next to it, if it has any.
+    let sibling = path.and_then(|p| {
+        let extension = p.extension()?.to_str()?;
+        let templates = p.with_file_name(format!("pkg_templates.{}", extension));
+        templates.exists().then_some(templates)
+    });
+    let templates = match templates_path.map(Path::to_path_buf).or(sibling) {
+        Some(templates_path) => read(&templates_path)
+            .unwrap_or_else(|| Err("it doesn't exist".into()))
+            .map_err(|e| {
+                InstallError::UnknownFatal(format!("{}: {}", templates_path.display(), e))
+            })?,
+        None => templates(),
+    };
+
+    let mut ok = true;
+    for (what, text) in contents {
+        let problems = check_file(&text, &what, &templates, ping);
+        if problems.errors.is_empty() && problems.warnings.is_empty() {
+            outputln!(green, "{} is fine.", what);
+            continue;
+        }
+        ok &= problems.errors.is_empty();
+        outputln!(
+            "{} has {} errors and {} warnings.",
+            what,
+            (problems.errors.len()),
+            (problems.warnings.len())
+        );
+        for error in &problems.errors {
+            outputln!(red, "  error: {}", error);
+        }
+        for warning in &problems.warnings {
+            outputln!(purple, "  warning: {}", warning);
+        }
+    }
+    Ok(ok)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn object(value: Value) -> Map<String, Value> {
+        match value {
+            Value::Object(fields) => fields,
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn expand_fills_in_from_templates_and_their_templates() {
+        let templates = object(json!({
+            "cmake": {"language": "C++", "cmake_args": ["-DBUILD_TESTING=OFF"]},
+            "header-only": {"template": "cmake", "description": "header only"},
+        }));
+        let entry =
+            json!({"template": "header-only", "url": "https://github.com/me/lib", "language": "C"});
+        let expanded = expand("lib", &entry, &templates, &mut vec![]).unwrap();
+        assert_eq!(expanded["url"], "https://github.com/me/lib");
+        assert_eq!(expanded["description"], "header only");
+        assert_eq!(expanded["cmake_args"], json!(["-DBUILD_TESTING=OFF"]));
+        // fields set closer to the package win.
+        assert_eq!(expanded["language"], "C");
+    }
+
+    #[test]
+    fn expand_finds_template_cycles() {
+        let templates = object(json!({
+            "a": {"template": "b"},
+            "b": {"template": "a"},
+        }));
+        let entry = json!({"template": "a"});
+        let e = expand("lib", &entry, &templates, &mut vec![]).unwrap_err();
+        assert!(e.contains("template cycle"), "{}", e);
+
+        let templates = object(json!({"a": {"template": "a"}}));
+        assert!(expand("lib", &entry, &templates, &mut vec![]).is_err());
+    }
+
+    #[test]
+    fn expand_rejects_unknown_templates() {
+        let entry = json!({"template": "nope"});
+        let e = expand("lib", &entry, &Map::new(), &mut vec![]).unwrap_err();
+        assert!(e.contains("unknown template `nope`"), "{}", e);
+    }
+
+    fn platform(os: &str, arch: &str) -> Condition {
+        Condition {
+            os: Some(os.into()),
+            arch: Some(arch.into()),
+        }
+    }
+
+    #[test]
+    fn conditions_match_what_they_name() {
+        let linux = Condition {
+            os: Some("linux".into()),
+            arch: None,
+        };
+        assert!(linux.matches(&platform("linux", "x86_64")));
+        assert!(linux.matches(&platform("linux", "aarch64")));
+        assert!(!linux.matches(&platform("macos", "aarch64")));
+        assert!(platform("macos", "aarch64").matches(&platform("macos", "aarch64")));
+        assert!(!platform("macos", "aarch64").matches(&platform("macos", "x86_64")));
+        assert!(Condition::default().matches(&platform("windows", "x86")));
+    }
+
+    #[test]
+    fn resolve_applies_matching_overrides_in_order() {
+        let mut package = Package::new("https://github.com/me/lib", "lib", Language::C);
+        package.overrides = serde_json::from_value(json!([
+            {"if": {"os": "linux"}, "cmake_args": ["-DLINUX=ON"], "notes": "linux"},
+            {"if": {"os": "linux", "arch": "aarch64"}, "notes": "arm"},
+            {"if": {"os": "macos"}, "url": "https://github.com/me/lib-mac"},
+        ]))
+        .unwrap();
+
+        let resolved = package.resolve(&platform("linux", "aarch64")).unwrap();
+        assert_eq!(resolved.url, "https://github.com/me/lib");
+        assert_eq!(resolved.cmake_args, ["-DLINUX=ON"]);
+        assert_eq!(resolved.notes.as_deref(), Some("arm"));
+        assert!(resolved.overrides.is_empty());
+
+        let resolved = package.resolve(&platform("macos", "aarch64")).unwrap();
+        assert_eq!(resolved.url, "https://github.com/me/lib-mac");
+        assert!(resolved.cmake_args.is_empty());
+    }
+
+    #[test]
+    fn resolve_rejects_overrides_of_the_wrong_type() {
+        let mut package = Package::new("https://github.com/me/lib", "lib", Language::C);
+        package.overrides = serde_json::from_value(json!([
+            {"if": {"os": "linux"}, "cmake_args": "-DLINUX=ON"},
+        ]))
+        .unwrap();
+        assert!(package.resolve(&platform("linux", "x86_64")).is_err());
+        assert!(package.resolve(&platform("macos", "x86_64")).is_ok());
+    }
+
+    #[test]
+    fn closest_suggests_a_name_a_few_typos_away() {
+        let registry = PackageRegistry {
+            reg: ["spdlog", "{fmt}", "zlib", "nlohmann-json"]
+                .into_iter()
+                .map(|name| (name.to_string(), Package::new("", "", Language::C)))
+                .collect(),
+            overlaid: HashMap::new(),
+        };
+        assert_eq!(registry.closest("spdlgo"), Some("spdlog"));
+        assert_eq!(registry.closest("SPDLOG"), Some("spdlog"));
+        assert_eq!(registry.closest("fmt"), Some("{fmt}"));
+        assert_eq!(registry.closest("zlb"), Some("zlib"));
+        assert_eq!(registry.closest("nlohmann"), None);
+        assert_eq!(registry.closest("boost"), None);
+    }
 }
@@ -0,0 +1,258 @@
+// `cinstall relocate`: moving an installed package to another prefix.
+//
+// The files in the package's manifest are staged again under the new prefix
+// and copied into place the same way an install does. The old prefix is baked
+// into some of them, so on the way:
+//
+// - pkg-config files, CMake package files, libtool archives and scripts have
+//   every mention of the old prefix replaced,
+// - programs and libraries have the old prefix in their rpath replaced, with
+//   `patchelf` on Linux and `install_name_tool` on macOS.
+//
+// Then the old files are removed and the database, manifest and environment
+// files point at the new prefix. Nothing has to be rebuilt.
+
+use crate::database::{self, Database, MergedDatabase};
+use crate::installer::InstallError;
+use crate::manifest::Manifest;
+use crate::{cmake_registry, doctor, modulefile, outputln, staging, workspace};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Whether the text in `file` can mention the prefix.
+fn is_text_with_paths(file: &Path, contents: &[u8]) -> bool {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let extension = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    matches!(extension, "pc" | "cmake" | "la")
+        || name.ends_with("-config")
+        || contents.starts_with(b"#!")
+}
+
+fn is_binary(contents: &[u8]) -> bool {
+    contents.starts_with(b"\x7fELF")
+        || [
+            [0xfe, 0xed, 0xfa, 0xcf],
+            [0xcf, 0xfa, 0xed, 0xfe],
+            [0xca, 0xfe, 0xba, 0xbe],
+        ]
+        .iter()
+        .any(|magic| contents.starts_with(magic))
+}
+
+fn replace(contents: &str, from: &Path, to: &Path) -> String {
+    contents.replace(&*from.to_string_lossy(), &to.to_string_lossy())
+}
+
+fn run(program: &str, args: &[&str]) -> Option<String> {
+    let output = Command::new(program).args(args).output().ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Point the rpath of `file` at `to` instead of `from`. Returns false if that
+/// was needed but couldn't be done.
+fn fix_rpath(file: &Path, from: &Path, to: &Path) -> bool {
+    let path = file.to_string_lossy();
+    if std::env::consts::OS == "macos" {
+        let Some(loads) = run("otool", &["-l", &path]) else {
+            return false;
+        };
+        let from_text = from.to_string_lossy();
+        for rpath in loads
+            .lines()
+            .filter_map(|l| l.trim().strip_prefix("path "))
+            .map(|l| l.split(" (offset").next().unwrap_or(l))
+            .filter(|r| r.starts_with(&*from_text))
+        {
+            let new = replace(rpath, from, to);
+            if run("install_name_tool", &["-rpath", rpath, &new, &path]).is_none() {
+                return false;
+            }
+        }
+        return true;
+    }
+
+    if doctor::find_program("patchelf").is_none() {
+        return false;
+    }
+    match run("patchelf", &["--print-rpath", &path]) {
+        Some(rpath) if rpath.contains(&*from.to_string_lossy()) => run(
+            "patchelf",
+            &["--set-rpath", &replace(&rpath, from, to), &path],
+        )
+        .is_some(),
+        // not dynamically linked, or nothing to change.
+        _ => true,
+    }
+}
+
+/// Stage `file` at `to`, fixing the prefix in it. Returns false if its rpath
+/// couldn't be fixed.
+pub fn restage(
+    file: &Path,
+    to: &Path,
+    from_prefix: &Path,
+    to_prefix: &Path,
+) -> Result<bool, InstallError> {
+    staging::copy_tree(file, to)?;
+    if to.is_symlink() {
+        if let Ok(target) = std::fs::read_link(to) {
+            if target.starts_with(from_prefix) {
+                let _ = std::fs::remove_file(to);
+                let target = to_prefix.join(target.strip_prefix(from_prefix).unwrap_or(&target));
+                std::os::unix::fs::symlink(target, to)
+                    .map_err(|e| InstallError::BadDirectory(format!("{}: {}", to.display(), e)))?;
+            }
+        }
+        return Ok(true);
+    }
+    let Ok(contents) = std::fs::read(to) else {
+        return Ok(true);
+    };
+    if is_binary(&contents) {
+        return Ok(fix_rpath(to, from_prefix, to_prefix));
+    }
+    if let Ok(text) = String::from_utf8(contents) {
+        if is_text_with_paths(to, text.as_bytes()) && text.contains(&*from_prefix.to_string_lossy())
+        {
+            std::fs::write(to, replace(&text, from_prefix, to_prefix))
+                .map_err(|_| InstallError::FailedToWriteToFile)?;
+        }
+    }
+    Ok(true)
+}
+
+fn relocate_run(name: &str, to: &Path) -> Result<(), InstallError> {
+    let (scope, installed) = match MergedDatabase::open().find(name) {
+        Some((scope, installed)) => (scope, installed.clone()),
+        None => {
+            return Err(InstallError::UnknownFatal(format!(
+                "`{}` is not installed.",
+                name
+            )))
+        }
+    };
+    if let Some(version_dir) = &installed.version_dir {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` was installed with --versioned into {}, which can't be moved. Uninstall it and install it again with `--prefix {}`.",
+            name,
+            version_dir.display(),
+            to.display()
+        )));
+    }
+    let from = installed.prefix.clone();
+    if from == to {
+        return Err(InstallError::UnknownFatal(format!(
+            "`{}` is already installed into {}.",
+            name,
+            to.display()
+        )));
+    }
+    let manifest = Manifest::load(name)?;
+    outputln!(
+        "moving `{}` from {} to {}",
+        name,
+        (from.display()),
+        (to.display())
+    );
+
+    let staging = workspace::root()
+        .join("staging")
+        .join(format!("relocate-{}", name.replace('/', "_")));
+    if staging.exists() {
+        let _ = std::fs::remove_dir_all(&staging);
+    }
+    let staged_prefix = staging.join(to.strip_prefix("/").unwrap_or(to));
+    let mut outside = vec![];
+    let mut rpaths_left = vec![];
+    for file in &manifest.files {
+        let Ok(relative) = file.path.strip_prefix(&from) else {
+            outside.push(file.path.clone());
+            continue;
+        };
+        if !file.path.exists() && !file.path.is_symlink() {
+            outputln!(
+                purple,
+                "`{}` is already gone, skipping.",
+                (file.path.display())
+            );
+            continue;
+        }
+        let staged = staged_prefix.join(relative);
+        if !restage(&file.path, &staged, &from, to)? {
+            rpaths_left.push(to.join(relative));
+        }
+    }
+
+    let mut files = staging::commit(name, &staging)?;
+    if !rpaths_left.is_empty() {
+        outputln!(
+            purple,
+            "the rpath of {} file(s) still points at {}, they may not find their libraries until it is changed (`patchelf --set-rpath`):",
+            (rpaths_left.len()),
+            (from.display())
+        );
+        for file in &rpaths_left {
+            eprintln!("  {}", file.display());
+        }
+    }
+
+    // links outside the prefix, like the ones `--symlink-bin` makes, move along.
+    for link in outside {
+        match std::fs::read_link(&link) {
+            Ok(target) if target.starts_with(&from) => {
+                let target = to.join(target.strip_prefix(&from).unwrap_or(&target));
+                let _ = std::fs::remove_file(&link);
+                std::os::unix::fs::symlink(&target, &link).map_err(|e| {
+                    InstallError::BadDirectory(format!("{}: {}", link.display(), e))
+                })?;
+                files.push(link);
+            }
+            _ => files.push(link),
+        }
+    }
+
+    let old = Manifest {
+        files: manifest
+            .files
+            .iter()
+            .filter(|f| f.path.starts_with(&from))
+            .cloned()
+            .collect(),
+        ..manifest.clone()
+    };
+    let removed = old.remove_files()?;
+    outputln!("removed {} files from {}.", removed, (from.display()));
+
+    Manifest::new(name, to, &files).save()?;
+    let mut db = Database::open(scope)?;
+    db.remove(name);
+    db.save()?;
+    let relocated = database::InstalledPackage {
+        prefix: to.to_path_buf(),
+        ..installed
+    };
+    if let Err(e) = modulefile::write(&relocated, &files) {
+        outputln!(red, "warning: failed to write the environment files. {}", e);
+    }
+    if cmake_registry::is_registered(name) {
+        cmake_registry::deregister(name);
+        cmake_registry::register(name, &files);
+    }
+    database::record_install(relocated)?;
+    Ok(())
+}
+
+/// Move the installed package `name` into the prefix `to`.
+pub fn relocate(name: &str, to: &Path) -> Result<(), InstallError> {
+    let to: PathBuf = match std::env::current_dir() {
+        Ok(cwd) => cwd.join(to),
+        Err(_) => to.to_path_buf(),
+    };
+    relocate_run(name, &to)
+}
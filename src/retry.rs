@@ -0,0 +1,117 @@
+// Retrying network operations.
+//
+// Clones, fetches and downloads that fail because of the network (a dropped
+// connection, DNS hiccups, a server having a bad minute) are tried again,
+// waiting twice as long before each new attempt. Failures that won't go away
+// by themselves, like a repository that doesn't exist or wrong credentials,
+// fail at once. The number of attempts and the first wait can be set with the
+// `retries` and `retry_delay` config keys.
+
+use crate::config::Config;
+use crate::installer::{InstallError, Step};
+use crate::outputln;
+use std::process::ExitStatus;
+use std::time::Duration;
+
+const DEFAULT_ATTEMPTS: u32 = 3;
+const DEFAULT_DELAY: u64 = 2;
+
+/// What git and curl say when the network, rather than the request, failed.
+const TRANSIENT_ERRORS: &[&str] = &[
+    "could not resolve host",
+    "temporary failure in name resolution",
+    "failed to connect",
+    "connection timed out",
+    "operation timed out",
+    "connection reset",
+    "connection refused",
+    "network is unreachable",
+    "early eof",
+    "unexpected disconnect",
+    "the remote end hung up unexpectedly",
+    "rpc failed",
+    "gnutls_handshake",
+    "ssl_error",
+    "returned error: 429",
+    "returned error: 500",
+    "returned error: 502",
+    "returned error: 503",
+    "returned error: 504",
+];
+
+/// Whether the output of a failed step says it failed because of the network.
+pub fn is_transient(output: &[String]) -> bool {
+    output.iter().any(|line| {
+        let line = line.to_lowercase();
+        TRANSIENT_ERRORS.iter().any(|e| line.contains(e))
+    })
+}
+
+/// What git says when the remote wants credentials it didn't get.
+const AUTH_ERRORS: &[&str] = &[
+    "terminal prompts disabled",
+    "could not read username",
+    "could not read password",
+    "authentication failed",
+    "permission denied (publickey",
+    "host key verification failed",
+    "returned error: 401",
+    "returned error: 403",
+];
+
+/// Whether the output of a failed step says the remote needs credentials.
+pub fn needs_credentials(output: &[String]) -> bool {
+    output.iter().any(|line| {
+        let line = line.to_lowercase();
+        AUTH_ERRORS.iter().any(|e| line.contains(e))
+    })
+}
+
+/// What git says when there is no repository at the URL.
+const NOT_FOUND_ERRORS: &[&str] = &[
+    "repository not found",
+    "does not appear to be a git repository",
+    "does not exist",
+    "returned error: 404",
+    "project you were looking for could not be found",
+];
+
+/// Whether the output of a failed step says there is nothing at the URL.
+pub fn not_found(output: &[String]) -> bool {
+    output.iter().any(|line| {
+        let line = line.to_lowercase();
+        NOT_FOUND_ERRORS.iter().any(|e| line.contains(e))
+    })
+}
+
+/// Run `step`, trying again after network errors.
+pub fn run(step: &Step) -> Result<ExitStatus, InstallError> {
+    run_with_output(step).map(|(status, _)| status)
+}
+
+/// Run `step`, trying again after network errors, returning the last lines
+/// the last attempt printed as well.
+pub fn run_with_output(step: &Step) -> Result<(ExitStatus, Vec<String>), InstallError> {
+    let config = Config::load().unwrap_or_default();
+    let attempts = config.retries.unwrap_or(DEFAULT_ATTEMPTS).max(1);
+    let mut delay = Duration::from_secs(config.retry_delay.unwrap_or(DEFAULT_DELAY));
+
+    let mut attempt = 1;
+    loop {
+        let (status, output) = step.run_with_output()?;
+        if status.success() || attempt == attempts || !is_transient(&output) {
+            return Ok((status, output));
+        }
+        attempt += 1;
+        outputln!(
+            purple,
+            "`{}` failed because of the network, trying again in {} seconds (attempt {} of {}).",
+            (step.program),
+            (delay.as_secs()),
+            attempt,
+            attempts
+        );
+        std::thread::sleep(delay);
+        delay *= 2;
+    }
+}
@@ -0,0 +1,205 @@
+// `cinstall rollback`: going back to what was installed before an upgrade.
+//
+// Before `cinstall upgrade` builds a package again, its database entry and
+// install manifest are put aside in `~/.local/share/cinstall/rollback/`, and
+// kept once the upgrade succeeds, in place of what an earlier upgrade kept.
+// With the `rollback_files` config key its files are copied there too.
+//
+// `cinstall rollback <package>` puts the copied files back, or without them
+// builds the commit that was installed before again. Either way the files
+// only the newer version installed are removed.
+
+use crate::database::{self, InstalledPackage, MergedDatabase};
+use crate::installer::InstallError;
+use crate::manifest::{self, Manifest};
+use crate::{modulefile, outputln, paths};
+use std::path::{Path, PathBuf};
+
+fn rollback_dir() -> PathBuf {
+    paths::data_dir().join("rollback")
+}
+
+fn dir(name: &str) -> PathBuf {
+    rollback_dir().join(manifest::file_name(name))
+}
+
+/// Where the copy of the installed file `path` is kept in `dir`.
+fn copy_of(dir: &Path, path: &Path) -> PathBuf {
+    dir.join("files")
+        .join(path.strip_prefix("/").unwrap_or(path))
+}
+
+/// Copy the file or symlink `from` to `to`, replacing what is there.
+fn copy(from: &Path, to: &Path) -> std::io::Result<()> {
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // copying onto a symlink would write where it points.
+    if std::fs::symlink_metadata(to).is_ok() {
+        std::fs::remove_file(to)?;
+    }
+    if std::fs::symlink_metadata(from)?.is_symlink() {
+        std::os::unix::fs::symlink(std::fs::read_link(from)?, to)
+    } else {
+        std::fs::copy(from, to).map(|_| ())
+    }
+}
+
+fn write_json(path: &Path, value: &impl serde::Serialize) -> Result<(), InstallError> {
+    let json = serde_json::to_string_pretty(value)
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    std::fs::write(path, json).map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Option<T> {
+    let contents = std::fs::read_to_string(path).ok()?;
+    serde_json::from_str(&contents).ok()
+}
+
+/// What was installed of a package before it was upgraded.
+pub struct Previous {
+    pub package: InstalledPackage,
+    pub manifest: Option<Manifest>,
+    dir: PathBuf,
+}
+
+impl Previous {
+    /// Whether its files were copied, so that it can be put back without
+    /// building it.
+    pub fn has_files(&self) -> bool {
+        self.manifest.is_some() && self.dir.join("files").is_dir()
+    }
+}
+
+/// What was put aside of a package while it is being upgraded.
+pub struct PutAside {
+    name: String,
+    dir: PathBuf,
+}
+
+impl PutAside {
+    /// Keep it for `cinstall rollback`, the upgrade succeeded.
+    pub fn keep(self) {
+        let kept = dir(&self.name);
+        let _ = std::fs::remove_dir_all(&kept);
+        if std::fs::rename(&self.dir, &kept).is_err() {
+            outputln!(
+                red,
+                "warning: failed to keep what was installed of `{}`, `cinstall rollback` can't go back to it.",
+                (self.name)
+            );
+        }
+    }
+
+    /// Throw it away, the upgrade failed so it is still what is installed.
+    pub fn discard(self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn put_aside_in(
+    installed: &InstalledPackage,
+    dir: &Path,
+    copy_files: bool,
+) -> Result<(), InstallError> {
+    std::fs::create_dir_all(dir).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    write_json(&dir.join("package.json"), installed)?;
+    let Ok(manifest) = Manifest::load(&installed.name) else {
+        return Ok(());
+    };
+    write_json(&dir.join("manifest.json"), &manifest)?;
+    if copy_files {
+        for file in &manifest.files {
+            copy(&file.path, &copy_of(dir, &file.path)).map_err(|e| {
+                InstallError::UnknownFatal(format!("{}: {}", file.path.display(), e))
+            })?;
+        }
+    }
+    Ok(())
+}
+
+/// Put aside what is installed of `name` before it is upgraded, with a copy
+/// of its files with `copy_files`. Nothing is when it isn't installed.
+pub fn put_aside(name: &str, copy_files: bool) -> Option<PutAside> {
+    let installed = MergedDatabase::open().find(name)?.1.clone();
+    let put_aside = PutAside {
+        name: name.to_string(),
+        dir: rollback_dir().join(format!("{}.new", manifest::file_name(name))),
+    };
+    let _ = std::fs::remove_dir_all(&put_aside.dir);
+    if let Err(e) = put_aside_in(&installed, &put_aside.dir, copy_files) {
+        outputln!(
+            red,
+            "warning: failed to put aside what is installed of `{}`, `cinstall rollback` won't be able to go back to it. {}",
+            name,
+            e
+        );
+        put_aside.discard();
+        return None;
+    }
+    Some(put_aside)
+}
+
+/// What was installed of `name` before its last upgrade, if it was kept.
+pub fn previous(name: &str) -> Option<Previous> {
+    let dir = dir(name);
+    Some(Previous {
+        package: read_json(&dir.join("package.json"))?,
+        manifest: read_json(&dir.join("manifest.json")),
+        dir,
+    })
+}
+
+/// Forget what was installed of `name` before its last upgrade.
+pub fn forget(name: &str) {
+    let _ = std::fs::remove_dir_all(dir(name));
+}
+
+/// Remove the files of `newer` that `older` doesn't have.
+pub fn remove_newer_files(newer: &Manifest, older: &Manifest) -> Result<(), InstallError> {
+    let kept: Vec<&Path> = older.files.iter().map(|f| f.path.as_path()).collect();
+    let only_newer = Manifest {
+        files: newer
+            .files
+            .iter()
+            .filter(|f| !kept.contains(&f.path.as_path()))
+            .cloned()
+            .collect(),
+        ..newer.clone()
+    };
+    if !only_newer.files.is_empty() {
+        let removed = only_newer.remove_files()?;
+        outputln!("removed {} files only the newer version had.", removed);
+    }
+    Ok(())
+}
+
+/// Put the copied files of `previous` back and record it as installed again.
+pub fn restore(previous: &Previous) -> Result<(), InstallError> {
+    let Some(manifest) = previous.manifest.as_ref().filter(|_| previous.has_files()) else {
+        return Err(InstallError::UnknownFatal(
+            "the files weren't copied, it has to be built again.".into(),
+        ));
+    };
+    let name = &previous.package.name;
+    if let Ok(newer) = Manifest::load(name) {
+        remove_newer_files(&newer, manifest)?;
+    }
+    for file in &manifest.files {
+        copy(&copy_of(&previous.dir, &file.path), &file.path)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", file.path.display(), e)))?;
+    }
+    outputln!(
+        "put {} files back into {}.",
+        (manifest.files.len()),
+        (manifest.prefix.display())
+    );
+    manifest.save()?;
+
+    let files: Vec<PathBuf> = manifest.files.iter().map(|f| f.path.clone()).collect();
+    if let Err(e) = modulefile::write(&previous.package, &files) {
+        outputln!(red, "warning: failed to write the environment files. {}", e);
+    }
+    database::record_install(previous.package.clone())?;
+    Ok(())
+}
@@ -0,0 +1,81 @@
+// Install runs.
+//
+// Every install gets a short random ID. It names the run's workspace and log
+// file, is recorded with its history transaction, and is shown in every message
+// printed while the run is going, so that a specific run can be referred to
+// even when several installs happen at once or their logs get mixed together.
+//
+// The log at `~/.local/share/cinstall/logs/<id>.jsonl` has one JSON event per line.
+
+use crate::paths;
+use rand::{distributions::Alphanumeric, thread_rng, Rng};
+use serde_json::{json, Value};
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+static CURRENT: Mutex<Option<String>> = Mutex::new(None);
+
+pub fn logs_dir() -> PathBuf {
+    paths::data_dir().join("logs")
+}
+
+pub fn log_path(id: &str) -> PathBuf {
+    logs_dir().join(format!("{}.jsonl", id))
+}
+
+/// Begin a new run and return its ID.
+pub fn start() -> String {
+    let id: String = thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(8)
+        .map(|c| char::from(c).to_ascii_lowercase())
+        .collect();
+    *CURRENT.lock().unwrap() = Some(id.clone());
+    id
+}
+
+/// Whether `id` could be the ID of a run, as `start` makes them.
+pub fn is_id(id: &str) -> bool {
+    id.len() == 8
+        && id
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit())
+}
+
+/// End the current run, messages go back to not mentioning one.
+pub fn finish() {
+    *CURRENT.lock().unwrap() = None;
+}
+
+/// The ID of the run in progress, if there is one.
+pub fn id() -> Option<String> {
+    CURRENT.lock().unwrap().clone()
+}
+
+/// Append an event to the current run's log. Logging is best effort; a run
+/// never fails because its log couldn't be written.
+pub fn event(kind: &str, fields: Value) {
+    let id = match id() {
+        Some(id) => id,
+        None => return,
+    };
+
+    let mut event = json!({
+        "run": id,
+        "time": chrono::Utc::now(),
+        "event": kind,
+    });
+    if let (Some(event), Value::Object(fields)) = (event.as_object_mut(), fields) {
+        event.extend(fields);
+    }
+
+    let _ = std::fs::create_dir_all(logs_dir());
+    if let Ok(mut file) = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(log_path(&id))
+    {
+        let _ = writeln!(file, "{}", event);
+    }
+}
@@ -0,0 +1,328 @@
+// Fuzzy search of the package registry.
+//
+// Every word of the query has to match the package somehow, and how well it
+// matches decides where the package is listed:
+//
+// - the name: equal, starting with the word, containing it, having its
+//   letters in order (`stbimg` for `stb-image`), being an abbreviation of it
+//   (`fmt` for `format`), or a typo away from it;
+// - a tag equal to or starting with the word;
+// - the description: a word equal to it, starting with it, containing it, or a
+//   typo away from it.
+//
+// The parts of the name and description that matched are remembered so they
+// can be highlighted.
+
+use crate::registry::Package;
+use std::ops::Range;
+
+pub struct Hit<'a> {
+    pub name: &'a str,
+    pub package: &'a Package,
+    pub score: u32,
+    /// What matched, in characters of the name and the description.
+    pub name_spans: Vec<Range<usize>>,
+    pub description_spans: Vec<Range<usize>>,
+}
+
+/// `text` lowercased one character at a time, so indices into it are indices
+/// into `text`.
+fn lowercase(text: &str) -> Vec<char> {
+    text.chars()
+        .map(|c| c.to_lowercase().next().unwrap_or(c))
+        .collect()
+}
+
+/// Where `needle` is in `haystack`, as character ranges.
+fn occurrences(haystack: &[char], needle: &[char]) -> Vec<Range<usize>> {
+    if needle.is_empty() || needle.len() > haystack.len() {
+        return vec![];
+    }
+    (0..=haystack.len() - needle.len())
+        .filter(|&i| haystack[i..i + needle.len()] == *needle)
+        .map(|i| i..i + needle.len())
+        .collect()
+}
+
+/// The positions of `needle`'s characters in `haystack`, in order, when they
+/// all are and the first ones are the same.
+fn subsequence(haystack: &[char], needle: &[char]) -> Option<Vec<usize>> {
+    if needle.first() != haystack.first() {
+        return None;
+    }
+    let mut positions = vec![];
+    let mut rest = 0;
+    for c in needle {
+        let found = haystack[rest..].iter().position(|h| h == c)? + rest;
+        positions.push(found);
+        rest = found + 1;
+    }
+    Some(positions)
+}
+
+/// Edits (insertions, deletions, substitutions and swaps of neighbours) to
+/// get from `a` to `b`.
+pub fn distance(a: &[char], b: &[char]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, cell) in d[0].iter_mut().enumerate() {
+        *cell = j;
+    }
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/// How many typos a word of the query may have.
+fn typos_allowed(word: &[char]) -> usize {
+    match word.len() {
+        0..=3 => 0,
+        4..=7 => 1,
+        _ => 2,
+    }
+}
+
+/// The `len` characters from `start`.
+fn span(start: usize, len: usize) -> Vec<Range<usize>> {
+    std::iter::once(start..start + len).collect()
+}
+
+/// Collapse positions into ranges of neighbouring characters.
+fn spans(positions: &[usize]) -> Vec<Range<usize>> {
+    let mut spans: Vec<Range<usize>> = vec![];
+    for &p in positions {
+        match spans.last_mut() {
+            Some(last) if last.end == p => last.end += 1,
+            _ => spans.push(p..p + 1),
+        }
+    }
+    spans
+}
+
+fn match_name(name: &[char], word: &[char]) -> Option<(u32, Vec<Range<usize>>)> {
+    if name == word {
+        return Some((100, span(0, name.len())));
+    }
+    let found = occurrences(name, word);
+    if let Some(first) = found.first() {
+        let score = if first.start == 0 { 80 } else { 60 };
+        return Some((score, found));
+    }
+    if word.len() >= 2 {
+        if let Some(positions) = subsequence(name, word) {
+            return Some((45, spans(&positions)));
+        }
+    }
+    // `{fmt}` is short for `format`, the punctuation isn't part of it.
+    let letters: Vec<usize> = (0..name.len())
+        .filter(|&i| name[i].is_alphanumeric())
+        .collect();
+    let short: Vec<char> = letters.iter().map(|&i| name[i]).collect();
+    if short.len() >= 2 && short.len() < word.len() && subsequence(word, &short).is_some() {
+        return Some((40, spans(&letters)));
+    }
+    let allowed = typos_allowed(word);
+    if allowed > 0 && distance(name, word) <= allowed {
+        return Some((35, span(0, name.len())));
+    }
+    None
+}
+
+fn match_tags(tags: &[String], word: &[char]) -> Option<u32> {
+    let word: String = word.iter().collect();
+    tags.iter()
+        .map(|tag| tag.to_lowercase())
+        .filter_map(|tag| {
+            if tag == word {
+                Some(50)
+            } else if tag.starts_with(&word) {
+                Some(30)
+            } else {
+                None
+            }
+        })
+        .max()
+}
+
+/// The words of `text`, with where they start.
+fn words(text: &[char]) -> Vec<(usize, &[char])> {
+    let mut words = vec![];
+    let mut start = None;
+    for i in 0..=text.len() {
+        let letter = text.get(i).is_some_and(|c| c.is_alphanumeric());
+        match (start, letter) {
+            (None, true) => start = Some(i),
+            (Some(s), false) => {
+                words.push((s, &text[s..i]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    words
+}
+
+fn match_description(description: &[char], word: &[char]) -> Option<(u32, Vec<Range<usize>>)> {
+    let words = words(description);
+    if let Some((start, w)) = words.iter().find(|(_, w)| *w == word) {
+        return Some((25, span(*start, w.len())));
+    }
+    let found = occurrences(description, word);
+    if !found.is_empty() {
+        let starts_word = words
+            .iter()
+            .any(|(start, _)| found.iter().any(|f| f.start == *start));
+        return Some((if starts_word { 20 } else { 15 }, found));
+    }
+    let allowed = typos_allowed(word);
+    let close: Vec<Range<usize>> = words
+        .iter()
+        .filter(|(_, w)| allowed > 0 && distance(w, word) <= allowed)
+        .map(|(start, w)| *start..start + w.len())
+        .collect();
+    (!close.is_empty()).then_some((10, close))
+}
+
+/// How well `name` matches the words of `query`, if every one of them matches.
+fn score<'a>(name: &'a str, package: &'a Package, query: &[Vec<char>]) -> Option<Hit<'a>> {
+    let lower_name = lowercase(name);
+    let description = lowercase(&package.description);
+    let mut hit = Hit {
+        name,
+        package,
+        score: 0,
+        name_spans: vec![],
+        description_spans: vec![],
+    };
+    for word in query {
+        let in_name = match_name(&lower_name, word);
+        let in_tags = match_tags(&package.tags, word);
+        let in_description = match_description(&description, word);
+        let best = [
+            in_name.as_ref().map(|m| m.0),
+            in_tags,
+            in_description.as_ref().map(|m| m.0),
+        ]
+        .into_iter()
+        .flatten()
+        .max()?;
+        hit.score += best;
+        if let Some((_, spans)) = in_name {
+            hit.name_spans.extend(spans);
+        }
+        if let Some((_, spans)) = in_description {
+            hit.description_spans.extend(spans);
+        }
+    }
+    Some(hit)
+}
+
+/// The packages matching `query`, best first.
+pub fn search<'a>(
+    packages: impl IntoIterator<Item = (&'a String, &'a Package)>,
+    query: &str,
+) -> Vec<Hit<'a>> {
+    let query: Vec<Vec<char>> = query.split_whitespace().map(lowercase).collect();
+    if query.is_empty() {
+        return vec![];
+    }
+    let mut hits: Vec<Hit> = packages
+        .into_iter()
+        .filter_map(|(name, package)| score(name, package, &query))
+        .collect();
+    hits.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.name.len().cmp(&b.name.len()))
+            .then(a.name.cmp(b.name))
+    });
+    hits
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::registry::Language;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    fn packages() -> Vec<(String, Package)> {
+        let package = |name: &str, description: &str, tags: &[&str]| {
+            let mut package = Package::new("https://github.com/me/x", description, Language::CXX);
+            package.tags = tags.iter().map(|t| t.to_string()).collect();
+            (name.to_string(), package)
+        };
+        vec![
+            package("{fmt}", "A modern formatting library", &["format"]),
+            package("spdlog", "Fast C++ logging library", &["logging"]),
+            package("stb-image", "Image loading from files or memory", &[]),
+            package("fmtlog", "A fast logging library built on fmt", &[]),
+            package("json", "JSON for modern C++", &["json", "serialization"]),
+        ]
+    }
+
+    fn names(packages: &[(String, Package)], query: &str) -> Vec<String> {
+        search(packages.iter().map(|(n, p)| (n, p)), query)
+            .into_iter()
+            .map(|hit| hit.name.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn distance_counts_edits() {
+        assert_eq!(distance(&chars("spdlog"), &chars("spdlog")), 0);
+        assert_eq!(distance(&chars("spdlog"), &chars("spdlg")), 1);
+        assert_eq!(distance(&chars("spdlog"), &chars("spdlogg")), 1);
+        assert_eq!(distance(&chars("spdlog"), &chars("spdlof")), 1);
+        // swapping neighbours is one typo, not two.
+        assert_eq!(distance(&chars("spdlog"), &chars("psdlog")), 1);
+        assert_eq!(distance(&chars(""), &chars("fmt")), 3);
+    }
+
+    #[test]
+    fn better_matches_come_first() {
+        let packages = packages();
+        let found = names(&packages, "fmt");
+        // an abbreviation ranks below a name starting with the word.
+        assert_eq!(found[..2], ["fmtlog", "{fmt}"]);
+        assert_eq!(names(&packages, "logging")[..2], ["spdlog", "fmtlog"]);
+    }
+
+    #[test]
+    fn names_match_with_typos_and_letters_in_order() {
+        let packages = packages();
+        assert_eq!(names(&packages, "spdlgo"), ["spdlog"]);
+        assert_eq!(names(&packages, "stbimg"), ["stb-image"]);
+        assert!(names(&packages, "qwerty").is_empty());
+    }
+
+    #[test]
+    fn every_word_has_to_match() {
+        let packages = packages();
+        assert_eq!(names(&packages, "fast logging"), ["spdlog", "fmtlog"]);
+        assert_eq!(names(&packages, "modern json"), ["json"]);
+        assert!(names(&packages, "json logging").is_empty());
+        assert!(names(&packages, "  ").is_empty());
+    }
+
+    #[test]
+    fn what_matched_is_remembered() {
+        let packages = packages();
+        let hits = search(packages.iter().map(|(n, p)| (n, p)), "image");
+        assert_eq!(hits[0].name_spans, span(4, 5));
+        assert_eq!(hits[0].description_spans, span(0, 5));
+    }
+}
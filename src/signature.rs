@@ -0,0 +1,222 @@
+// `--verify-signature`: checking upstream's OpenPGP signature before building.
+//
+// Registry packages can list the fingerprints of the keys their releases are
+// signed with (`signing_keys`), and `--signing-key` adds more for any URL. A
+// checkout of a signed tag is checked with `git verify-tag`, anything else
+// with `git verify-commit`, and an archive with the detached signature next
+// to it (`<archive>.asc` or `.sig`). The signature has to be good and made by
+// one of the keys, otherwise nothing is built.
+//
+// The keys have to be in your gpg keyring already, cinstall doesn't fetch
+// them, a key that was fetched the same moment it is trusted proves nothing.
+// They are given as full 40 digit fingerprints or 16 digit long key ids,
+// shorter ids are too easy to make a key for.
+
+use crate::installer::{InstallError, Step};
+use crate::outputln;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+fn normalize(fingerprint: &str) -> String {
+    let key = fingerprint
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_uppercase();
+    match key.strip_prefix("0X") {
+        Some(key) => key.to_string(),
+        None => key,
+    }
+}
+
+/// What is wrong with `key` as a signing key, if anything.
+pub fn key_problem(key: &str) -> Option<String> {
+    let normalized = normalize(key);
+    let hex = normalized.chars().all(|c| c.is_ascii_hexdigit());
+    match normalized.len() {
+        40 | 16 if hex => None,
+        _ => Some(format!(
+            "`{}` isn't a signing key, give a full fingerprint (40 hex digits) or a long key id (16)",
+            key
+        )),
+    }
+}
+
+/// Fail unless there are `keys` and all of them are fingerprints or long ids.
+fn check_keys(keys: &[String]) -> Result<(), InstallError> {
+    if keys.is_empty() {
+        return Err(no_keys());
+    }
+    match keys.iter().find_map(|k| key_problem(k)) {
+        Some(problem) => Err(InstallError::BadSignature(format!("{}.", problem))),
+        None => Ok(()),
+    }
+}
+
+/// The fingerprints of the signature `status` (gpg's `--status-fd` output)
+/// describes, if it is a good one: the key that signed, and its primary key.
+fn good_signers(status: &str) -> Vec<String> {
+    status
+        .lines()
+        .filter_map(|l| l.strip_prefix("[GNUPG:] VALIDSIG "))
+        .flat_map(|l| {
+            let fields: Vec<&str> = l.split_whitespace().collect();
+            [fields.first(), fields.get(9)]
+                .into_iter()
+                .flatten()
+                .map(|f| normalize(f))
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Check gpg's `status` against `keys`.
+fn check(status: &str, keys: &[String], what: &str) -> Result<(), InstallError> {
+    let signers = good_signers(status);
+    if signers.is_empty() {
+        let missing_key = status
+            .lines()
+            .find_map(|l| l.strip_prefix("[GNUPG:] NO_PUBKEY "));
+        return Err(InstallError::BadSignature(match missing_key {
+            Some(key) => format!(
+                "{} is signed with {}, which isn't in your keyring (`gpg --recv-keys {}` if you trust it).",
+                what, key, key
+            ),
+            None if status.contains("BADSIG") => format!("{} has a bad signature.", what),
+            None => format!("{} isn't signed.", what),
+        }));
+    }
+    // long key ids are the last 16 digits of the fingerprint.
+    let trusted = keys.iter().map(|k| normalize(k)).any(|k| {
+        signers
+            .iter()
+            .any(|s| *s == k || (k.len() == 16 && s.len() == 40 && s.ends_with(&k)))
+    });
+    if !trusted {
+        return Err(InstallError::BadSignature(format!(
+            "{} is signed by {}, which isn't one of the package's keys ({}).",
+            what,
+            signers[0],
+            keys.join(", ")
+        )));
+    }
+    outputln!(green, "{} has a good signature by {}.", what, (signers[0]));
+    Ok(())
+}
+
+fn no_keys() -> InstallError {
+    InstallError::BadSignature(
+        "the package lists no signing keys, give them with `--signing-key <fingerprint>`.".into(),
+    )
+}
+
+/// Check the signature of the tag or commit checked out in `dir`.
+pub fn verify_checkout(dir: &Path, keys: &[String]) -> Result<(), InstallError> {
+    check_keys(keys)?;
+    let is_tag = Command::new("git")
+        .args(["cat-file", "-t", "FETCH_HEAD"])
+        .current_dir(dir)
+        .output()
+        .is_ok_and(|o| String::from_utf8_lossy(&o.stdout).trim() == "tag");
+    let (command, object, what) = if is_tag {
+        ("verify-tag", "FETCH_HEAD", "the tag")
+    } else {
+        ("verify-commit", "HEAD", "the commit")
+    };
+    let output = Command::new("git")
+        .args([command, "--raw", object])
+        .current_dir(dir)
+        .output()
+        .map_err(|e| InstallError::CouldNotStartProcess(format!("git: {}", e)))?;
+    // `--raw` puts gpg's status lines on stderr.
+    check(&String::from_utf8_lossy(&output.stderr), keys, what)
+}
+
+/// Check the detached signature of `archive`, downloaded from `url`.
+pub fn verify_archive(archive: &Path, url: &Url, keys: &[String]) -> Result<(), InstallError> {
+    check_keys(keys)?;
+    let parent = archive.parent().unwrap_or(Path::new("/tmp"));
+    let signature = PathBuf::from(format!("{}.sig", archive.display()));
+    let found = [".asc", ".sig"].iter().any(|ext| {
+        let download = Step::new("curl", parent)
+            .arg("-fsSL")
+            .arg("-o")
+            .arg(signature.display().to_string())
+            .arg(format!("{}{}", url, ext));
+        matches!(download.run(), Ok(status) if status.success())
+    });
+    if !found {
+        return Err(InstallError::BadSignature(format!(
+            "there is no signature for {} ({}.asc or .sig).",
+            url, url
+        )));
+    }
+    let output = Command::new("gpg")
+        .args(["--batch", "--status-fd", "1", "--verify"])
+        .arg(&signature)
+        .arg(archive)
+        .output()
+        .map_err(|e| InstallError::CouldNotStartProcess(format!("gpg: {}", e)));
+    let _ = std::fs::remove_file(&signature);
+    check(
+        &String::from_utf8_lossy(&output?.stdout),
+        keys,
+        "the archive",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIGNER: &str = "0123456789ABCDEF0123456789ABCDEF01234567";
+    const PRIMARY: &str = "FEDCBA9876543210FEDCBA9876543210FEDCBA98";
+
+    fn status() -> String {
+        format!(
+            "[GNUPG:] GOODSIG 89ABCDEF01234567 someone\n[GNUPG:] VALIDSIG {} 2024-01-01 1704067200 0 4 0 1 10 00 {}\n",
+            SIGNER, PRIMARY
+        )
+    }
+
+    #[test]
+    fn keys_are_fingerprints_or_long_ids() {
+        assert!(key_problem(SIGNER).is_none());
+        assert!(key_problem("0123 4567 89ab cdef 0123  4567 89AB CDEF 0123 4567").is_none());
+        assert!(key_problem("0x89ABCDEF01234567").is_none());
+        assert!(key_problem("").is_some());
+        assert!(key_problem("01234567").is_some());
+        assert!(key_problem("89ABCDEF0123456").is_some());
+        assert!(key_problem("Z123456789ABCDEF").is_some());
+    }
+
+    #[test]
+    fn empty_and_short_keys_are_refused() {
+        assert!(check_keys(&[]).is_err());
+        assert!(check_keys(&["".into()]).is_err());
+        assert!(check_keys(&[SIGNER.into(), "01234567".into()]).is_err());
+        assert!(check_keys(&[SIGNER.into()]).is_ok());
+    }
+
+    #[test]
+    fn the_signer_has_to_be_one_of_the_keys() {
+        assert!(check(&status(), &[SIGNER.into()], "the tag").is_ok());
+        assert!(check(&status(), &[PRIMARY.to_lowercase()], "the tag").is_ok());
+        assert!(check(&status(), &["89ABCDEF01234567".into()], "the tag").is_ok());
+        assert!(check(&status(), &["FEDCBA9876543210".into()], "the tag").is_err());
+        assert!(check(&status(), &["01234567".into()], "the tag").is_err());
+        assert!(check(&status(), &["".into()], "the tag").is_err());
+    }
+
+    #[test]
+    fn bad_and_missing_signatures_fail() {
+        assert!(check(
+            "[GNUPG:] BADSIG 89ABCDEF01234567 someone\n",
+            &[SIGNER.into()],
+            "the tag"
+        )
+        .is_err());
+        assert!(check("", &[SIGNER.into()], "the tag").is_err());
+    }
+}
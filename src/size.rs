@@ -0,0 +1,202 @@
+// `cinstall size`: how much space installed packages take up.
+//
+// Sizes come from the files in each package's manifest as they are on disk
+// now, symlinks counting for nothing. `cinstall size --all` lists every
+// package, biggest first, and `cinstall size <package>` breaks one down by
+// directory and by kind of file and shows its largest files, to find what is
+// worth removing.
+
+use crate::database::MergedDatabase;
+use crate::manifest::Manifest;
+use crate::outputln;
+use crate::table::Table;
+use std::collections::HashMap;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+/// `1536` is `1.5 KiB`.
+pub fn human_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KiB", "MiB", "GiB", "TiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+/// The regular files of `manifest` that still exist, with their sizes.
+fn files_of(manifest: &Manifest) -> Vec<(PathBuf, u64, bool)> {
+    manifest
+        .files
+        .iter()
+        .filter_map(|f| {
+            let metadata = std::fs::symlink_metadata(&f.path).ok()?;
+            metadata.is_file().then(|| {
+                let executable = metadata.permissions().mode() & 0o111 != 0;
+                (f.path.clone(), metadata.len(), executable)
+            })
+        })
+        .collect()
+}
+
+/// The size of the files the package `name` installed and how many there are,
+/// if it has a manifest.
+pub fn of(name: &str) -> Option<(u64, usize)> {
+    let files = files_of(&Manifest::load(name).ok()?);
+    Some((files.iter().map(|(_, size, _)| size).sum(), files.len()))
+}
+
+/// The directory under the prefix `file` is counted in: `bin`, `include`, or
+/// one level deeper for `lib` and `share`, which hold very different things
+/// (`lib/cmake`, `share/doc`).
+fn directory_of(prefix: &Path, file: &Path) -> String {
+    let Ok(relative) = file.strip_prefix(prefix) else {
+        return "(outside the prefix)".into();
+    };
+    let parts: Vec<String> = relative
+        .parent()
+        .map(|p| {
+            p.components()
+                .map(|c| c.as_os_str().to_string_lossy().to_string())
+                .collect()
+        })
+        .unwrap_or_default();
+    match parts.as_slice() {
+        [] => ".".into(),
+        [top, next, ..] if top == "lib" || top == "share" => format!("{}/{}", top, next),
+        [top, ..] => top.clone(),
+    }
+}
+
+fn kind_of(prefix: &Path, file: &Path, executable: bool) -> &'static str {
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let extension = file
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default();
+    let in_dir = |dir: &str| file.starts_with(prefix.join(dir));
+    if matches!(
+        extension,
+        "h" | "hh" | "hpp" | "hxx" | "inl" | "ipp" | "tcc"
+    ) || in_dir("include")
+    {
+        "headers"
+    } else if extension == "a" {
+        "static libraries"
+    } else if name.ends_with(".so") || name.contains(".so.") || extension == "dylib" {
+        "shared libraries"
+    } else if matches!(extension, "cmake" | "pc" | "la") {
+        "build system files"
+    } else if in_dir("share/doc") || in_dir("share/man") || in_dir("share/info") {
+        "documentation"
+    } else if executable {
+        "programs"
+    } else {
+        "other"
+    }
+}
+
+/// Add `size` to the `key` entry of `totals`, counting the file.
+fn add(totals: &mut HashMap<String, (u64, usize)>, key: String, size: u64) {
+    let entry = totals.entry(key).or_default();
+    entry.0 += size;
+    entry.1 += 1;
+}
+
+fn print_totals(header: &'static str, totals: HashMap<String, (u64, usize)>, long: bool) {
+    let mut totals: Vec<(String, (u64, usize))> = totals.into_iter().collect();
+    totals.sort_by_key(|(_, (size, _))| std::cmp::Reverse(*size));
+    let mut table = Table::new(&[header, "SIZE", "FILES"]);
+    for (key, (size, count)) in totals {
+        table.row(vec![key, human_size(size), count.to_string()]);
+    }
+    table.print(long);
+}
+
+/// Break the installed package `name` down.
+pub fn package(name: &str, long: bool) -> bool {
+    let manifest = match Manifest::load(name) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            outputln!(red, "{}", e);
+            return false;
+        }
+    };
+    let mut files = files_of(&manifest);
+    let total: u64 = files.iter().map(|(_, size, _)| size).sum();
+    outputln!(
+        "`{}` takes up {} in {} files under {}.",
+        name,
+        (human_size(total)),
+        (files.len()),
+        (manifest.prefix.display())
+    );
+    if files.is_empty() {
+        return true;
+    }
+
+    let mut directories = HashMap::new();
+    let mut kinds = HashMap::new();
+    for (file, size, executable) in &files {
+        add(
+            &mut directories,
+            directory_of(&manifest.prefix, file),
+            *size,
+        );
+        add(
+            &mut kinds,
+            kind_of(&manifest.prefix, file, *executable).to_string(),
+            *size,
+        );
+    }
+    print_totals("DIRECTORY", directories, long);
+    println!();
+    print_totals("KIND", kinds, long);
+    println!();
+
+    files.sort_by_key(|(_, size, _)| std::cmp::Reverse(*size));
+    let mut table = Table::new(&["SIZE", "LARGEST FILES"]);
+    for (file, size, _) in files.iter().take(10) {
+        table.row(vec![human_size(*size), file.display().to_string()]);
+    }
+    table.print(long);
+    true
+}
+
+/// List every installed package by the space it takes up.
+pub fn all(long: bool) {
+    let mut packages: Vec<(String, u64, usize, PathBuf)> = MergedDatabase::open()
+        .iter()
+        .map(|(_, package)| {
+            let (size, count) = of(&package.name).unwrap_or_default();
+            (package.name.clone(), size, count, package.prefix.clone())
+        })
+        .collect();
+    if packages.is_empty() {
+        outputln!(purple, "no packages are installed.");
+        return;
+    }
+    packages.sort_by_key(|(_, size, _, _)| std::cmp::Reverse(*size));
+
+    let total: u64 = packages.iter().map(|(_, size, _, _)| size).sum();
+    let mut table = Table::new(&["NAME", "SIZE", "FILES", "PREFIX"]);
+    for (name, size, count, prefix) in packages {
+        table.row(vec![
+            name,
+            human_size(size),
+            count.to_string(),
+            prefix.display().to_string(),
+        ]);
+    }
+    table.print(long);
+    outputln!(
+        "installed packages take up {} in total.",
+        (human_size(total))
+    );
+}
@@ -0,0 +1,536 @@
+// Working out where a package comes from.
+//
+// A target on the command line can be a registry name (`{fmt}`), a URL, or a
+// GitHub `owner/repo`. URLs have to be on GitHub, GitLab, Bitbucket, Codeberg,
+// or a host the user allowed with `--host-ok` or the `hosts` config key (a
+// self-hosted GitLab, say), and links to a branch, commit or archive on the
+// known hosts are understood too. Release archives (`.tar.gz`, `.zip`) can come
+// from anywhere, they are downloaded instead of cloned. A local directory
+// (`./fmt`, `/src/fmt` or `file:///src/fmt`) is built where it is, without
+// cloning it, for trying out changes before they are pushed. SSH remotes (`git@host:owner/repo.git`) work
+// the same way, and `--allow-any-host` skips the host check for anything git
+// can clone. Any target can end in `@<ref>` to install a
+// branch, tag or commit, or in `#pull/<number>` to install a pull request, and
+// `--fork owner/repo@branch` swaps the repository for a fork while keeping the
+// package's name. `--ssh`, or the `protocol` config key set to `ssh`, clones
+// the HTTPS URLs of all of these over SSH.
+
+use crate::cli::usage_error;
+use crate::config::Config;
+use crate::installer::{InstallError, Recipe};
+use crate::paths;
+use crate::registry::{self, PackageRegistry, Requirements, Variant};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use url::Url;
+
+pub struct Source {
+    pub name: String,
+    pub url: Url,
+    /// A ref that came with the target itself, like `fmt@10.2.1` or a pull request's head.
+    pub git_ref: Option<String>,
+    /// The ref the registry pins the package to.
+    pub pinned: Option<String>,
+    /// Extra arguments for cmake from the registry.
+    pub cmake_args: Vec<String>,
+    /// The programs the registry says the package provides.
+    pub binaries: Vec<String>,
+    /// What the registry says the package needs to build.
+    pub requires: Requirements,
+    /// Where else the registry says the package can be cloned from.
+    pub mirrors: Vec<Url>,
+    /// The checksum the registry pins the package's archive to.
+    pub sha256: Option<String>,
+    /// The keys the registry says upstream signs with.
+    pub signing_keys: Vec<String>,
+    /// What the registry says to tell the user after installing the package.
+    pub notes: Option<String>,
+    /// The registry packages the package needs installed first.
+    pub dependencies: Vec<String>,
+    /// The make targets the registry says install the package.
+    pub make_targets: Vec<String>,
+    /// The environment the registry says to build the package with.
+    pub env: Vec<(String, String)>,
+    /// The registry's own commands for building the package.
+    pub recipe: Recipe,
+    /// The patches the registry applies to the package before building it.
+    pub patches: Vec<String>,
+    /// The variants the registry offers for the package.
+    pub variants: BTreeMap<String, Variant>,
+}
+
+/// Use the last part of the URL path as the package name, so that
+/// `https://github.com/fmtlib/fmt` is tracked as `fmt`.
+pub fn package_name_for_url(url: &Url) -> String {
+    url.path_segments()
+        .and_then(|mut segments| segments.rfind(|s| !s.is_empty()))
+        .map(|name| name.trim_end_matches(".git").to_string())
+        .unwrap_or_else(|| url.to_string())
+}
+
+/// `owner/repo`, as a GitHub URL.
+pub fn github_url(repo: &str) -> Option<Url> {
+    let mut parts = repo.split('/');
+    let (owner, name) = (parts.next()?, parts.next()?);
+    if owner.is_empty() || name.is_empty() || parts.next().is_some() {
+        return None;
+    }
+    Url::parse(&format!("https://github.com/{}/{}", owner, name)).ok()
+}
+
+/// `pull/3000` becomes the ref GitHub keeps the pull request's head at.
+fn fragment_ref(fragment: &str) -> String {
+    let number = fragment
+        .strip_prefix("pull/")
+        .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()));
+    match number {
+        Some(number) => format!("refs/pull/{}/head", number),
+        None => usage_error(format!(
+            "expected `#pull/<number>` after the package, got `#{}`",
+            fragment
+        )),
+    }
+}
+
+const KNOWN_HOSTS: &[&str] = &["github.com", "gitlab.com", "bitbucket.org", "codeberg.org"];
+
+const ARCHIVE_EXTENSIONS: &[&str] = &[".tar.gz", ".tar.bz2", ".tar.xz", ".tgz", ".zip"];
+
+/// Whether `url` is a release archive, which is downloaded rather than cloned.
+pub fn is_archive(url: &Url) -> bool {
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .any(|ext| url.path().ends_with(ext))
+}
+
+/// The name of the project an archive from anywhere but the known hosts is
+/// of, `zlib-1.3.1.tar.gz` is `zlib`.
+fn archive_name(url: &Url) -> String {
+    let file = package_name_for_url(url);
+    let stem = archive_ref(&file).unwrap_or(file);
+    match stem.rsplit_once('-') {
+        Some((name, version))
+            if !name.is_empty() && version.starts_with(|c: char| c.is_ascii_digit()) =>
+        {
+            name.to_string()
+        }
+        _ => stem,
+    }
+}
+
+/// The ref in an archive's file name, `v1.2.tar.gz` is `v1.2`.
+fn archive_ref(file: &str) -> Option<String> {
+    ARCHIVE_EXTENSIONS
+        .iter()
+        .find_map(|ext| file.strip_suffix(ext))
+        .filter(|r| !r.is_empty())
+        .map(String::from)
+}
+
+/// Turn a link to a page or archive of a project into the URL it is cloned
+/// from, along with the ref the link was for. These are understood:
+///
+/// - GitHub: `/tree/<ref>`, `/commit/<sha>`, `/releases/tag/<tag>`, `/archive/[refs/tags/]<ref>.tar.gz`
+/// - GitLab: `/-/tree/<ref>`, `/-/commit/<sha>`, `/-/archive/<ref>/<file>`
+/// - Codeberg: `/src/branch/<ref>`, `/src/tag/<ref>`, `/src/commit/<sha>`, `/archive/<ref>.tar.gz`
+/// - Bitbucket: `/src/<ref>`, `/commits/<sha>`, `/get/<ref>.tar.gz`
+///
+/// A user name in the URL, as in the clone URLs Bitbucket hands out, is dropped.
+fn clone_url(url: &Url) -> (Url, Option<String>) {
+    let mut clean = url.clone();
+    // over SSH the user name is how git logs in.
+    if clean.scheme() != "ssh" {
+        let _ = clean.set_username("");
+        let _ = clean.set_password(None);
+    }
+    clean.set_query(None);
+    clean.set_fragment(None);
+
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    let (repo, rest): (&[&str], &[&str]) = match url.host_str() {
+        // GitLab projects can be in nested groups, pages start after `/-/`.
+        Some("gitlab.com") => match segments.iter().position(|s| *s == "-") {
+            Some(i) => (&segments[..i], &segments[i + 1..]),
+            None => (&segments[..], &[]),
+        },
+        Some("github.com" | "codeberg.org" | "bitbucket.org") if segments.len() > 2 => {
+            segments.split_at(2)
+        }
+        _ => return (clean, None),
+    };
+
+    let git_ref = match rest {
+        ["tree" | "blob" | "commit" | "commits", r, ..] => Some(r.to_string()),
+        ["src", "branch" | "tag" | "commit", r, ..] => Some(r.to_string()),
+        ["src", r, ..] => Some(r.to_string()),
+        ["releases", "tag", r, ..] => Some(r.to_string()),
+        ["archive", "refs", "tags" | "heads", file] => archive_ref(file),
+        ["archive", r, _file] => Some(r.to_string()),
+        ["archive" | "get", file] => archive_ref(file),
+        _ => None,
+    };
+    clean.set_path(&repo.join("/"));
+    (clean, git_ref)
+}
+
+fn host_allowed(host: &str, host_ok: &[String]) -> bool {
+    let configured = Config::load()
+        .ok()
+        .and_then(|c| c.hosts)
+        .unwrap_or_default();
+    KNOWN_HOSTS.contains(&host) || host_ok.iter().chain(&configured).any(|h| h == host)
+}
+
+/// `git@host:owner/repo.git`, the form git uses for SSH remotes, as an
+/// `ssh://` URL. Anything else is returned as is.
+fn scp_to_url(target: &str) -> std::borrow::Cow<'_, str> {
+    if target.contains("://") {
+        return target.into();
+    }
+    let Some((user_host, path)) = target.split_once(':') else {
+        return target.into();
+    };
+    match user_host.split_once('@') {
+        Some((user, host)) if !user.is_empty() && !host.is_empty() && !user_host.contains('/') => {
+            format!("ssh://{}@{}/{}", user, host, path.trim_start_matches('/')).into()
+        }
+        _ => target.into(),
+    }
+}
+
+/// How git repositories given as HTTPS URLs are cloned.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Protocol {
+    Https,
+    /// For private repositories cloned with SSH keys, or networks that block
+    /// git over HTTPS.
+    Ssh,
+}
+
+/// The SSH remote for an HTTPS `url`, `https://github.com/owner/repo` is
+/// `ssh://git@github.com/owner/repo.git`. Archives, local directories and
+/// remotes that already use SSH are returned as they are.
+pub fn ssh_url(url: &Url) -> Url {
+    if !matches!(url.scheme(), "http" | "https") || is_archive(url) {
+        return url.clone();
+    }
+    let path = url.path().trim_end_matches('/');
+    let path = if path.ends_with(".git") {
+        path.to_string()
+    } else {
+        format!("{}.git", path)
+    };
+    Url::parse(&format!(
+        "ssh://git@{}{}",
+        url.host_str().unwrap_or_default(),
+        path
+    ))
+    .unwrap_or_else(|_| url.clone())
+}
+
+/// `./fmt`, `../fmt`, `/src/fmt` or `~/src/fmt` as a `file://` URL. Only
+/// targets that look like paths count, `fmtlib/fmt` is a GitHub project even
+/// if there happens to be a directory by that name.
+fn local_url(target: &str) -> Option<Url> {
+    let path = if target == "~" || target.starts_with("~/") {
+        paths::home_dir().join(target.trim_start_matches('~').trim_start_matches('/'))
+    } else if target.starts_with('/')
+        || target.starts_with("./")
+        || target.starts_with("../")
+        || target == "."
+        || target == ".."
+    {
+        PathBuf::from(target)
+    } else {
+        return None;
+    };
+    let path = path
+        .canonicalize()
+        .unwrap_or_else(|e| usage_error(format!("`{}` isn't a directory: {}", target, e)));
+    Url::from_directory_path(&path).ok()
+}
+
+/// Whether `url` is a directory on this machine, which is built where it is.
+pub fn is_local(url: &Url) -> bool {
+    url.scheme() == "file" && !is_archive(url)
+}
+
+/// Split `fmt@10.2.1` into the package and the ref. In a URL only an `@` in the
+/// path counts (`https://user@bitbucket.org/...` has a user name), and the ref
+/// may itself contain slashes (`fmt@feature/x`).
+fn split_ref(target: &str) -> (&str, Option<&str>) {
+    let start = match target.find("://") {
+        Some(i) => target[i + 3..]
+            .find('/')
+            .map_or(target.len(), |j| i + 3 + j),
+        None => 0,
+    };
+    match target[start..].find('@') {
+        Some(i) => {
+            let git_ref = &target[start + i + 1..];
+            if git_ref.is_empty() {
+                usage_error(format!("expected a ref after the `@` in `{}`", target));
+            }
+            (&target[..start + i], Some(git_ref))
+        }
+        None => (target, None),
+    }
+}
+
+impl Source {
+    /// A source nothing is known about but where it is.
+    pub fn plain(name: String, url: Url, git_ref: Option<String>) -> Self {
+        Source {
+            name,
+            url,
+            git_ref,
+            pinned: None,
+            cmake_args: vec![],
+            binaries: vec![],
+            requires: Requirements::default(),
+            mirrors: vec![],
+            sha256: None,
+            signing_keys: vec![],
+            notes: None,
+            dependencies: vec![],
+            make_targets: vec![],
+            env: vec![],
+            recipe: Recipe::default(),
+            patches: vec![],
+            variants: BTreeMap::new(),
+        }
+    }
+}
+
+/// Exit because `name` isn't in the registry, suggesting the closest one that is.
+pub fn unknown_package(registry: &PackageRegistry, name: &str) -> ! {
+    match registry.closest(name) {
+        Some(closest) => usage_error(format!(
+            "unknown package `{}`; did you mean `{}`?",
+            name, closest
+        )),
+        None => usage_error(format!(
+            "unknown package `{}`, try `cinstall search {}` or give its URL or owner/repo.",
+            name, name
+        )),
+    }
+}
+
+/// Work out the package name and URL that `target` refers to. A registry
+/// entry whose overrides for this platform don't make a package is an error.
+pub fn resolve(
+    registry: &PackageRegistry,
+    target: &str,
+    fork: Option<&str>,
+    host_ok: &[String],
+    any_host: bool,
+) -> Result<Source, InstallError> {
+    let target = scp_to_url(target);
+    let (base, fragment) = match target.split_once('#') {
+        Some((base, fragment)) => (base, Some(fragment)),
+        None => (target.as_ref(), None),
+    };
+    let (base, at_ref) = split_ref(base);
+
+    let mut source = if let Some(package) = registry.get(base) {
+        let package = package
+            .resolve(&registry::current_platform())
+            .map_err(|e| {
+                InstallError::UnknownFatal(format!(
+                    "the registry entry for `{}` is invalid: {}",
+                    base, e
+                ))
+            })?;
+        // registries are checked when they are loaded, but say so rather
+        // than crash if one got through.
+        let url = Url::parse(&package.url).unwrap_or_else(|e| {
+            usage_error(format!(
+                "the registry entry for `{}` has the invalid URL `{}` ({})",
+                base, package.url, e
+            ))
+        });
+        Source {
+            name: base.to_string(),
+            url,
+            git_ref: None,
+            pinned: package.default_ref,
+            cmake_args: package
+                .cmake_args
+                .into_iter()
+                .chain(
+                    package
+                        .defines
+                        .iter()
+                        .map(|(k, v)| format!("-D{}={}", k, v)),
+                )
+                .collect(),
+            binaries: package.binaries,
+            requires: package.requires,
+            mirrors: package
+                .mirrors
+                .iter()
+                .filter_map(|m| Url::parse(m).ok())
+                .collect(),
+            sha256: package.sha256,
+            signing_keys: package.signing_keys,
+            notes: package.notes,
+            dependencies: package.dependencies,
+            make_targets: package.make_targets,
+            env: package.env.into_iter().collect(),
+            recipe: Recipe {
+                configure: package.configure,
+                build: package.build,
+                install: package.install,
+            },
+            patches: package.patches,
+            variants: package.variants,
+        }
+    } else {
+        let url = match Url::parse(base) {
+            Ok(url) => url,
+            Err(e) => local_url(base)
+                .or_else(|| github_url(base))
+                .unwrap_or_else(|| {
+                    // a bare word was meant to be a package name.
+                    if !base.contains(['/', ':']) {
+                        unknown_package(registry, base);
+                    }
+                    usage_error(format!(
+                        "invalid argument (expect package-name/url/owner/repo/path): {} ({})",
+                        e, base
+                    ))
+                }),
+        };
+        let host = url.host_str().unwrap_or_default();
+        let archive = is_archive(&url);
+        // the host check is about knowing where git repositories are, an
+        // archive is just a download.
+        if !archive && !is_local(&url) && !any_host && !host_allowed(host, host_ok) {
+            usage_error(format!(
+                "`{}` isn't a known host, pass `--host-ok {}` if it hosts git repositories (or `--allow-any-host`).",
+                host, host
+            ));
+        }
+        let (clone, git_ref) = clone_url(&url);
+        let (name, url, git_ref) = match (archive, KNOWN_HOSTS.contains(&host)) {
+            (true, true) => (package_name_for_url(&clone), url, None),
+            (true, false) => (archive_name(&url), url, None),
+            (false, _) => (package_name_for_url(&clone), clone, git_ref),
+        };
+        Source::plain(name, url, git_ref)
+    };
+
+    if at_ref.is_some() && fragment.is_some() {
+        usage_error("a ref can't be combined with a pull request");
+    }
+    if let Some(git_ref) = at_ref {
+        source.git_ref = Some(git_ref.to_string());
+        source.pinned = None;
+    }
+    if let Some(fragment) = fragment {
+        source.git_ref = Some(fragment_ref(fragment));
+        source.pinned = None;
+    }
+
+    if let Some(fork) = fork {
+        let (repo, branch) = match fork.split_once('@') {
+            Some((repo, branch)) => (repo, Some(branch)),
+            None => (fork, None),
+        };
+        source.url = github_url(repo).unwrap_or_else(|| {
+            usage_error(format!(
+                "expected `--fork owner/repo[@branch]`, got `{}`",
+                fork
+            ))
+        });
+        if source.git_ref.is_some() && branch.is_some() {
+            usage_error("a ref or pull request can't be combined with a fork branch");
+        }
+        if branch.is_some() {
+            source.git_ref = branch.map(String::from);
+        }
+        // a pin, mirrors, checksum and keys are about the upstream repository, not the fork.
+        source.pinned = None;
+        source.mirrors.clear();
+        source.sha256 = None;
+        source.signing_keys.clear();
+    }
+
+    Ok(source)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_ref_splits_at_the_at() {
+        assert_eq!(split_ref("fmt"), ("fmt", None));
+        assert_eq!(split_ref("fmt@10.2.1"), ("fmt", Some("10.2.1")));
+        assert_eq!(
+            split_ref("fmtlib/fmt@master"),
+            ("fmtlib/fmt", Some("master"))
+        );
+        assert_eq!(split_ref("fmt@feature/x"), ("fmt", Some("feature/x")));
+    }
+
+    #[test]
+    fn split_ref_ignores_a_user_name_in_a_url() {
+        assert_eq!(
+            split_ref("https://user@bitbucket.org/owner/repo"),
+            ("https://user@bitbucket.org/owner/repo", None)
+        );
+        assert_eq!(
+            split_ref("https://user@bitbucket.org/owner/repo@v1"),
+            ("https://user@bitbucket.org/owner/repo", Some("v1"))
+        );
+        assert_eq!(
+            split_ref("https://github.com/fmtlib/fmt@10.2.1"),
+            ("https://github.com/fmtlib/fmt", Some("10.2.1"))
+        );
+    }
+
+    #[test]
+    fn known_hosts_and_hosts_passed_are_allowed() {
+        assert!(host_allowed("github.com", &[]));
+        assert!(host_allowed("gitlab.com", &[]));
+        assert!(host_allowed(
+            "git.example.invalid",
+            &["git.example.invalid".into()]
+        ));
+        assert!(!host_allowed(
+            "git.example.invalid",
+            &["gitlab.example.invalid".into()]
+        ));
+    }
+
+    #[test]
+    fn scp_remotes_become_ssh_urls() {
+        assert_eq!(
+            scp_to_url("git@github.com:fmtlib/fmt.git"),
+            "ssh://git@github.com/fmtlib/fmt.git"
+        );
+        assert_eq!(
+            scp_to_url("git@git.example.invalid:/srv/fmt.git"),
+            "ssh://git@git.example.invalid/srv/fmt.git"
+        );
+    }
+
+    #[test]
+    fn other_targets_are_left_as_they_are() {
+        assert_eq!(scp_to_url("fmt"), "fmt");
+        assert_eq!(scp_to_url("fmtlib/fmt"), "fmtlib/fmt");
+        assert_eq!(
+            scp_to_url("https://user@bitbucket.org/owner/repo"),
+            "https://user@bitbucket.org/owner/repo"
+        );
+        // no user name, or a path before the colon, isn't a remote.
+        assert_eq!(scp_to_url("github.com:fmtlib/fmt"), "github.com:fmtlib/fmt");
+        assert_eq!(scp_to_url("./git@x:y"), "./git@x:y");
+    }
+}
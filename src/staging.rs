@@ -0,0 +1,387 @@
+// Staged installs.
+//
+// Build systems are asked to install into a staging directory (using
+// `DESTDIR`) instead of straight onto the system. Once that succeeds the
+// staged tree is copied into place, which gives us the exact list of files
+// for the manifest and a chance to spot files owned by other packages first.
+//
+// Install rules sometimes write outside the prefix they were given: config
+// into /etc, systemd units, a file in /etc/ld.so.conf.d. Those files are
+// listed before anything is copied, and only installed with
+// `--allow-system-paths` or when you say so; otherwise they are left out.
+// The ones that are installed go in the manifest like any other file, so
+// uninstalling removes them too.
+//
+// Staged files are copied into place in parallel (`copy_jobs` in the config
+// sets how many at once). Each copy is hashed as it is written and read back
+// to check it, keeps the modification time, and is made readable by everyone
+// whatever the umask was when it was built, as are the directories made for
+// it. It is written next to where it goes and renamed over the old file once
+// it checks out, so a program that is running can be replaced and a copy that
+// fails leaves the old file as it was. Files staged as hard links of each
+// other are installed as hard links.
+
+use crate::config::Config;
+use crate::installer::InstallError;
+use crate::manifest;
+use crate::size;
+use crate::workspace;
+use crate::{output, outputln, ui};
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+
+/// The staging directory for the run `run_id`.
+pub fn staging_dir(run_id: &str) -> PathBuf {
+    workspace::root().join("staging").join(run_id)
+}
+
+/// Every file and symlink below `dir`, relative to `root`.
+fn walk(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), InstallError> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", dir.display(), e)))?;
+
+    for entry in entries {
+        let entry = entry.map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+        let path = entry.path();
+        let file_type = entry
+            .file_type()
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", path.display(), e)))?;
+
+        if file_type.is_dir() {
+            walk(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// The files in the staging directory, as the absolute paths they will be installed to.
+pub fn staged_files(staging: &Path) -> Result<Vec<PathBuf>, InstallError> {
+    let mut files = vec![];
+    if staging.exists() {
+        walk(staging, staging, &mut files)?;
+    }
+    files.sort();
+    Ok(files.into_iter().map(|f| Path::new("/").join(f)).collect())
+}
+
+/// Warn about staged files that another package (or nobody cinstall knows of) already put there.
+fn report_conflicts(package: &str, files: &[PathBuf]) {
+    let owners = manifest::owners();
+    for file in files {
+        match owners.get(file) {
+            Some(owner) if owner != package => outputln!(
+                red,
+                "`{}` is owned by the package `{}` and will be overwritten.",
+                (file.display()),
+                owner
+            ),
+            Some(_) => (),
+            None if file.exists() => outputln!(
+                purple,
+                "`{}` already exists and will be overwritten.",
+                (file.display())
+            ),
+            None => (),
+        }
+    }
+}
+
+/// What a file installed outside the prefix probably is, going by where it goes.
+fn system_path_kind(file: &Path) -> &'static str {
+    let under = |dir: &str| file.starts_with(dir);
+    if ["/etc/systemd", "/lib/systemd", "/usr/lib/systemd"]
+        .iter()
+        .any(|d| under(d))
+    {
+        "a systemd unit"
+    } else if under("/etc/ld.so.conf.d") || under("/etc/ld.so.conf") {
+        "a dynamic linker path"
+    } else if ["/etc/udev", "/lib/udev", "/usr/lib/udev"]
+        .iter()
+        .any(|d| under(d))
+    {
+        "a udev rule"
+    } else if under("/etc/profile.d") {
+        "a login shell script"
+    } else if under("/etc") {
+        "system configuration"
+    } else {
+        "outside the prefix"
+    }
+}
+
+/// Find the staged files that would be installed outside `prefix` and leave
+/// them out, unless `allow` is set or you say to install them too.
+pub fn hold_back_system_paths(
+    staging: &Path,
+    prefix: &Path,
+    allow: bool,
+) -> Result<(), InstallError> {
+    let outside: Vec<PathBuf> = staged_files(staging)?
+        .into_iter()
+        .filter(|file| !file.starts_with(prefix))
+        .collect();
+    if outside.is_empty() {
+        return Ok(());
+    }
+
+    outputln!(
+        purple,
+        "the build installs {} files outside {}:",
+        (outside.len()),
+        (prefix.display())
+    );
+    for file in &outside {
+        eprintln!("  {} ({})", file.display(), system_path_kind(file));
+    }
+    if allow {
+        return Ok(());
+    }
+    if ui::interactive() {
+        output!(on_blue, "install these too? [y/N] ");
+        let input: String = text_io::read!("{}\n");
+        if input.to_lowercase().starts_with('y') {
+            return Ok(());
+        }
+    }
+
+    for file in &outside {
+        let staged = staging.join(file.strip_prefix("/").unwrap_or(file));
+        std::fs::remove_file(&staged)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", staged.display(), e)))?;
+    }
+    outputln!(
+        purple,
+        "left them out, reinstall with --allow-system-paths to install them too."
+    );
+    Ok(())
+}
+
+fn copy_entry(from: &Path, to: &Path) -> Result<(), InstallError> {
+    let bad_directory = |e: std::io::Error| {
+        InstallError::BadDirectory(format!("{}: {} (you may need to `sudo`)", to.display(), e))
+    };
+
+    if let Some(parent) = to.parent() {
+        std::fs::create_dir_all(parent).map_err(bad_directory)?;
+    }
+
+    let metadata = std::fs::symlink_metadata(from)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", from.display(), e)))?;
+
+    if metadata.file_type().is_symlink() {
+        let target = std::fs::read_link(from)
+            .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", from.display(), e)))?;
+        if to.exists() || to.is_symlink() {
+            std::fs::remove_file(to).map_err(bad_directory)?;
+        }
+        std::os::unix::fs::symlink(target, to).map_err(bad_directory)?;
+    } else {
+        // copy() keeps the permission bits, so executables stay executable.
+        std::fs::copy(from, to).map_err(bad_directory)?;
+    }
+    Ok(())
+}
+
+/// Create the directory `dir` and its missing parents, readable by everyone.
+fn create_dir(dir: &Path) -> Result<(), InstallError> {
+    let missing: Vec<&Path> = dir.ancestors().take_while(|d| !d.exists()).collect();
+    let bad_directory = |e: std::io::Error| {
+        InstallError::BadDirectory(format!("{}: {} (you may need to `sudo`)", dir.display(), e))
+    };
+    std::fs::create_dir_all(dir).map_err(bad_directory)?;
+    for created in missing {
+        std::fs::set_permissions(created, std::fs::Permissions::from_mode(0o755))
+            .map_err(bad_directory)?;
+    }
+    Ok(())
+}
+
+/// Copy the staged file `from` to `to`, returning how many bytes were
+/// written. Symlinks are recreated as they are.
+fn install_file(from: &Path, to: &Path) -> Result<u64, InstallError> {
+    let bad_directory = |e: std::io::Error| {
+        InstallError::BadDirectory(format!("{}: {} (you may need to `sudo`)", to.display(), e))
+    };
+    let unreadable =
+        |e: std::io::Error| InstallError::UnknownFatal(format!("{}: {}", from.display(), e));
+
+    let metadata = std::fs::symlink_metadata(from).map_err(unreadable)?;
+    if !metadata.is_file() {
+        copy_entry(from, to)?;
+        return Ok(0);
+    }
+    if let Some(parent) = to.parent() {
+        create_dir(parent)?;
+    }
+    let temporary = temporary_path(to);
+    let copied = copy_file(from, &temporary, &metadata)
+        .and_then(|_| std::fs::rename(&temporary, to).map_err(bad_directory));
+    if copied.is_err() {
+        let _ = std::fs::remove_file(&temporary);
+    }
+    copied.map(|_| metadata.len())
+}
+
+/// Where a file is written before it is renamed to `to`, in the same
+/// directory so the rename can't cross file systems.
+fn temporary_path(to: &Path) -> PathBuf {
+    let name = to.file_name().unwrap_or_default().to_string_lossy();
+    to.with_file_name(format!(".{}.cinstall-{}", name, std::process::id()))
+}
+
+/// Copy the staged file `from` to `to`, which is made anew, and check it.
+fn copy_file(from: &Path, to: &Path, metadata: &std::fs::Metadata) -> Result<(), InstallError> {
+    let bad_directory = |e: std::io::Error| {
+        InstallError::BadDirectory(format!("{}: {} (you may need to `sudo`)", to.display(), e))
+    };
+    let unreadable =
+        |e: std::io::Error| InstallError::UnknownFatal(format!("{}: {}", from.display(), e));
+
+    // left over from a copy that was interrupted.
+    let _ = std::fs::remove_file(to);
+    let mut source = std::fs::File::open(from).map_err(unreadable)?;
+    let mut target = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(to)
+        .map_err(bad_directory)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = source.read(&mut buffer).map_err(unreadable)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        target.write_all(&buffer[..read]).map_err(bad_directory)?;
+    }
+
+    // readable by everyone, and executable by everyone if it was by its owner.
+    let mode = metadata.mode() & 0o7777;
+    let mode = mode | 0o444 | if mode & 0o100 != 0 { 0o111 } else { 0 };
+    target
+        .set_permissions(std::fs::Permissions::from_mode(mode))
+        .map_err(bad_directory)?;
+    if let Ok(modified) = metadata.modified() {
+        let _ = target.set_modified(modified);
+    }
+    drop(target);
+
+    let written = format!("{:x}", hasher.finalize());
+    if manifest::sha256_of(to).as_deref() != Some(written.as_str()) {
+        return Err(InstallError::UnknownFatal(format!(
+            "{} isn't what was staged after copying it, the disk may be failing.",
+            to.display()
+        )));
+    }
+    Ok(())
+}
+
+/// How many files to copy at once, from the `copy_jobs` config key.
+fn copy_jobs() -> usize {
+    Config::load()
+        .ok()
+        .and_then(|config| config.copy_jobs)
+        .unwrap_or(0)
+}
+
+/// Copy `files` from `staging` into place in parallel, hard links as hard
+/// links, returning how many bytes were written.
+fn install_files(staging: &Path, files: &[PathBuf]) -> Result<u64, InstallError> {
+    let staged = |file: &PathBuf| staging.join(file.strip_prefix("/").unwrap_or(file));
+
+    // the first file of every set of hard links is copied, the rest link to it.
+    let mut first_link: HashMap<(u64, u64), &PathBuf> = HashMap::new();
+    let mut copies = vec![];
+    let mut links = vec![];
+    for file in files {
+        match std::fs::symlink_metadata(staged(file)) {
+            Ok(m) if m.is_file() && m.nlink() > 1 => match first_link.get(&(m.dev(), m.ino())) {
+                Some(first) => links.push((file, *first)),
+                None => {
+                    first_link.insert((m.dev(), m.ino()), file);
+                    copies.push(file);
+                }
+            },
+            _ => copies.push(file),
+        }
+    }
+
+    // 0 threads means one per CPU.
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(copy_jobs())
+        .build()
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    let written: Vec<u64> = pool.install(|| {
+        copies
+            .par_iter()
+            .map(|file| install_file(&staged(file), file))
+            .collect::<Result<_, _>>()
+    })?;
+
+    for (file, first) in links {
+        if file.exists() || file.is_symlink() {
+            let _ = std::fs::remove_file(file);
+        }
+        // a link can't cross file systems, a copy will do then.
+        if std::fs::hard_link(first, file).is_err() {
+            install_file(&staged(file), file)?;
+        }
+    }
+    Ok(written.iter().sum())
+}
+
+/// Copy the file or directory `from` to `to`, keeping symlinks as they are.
+pub fn copy_tree(from: &Path, to: &Path) -> Result<(), InstallError> {
+    let is_dir = std::fs::symlink_metadata(from).is_ok_and(|m| m.is_dir());
+    if !is_dir {
+        return copy_entry(from, to);
+    }
+    let entries = std::fs::read_dir(from)
+        .map_err(|e| InstallError::UnknownFatal(format!("{}: {}", from.display(), e)))?;
+    for entry in entries.flatten() {
+        copy_tree(&entry.path(), &to.join(entry.file_name()))?;
+    }
+    Ok(())
+}
+
+/// Copy everything staged for `package` onto the system and remove the staging
+/// directory, returning the installed paths.
+pub fn commit(package: &str, staging: &Path) -> Result<Vec<PathBuf>, InstallError> {
+    let files = staged_files(staging)?;
+    if files.is_empty() {
+        return Ok(files);
+    }
+
+    report_conflicts(package, &files);
+
+    outputln!(green, "copying {} staged files into place.", (files.len()));
+    let started = Instant::now();
+    let bytes = install_files(staging, &files)?;
+    let seconds = started.elapsed().as_secs_f64().max(0.001);
+    outputln!(
+        "copied {} in {:.2}s ({}/s).",
+        (size::human_size(bytes)),
+        seconds,
+        (size::human_size((bytes as f64 / seconds) as u64))
+    );
+
+    if let Err(e) = std::fs::remove_dir_all(staging) {
+        outputln!(
+            purple,
+            "failed to remove the staging directory {}. {}",
+            (staging.display()),
+            e
+        );
+    }
+
+    Ok(files)
+}
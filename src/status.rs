@@ -0,0 +1,164 @@
+// `cinstall status`: how the packages of a project are doing.
+//
+// For the `cinstall.toml` the current directory is in, each dependency is
+// listed with the ref it asks for, what is installed and what `cinstall.lock`
+// has, and is:
+//
+// - developed, when it is built from a checkout of your own with
+//   `cinstall develop`, which isn't compared with anything;
+// - missing, when it isn't installed;
+// - outdated, when what is installed isn't the ref the manifest asks for or
+//   the commit the lockfile has;
+// - ok otherwise.
+//
+// Installed packages that neither the project nor any of its dependencies
+// need are listed after them. `--write-lock` records what is installed now
+// in `cinstall.lock`.
+
+use crate::database::{InstalledPackage, MergedDatabase};
+use crate::installer::InstallError;
+use crate::project::{self, Dependency, Locked, Lockfile, ProjectManifest};
+use crate::registry::PackageRegistry;
+use crate::table::Table;
+use crate::{develop, outputln};
+use std::collections::BTreeSet;
+use std::path::Path;
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(10)]
+}
+
+/// Whether `installed` is what `dependency` and `locked` ask for, and if not why.
+fn check(
+    dependency: &Dependency,
+    installed: &InstalledPackage,
+    locked: Option<&Locked>,
+) -> Result<(), String> {
+    if let Some(wanted) = dependency.git_ref() {
+        if installed.git_ref.as_deref() != Some(wanted) {
+            return Err(format!("outdated, `{}` is asked for", wanted));
+        }
+    }
+    match locked.and_then(|l| l.commit.as_deref()) {
+        Some(commit) if installed.commit.as_deref() != Some(commit) => {
+            Err(format!("outdated, the lockfile has {}", short(commit)))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The declared dependencies and everything they need in turn.
+fn needed(registry: &PackageRegistry, manifest: &ProjectManifest) -> BTreeSet<String> {
+    let mut needed = BTreeSet::new();
+    let mut todo: Vec<String> = manifest.dependencies.keys().cloned().collect();
+    while let Some(name) = todo.pop() {
+        if let Some(package) = registry.get(&name) {
+            todo.extend(
+                package
+                    .dependencies
+                    .iter()
+                    .filter(|d| !needed.contains(*d))
+                    .cloned(),
+            );
+        }
+        needed.insert(name);
+    }
+    needed
+}
+
+/// Show the status of the project `dir` is in, writing its lockfile with
+/// `write_lock`. Returns whether everything is installed and up to date.
+pub fn status(
+    registry: &PackageRegistry,
+    dir: &Path,
+    write_lock: bool,
+) -> Result<bool, InstallError> {
+    let root = project::find_root(dir).ok_or_else(|| {
+        InstallError::UnknownFatal(format!(
+            "{} isn't in a project, there is no {} in it or above it.",
+            dir.display(),
+            project::MANIFEST_NAME
+        ))
+    })?;
+    let manifest = ProjectManifest::load(&root)?;
+    let lock = Lockfile::load(&root)?;
+    let database = MergedDatabase::open();
+    outputln!("`{}` in {}.", (manifest.project.name), (root.display()));
+
+    let mut table = Table::new(&["NAME", "WANTED", "INSTALLED", "LOCKED", "STATUS"]);
+    let mut healthy = true;
+    let mut new_lock = Lockfile::default();
+    for (name, dependency) in &manifest.dependencies {
+        let installed = database.find(name).map(|(_, p)| p);
+        let locked = lock.as_ref().and_then(|l| l.packages.get(name));
+        let developed = develop::path(name);
+        let result = match (installed, &developed) {
+            (Some(_), Some(path)) => Ok(format!("developed, from {}", path.display())),
+            (Some(installed), None) => check(dependency, installed, locked).map(|_| "ok".into()),
+            (None, _) => Err("missing".to_string()),
+        };
+        healthy &= result.is_ok();
+        table.row(vec![
+            name.clone(),
+            dependency.git_ref().unwrap_or("*").to_string(),
+            installed.map_or("-".into(), InstalledPackage::built_at),
+            locked
+                .and_then(|l| l.commit.as_deref())
+                .map_or("-".into(), |c| short(c).to_string()),
+            result.unwrap_or_else(|e| e),
+        ]);
+        // the lockfile is about upstream, a checkout keeps what it had.
+        if let (Some(locked), Some(_)) = (locked, &developed) {
+            new_lock.packages.insert(name.clone(), locked.clone());
+        } else if let (Some(installed), None) = (installed, &developed) {
+            new_lock.packages.insert(
+                name.clone(),
+                Locked {
+                    url: installed.url.clone(),
+                    git_ref: installed.git_ref.clone(),
+                    commit: installed.commit.clone(),
+                },
+            );
+        }
+    }
+    if manifest.dependencies.is_empty() {
+        outputln!(
+            purple,
+            "it has no dependencies, add them to {}.",
+            (project::MANIFEST_NAME)
+        );
+    } else {
+        table.print(false);
+    }
+
+    let needed = needed(registry, &manifest);
+    let mut unreferenced: Vec<&str> = database
+        .iter()
+        .map(|(_, p)| p.name.as_str())
+        .filter(|name| !needed.contains(*name))
+        .collect();
+    unreferenced.sort();
+    unreferenced.dedup();
+    if !unreferenced.is_empty() {
+        outputln!(
+            "installed but not needed by the project: {}.",
+            (unreferenced.join(", "))
+        );
+    }
+    if lock.is_none() && !write_lock {
+        outputln!(
+            "there is no {}, `cinstall status --write-lock` records what is installed now.",
+            (project::LOCK_NAME)
+        );
+    }
+
+    if write_lock {
+        new_lock.save(&root)?;
+        outputln!(
+            green,
+            "wrote {}.",
+            (root.join(project::LOCK_NAME).display())
+        );
+    }
+    Ok(healthy)
+}
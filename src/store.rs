@@ -0,0 +1,355 @@
+// A store of builds to reuse.
+//
+// With the `store_dir` config key set, what every build cinstall makes
+// installs is kept there, keyed by the repository, the commit and everything
+// it was built with: the prefix, the build system and its options, the
+// compilers and their versions, the flags in the environment (`CFLAGS`,
+// `LDFLAGS`, ...) and the platform. An install that would build exactly that
+// again, by anyone using the same store, copies it out of the store instead
+// of building it, unless `--force` is given. Local directories and archives
+// have no commit to go by and are always built.
+//
+// The checksum of every file is kept with the build, and a build whose copy
+// doesn't match them, since the store may be writable by others, isn't
+// installed.
+//
+// Every install of a stored build leaves a reference to the database that
+// records it in the build's directory. `cinstall gc` removes the builds none
+// of whose references are installed at that commit any more, keeping those
+// referenced from a database it can't read, like another user's.
+
+use crate::config::Config;
+use crate::database::{self, InstalledPackage};
+use crate::installer::{InstallError, InstallMethod, InstallOptions};
+use crate::{manifest, outputln, size, staging};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::Url;
+
+/// What a stored build is a build of.
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    url: String,
+    commit: String,
+    /// The prefix it was built for.
+    prefix: PathBuf,
+    stored_at: DateTime<Utc>,
+    /// The checksum of every file, by where it is installed. Not kept by
+    /// versions of cinstall before checksums, whose builds aren't used.
+    #[serde(default)]
+    checksums: Option<BTreeMap<PathBuf, String>>,
+}
+
+/// An installed package using a stored build.
+#[derive(Serialize, Deserialize)]
+struct Reference {
+    database: PathBuf,
+    package: String,
+}
+
+fn root() -> Option<PathBuf> {
+    Config::load().ok()?.store_dir
+}
+
+fn hash(text: &str) -> String {
+    format!("{:x}", Sha256::digest(text.as_bytes()))
+}
+
+/// The first line `compiler --version` prints, `compiler` being a command
+/// like `ccache gcc`.
+fn compiler_version(compiler: &str) -> Option<String> {
+    let mut words = compiler.split_whitespace();
+    let output = Command::new(words.next()?)
+        .args(words)
+        .arg("--version")
+        .output()
+        .ok()
+        .filter(|o| o.status.success())?;
+    String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .next()
+        .map(String::from)
+}
+
+/// What the project is built with that isn't in its options: the compilers
+/// cmake and make pick up and the flags they are given from the environment.
+fn toolchain() -> serde_json::Value {
+    let var = |name: &str| std::env::var(name).ok();
+    let cc = var("CC").unwrap_or_else(|| "cc".into());
+    let cxx = var("CXX").unwrap_or_else(|| "c++".into());
+    json!({
+        "cc": compiler_version(&cc),
+        "cxx": compiler_version(&cxx),
+        "cc_command": cc,
+        "cxx_command": cxx,
+        "cflags": var("CFLAGS"),
+        "cxxflags": var("CXXFLAGS"),
+        "cppflags": var("CPPFLAGS"),
+        "ldflags": var("LDFLAGS"),
+    })
+}
+
+/// The key a build of `commit` of `url` with `method` and `options` is kept
+/// under, if there is a store.
+pub fn key(
+    url: &Url,
+    commit: &str,
+    method: &InstallMethod,
+    options: &InstallOptions,
+) -> Option<String> {
+    root()?;
+    let build = json!({
+        "url": url.as_str(),
+        "commit": commit,
+        "prefix": options.prefix,
+        "method": method.to_string(),
+        "cmake_args": options.cmake_args,
+        "configure_args": options.configure_args,
+        "make_targets": options.make_targets,
+        "env": options.env,
+        "recipe": options.recipe,
+        "patches": options.patches,
+        "binaries": options.binaries,
+        "multiarch": options.multiarch,
+        "toolchain": toolchain(),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+    });
+    Some(hash(&build.to_string()))
+}
+
+/// The directory of the build kept under `key`, if there is one.
+pub fn find(key: &str) -> Option<PathBuf> {
+    let dir = root()?.join(key);
+    (dir.join("entry.json").is_file() && dir.join("files").is_dir()).then_some(dir)
+}
+
+/// What `path` holds, to tell whether a copy of it was changed: the SHA-256
+/// of a file, or where a symlink points.
+fn checksum(path: &Path) -> Option<String> {
+    if std::fs::symlink_metadata(path).ok()?.is_symlink() {
+        let target = std::fs::read_link(path).ok()?;
+        return Some(format!("-> {}", target.display()));
+    }
+    manifest::sha256_of(path)
+}
+
+/// The checksum of every file staged in `staging`, by where it is installed.
+fn checksums(staging: &Path) -> Result<BTreeMap<PathBuf, String>, InstallError> {
+    let mut checksums = BTreeMap::new();
+    for file in staging::staged_files(staging)? {
+        let copy = staging.join(file.strip_prefix("/").unwrap_or(&file));
+        let sum = checksum(&copy)
+            .ok_or_else(|| InstallError::UnknownFatal(format!("{}: unreadable", copy.display())))?;
+        checksums.insert(file, sum);
+    }
+    Ok(checksums)
+}
+
+/// Install the build kept in `dir` as `name`, as if it had just been built
+/// into `staging`. What is copied out of the store has to match the checksums
+/// kept with it.
+pub fn install(
+    dir: &Path,
+    name: &str,
+    options: &InstallOptions,
+    staging: &Path,
+) -> Result<Vec<PathBuf>, InstallError> {
+    let kept = std::fs::read_to_string(dir.join("entry.json"))
+        .ok()
+        .and_then(|contents| serde_json::from_str::<Entry>(&contents).ok())
+        .and_then(|entry| entry.checksums);
+    staging::copy_tree(&dir.join("files"), staging)?;
+    let copied = checksums(staging);
+    if kept.is_none() || copied.as_ref().ok() != kept.as_ref() {
+        let _ = std::fs::remove_dir_all(staging);
+        return Err(InstallError::UnknownFatal(format!(
+            "the build in the store at {} doesn't match its checksums, it was changed or kept by an older cinstall. `--force` builds it again.",
+            dir.display()
+        )));
+    }
+    staging::hold_back_system_paths(staging, &options.prefix, options.allow_system_paths)?;
+    staging::commit(name, staging)
+}
+
+fn save_in(
+    dir: &Path,
+    url: &Url,
+    commit: &str,
+    prefix: &Path,
+    files: &[PathBuf],
+) -> Result<(), InstallError> {
+    for file in files {
+        let stored = dir
+            .join("files")
+            .join(file.strip_prefix("/").unwrap_or(file));
+        staging::copy_tree(file, &stored)?;
+    }
+    let entry = Entry {
+        url: url.to_string(),
+        commit: commit.to_string(),
+        prefix: prefix.to_path_buf(),
+        stored_at: Utc::now(),
+        checksums: Some(checksums(&dir.join("files"))?),
+    };
+    let json = serde_json::to_string_pretty(&entry)
+        .map_err(|e| InstallError::UnknownFatal(e.to_string()))?;
+    std::fs::write(dir.join("entry.json"), json).map_err(|_| InstallError::FailedToWriteToFile)
+}
+
+/// Keep the build of `commit` of `url` for `prefix`, which installed `files`,
+/// under `key`. Keeping it is best effort, an install never fails over it.
+pub fn save(key: &str, url: &Url, commit: &str, prefix: &Path, files: &[PathBuf]) {
+    let Some(root) = root() else {
+        return;
+    };
+    // put together beside the store and moved in whole, so that nobody finds
+    // half of it.
+    let partial = root.join(format!(".{}.{}", key, std::process::id()));
+    let _ = std::fs::remove_dir_all(&partial);
+    let saved = save_in(&partial, url, commit, prefix, files).and_then(|_| {
+        // a build made again over one that is kept still has its users.
+        let _ = std::fs::rename(root.join(key).join("refs"), partial.join("refs"));
+        let _ = std::fs::remove_dir_all(root.join(key));
+        std::fs::rename(&partial, root.join(key))
+            .map_err(|e| InstallError::BadDirectory(format!("{}: {}", root.display(), e)))
+    });
+    match saved {
+        Ok(_) => outputln!(
+            "kept the build in the store at {}.",
+            (root.join(key).display())
+        ),
+        Err(e) => {
+            let _ = std::fs::remove_dir_all(&partial);
+            outputln!(
+                purple,
+                "warning: failed to keep the build in the store. {}",
+                e
+            );
+        }
+    }
+}
+
+/// Record that `package` in the database at `database` uses the build kept
+/// under `key`.
+pub fn add_reference(key: &str, database: &Path, package: &str) {
+    let Some(dir) = root().map(|root| root.join(key).join("refs")) else {
+        return;
+    };
+    let reference = Reference {
+        database: database.to_path_buf(),
+        package: package.to_string(),
+    };
+    let name = hash(&format!("{}\0{}", database.display(), package));
+    let written = std::fs::create_dir_all(&dir).and_then(|_| {
+        std::fs::write(
+            dir.join(&name[..16]),
+            serde_json::to_string(&reference).unwrap_or_default(),
+        )
+    });
+    if let Err(e) = written {
+        outputln!(
+            purple,
+            "warning: failed to record that `{}` uses the build in the store, `cinstall gc` may remove it. {}",
+            package,
+            e
+        );
+    }
+}
+
+/// Whether `package` is what `entry` is a build of.
+fn is_build_of(package: &InstalledPackage, entry: &Entry) -> bool {
+    package.url == entry.url
+        && package.commit.as_deref() == Some(entry.commit.as_str())
+        && package.version_dir.as_ref().unwrap_or(&package.prefix) == &entry.prefix
+}
+
+/// Whether any installed package uses the build kept in `dir`.
+fn is_used(dir: &Path, entry: &Entry) -> bool {
+    let Ok(references) = std::fs::read_dir(dir.join("refs")) else {
+        return false;
+    };
+    references.flatten().any(|file| {
+        let reference = std::fs::read_to_string(file.path())
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Reference>(&contents).ok());
+        let Some(reference) = reference else {
+            return false;
+        };
+        match database::packages_at(&reference.database) {
+            Ok(packages) => packages
+                .iter()
+                .any(|p| p.name == reference.package && is_build_of(p, entry)),
+            // a database that can't be read may well still have it.
+            Err(_) => true,
+        }
+    })
+}
+
+fn dir_size(dir: &Path) -> u64 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .map(|entry| match entry.file_type() {
+            Ok(t) if t.is_dir() => dir_size(&entry.path()),
+            Ok(t) if t.is_file() => entry.metadata().map_or(0, |m| m.len()),
+            _ => 0,
+        })
+        .sum()
+}
+
+/// `cinstall gc`: remove the builds in the store no installed package uses.
+pub fn gc() -> Result<(), InstallError> {
+    let Some(root) = root() else {
+        outputln!(
+            purple,
+            "there is no store, `cinstall config set store_dir <dir>` keeps builds there to reuse."
+        );
+        return Ok(());
+    };
+    let Ok(entries) = std::fs::read_dir(&root) else {
+        outputln!("the store at {} is empty.", (root.display()));
+        return Ok(());
+    };
+    let (mut kept, mut removed, mut freed) = (0, 0, 0);
+    for dir in entries.flatten().map(|e| e.path()) {
+        let entry = std::fs::read_to_string(dir.join("entry.json"))
+            .ok()
+            .and_then(|contents| serde_json::from_str::<Entry>(&contents).ok());
+        // builds being put together have no entry yet.
+        let Some(entry) = entry else {
+            continue;
+        };
+        if is_used(&dir, &entry) {
+            kept += 1;
+            continue;
+        }
+        let size = dir_size(&dir);
+        std::fs::remove_dir_all(&dir).map_err(|e| {
+            InstallError::BadDirectory(format!("{}: {} (you may need to `sudo`)", dir.display(), e))
+        })?;
+        outputln!(
+            "removed the build of {} at {} for {}.",
+            (entry.url),
+            (&entry.commit[..entry.commit.len().min(10)]),
+            (entry.prefix.display())
+        );
+        removed += 1;
+        freed += size;
+    }
+    outputln!(
+        green,
+        "removed {} builds no installed package uses, freeing {}; {} are still used.",
+        removed,
+        (size::human_size(freed)),
+        kept
+    );
+    Ok(())
+}
@@ -0,0 +1,195 @@
+// Aligned, colored tables for list, search and info output.
+//
+// Tables go to stdout so they can be piped. The last column is truncated to
+// fit the terminal unless `--long` is given or stdout isn't a terminal.
+
+use crate::pager;
+use crate::ui::{self, Tone};
+use std::ops::Range;
+use terminal_size::{terminal_size, Width};
+
+pub struct Table {
+    headers: Vec<&'static str>,
+    rows: Vec<Vec<String>>,
+    // the character ranges of each cell to highlight, by row.
+    highlights: Vec<Vec<Vec<Range<usize>>>>,
+}
+
+/// Cut `text` down to `width` characters, marking that it was cut.
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+fn pad(text: &str, width: usize) -> String {
+    let len = text.chars().count();
+    format!("{}{}", text, " ".repeat(width.saturating_sub(len)))
+}
+
+/// Paint `text` in `tone`, with the characters in `highlights` (up to
+/// `visible`) highlighted instead.
+fn paint(text: &str, tone: Option<Tone>, highlights: &[Range<usize>], visible: usize) -> String {
+    let highlighted = |i: usize| i < visible && highlights.iter().any(|r| r.contains(&i));
+    let chars: Vec<char> = text.chars().collect();
+    let mut out = String::new();
+    let mut start = 0;
+    while start < chars.len() {
+        let lit = highlighted(start);
+        let end = (start..chars.len())
+            .find(|&i| highlighted(i) != lit)
+            .unwrap_or(chars.len());
+        let part: String = chars[start..end].iter().collect();
+        match (lit, tone) {
+            (true, _) => out.push_str(&ui::paint(&part, Tone::Highlight)),
+            (false, Some(tone)) => out.push_str(&ui::paint(&part, tone)),
+            (false, None) => out.push_str(&part),
+        }
+        start = end;
+    }
+    out
+}
+
+impl Table {
+    /// A table with a header row. Pass no headers for a plain key/value listing.
+    pub fn new(headers: &[&'static str]) -> Self {
+        Self {
+            headers: headers.to_vec(),
+            rows: vec![],
+            highlights: vec![],
+        }
+    }
+
+    pub fn row(&mut self, row: Vec<String>) {
+        self.rows.push(row);
+        self.highlights.push(vec![]);
+    }
+
+    /// A row with the given character ranges of each cell highlighted.
+    pub fn highlighted_row(&mut self, row: Vec<String>, highlights: Vec<Vec<Range<usize>>>) {
+        self.rows.push(row);
+        self.highlights.push(highlights);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rows.is_empty()
+    }
+
+    fn widths(&self) -> Vec<usize> {
+        let columns = self
+            .rows
+            .iter()
+            .map(Vec::len)
+            .chain(std::iter::once(self.headers.len()))
+            .max()
+            .unwrap_or(0);
+        (0..columns)
+            .map(|i| {
+                let header = self.headers.get(i).map_or(0, |h| h.chars().count());
+                self.rows
+                    .iter()
+                    .filter_map(|row| row.get(i))
+                    .map(|cell| cell.chars().count())
+                    .max()
+                    .unwrap_or(0)
+                    .max(header)
+            })
+            .collect()
+    }
+
+    pub fn render(&self, long: bool) -> String {
+        let mut out = String::new();
+        let mut widths = self.widths();
+        let terminal_width = terminal_size().map(|(Width(w), _)| w as usize);
+
+        // the last column gets whatever room the others leave, but never less than 10.
+        if let (Some(terminal_width), false, Some((last, rest))) =
+            (terminal_width, long, widths.split_last_mut())
+        {
+            let used: usize = rest.iter().map(|w| w + 2).sum();
+            *last = (*last).min(terminal_width.saturating_sub(used).max(10));
+        }
+
+        if !self.headers.is_empty() {
+            let header: Vec<String> = self
+                .headers
+                .iter()
+                .zip(&widths)
+                .map(|(h, w)| pad(h, *w))
+                .collect();
+            out.push_str(&format!(
+                "{}\n",
+                ui::paint(header.join("  ").trim_end(), Tone::Heading)
+            ));
+        }
+
+        for (row, highlights) in self.rows.iter().zip(&self.highlights) {
+            let mut line = String::new();
+            for (i, cell) in row.iter().enumerate() {
+                let last = i + 1 == row.len();
+                let text = if last {
+                    truncate(cell, widths[i])
+                } else {
+                    pad(cell, widths[i])
+                };
+                // a cut cell ends in `…`, which isn't part of any match.
+                let len = cell.chars().count();
+                let visible = if last && len > widths[i] {
+                    widths[i].saturating_sub(1)
+                } else {
+                    len
+                };
+                // colouring after padding keeps the escape codes out of the widths.
+                let tone = (i == 0).then_some(Tone::Emphasis);
+                let highlights = highlights.get(i).map_or(&[][..], Vec::as_slice);
+                line.push_str(&paint(&text, tone, highlights, visible));
+                if !last {
+                    line.push_str("  ");
+                }
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+
+    pub fn print(&self, long: bool) {
+        pager::page(&self.render(long));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn truncate_keeps_text_that_fits() {
+        assert_eq!(truncate("fmt", 3), "fmt");
+        assert_eq!(truncate("fmt", 10), "fmt");
+    }
+
+    #[test]
+    fn truncate_marks_the_cut() {
+        assert_eq!(truncate("spdlog", 4), "spd…");
+        // widths are in characters, not bytes.
+        assert_eq!(truncate("Ünïcödé", 4), "Ünï…");
+    }
+
+    #[test]
+    fn pad_fills_to_the_width_and_never_cuts() {
+        assert_eq!(pad("fmt", 5), "fmt  ");
+        assert_eq!(pad("spdlog", 3), "spdlog");
+        assert_eq!(pad("é", 2), "é ");
+    }
+
+    #[test]
+    fn columns_are_as_wide_as_their_widest_cell_or_header() {
+        let mut table = Table::new(&["NAME", "LANGUAGE"]);
+        table.row(vec!["abseil-cpp".into(), "C".into()]);
+        table.row(vec!["fmt".into(), "C++".into()]);
+        assert_eq!(table.widths(), vec![10, 8]);
+    }
+}
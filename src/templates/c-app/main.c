@@ -0,0 +1,6 @@
+#include <stdio.h>
+
+int main(void) {
+    printf("hello from {{name}}!\n");
+    return 0;
+}
@@ -0,0 +1,6 @@
+#include <iostream>
+
+int main() {
+    std::cout << "hello from {{name}}!\n";
+    return 0;
+}
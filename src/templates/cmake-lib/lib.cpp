@@ -0,0 +1,9 @@
+#include <{{name}}/{{name}}.hpp>
+
+namespace {{ident}} {
+
+int answer() {
+    return 42;
+}
+
+}
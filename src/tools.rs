@@ -0,0 +1,171 @@
+// Build tools installed without sudo.
+//
+// When a tool is missing and the system package manager can't be used (there
+// is no sudo, as in many containers and restricted accounts, or the package
+// manager isn't one cinstall knows), cinstall offers to put the tool in
+// `~/.cinstall/tools` instead: ninja from its prebuilt release, and cmake,
+// meson and scons from PyPI into a virtualenv of their own. `~/.cinstall/tools/bin`
+// is added to PATH whenever cinstall runs, so the builds find them.
+
+use crate::doctor;
+use crate::installer::{InstallError, Step};
+use crate::{outputln, paths, retry};
+use std::os::unix::fs::MetadataExt;
+use std::path::PathBuf;
+
+/// `~/.cinstall/tools`
+pub fn dir() -> PathBuf {
+    paths::home_dir().join(".cinstall").join("tools")
+}
+
+pub fn bin_dir() -> PathBuf {
+    dir().join("bin")
+}
+
+/// Put the tools installed before on PATH, for cinstall and the builds it runs.
+pub fn add_to_path() {
+    let bin = bin_dir();
+    if !bin.is_dir() {
+        return;
+    }
+    let path = std::env::var_os("PATH").unwrap_or_default();
+    let mut dirs: Vec<PathBuf> = std::env::split_paths(&path).collect();
+    if dirs.contains(&bin) {
+        return;
+    }
+    dirs.insert(0, bin);
+    if let Ok(path) = std::env::join_paths(dirs) {
+        std::env::set_var("PATH", path);
+    }
+}
+
+pub fn is_root() -> bool {
+    std::fs::metadata("/proc/self").is_ok_and(|m| m.uid() == 0)
+}
+
+/// Whether the system package manager can be run as root, with sudo or
+/// because cinstall already is root.
+pub fn can_use_package_manager() -> bool {
+    is_root() || doctor::find_program("sudo").is_some()
+}
+
+/// The PyPI package `program` comes from, for the tools that are installed
+/// from there.
+fn pypi_package(program: &str) -> Option<&'static str> {
+    match program {
+        "cmake" => Some("cmake"),
+        "meson" => Some("meson"),
+        "ninja" => Some("ninja"),
+        "scons" => Some("scons"),
+        _ => None,
+    }
+}
+
+/// Whether `program` can be installed into the tools directory.
+pub fn can_bootstrap(program: &str) -> bool {
+    pypi_package(program).is_some()
+}
+
+/// The name of ninja's prebuilt release for this machine.
+fn ninja_asset() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("linux", "x86_64") => Some("ninja-linux.zip"),
+        ("linux", "aarch64") => Some("ninja-linux-aarch64.zip"),
+        ("macos", _) => Some("ninja-mac.zip"),
+        _ => None,
+    }
+}
+
+fn run(step: Step) -> Result<bool, InstallError> {
+    Ok(retry::run(&step)?.success())
+}
+
+/// Download ninja's prebuilt binary into the tools directory.
+fn prebuilt_ninja() -> Result<bool, InstallError> {
+    let (Some(asset), Some(_)) = (ninja_asset(), doctor::find_program("unzip")) else {
+        return Ok(false);
+    };
+    let archive = dir().join(asset);
+    let url = format!(
+        "https://github.com/ninja-build/ninja/releases/latest/download/{}",
+        asset
+    );
+    let downloaded = run(Step::new("curl", &dir())
+        .arg("-fSL")
+        .arg("-o")
+        .arg(archive.display().to_string())
+        .arg(url))?
+        && run(Step::new("unzip", &dir())
+            .arg("-o")
+            .arg(archive.display().to_string())
+            .arg("-d")
+            .arg(bin_dir().display().to_string()))?;
+    let _ = std::fs::remove_file(&archive);
+    Ok(downloaded)
+}
+
+/// Install `package` from PyPI into a virtualenv and link `program` into the
+/// tools' bin directory.
+fn from_pypi(program: &str, package: &str) -> Result<bool, InstallError> {
+    let Some(python) = doctor::find_program("python3") else {
+        outputln!(
+            red,
+            "python3 is needed to install {} without sudo.",
+            program
+        );
+        return Ok(false);
+    };
+    let venv = dir().join("venv");
+    if !venv.join("bin").join("pip").is_file()
+        && !run(Step::new(&python.display().to_string(), &dir())
+            .arg("-m")
+            .arg("venv")
+            .arg(venv.display().to_string()))?
+    {
+        outputln!(
+            red,
+            "failed to create a virtualenv in {}, python3's venv module may not be installed.",
+            (venv.display())
+        );
+        return Ok(false);
+    }
+    let pip = venv.join("bin").join("pip");
+    if !run(Step::new(&pip.display().to_string(), &dir())
+        .arg("install")
+        .arg("--upgrade")
+        .arg(package))?
+    {
+        return Ok(false);
+    }
+    let link = bin_dir().join(program);
+    let _ = std::fs::remove_file(&link);
+    std::os::unix::fs::symlink(venv.join("bin").join(program), &link)
+        .map_err(|_| InstallError::FailedToWriteToFile)?;
+    Ok(true)
+}
+
+/// Install `program` into the tools directory.
+pub fn bootstrap(program: &str) -> Result<(), InstallError> {
+    let Some(package) = pypi_package(program) else {
+        return Err(InstallError::UnknownPackageManager);
+    };
+    std::fs::create_dir_all(bin_dir()).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    outputln!("installing `{}` into {}.", program, (dir().display()));
+
+    let installed = (program == "ninja" && prebuilt_ninja()?) || from_pypi(program, package)?;
+    if !installed {
+        return Err(InstallError::UnknownFatal(format!(
+            "failed to install `{}` into {}.",
+            program,
+            dir().display()
+        )));
+    }
+    add_to_path();
+    outputln!(
+        green,
+        "installed `{}`, cinstall finds it in {} from now on.",
+        program,
+        (bin_dir().display())
+    );
+    Ok(())
+}
@@ -0,0 +1,198 @@
+// How output looks.
+//
+// Every message goes through `outputln!`/`output!`, which ask this module for
+// their label, and anything else that wants colour uses `paint`. The theme and
+// the label text come from the config; `plain` uses no colour and only ASCII
+// so it works on dumb terminals, and `high-contrast` uses bright colours and a
+// symbol per kind of message so that nothing relies on colour alone.
+//
+// Accessibility mode (`--a11y`) is the plain theme with the kind of every
+// message spelled out in words, so a screen reader announces prompts and
+// errors as such, and long build steps report that they are still running.
+//
+// Porcelain mode (`--porcelain`, `CINSTALL_PORCELAIN=1`, or detected when
+// cinstall runs under CI or make) is for other build systems calling
+// cinstall: no colour, no prompts, every message is one line starting with
+// `cinstall: <kind>: `, and stdout carries nothing but the JSON report of
+// each install.
+
+use crate::run;
+use colored::{ColoredString, Colorize};
+use serde::{Deserialize, Serialize};
+use std::io::IsTerminal;
+use std::sync::Mutex;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    Default,
+    Plain,
+    HighContrast,
+}
+
+/// What a piece of output means, which decides how it is shown.
+#[derive(Clone, Copy)]
+pub enum Tone {
+    Info,
+    Success,
+    Warning,
+    Error,
+    Prompt,
+    Heading,
+    Emphasis,
+    /// The part of a search result that matched.
+    Highlight,
+}
+
+impl Tone {
+    /// The tone for the colour a message was written with, e.g. `outputln!(red, ...)`.
+    pub fn from_color(color: &str) -> Self {
+        match color {
+            "green" => Tone::Success,
+            "purple" | "yellow" => Tone::Warning,
+            "red" => Tone::Error,
+            "on_blue" => Tone::Prompt,
+            _ => Tone::Info,
+        }
+    }
+}
+
+struct Settings {
+    theme: Theme,
+    label: String,
+    a11y: bool,
+    porcelain: bool,
+}
+
+static SETTINGS: Mutex<Option<Settings>> = Mutex::new(None);
+
+/// Whether cinstall is being run by another build system: asked to with
+/// `porcelain`, `CINSTALL_PORCELAIN`, or running under CI or make.
+pub fn detect_porcelain(porcelain: bool) -> bool {
+    let set =
+        |name: &str| std::env::var(name).is_ok_and(|v| !v.is_empty() && v != "0" && v != "false");
+    porcelain || set("CINSTALL_PORCELAIN") || set("CI") || set("MAKELEVEL")
+}
+
+/// Use `theme` (or pick one from the terminal when not set) and `label` from now on.
+pub fn configure(theme: Option<Theme>, label: Option<String>, a11y: bool, porcelain: bool) {
+    let dumb = std::env::var("TERM").is_ok_and(|t| t == "dumb");
+    let theme = match theme {
+        _ if a11y || porcelain => Theme::Plain,
+        Some(theme) => theme,
+        None if dumb => Theme::Plain,
+        None => Theme::Default,
+    };
+    if theme == Theme::Plain {
+        colored::control::set_override(false);
+    }
+    *SETTINGS.lock().unwrap() = Some(Settings {
+        theme,
+        label: label.unwrap_or_else(|| "installer".into()),
+        a11y,
+        porcelain,
+    });
+}
+
+fn settings() -> (Theme, String, bool) {
+    match &*SETTINGS.lock().unwrap() {
+        Some(s) => (s.theme, s.label.clone(), s.a11y),
+        None => (Theme::Default, "installer".into(), false),
+    }
+}
+
+/// Whether accessibility mode is on.
+pub fn a11y() -> bool {
+    settings().2
+}
+
+/// Whether porcelain mode is on.
+pub fn porcelain() -> bool {
+    SETTINGS
+        .lock()
+        .unwrap()
+        .as_ref()
+        .is_some_and(|s| s.porcelain)
+}
+
+/// Whether there is someone to ask: a terminal, and not porcelain mode.
+pub fn interactive() -> bool {
+    !porcelain() && std::io::stdin().is_terminal()
+}
+
+fn style(text: &str, tone: Tone, theme: Theme) -> ColoredString {
+    match (theme, tone) {
+        (Theme::Plain, _) => text.normal(),
+        (Theme::Default, Tone::Info) => text.bold().cyan(),
+        (Theme::Default, Tone::Success) => text.bold().green(),
+        (Theme::Default, Tone::Warning) => text.bold().purple(),
+        (Theme::Default, Tone::Error) => text.bold().red(),
+        (Theme::Default, Tone::Prompt) => text.bold().on_blue(),
+        (Theme::Default, Tone::Heading) => text.bold().underline(),
+        (Theme::Default, Tone::Emphasis) => text.bright_cyan(),
+        (Theme::Default, Tone::Highlight) => text.bold().yellow(),
+        (Theme::HighContrast, Tone::Info) => text.bold().bright_white(),
+        (Theme::HighContrast, Tone::Success) => text.bold().bright_green(),
+        (Theme::HighContrast, Tone::Warning) => text.bold().bright_yellow(),
+        (Theme::HighContrast, Tone::Error) => text.bold().bright_red(),
+        (Theme::HighContrast, Tone::Prompt) => text.bold().bright_white().on_blue(),
+        (Theme::HighContrast, Tone::Heading) => text.bold().bright_white().underline(),
+        (Theme::HighContrast, Tone::Emphasis) => text.bold().bright_white(),
+        (Theme::HighContrast, Tone::Highlight) => text.bold().bright_yellow().underline(),
+    }
+}
+
+/// The marker put after the label, so a message's kind can be told without colour.
+fn symbol(tone: Tone, theme: Theme, a11y: bool) -> &'static str {
+    if a11y {
+        return match tone {
+            Tone::Warning => "warning: ",
+            Tone::Error => "error: ",
+            Tone::Prompt => "question: ",
+            _ => "",
+        };
+    }
+    match (theme, tone) {
+        (Theme::Default, _) => "",
+        (Theme::Plain, Tone::Success) => "+ ",
+        (Theme::Plain, Tone::Warning) => "! ",
+        (Theme::Plain, Tone::Error) => "x ",
+        (Theme::Plain, Tone::Prompt) => "? ",
+        (Theme::Plain, _) => "",
+        (Theme::HighContrast, Tone::Success) => "✔ ",
+        (Theme::HighContrast, Tone::Warning) => "⚠ ",
+        (Theme::HighContrast, Tone::Error) => "✖ ",
+        (Theme::HighContrast, Tone::Prompt) => "? ",
+        (Theme::HighContrast, _) => "",
+    }
+}
+
+/// `text` in the current theme's style for `tone`.
+pub fn paint(text: &str, tone: Tone) -> String {
+    style(text, tone, settings().0).to_string()
+}
+
+/// What every message starts with, e.g. `[installer] ` or `[installer 4kq0x7za] `
+/// while an install run is going.
+pub fn label(tone: Tone) -> String {
+    if porcelain() {
+        let kind = match tone {
+            Tone::Success => "ok",
+            Tone::Warning => "warning",
+            Tone::Error => "error",
+            Tone::Prompt => "question",
+            _ => "info",
+        };
+        return format!("cinstall: {}: ", kind);
+    }
+    let (theme, label, a11y) = settings();
+    let label = match run::id() {
+        Some(id) => format!("{} {}", label, id),
+        None => label,
+    };
+    let symbol = match symbol(tone, theme, a11y) {
+        "" => String::new(),
+        symbol => style(symbol, tone, theme).to_string(),
+    };
+    format!("[{}] {}", style(&label, tone, theme), symbol)
+}
@@ -0,0 +1,246 @@
+// `cinstall upgrade`: rebuilding a package when upstream has something new.
+//
+// What was installed is compared with the remote without cloning it, using
+// `git ls-remote`:
+//
+// - installed from the default branch, or a branch: its tip now;
+// - installed at a tag: for GitHub projects the latest release, when it is
+//   newer, otherwise the newest tag that looks like the same kind of release
+//   (`v1.2.3` is followed by `v1.3.0`, not by `nightly`), leaving out
+//   pre-releases unless a pre-release was installed;
+// - installed at a commit: nothing to follow, it stays where it is.
+//
+// Only when there is something newer is the package built again, with the
+// checkout kept from the last build fetched rather than cloned anew.
+// `--all` goes through every installed package, building each one in a
+// cinstall of its own so that one failing doesn't stop the others.
+
+use crate::database::InstalledPackage;
+use crate::{develop, github, source};
+use std::process::Command;
+use url::Url;
+
+/// What upstream has compared with what is installed.
+pub enum Verdict {
+    /// Nothing newer, and why it is the newest.
+    UpToDate(String),
+    /// Something newer: the ref to install it with, the tag or commit it is
+    /// at, and what it is.
+    Newer {
+        git_ref: Option<String>,
+        available: String,
+        what: String,
+    },
+    /// Upstream couldn't be compared with, and why.
+    Unknown(String),
+}
+
+/// The refs of the repository at `url`, as `(commit, ref)`.
+fn ls_remote(url: &str) -> Result<Vec<(String, String)>, String> {
+    let output = Command::new("git")
+        .args(github::git_auth_args())
+        .args(["ls-remote", url])
+        .env("GIT_TERMINAL_PROMPT", "0")
+        .output()
+        .map_err(|e| format!("failed to run git: {}", e))?;
+    if !output.status.success() {
+        return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|line| {
+            let (commit, name) = line.split_once('\t')?;
+            Some((commit.to_string(), name.to_string()))
+        })
+        .collect())
+}
+
+/// The commit `git_ref` (the default branch if `None`) of the repository at
+/// `url` is at, without cloning it.
+pub fn remote_commit(url: &str, git_ref: Option<&str>) -> Option<String> {
+    let refs = ls_remote(url).ok()?;
+    let names = match git_ref {
+        None => vec!["HEAD".to_string()],
+        // an annotated tag's commit is the `^{}` line after it.
+        Some(r) => vec![
+            format!("refs/tags/{}^{{}}", r),
+            format!("refs/tags/{}", r),
+            format!("refs/heads/{}", r),
+        ],
+    };
+    names.iter().find_map(|name| {
+        refs.iter()
+            .find(|(_, r)| r == name)
+            .map(|(commit, _)| commit.clone())
+    })
+}
+
+/// The text before a tag's first digit and the numbers in it, `v1.2.3` is
+/// `("v", [1, 2, 3])` and `curl-8_5_0` is `("curl-", [8, 5, 0])`.
+fn tag_version(tag: &str) -> Option<(&str, Vec<u64>)> {
+    let start = tag.find(|c: char| c.is_ascii_digit())?;
+    let numbers = tag[start..]
+        .split(|c: char| !c.is_ascii_digit())
+        .filter(|part| !part.is_empty())
+        .map(|part| part.parse().ok())
+        .collect::<Option<Vec<u64>>>()?;
+    Some((&tag[..start], numbers))
+}
+
+fn is_prerelease(tag: &str) -> bool {
+    let tag = tag.to_lowercase();
+    ["rc", "alpha", "beta", "pre", "dev", "snapshot"]
+        .iter()
+        .any(|word| tag.contains(word))
+}
+
+/// The newest tag among `tags` that is the same kind of release as `current`.
+fn newest_tag<'a>(current: &str, tags: &[&'a str]) -> Option<&'a str> {
+    let (prefix, _) = tag_version(current)?;
+    let prerelease = is_prerelease(current);
+    tags.iter()
+        .filter(|tag| prerelease || !is_prerelease(tag))
+        .filter_map(|tag| {
+            let (p, version) = tag_version(tag)?;
+            (p == prefix).then_some((version, *tag))
+        })
+        .max()
+        .map(|(_, tag)| tag)
+}
+
+/// The tag of the latest GitHub release of `installed`, when it is one of
+/// `tags` and the same kind of release as `current`, and not older.
+fn latest_release<'a>(
+    installed: &InstalledPackage,
+    current: &str,
+    tags: &[&'a str],
+) -> Option<&'a str> {
+    let repo = github::repo_of(&Url::parse(&installed.url).ok()?)?;
+    let (release, _) = github::repo_info(&repo).ok()?.latest_release?;
+    let (prefix, version) = tag_version(current)?;
+    let (release_prefix, release_version) = tag_version(&release)?;
+    if release_prefix != prefix || release_version < version {
+        return None;
+    }
+    tags.iter().find(|tag| **tag == release).copied()
+}
+
+fn short(commit: &str) -> &str {
+    &commit[..commit.len().min(10)]
+}
+
+/// Compare `installed` with its upstream.
+pub fn check(installed: &InstalledPackage) -> Verdict {
+    let refs = match ls_remote(&installed.url) {
+        Ok(refs) => refs,
+        Err(e) => return Verdict::Unknown(format!("couldn't ask {}. {}", installed.url, e)),
+    };
+    let commit_of = |name: &str| {
+        refs.iter()
+            .find(|(_, r)| r == name)
+            .map(|(commit, _)| commit.as_str())
+    };
+    let installed_commit = installed.commit.as_deref().unwrap_or_default();
+
+    let (branch, tip) = match &installed.git_ref {
+        None => ("the default branch".to_string(), commit_of("HEAD")),
+        Some(r) if commit_of(&format!("refs/heads/{}", r)).is_some() => (
+            format!("`{}`", r),
+            commit_of(&format!("refs/heads/{}", r)),
+        ),
+        Some(r) if commit_of(&format!("refs/tags/{}", r)).is_some() => {
+            let tags: Vec<&str> = refs
+                .iter()
+                .filter_map(|(_, name)| name.strip_prefix("refs/tags/"))
+                .filter(|name| !name.ends_with("^{}"))
+                .collect();
+            let newest = latest_release(installed, r, &tags).or_else(|| newest_tag(r, &tags));
+            return match newest {
+                Some(newest) if newest != r => Verdict::Newer {
+                    git_ref: Some(newest.to_string()),
+                    available: newest.to_string(),
+                    what: format!("the tag `{}`, `{}` is installed", newest, r),
+                },
+                _ => Verdict::UpToDate(format!("`{}` is the newest tag", r)),
+            };
+        }
+        Some(r) => {
+            return Verdict::UpToDate(format!(
+                "it was installed at `{}`, which isn't a branch or tag to follow (install another ref with `--ref`)",
+                r
+            ))
+        }
+    };
+    match tip {
+        None => Verdict::Unknown(format!("{} of {} wasn't found", branch, installed.url)),
+        Some(tip) if tip == installed_commit => Verdict::UpToDate(format!(
+            "{} is still at {}",
+            branch,
+            short(installed_commit)
+        )),
+        Some(tip) => Verdict::Newer {
+            git_ref: installed.git_ref.clone(),
+            available: short(tip).to_string(),
+            what: match installed.commit.as_deref() {
+                Some(commit) => format!(
+                    "{} is at {}, {} is installed",
+                    branch,
+                    short(tip),
+                    short(commit)
+                ),
+                None => format!("{} is at {}", branch, short(tip)),
+            },
+        },
+    }
+}
+
+/// Whether `installed` came from a repository, rather than an archive or a
+/// directory on this machine.
+pub fn has_upstream(installed: &InstalledPackage) -> bool {
+    Url::parse(&installed.url).is_ok_and(|url| !source::is_archive(&url) && !source::is_local(&url))
+}
+
+/// What `cinstall upgrade` does with an installed package.
+pub enum Plan {
+    /// Build it again at `git_ref`, for the reason given.
+    Build {
+        git_ref: Option<String>,
+        why: String,
+    },
+    /// Nothing is newer, and why it is the newest.
+    UpToDate(String),
+    /// There is nothing to compare with, and why.
+    Skip(String),
+    /// Upstream couldn't be compared with, and why.
+    Unknown(String),
+}
+
+/// What to do with `installed`, building it again anyway with `force`.
+pub fn plan(installed: &InstalledPackage, force: bool) -> Plan {
+    let again = |why: String| Plan::Build {
+        git_ref: installed.git_ref.clone(),
+        why: format!("{}, building it again because of --force", why),
+    };
+    if let Some(path) = develop::path(&installed.name) {
+        let why = format!(
+            "it is built from your checkout at {} until `cinstall develop {} --remove`",
+            path.display(),
+            installed.name
+        );
+        return if force { again(why) } else { Plan::Skip(why) };
+    }
+    if !has_upstream(installed) {
+        let why = format!(
+            "it was installed from {}, there is no upstream to compare with",
+            installed.url
+        );
+        return if force { again(why) } else { Plan::Skip(why) };
+    }
+    match check(installed) {
+        Verdict::Newer { git_ref, what, .. } => Plan::Build { git_ref, why: what },
+        Verdict::UpToDate(why) if force => again(why),
+        Verdict::UpToDate(why) => Plan::UpToDate(why),
+        Verdict::Unknown(why) if force => again(why),
+        Verdict::Unknown(why) => Plan::Unknown(why),
+    }
+}
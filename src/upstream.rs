@@ -0,0 +1,90 @@
+// `--check-upstream`: look for known build problems before building.
+//
+// GitHub's issue search is asked for open issues labelled `build` or `compile`
+// that mention this platform, and the most recently updated ones are shown.
+// This is advisory only, anything going wrong here is a note and the install
+// carries on.
+
+use crate::github;
+use crate::outputln;
+use serde::Deserialize;
+use url::Url;
+
+const SHOWN: usize = 5;
+
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct SearchResult {
+    total_count: u64,
+    items: Vec<Issue>,
+}
+
+/// The platform as issues usually spell it.
+fn platform() -> &'static str {
+    match std::env::consts::OS {
+        os @ ("macos" | "windows" | "freebsd") => os,
+        _ => "linux",
+    }
+}
+
+fn search(repo: &str) -> Result<SearchResult, String> {
+    let query = format!(
+        "repo:{} is:issue is:open label:build,compile {}",
+        repo,
+        platform()
+    );
+    let api = github::api_url(
+        "search/issues",
+        &[
+            ("q", query.as_str()),
+            ("sort", "updated"),
+            ("per_page", &SHOWN.to_string()),
+        ],
+    );
+    let result = github::request("GET", &api, None)?;
+    serde_json::from_value(result).map_err(|e| e.to_string())
+}
+
+/// Print open build issues for the project at `url`.
+pub fn check(url: &Url) {
+    let Some(repo) = github::repo_of(url) else {
+        outputln!(
+            purple,
+            "upstream issues can only be checked for GitHub projects."
+        );
+        return;
+    };
+    outputln!(
+        "checking {} for open build issues on {}",
+        repo,
+        (platform())
+    );
+
+    let result = match search(&repo) {
+        Ok(result) => result,
+        Err(e) => {
+            outputln!(purple, "couldn't check upstream issues. {}", e);
+            return;
+        }
+    };
+    if result.items.is_empty() {
+        outputln!(green, "no open build issues mention {}.", (platform()));
+        return;
+    }
+    outputln!(
+        purple,
+        "{} open build issue(s) mention {}, the latest:",
+        (result.total_count),
+        (platform())
+    );
+    for issue in &result.items {
+        eprintln!("  #{} {}", issue.number, issue.title);
+        eprintln!("      {}", issue.html_url);
+    }
+}
@@ -0,0 +1,97 @@
+// `cinstall verify`: checking that installed files are still what was installed.
+//
+// Every file in a package's manifest has to still be there. With `--hashes`
+// the size and SHA-256 recorded at install time are compared as well, which
+// catches files another tool overwrote and ones a failing disk truncated.
+
+use crate::database::MergedDatabase;
+use crate::manifest::{self, Manifest, ManifestFile};
+use crate::outputln;
+
+/// What is wrong with `file`, if anything.
+fn problem(file: &ManifestFile, hashes: bool) -> Option<&'static str> {
+    let Ok(metadata) = std::fs::symlink_metadata(&file.path) else {
+        return Some("missing");
+    };
+    if !hashes || !metadata.is_file() {
+        return None;
+    }
+    match (file.size, &file.sha256) {
+        (Some(size), _) if metadata.len() < size => Some("truncated"),
+        (_, Some(sha256)) if manifest::sha256_of(&file.path).as_ref() != Some(sha256) => {
+            Some("modified")
+        }
+        _ => None,
+    }
+}
+
+/// Check the package `name`, returning whether it is intact.
+fn verify_package(name: &str, hashes: bool) -> bool {
+    let manifest = match Manifest::load(name) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            outputln!(red, "`{}`: {}", name, e);
+            return false;
+        }
+    };
+    let problems: Vec<(&ManifestFile, &str)> = manifest
+        .files
+        .iter()
+        .filter_map(|f| problem(f, hashes).map(|p| (f, p)))
+        .collect();
+    let unhashed = manifest
+        .files
+        .iter()
+        .filter(|f| f.sha256.is_none() && f.path.is_file() && !f.path.is_symlink())
+        .count();
+
+    if problems.is_empty() {
+        outputln!(
+            green,
+            "`{}`: all {} files are intact.",
+            name,
+            (manifest.files.len())
+        );
+    } else {
+        outputln!(
+            red,
+            "`{}`: {} of {} files have problems:",
+            name,
+            (problems.len()),
+            (manifest.files.len())
+        );
+        for (file, problem) in &problems {
+            eprintln!("  {:<9} {}", problem, file.path.display());
+        }
+    }
+    if hashes && unhashed > 0 {
+        outputln!(
+            purple,
+            "`{}`: {} files were installed before checksums were recorded, reinstall it to check them.",
+            name,
+            unhashed
+        );
+    }
+    problems.is_empty()
+}
+
+/// Check `package`, or every installed package, returning whether they are
+/// all intact.
+pub fn verify(package: Option<&str>, hashes: bool) -> bool {
+    let names: Vec<String> = match package {
+        Some(name) => vec![name.to_string()],
+        None => MergedDatabase::open()
+            .iter()
+            .map(|(_, p)| p.name.clone())
+            .collect(),
+    };
+    if names.is_empty() {
+        outputln!("there are no installed packages to verify.");
+        return true;
+    }
+    let mut intact = true;
+    for name in &names {
+        intact &= verify_package(name, hashes);
+    }
+    intact
+}
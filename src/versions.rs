@@ -0,0 +1,175 @@
+// Versioned installs.
+//
+// With `--versioned` (or the `versioned` config key) a package isn't
+// installed into the prefix itself but into a directory of its own,
+// `/opt/cinstall/<package>/<version>` by default (the `versions_dir` config
+// key), and every file in it is linked into the prefix. The version is the
+// ref that was asked for, or otherwise the commit that was built.
+//
+// Installing another version builds it next to the ones already there and
+// moves the links over to it, removing the ones it has no file for, so the
+// older versions can still be used from their directories. The manifest has
+// the links, and uninstalling removes them and the package's directory with
+// every version in it.
+//
+// A link never replaces a file of another package, or one cinstall didn't
+// install, without `--force`.
+
+use crate::installer::InstallError;
+use crate::manifest::{self, Manifest};
+use crate::outputln;
+use std::path::{Path, PathBuf};
+
+/// Where versioned installs go when the `versions_dir` config key isn't set.
+pub const DEFAULT_ROOT: &str = "/opt/cinstall";
+
+/// The directory every version of `name` is kept in under `root`.
+fn package_dir(root: &Path, name: &str) -> PathBuf {
+    root.join(manifest::file_name(name))
+}
+
+/// The directory to install the version of `name` that `git_ref` asks for,
+/// or `commit` is, into.
+pub fn version_dir(
+    root: &Path,
+    name: &str,
+    git_ref: Option<&str>,
+    commit: Option<&str>,
+) -> PathBuf {
+    let version = match (git_ref, commit) {
+        (Some(r), _) if r != "head" => r.to_string(),
+        (_, Some(commit)) => commit[..commit.len().min(10)].to_string(),
+        _ => chrono::Utc::now().format("%Y%m%d%H%M%S").to_string(),
+    };
+    package_dir(root, name).join(manifest::file_name(&version))
+}
+
+/// The directory the versions are kept in that `version_dir` is in.
+pub fn root_of(version_dir: &Path) -> Option<&Path> {
+    version_dir.parent()?.parent()
+}
+
+/// Report the links to make in `prefix` that would replace a file another
+/// package installed, or one nobody cinstall knows of did, returning how
+/// many there are.
+fn report_conflicts(name: &str, links: &[PathBuf], force: bool) -> usize {
+    let owners = manifest::owners();
+    let what = if force { "will be" } else { "would be" };
+    let mut conflicts = 0;
+    for link in links {
+        match owners.get(link) {
+            Some(owner) if owner != name => outputln!(
+                red,
+                "`{}` is owned by the package `{}` and {} replaced by a link.",
+                (link.display()),
+                owner,
+                what
+            ),
+            Some(_) => continue,
+            None if std::fs::symlink_metadata(link).is_ok() => outputln!(
+                purple,
+                "`{}` already exists and {} replaced by a link.",
+                (link.display()),
+                what
+            ),
+            None => continue,
+        }
+        conflicts += 1;
+    }
+    conflicts
+}
+
+/// Link the files installed into `version_dir` into `prefix`, returning the
+/// links and the files that were installed elsewhere. The links an earlier
+/// version of `name` made that this one has no file for are removed. Files
+/// that aren't `name`'s are only replaced with `force`.
+pub fn link(
+    name: &str,
+    version_dir: &Path,
+    prefix: &Path,
+    files: &[PathBuf],
+    force: bool,
+) -> Result<Vec<PathBuf>, InstallError> {
+    let links: Vec<PathBuf> = files
+        .iter()
+        .filter_map(|file| file.strip_prefix(version_dir).ok())
+        .map(|relative| prefix.join(relative))
+        .collect();
+    let conflicts = report_conflicts(name, &links, force);
+    if conflicts > 0 && !force {
+        return Err(InstallError::UnknownFatal(format!(
+            "{} files in {} aren't `{}`'s, pass --force to replace them with links.",
+            conflicts,
+            prefix.display(),
+            name
+        )));
+    }
+
+    let mut linked = vec![];
+    let mut links = 0;
+    for file in files {
+        let Ok(relative) = file.strip_prefix(version_dir) else {
+            linked.push(file.clone());
+            continue;
+        };
+        let link = prefix.join(relative);
+        let error = |e: std::io::Error| {
+            InstallError::BadDirectory(format!(
+                "{}: {} (you may need to `sudo`)",
+                link.display(),
+                e
+            ))
+        };
+        if let Some(parent) = link.parent() {
+            std::fs::create_dir_all(parent).map_err(error)?;
+        }
+        match std::fs::symlink_metadata(&link) {
+            Ok(m) if m.is_symlink() || m.is_file() => std::fs::remove_file(&link).map_err(error)?,
+            _ => (),
+        }
+        std::os::unix::fs::symlink(file, &link).map_err(error)?;
+        linked.push(link);
+        links += 1;
+    }
+
+    let package_dir = version_dir.parent().unwrap_or(version_dir);
+    if let Ok(earlier) = Manifest::load(name) {
+        let stale = Manifest {
+            files: earlier
+                .files
+                .into_iter()
+                .filter(|f| !linked.contains(&f.path))
+                .filter(|f| std::fs::read_link(&f.path).is_ok_and(|to| to.starts_with(package_dir)))
+                .collect(),
+            ..earlier
+        };
+        if !stale.files.is_empty() {
+            stale.remove_files()?;
+        }
+    }
+    outputln!(
+        "linked {} files in {} into {}.",
+        links,
+        (version_dir.display()),
+        (prefix.display())
+    );
+    Ok(linked)
+}
+
+/// Remove the directory `version_dir` is in, with every version in it.
+pub fn remove(version_dir: &Path) -> Result<(), InstallError> {
+    let Some(package_dir) = version_dir.parent() else {
+        return Ok(());
+    };
+    if package_dir.exists() {
+        std::fs::remove_dir_all(package_dir).map_err(|e| {
+            InstallError::BadDirectory(format!(
+                "{}: {} (you may need to `sudo`)",
+                package_dir.display(),
+                e
+            ))
+        })?;
+        outputln!("removed {}.", (package_dir.display()));
+    }
+    Ok(())
+}
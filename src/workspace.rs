@@ -0,0 +1,433 @@
+// The workspace in `~/.cache/cinstall/work` that projects are cloned and
+// built in. It belongs to the user cinstall runs as and nobody else can get
+// into it, since what is built there is run, often with `sudo`.
+//
+// Checkouts are keyed by URL rather than by run, so a clone that was cut off
+// (Ctrl-C, a dropped connection, a reboot) is found again by the next install
+// of the same URL. A clone happens in `<checkout>.partial` and is only moved
+// into place once it finished, which is how an interrupted one is recognised.
+// A checkout that belongs to another user is never reused, it is cloned
+// again.
+//
+// Clones are shallow unless the whole history is asked for: only the commit
+// being built is downloaded, which matters for repositories like llvm or boost.
+//
+// Release archives (`.tar.gz`, `.zip`, ...) are downloaded with curl and
+// extracted instead, which doesn't need git at all. They are extracted afresh
+// every time, so there is nothing to resume. A checksum pinned in the registry
+// or given with `--sha256` is checked before anything is extracted.
+//
+// Private repositories are cloned with whatever git would use on its own (a
+// credential helper, or the SSH agent for `ssh://` remotes), plus
+// `$GITHUB_TOKEN` for github.com. When those don't have credentials git asks
+// for them on the terminal, and without one the install fails with
+// `AuthRequired` naming the remote, so that scripts can tell.
+//
+// Everything that goes over the network is tried again when the network
+// fails, see `retry`.
+
+use crate::installer::{self, InstallError, Step};
+use crate::{github, manifest, mirrors, outputln, paths, retry, signature, ui};
+use sha2::{Digest, Sha256};
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+/// The workspace, made if it isn't there yet, with only its owner allowed in.
+pub fn root() -> PathBuf {
+    let root = paths::cache_dir().join("work");
+    if !root.is_dir() {
+        let _ = std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(&root);
+    }
+    if is_owned(&root) {
+        let _ = std::fs::set_permissions(&root, std::fs::Permissions::from_mode(0o700));
+    }
+    root
+}
+
+/// Whether `path` belongs to the user cinstall runs as.
+fn is_owned(path: &Path) -> bool {
+    match (
+        std::fs::symlink_metadata(path),
+        std::fs::metadata("/proc/self"),
+    ) {
+        (Ok(metadata), Ok(this)) => metadata.uid() == this.uid(),
+        _ => false,
+    }
+}
+
+/// Where the checkout of `url` lives, e.g.
+/// `~/.cache/cinstall/work/src/github.com_fmtlib_fmt-1a2b3c4d`. The hash of the
+/// URL tells apart the ones that only differ in characters that aren't kept.
+pub fn source_dir(url: &Url) -> PathBuf {
+    let location = format!("{}{}", url.host_str().unwrap_or(""), url.path());
+    let location = location.trim_end_matches('/').trim_end_matches(".git");
+    let name: String = location
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '.' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let hash = format!("{:x}", Sha256::digest(location.as_bytes()));
+    root().join("src").join(format!("{}-{}", name, &hash[..8]))
+}
+
+fn partial_dir(source: &Path) -> PathBuf {
+    let mut name = source.as_os_str().to_owned();
+    name.push(".partial");
+    PathBuf::from(name)
+}
+
+/// A git command that talks to the remote. With a terminal, git and ssh may
+/// ask on it for credentials, so the step stays in the terminal's process
+/// group. Without one they are told not to ask, so that a repository that
+/// needs them fails instead of hanging.
+fn git(dir: &Path) -> Step {
+    let mut step = Step::new("git", dir).args(&github::git_auth_args());
+    if ui::interactive() {
+        step = step.attached();
+    } else {
+        step = step.env("GIT_TERMINAL_PROMPT", "0");
+        if std::env::var_os("GIT_SSH_COMMAND").is_none() {
+            step = step.env("GIT_SSH_COMMAND", "ssh -o BatchMode=yes");
+        }
+    }
+    step
+}
+
+/// Run `steps` in order, stopping at the first failure. Each step that ran is
+/// added to `done`.
+fn run_steps(steps: Vec<Step>, done: &mut Vec<Step>) -> bool {
+    for step in steps {
+        let ok = matches!(retry::run(&step), Ok(status) if status.success());
+        done.push(step);
+        if !ok {
+            return false;
+        }
+    }
+    true
+}
+
+/// Fetch `git_ref` (a branch, tag or commit, `HEAD` being the default branch)
+/// from `url` into an existing checkout and switch to it. The URL is used
+/// rather than `origin`, which may have been cloned over HTTPS before `--ssh`.
+fn fetch_steps(dir: &Path, url: &Url, git_ref: &str, full_history: bool) -> Vec<Step> {
+    let mut fetch = git(dir).arg("fetch");
+    if !full_history {
+        fetch = fetch.arg("--depth").arg("1");
+    } else if dir.join(".git").join("shallow").exists() {
+        fetch = fetch.arg("--unshallow");
+    }
+    vec![
+        fetch.arg(url.to_string()).arg(git_ref),
+        Step::new("git", dir)
+            .arg("checkout")
+            .arg("--quiet")
+            .arg("--force")
+            .arg("FETCH_HEAD"),
+    ]
+}
+
+/// The commit checked out in `dir`.
+pub fn head_commit(dir: &Path) -> Option<String> {
+    let output = std::process::Command::new("git")
+        .args(["rev-parse", "HEAD"])
+        .current_dir(dir)
+        .output()
+        .ok()?;
+    output
+        .status
+        .success()
+        .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Whether the checkout in `dir` keeps files in Git LFS.
+pub fn uses_lfs(dir: &Path) -> bool {
+    let output = std::process::Command::new("git")
+        .args(["ls-files", "--", "*.gitattributes"])
+        .current_dir(dir)
+        .output();
+    let Ok(output) = output else {
+        return false;
+    };
+    String::from_utf8_lossy(&output.stdout).lines().any(|file| {
+        std::fs::read_to_string(dir.join(file)).is_ok_and(|text| text.contains("filter=lfs"))
+    })
+}
+
+/// Fetch the files the checkout in `dir` keeps in Git LFS. Without them the
+/// build would only see the small pointer files git checks out in their place.
+pub fn fetch_lfs(dir: &Path) -> Result<Step, InstallError> {
+    outputln!("the project keeps files in Git LFS, fetching them.");
+    installer::verify_has_programs(&["git-lfs"])?;
+    let pull = git(dir).arg("lfs").arg("pull");
+    if !retry::run(&pull)?.success() {
+        return Err(InstallError::StepFailed(pull.to_string()));
+    }
+    Ok(pull)
+}
+
+/// Check out the git submodules of the checkout in `dir`, if it has any, at
+/// the commits it records.
+pub fn update_submodules(dir: &Path, full_history: bool) -> Result<Option<Step>, InstallError> {
+    if !dir.join(".gitmodules").is_file() {
+        return Ok(None);
+    }
+    outputln!("the project has git submodules, checking them out.");
+    let mut update = git(dir)
+        .arg("submodule")
+        .arg("update")
+        .arg("--init")
+        .arg("--recursive");
+    if !full_history {
+        update = update.arg("--depth").arg("1");
+    }
+    if !retry::run(&update)?.success() {
+        return Err(InstallError::StepFailed(update.to_string()));
+    }
+    Ok(Some(update))
+}
+
+fn remove(dir: &Path) -> Result<(), InstallError> {
+    std::fs::remove_dir_all(dir)
+        .map_err(|e| InstallError::BadDirectory(format!("{}: {}", dir.display(), e)))
+}
+
+/// Make sure there is an up to date checkout of `url` at `git_ref`, returning
+/// where it is and the git commands that were run to get it there.
+pub fn checkout(
+    url: &Url,
+    git_ref: Option<&str>,
+    full_history: bool,
+) -> Result<(PathBuf, Vec<Step>), InstallError> {
+    let wanted = git_ref.unwrap_or("HEAD");
+    let source = source_dir(url);
+    let partial = partial_dir(&source);
+    let parent = source.parent().unwrap_or(Path::new("/tmp"));
+    std::fs::create_dir_all(parent).map_err(|_| InstallError::FailedToCreateDirectory)?;
+    let mut done = vec![];
+
+    // what another user left here could run anything when it is built.
+    for dir in [&source, &partial] {
+        if dir.exists() && !is_owned(dir) {
+            outputln!(
+                purple,
+                "{} belongs to another user, cloning again instead of using it.",
+                (dir.display())
+            );
+            remove(dir)?;
+        }
+    }
+
+    if source.join(".git").exists() {
+        outputln!("updating the existing checkout in {}", (source.display()));
+        let mut steps = fetch_steps(&source, url, wanted, full_history);
+        // leftovers from an earlier build must not leak into this one.
+        steps.push(Step::new("git", &source).arg("clean").arg("-ffdx"));
+        if run_steps(steps, &mut done) {
+            return Ok((source, done));
+        }
+        outputln!(purple, "failed to update the checkout, cloning it again.");
+        remove(&source)?;
+    }
+
+    if partial.join(".git").exists() {
+        outputln!(
+            "found an interrupted clone of {} in {}, resuming it.",
+            url,
+            (partial.display())
+        );
+        if run_steps(fetch_steps(&partial, url, wanted, full_history), &mut done) {
+            std::fs::rename(&partial, &source)
+                .map_err(|e| InstallError::BadDirectory(format!("{}: {}", source.display(), e)))?;
+            return Ok((source, done));
+        }
+        outputln!(
+            purple,
+            "the interrupted clone can't be resumed, starting over."
+        );
+    }
+    if partial.exists() {
+        remove(&partial)?;
+    }
+
+    let mut clone = git(parent).arg("clone");
+    if !full_history {
+        // servers that don't support filtering ignore it with a warning.
+        clone = clone.arg("--depth").arg("1").arg("--filter=blob:none");
+    }
+    let clone = clone
+        .arg(url.to_string())
+        .arg(partial.display().to_string());
+    let (status, output) = retry::run_with_output(&clone)?;
+    done.push(clone);
+    if !status.success() {
+        let code = status.code().unwrap_or(-1);
+        outputln!(
+            red,
+            "failed to git clone to repository (exited with code {})",
+            code
+        );
+        return Err(clone_failure(url, &output));
+    }
+    if let Some(git_ref) = git_ref {
+        if !run_steps(fetch_steps(&partial, url, git_ref, full_history), &mut done) {
+            return Err(InstallError::UnknownFatal(format!(
+                "`{}` is not a branch, tag or commit of {}.",
+                git_ref, url
+            )));
+        }
+    }
+
+    std::fs::rename(&partial, &source)
+        .map_err(|e| InstallError::BadDirectory(format!("{}: {}", source.display(), e)))?;
+    Ok((source, done))
+}
+
+/// Why cloning `url` failed, from what git printed and, when that doesn't
+/// say, from asking the host.
+fn clone_failure(url: &Url, output: &[String]) -> InstallError {
+    let host = url.host_str().unwrap_or_default().to_string();
+    if retry::is_transient(output) {
+        return InstallError::NetworkError(host);
+    }
+    if retry::not_found(output) {
+        return InstallError::RepositoryNotFound(url.to_string());
+    }
+    if retry::needs_credentials(output) {
+        // GitHub asks for credentials for repositories that don't exist, so
+        // that it doesn't give away which private ones do. A token that can
+        // see private repositories can tell the two apart.
+        if let (Some(repo), Some(_)) = (github::repo_of(url), github::token()) {
+            let api = github::api_url(&format!("repos/{}", repo), &[]);
+            if matches!(github::request("GET", &api, None), Err(e) if e.contains("404")) {
+                return InstallError::RepositoryNotFound(url.to_string());
+            }
+        }
+        return InstallError::AuthRequired(url.to_string());
+    }
+    if matches!(url.scheme(), "http" | "https") && !mirrors::recheck(url) {
+        return InstallError::NetworkError(host);
+    }
+    if url.scheme() == "https" {
+        outputln!(
+            purple,
+            "if the repository is private, set $GITHUB_TOKEN, set up a git credential helper, or pass --ssh to clone it with your SSH keys."
+        );
+    }
+    InstallError::FailedToClone
+}
+
+/// Refuse `archive` unless its SHA-256 is `expected`.
+fn check_sha256(archive: &Path, expected: &str) -> Result<(), InstallError> {
+    let expected = expected.trim_start_matches("sha256:").to_lowercase();
+    let actual = manifest::sha256_of(archive).unwrap_or_default();
+    if actual != expected {
+        return Err(InstallError::UnknownFatal(format!(
+            "the SHA-256 of {} is {}, not {}. it was changed or corrupted, refusing to build it.",
+            archive.display(),
+            actual,
+            expected
+        )));
+    }
+    outputln!(green, "the SHA-256 of {} matches.", (archive.display()));
+    Ok(())
+}
+
+/// Download the archive at `url` and extract it, returning where the project
+/// is and the commands that were run to get it there. Archives usually hold a
+/// single `project-1.0/` directory, which is then the project. With `sha256`
+/// the archive has to have that checksum, with `signing_keys` a good signature
+/// by one of them.
+pub fn download(
+    url: &Url,
+    sha256: Option<&str>,
+    signing_keys: Option<&[String]>,
+) -> Result<(PathBuf, Vec<Step>), InstallError> {
+    let source = source_dir(url);
+    let partial = partial_dir(&source);
+    let parent = source.parent().unwrap_or(Path::new("/tmp"));
+    for dir in [&source, &partial] {
+        if dir.exists() {
+            remove(dir)?;
+        }
+    }
+    std::fs::create_dir_all(&partial).map_err(|_| InstallError::FailedToCreateDirectory)?;
+
+    let file = url
+        .path_segments()
+        .and_then(|mut s| s.next_back())
+        .unwrap_or("archive");
+    let archive = parent.join(file);
+    let extract = if file.ends_with(".zip") {
+        Step::new("unzip", parent)
+            .arg("-q")
+            .arg(archive.display().to_string())
+            .arg("-d")
+            .arg(partial.display().to_string())
+    } else {
+        Step::new("tar", parent)
+            .arg("-xf")
+            .arg(archive.display().to_string())
+            .arg("-C")
+            .arg(partial.display().to_string())
+    };
+    // no progress bar for screen readers or for scripts reading the output.
+    let progress = match ui::a11y() || ui::porcelain() {
+        true => "-sS",
+        false => "--progress-bar",
+    };
+    let curl = Step::new("curl", parent)
+        .arg("-fL")
+        .arg(progress)
+        .arg("-o")
+        .arg(archive.display().to_string())
+        .arg(url.to_string());
+    let mut done = vec![];
+    let downloaded = run_steps(vec![curl], &mut done);
+    let checked = match sha256 {
+        Some(expected) if downloaded => check_sha256(&archive, expected),
+        _ => Ok(()),
+    }
+    .and_then(|_| match signing_keys {
+        Some(keys) if downloaded => signature::verify_archive(&archive, url, keys),
+        _ => Ok(()),
+    });
+    let ok = downloaded && checked.is_ok() && run_steps(vec![extract], &mut done);
+    let _ = std::fs::remove_file(&archive);
+    checked?;
+    if !downloaded && !mirrors::recheck(url) {
+        return Err(InstallError::NetworkError(
+            url.host_str().unwrap_or_default().to_string(),
+        ));
+    }
+    if !ok {
+        return Err(InstallError::StepFailed(
+            done.last().map(|s| s.to_string()).unwrap_or_default(),
+        ));
+    }
+
+    let entries: Vec<PathBuf> = std::fs::read_dir(&partial)
+        .map_err(|e| InstallError::BadDirectory(format!("{}: {}", partial.display(), e)))?
+        .flatten()
+        .map(|e| e.path())
+        .collect();
+    let top = match entries.as_slice() {
+        [only] if only.is_dir() => only.clone(),
+        _ => partial.clone(),
+    };
+    std::fs::rename(&top, &source)
+        .map_err(|e| InstallError::BadDirectory(format!("{}: {}", source.display(), e)))?;
+    if partial.exists() {
+        remove(&partial)?;
+    }
+    outputln!("extracted {} into {}", file, (source.display()));
+    Ok((source, done))
+}